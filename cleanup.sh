@@ -37,12 +37,13 @@ sudo iptables -F FORWARD 2>/dev/null || true
 
 # Limpar containers
 echo "Cleaning containers..."
-sudo rm -rf ./containers/rustainer_* 2>/dev/null || true
+ROOT="${RUSTAINER_ROOT:-/var/lib/rustainer}"
+sudo rm -rf "$ROOT"/containers/rustainer_* 2>/dev/null || true
 
 # Verificação final
 echo "🔍 Final verification..."
 echo "Namespaces: $(ip netns list 2>/dev/null | grep rustainer | wc -l)"
 echo "Veth interfaces: $(ip link show 2>/dev/null | grep -E "(veth.*[ch])" | wc -l)"
-echo "Containers: $(ls -la containers/ 2>/dev/null | grep rustainer | wc -l)"
+echo "Containers: $(ls -la "$ROOT"/containers/ 2>/dev/null | grep rustainer | wc -l)"
 
 echo "✅ Cleanup completed!"
\ No newline at end of file
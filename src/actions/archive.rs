@@ -0,0 +1,456 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::actions::{
+    container, events, images, pull,
+    store::{self, LockMode},
+    types::{Compression, ImageManifest, Layer, Platform},
+};
+
+/// The annotation naming an image in an OCI layout.
+const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+/// How images are laid out in an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// As written by `docker save`.
+    Docker,
+    /// An OCI image layout, as written by `skopeo copy oci-archive:` or
+    /// `buildah push`.
+    Oci,
+}
+
+/// An image to run from an archive instead of the store, given as
+/// `docker-archive:PATH[:REFERENCE]` or `oci-archive:PATH[:REFERENCE]`.
+#[derive(Debug, Clone)]
+pub struct ArchiveReference {
+    pub format: ArchiveFormat,
+    pub path: String,
+    /// Picks the image out of archives holding several and names it.
+    pub reference: Option<String>,
+}
+
+impl ArchiveReference {
+    /// Parses an image given to `run`, `None` unless it names an archive.
+    pub fn parse(image: &str) -> Option<Self> {
+        let (format, rest) = if let Some(rest) = image.strip_prefix("docker-archive:") {
+            (ArchiveFormat::Docker, rest)
+        } else if let Some(rest) = image.strip_prefix("oci-archive:") {
+            (ArchiveFormat::Oci, rest)
+        } else {
+            return None;
+        };
+
+        // Paths can contain colons too, an existing file wins.
+        let (path, reference) = match rest.split_once(':') {
+            Some((path, reference)) if !Path::new(rest).exists() && !reference.is_empty() => {
+                (path, Some(reference.to_string()))
+            }
+            _ => (rest, None),
+        };
+        Some(ArchiveReference {
+            format,
+            path: path.to_string(),
+            reference,
+        })
+    }
+}
+
+/// An entry of a docker-archive's manifest.json.
+#[derive(Debug, Deserialize)]
+struct DockerArchiveEntry {
+    #[serde(rename = "Config")]
+    config: String,
+    #[serde(rename = "RepoTags", default)]
+    repo_tags: Option<Vec<String>>,
+    #[serde(rename = "Layers")]
+    layers: Vec<String>,
+}
+
+/// An OCI layout's index.json, or an image index it points to.
+#[derive(Debug, Deserialize)]
+struct OciIndex {
+    manifests: Vec<OciDescriptor>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OciDescriptor {
+    #[serde(rename = "mediaType", default)]
+    media_type: String,
+    digest: String,
+    #[serde(default)]
+    platform: Option<Platform>,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+}
+
+impl OciDescriptor {
+    fn is_index(&self) -> bool {
+        self.media_type.ends_with("image.index.v1+json")
+            || self.media_type.ends_with("manifest.list.v2+json")
+    }
+}
+
+/// The files of the image found in an unpacked archive.
+struct ArchiveImage {
+    name: Option<String>,
+    config: PathBuf,
+    /// Each with its media type, which docker-archives do not record.
+    layers: Vec<(PathBuf, Option<String>)>,
+}
+
+/// Loads the image in an archive into the store, replacing any image of the
+/// same name, and returns the reference it was stored under.
+pub fn load_archive(
+    archive: &ArchiveReference,
+    platform: Option<&str>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let wanted = match platform {
+        Some(platform) => platform.parse::<Platform>()?,
+        None => Platform::host(),
+    };
+    println!("📦 Loading image from {}", archive.path);
+
+    let staging = PathBuf::from(store::path(&format!(
+        "{}/archive-{}",
+        store::TMP_DIR,
+        std::process::id()
+    )));
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging)?;
+    let result =
+        unpack(&archive.path, &staging).and_then(|_| load_unpacked(archive, &staging, &wanted));
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+fn unpack(path: &str, staging: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    tar::Archive::new(file)
+        .unpack(staging)
+        .map_err(|e| format!("Failed to unpack {}: {}", path, e).into())
+}
+
+fn load_unpacked(
+    archive: &ArchiveReference,
+    staging: &Path,
+    wanted: &Platform,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let found = match archive.format {
+        ArchiveFormat::Docker => docker_image(archive, staging)?,
+        ArchiveFormat::Oci => oci_image(archive, staging, wanted)?,
+    };
+    let image = match found.name {
+        Some(name) => name,
+        None => default_name(&archive.path, "latest"),
+    };
+    let (repository, tag) = pull::parse_image_tag(&image);
+    if pull::is_digest(&tag) {
+        return Err("Images loaded from archives cannot be named by digest, use a tag".into());
+    }
+    let image = pull::format_image_reference(&repository, &tag);
+
+    let store_lock = store::lock_store(LockMode::Shared)?;
+    let image_lock = store::lock_image(&repository, &tag, LockMode::Exclusive)?;
+    let image_dir = store::image_dir(&repository, &tag);
+    fs::create_dir_all(&image_dir)?;
+
+    let config_json = fs::read(&found.config)?;
+    let (config_digest, config_size) = store_blob(&found.config, &image_dir)?;
+    // Archives may list a layer twice, it is moved the first time.
+    let mut stored: HashMap<&Path, (String, u64)> = HashMap::new();
+    let mut layers = Vec::new();
+    for (path, media_type) in &found.layers {
+        let media_type = match media_type {
+            Some(media_type) => media_type.clone(),
+            None => layer_media_type(path)?,
+        };
+        let (digest, size) = match stored.get(path.as_path()) {
+            Some(blob) => blob.clone(),
+            None => store_blob(path, &image_dir)?,
+        };
+        stored.insert(path, (digest.clone(), size));
+        layers.push(Layer {
+            media_type,
+            size,
+            digest,
+            annotations: HashMap::new(),
+        });
+    }
+
+    let (manifest_media_type, config_media_type) = match archive.format {
+        ArchiveFormat::Docker => (
+            "application/vnd.docker.distribution.manifest.v2+json",
+            "application/vnd.docker.container.image.v1+json",
+        ),
+        ArchiveFormat::Oci => (
+            "application/vnd.oci.image.manifest.v1+json",
+            "application/vnd.oci.image.config.v1+json",
+        ),
+    };
+    let manifest = ImageManifest {
+        schema_version: 2,
+        media_type: manifest_media_type.to_string(),
+        config: Layer {
+            media_type: config_media_type.to_string(),
+            size: config_size,
+            digest: config_digest.clone(),
+            annotations: HashMap::new(),
+        },
+        layers,
+    };
+    fs::write(
+        format!("{}/manifest.json", image_dir),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    let platform =
+        serde_json::from_slice::<Platform>(&config_json).unwrap_or_else(|_| wanted.clone());
+    if !platform.satisfies(wanted) {
+        println!(
+            "⚠️ Warning: The image's platform ({}) does not match the requested platform ({})",
+            platform, wanted
+        );
+    }
+    pull::save_image_metadata(
+        &image_dir,
+        &serde_json::json!({
+            "platform": platform.to_string(),
+            "last_used": container::now_secs(),
+        }),
+    )?;
+
+    events::emit(
+        "image",
+        "load",
+        &image,
+        serde_json::json!({ "digest": config_digest, "source": archive.path }),
+    );
+    println!("✅ Loaded image {}", image);
+
+    drop(image_lock);
+    drop(store_lock);
+    images::enforce_size_limit(&image_dir)?;
+    Ok(image)
+}
+
+fn docker_image(
+    archive: &ArchiveReference,
+    staging: &Path,
+) -> Result<ArchiveImage, Box<dyn std::error::Error>> {
+    let entries: Vec<DockerArchiveEntry> = serde_json::from_slice(
+        &fs::read(staging.join("manifest.json"))
+            .map_err(|_| format!("{} is not a docker-archive", archive.path))?,
+    )?;
+
+    let entry = match &archive.reference {
+        Some(reference) => {
+            let wanted = pull::parse_image_tag(reference);
+            entries
+                .iter()
+                .find(|entry| {
+                    entry
+                        .repo_tags
+                        .iter()
+                        .flatten()
+                        .any(|tag| pull::parse_image_tag(tag) == wanted)
+                })
+                // An archive of one image may be given any name.
+                .or_else(|| entries.first().filter(|_| entries.len() == 1))
+                .ok_or_else(|| format!("No image {} in {}", reference, archive.path))?
+        }
+        None => match entries.as_slice() {
+            [entry] => entry,
+            [] => return Err(format!("{} holds no image", archive.path).into()),
+            _ => {
+                return Err(format!(
+                    "{} holds several images, name one as docker-archive:{}:IMAGE",
+                    archive.path, archive.path
+                )
+                .into())
+            }
+        },
+    };
+
+    let name = archive.reference.clone().or_else(|| {
+        entry
+            .repo_tags
+            .as_ref()
+            .and_then(|tags| tags.first().cloned())
+    });
+    let layers = entry
+        .layers
+        .iter()
+        .map(|layer| Ok((archive_file(staging, layer)?, None)))
+        .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+    Ok(ArchiveImage {
+        name,
+        config: archive_file(staging, &entry.config)?,
+        layers,
+    })
+}
+
+fn oci_image(
+    archive: &ArchiveReference,
+    staging: &Path,
+    wanted: &Platform,
+) -> Result<ArchiveImage, Box<dyn std::error::Error>> {
+    let index: OciIndex = serde_json::from_slice(
+        &fs::read(staging.join("index.json"))
+            .map_err(|_| format!("{} is not an oci-archive", archive.path))?,
+    )?;
+
+    let descriptor = match &archive.reference {
+        Some(reference) => index
+            .manifests
+            .iter()
+            .find(|descriptor| descriptor.annotations.get(REF_NAME_ANNOTATION) == Some(reference))
+            .or_else(|| {
+                index
+                    .manifests
+                    .first()
+                    .filter(|_| index.manifests.len() == 1)
+            })
+            .ok_or_else(|| format!("No image {} in {}", reference, archive.path))?,
+        None => match index.manifests.as_slice() {
+            [descriptor] => descriptor,
+            [] => return Err(format!("{} holds no image", archive.path).into()),
+            _ => {
+                return Err(format!(
+                    "{} holds several images, name one as oci-archive:{}:NAME",
+                    archive.path, archive.path
+                )
+                .into())
+            }
+        },
+    };
+
+    // Layouts name images by tag, or by their whole reference.
+    let name = match (
+        &archive.reference,
+        descriptor.annotations.get(REF_NAME_ANNOTATION),
+    ) {
+        (Some(reference), _) | (None, Some(reference))
+            if reference.contains(':') || reference.contains('/') =>
+        {
+            Some(reference.clone())
+        }
+        (Some(tag), _) | (None, Some(tag)) => Some(default_name(&archive.path, tag)),
+        (None, None) => None,
+    };
+
+    let mut manifest_path = blob_path(staging, &descriptor.digest)?;
+    if descriptor.is_index() {
+        let nested: OciIndex = serde_json::from_slice(&fs::read(&manifest_path)?)?;
+        let selected = nested
+            .manifests
+            .iter()
+            .find(|m| m.platform.as_ref().is_some_and(|p| p.satisfies(wanted)))
+            .ok_or_else(|| format!("No image for {} in {}", wanted, archive.path))?;
+        manifest_path = blob_path(staging, &selected.digest)?;
+    }
+    let manifest: ImageManifest = serde_json::from_slice(&fs::read(&manifest_path)?)?;
+
+    let layers = manifest
+        .layers
+        .iter()
+        .map(|layer| {
+            Ok((
+                blob_path(staging, &layer.digest)?,
+                Some(layer.media_type.clone()),
+            ))
+        })
+        .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+    Ok(ArchiveImage {
+        name,
+        config: blob_path(staging, &manifest.config.digest)?,
+        layers,
+    })
+}
+
+/// A file of an unpacked archive, which may not lead out of it.
+fn archive_file(staging: &Path, name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = fs::canonicalize(staging.join(name))
+        .map_err(|_| format!("The archive has no file {}", name))?;
+    if !path.starts_with(fs::canonicalize(staging)?) || !path.is_file() {
+        return Err(format!("The archive has no file {}", name).into());
+    }
+    Ok(path)
+}
+
+/// A blob of an unpacked OCI layout, checked against its digest.
+fn blob_path(staging: &Path, digest: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let hex = digest
+        .strip_prefix("sha256:")
+        .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| format!("Unsupported digest {}", digest))?;
+    let path = archive_file(staging, &format!("blobs/sha256/{}", hex))?;
+    let (actual, _) = file_digest(&path)?;
+    if actual != digest {
+        return Err(format!("Blob {} does not match its digest, got {}", digest, actual).into());
+    }
+    Ok(path)
+}
+
+fn file_digest(path: &Path) -> Result<(String, u64), Box<dyn std::error::Error>> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((format!("sha256:{:x}", hasher.finalize()), size))
+}
+
+/// Moves a file of the unpacked archive into the image's directory, named
+/// by its digest like pulled blobs.
+fn store_blob(path: &Path, image_dir: &str) -> Result<(String, u64), Box<dyn std::error::Error>> {
+    let (digest, size) = file_digest(path)?;
+    fs::rename(
+        path,
+        format!("{}/{}", image_dir, digest.replace("sha256:", "")),
+    )?;
+    Ok((digest, size))
+}
+
+/// The media type of a docker-archive layer, told from how it starts.
+fn layer_media_type(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut magic = [0u8; 4];
+    let read = File::open(path)?.read(&mut magic)?;
+    let magic = &magic[..read];
+    let compression = [Compression::Gzip, Compression::Zstd]
+        .into_iter()
+        .find(|compression| magic.starts_with(compression.magic()));
+
+    Ok(match compression {
+        Some(Compression::Gzip) => "application/vnd.docker.image.rootfs.diff.tar.gzip",
+        Some(Compression::Zstd) => "application/vnd.docker.image.rootfs.diff.tar.zstd",
+        _ => "application/vnd.docker.image.rootfs.diff.tar",
+    }
+    .to_string())
+}
+
+/// The name of an image the archive does not name, after the archive file
+/// and under `localhost/` so it is never taken for a registry image.
+fn default_name(path: &str, tag: &str) -> String {
+    let stem: String = Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("archive")
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let stem = match stem.trim_matches(['.', '_', '-']) {
+        "" => "archive",
+        stem => stem,
+    };
+    format!("localhost/{}:{}", stem, tag)
+}
@@ -0,0 +1,87 @@
+use caps::{CapSet, Capability};
+
+/// The capabilities a container keeps by default, the same set Docker grants.
+const DEFAULT_CAPABILITIES: [Capability; 14] = [
+    Capability::CAP_CHOWN,
+    Capability::CAP_DAC_OVERRIDE,
+    Capability::CAP_FSETID,
+    Capability::CAP_FOWNER,
+    Capability::CAP_MKNOD,
+    Capability::CAP_NET_RAW,
+    Capability::CAP_SETGID,
+    Capability::CAP_SETUID,
+    Capability::CAP_SETFCAP,
+    Capability::CAP_SETPCAP,
+    Capability::CAP_NET_BIND_SERVICE,
+    Capability::CAP_SYS_CHROOT,
+    Capability::CAP_KILL,
+    Capability::CAP_AUDIT_WRITE,
+];
+
+/// Parses a capability as given to `--cap-add`/`--cap-drop`, with or without
+/// the `CAP_` prefix and in any case.
+fn parse_capability(name: &str) -> Result<Capability, Box<dyn std::error::Error>> {
+    let name = name.to_uppercase();
+    let name = if name.starts_with("CAP_") {
+        name
+    } else {
+        format!("CAP_{}", name)
+    };
+
+    name.parse()
+        .map_err(|_| format!("Unknown capability: {}", name).into())
+}
+
+/// Works out the capabilities the container keeps, as `CAP_*` names. `ALL`
+/// adds or drops every capability, and explicit additions win over
+/// `--cap-drop ALL`.
+pub fn resolve(
+    cap_add: &[String],
+    cap_drop: &[String],
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let is_all = |name: &String| name.eq_ignore_ascii_case("all");
+
+    let mut kept: Vec<Capability> = if cap_add.iter().any(is_all) {
+        caps::all().into_iter().collect()
+    } else if cap_drop.iter().any(is_all) {
+        Vec::new()
+    } else {
+        DEFAULT_CAPABILITIES.to_vec()
+    };
+
+    for name in cap_drop.iter().filter(|name| !is_all(name)) {
+        let cap = parse_capability(name)?;
+        kept.retain(|kept| *kept != cap);
+    }
+    for name in cap_add.iter().filter(|name| !is_all(name)) {
+        let cap = parse_capability(name)?;
+        if !kept.contains(&cap) {
+            kept.push(cap);
+        }
+    }
+
+    kept.sort_by_key(|cap| cap.index());
+    Ok(kept.iter().map(|cap| cap.to_string()).collect())
+}
+
+/// The capabilities to remove from the bounding set so only `kept` remain,
+/// skipping any the current process has already lost.
+pub fn to_drop(kept: &[String]) -> Vec<Capability> {
+    let kept: Vec<Capability> = kept.iter().filter_map(|name| name.parse().ok()).collect();
+
+    caps::all()
+        .into_iter()
+        .filter(|cap| !kept.contains(cap))
+        .filter(|cap| caps::has_cap(None, CapSet::Bounding, *cap).unwrap_or(false))
+        .collect()
+}
+
+/// Limits what the next exec can gain to the bounding set, for use between
+/// fork and exec: nothing here allocates unless it fails.
+pub fn apply(to_drop: &[Capability]) -> Result<(), caps::errors::CapsError> {
+    for cap in to_drop {
+        caps::drop(None, CapSet::Bounding, *cap)?;
+    }
+    caps::clear(None, CapSet::Inheritable)?;
+    caps::clear(None, CapSet::Ambient)
+}
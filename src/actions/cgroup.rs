@@ -0,0 +1,524 @@
+use crate::actions::{container, utils};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// Parent group of container cgroups without `--cgroup-parent`, relative to
+/// the cgroup root.
+const CGROUP_PARENT: &str = "rustainer";
+const DELEGATION_LEAF: &str = "init";
+const DEFAULT_CPU_PERIOD: u64 = 100_000;
+/// Applied when `--pids-limit` is not given, enough for any sane workload but
+/// low enough to stop a fork bomb.
+const DEFAULT_PIDS_LIMIT: u64 = 4096;
+const REMOVE_TIMEOUT: Duration = Duration::from_secs(2);
+/// The kernel refuses to run most processes below this.
+const MIN_MEMORY: u64 = 6 * 1024 * 1024;
+
+/// Resource limits given on the `run` command line.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    pub memory: Option<u64>,
+    /// Memory plus swap, `-1` for unlimited swap.
+    pub memory_swap: Option<i64>,
+    pub memory_reservation: Option<u64>,
+    /// Number of CPUs, converted to a quota over the default period.
+    pub cpus: Option<f64>,
+    /// Relative weight in Docker's 2-262144 share range.
+    pub cpu_shares: Option<u64>,
+    /// Microseconds per period, `-1` for no quota.
+    pub cpu_quota: Option<i64>,
+    pub cpu_period: Option<u64>,
+    /// Maximum number of processes, `0` or `-1` for unlimited.
+    pub pids_limit: Option<i64>,
+    /// Relative IO weight in Docker's 10-1000 range.
+    pub blkio_weight: Option<u64>,
+    pub device_read_bps: Vec<DeviceRate>,
+    pub device_write_bps: Vec<DeviceRate>,
+    /// CPUs the container may run on, as a list like `0-3,6`.
+    pub cpuset_cpus: Option<String>,
+    /// NUMA memory nodes the container may allocate from.
+    pub cpuset_mems: Option<String>,
+}
+
+/// A bytes per second limit on a block device, from `--device-read-bps` or
+/// `--device-write-bps`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceRate {
+    pub path: String,
+    pub major: u64,
+    pub minor: u64,
+    pub rate: u64,
+}
+
+impl DeviceRate {
+    /// Parses `PATH:RATE`, with RATE a size such as `10mb` per second.
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (path, rate) = spec
+            .rsplit_once(':')
+            .ok_or_else(|| format!("Invalid device rate: {}. Expected PATH:RATE", spec))?;
+        let metadata =
+            fs::metadata(path).map_err(|e| format!("Failed to stat device {}: {}", path, e))?;
+        if !metadata.file_type().is_block_device() {
+            return Err(format!("{} is not a block device", path).into());
+        }
+
+        Ok(DeviceRate {
+            path: path.to_string(),
+            major: libc::major(metadata.rdev()) as u64,
+            minor: libc::minor(metadata.rdev()) as u64,
+            rate: utils::parse_size(rate)?,
+        })
+    }
+}
+
+impl ResourceLimits {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(memory) = self.memory {
+            if memory < MIN_MEMORY {
+                return Err("Minimum memory limit allowed is 6MB".into());
+            }
+        }
+
+        if let Some(memory_swap) = self.memory_swap.filter(|swap| *swap != -1) {
+            let memory = self
+                .memory
+                .ok_or("You should always set the memory limit when using the memory-swap limit")?;
+            if memory_swap < memory as i64 {
+                return Err(
+                    "Minimum memory-swap limit should be larger than the memory limit".into(),
+                );
+            }
+        }
+
+        if let (Some(memory), Some(reservation)) = (self.memory, self.memory_reservation) {
+            if reservation > memory {
+                return Err(
+                    "Minimum memory limit can not be less than the memory reservation limit".into(),
+                );
+            }
+        }
+
+        if let Some(cpus) = self.cpus {
+            if self.cpu_quota.is_some() || self.cpu_period.is_some() {
+                return Err(
+                    "Conflicting options: --cpus and --cpu-period/--cpu-quota cannot both be set"
+                        .into(),
+                );
+            }
+            let available = std::thread::available_parallelism().map_or(1, |n| n.get());
+            if !(0.01..=available as f64).contains(&cpus) {
+                return Err(format!(
+                    "Range of CPUs is from 0.01 to {}, as there are only {} CPUs available",
+                    available, available
+                )
+                .into());
+            }
+        }
+        if let Some(shares) = self.cpu_shares {
+            if !(2..=262144).contains(&shares) {
+                return Err("CPU shares must be between 2 and 262144".into());
+            }
+        }
+        if let Some(period) = self.cpu_period {
+            if !(1000..=1_000_000).contains(&period) {
+                return Err("CPU period must be between 1000 and 1000000 microseconds".into());
+            }
+        }
+        if let Some(quota) = self.cpu_quota {
+            if quota != -1 && quota < 1000 {
+                return Err("CPU quota must be at least 1000 microseconds, or -1".into());
+            }
+        }
+        if let Some(limit) = self.pids_limit {
+            if limit < -1 {
+                return Err("Pids limit must be positive, or 0 or -1 for unlimited".into());
+            }
+        }
+        if let Some(weight) = self.blkio_weight {
+            if !(10..=1000).contains(&weight) {
+                return Err("Blkio weight must be between 10 and 1000".into());
+            }
+        }
+        for list in [&self.cpuset_cpus, &self.cpuset_mems].into_iter().flatten() {
+            if !is_cpu_list(list) {
+                return Err(
+                    format!("Invalid cpuset list: {}. Expected a list like 0-3,6", list).into(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether no limit was given explicitly; the default PID limit does not
+    /// count since it is skipped on hosts without the pids controller.
+    pub fn is_empty(&self) -> bool {
+        self.files().is_empty()
+    }
+
+    /// The cgroup v2 interface files and values implementing these limits.
+    fn files(&self) -> Vec<(&'static str, String)> {
+        let mut files = Vec::new();
+
+        if let Some(memory) = self.memory {
+            files.push(("memory.max", memory.to_string()));
+
+            // Like Docker, an unset swap limit allows as much swap as memory.
+            let swap = match self.memory_swap {
+                Some(-1) => "max".to_string(),
+                Some(total) => (total as u64).saturating_sub(memory).to_string(),
+                None => memory.to_string(),
+            };
+            files.push(("memory.swap.max", swap));
+        }
+        if let Some(reservation) = self.memory_reservation {
+            files.push(("memory.low", reservation.to_string()));
+        }
+
+        let period = self.cpu_period.unwrap_or(DEFAULT_CPU_PERIOD);
+        if let Some(cpus) = self.cpus {
+            let quota = (cpus * period as f64).round() as u64;
+            files.push(("cpu.max", format!("{} {}", quota, period)));
+        } else if self.cpu_quota.is_some() || self.cpu_period.is_some() {
+            let quota = match self.cpu_quota {
+                Some(quota) if quota > 0 => quota.to_string(),
+                _ => "max".to_string(),
+            };
+            files.push(("cpu.max", format!("{} {}", quota, period)));
+        }
+        if let Some(shares) = self.cpu_shares {
+            // Maps the share range linearly onto cgroup v2's 1-10000 weights.
+            let weight = 1 + (shares - 2) * 9999 / 262142;
+            files.push(("cpu.weight", weight.to_string()));
+        }
+
+        if let Some(weight) = self.blkio_weight {
+            // Maps Docker's range linearly onto cgroup v2's 1-10000 weights.
+            let weight = 1 + (weight - 10) * 9999 / 990;
+            files.push(("io.weight", format!("default {}", weight)));
+        }
+        // io.max takes one line per device, written one at a time.
+        for rate in &self.device_read_bps {
+            files.push((
+                "io.max",
+                format!("{}:{} rbps={}", rate.major, rate.minor, rate.rate),
+            ));
+        }
+        for rate in &self.device_write_bps {
+            files.push((
+                "io.max",
+                format!("{}:{} wbps={}", rate.major, rate.minor, rate.rate),
+            ));
+        }
+
+        if let Some(cpus) = &self.cpuset_cpus {
+            files.push(("cpuset.cpus", cpus.clone()));
+        }
+        if let Some(mems) = &self.cpuset_mems {
+            files.push(("cpuset.mems", mems.clone()));
+        }
+
+        if let Some(limit) = self.pids_limit {
+            let value = if limit > 0 {
+                limit.to_string()
+            } else {
+                "max".to_string()
+            };
+            files.push(("pids.max", value));
+        }
+
+        files
+    }
+}
+
+/// Whether the unified cgroup v2 hierarchy is mounted at the cgroup root.
+pub fn is_available() -> bool {
+    Path::new(CGROUP_ROOT).join("cgroup.controllers").exists()
+}
+
+/// The container's cgroup, under the parent recorded when it was created.
+pub fn cgroup_path(container_id: &str) -> String {
+    let parent = container::load_metadata(container_id)
+        .and_then(|metadata| metadata.get("cgroup_parent")?.as_str().map(String::from));
+    path_under(parent.as_deref(), container_id)
+}
+
+/// Where a container's cgroup goes below `parent`, a path relative to the
+/// cgroup root, or below the default parent.
+pub fn path_under(parent: Option<&str>, container_id: &str) -> String {
+    let parent = parent.map_or(CGROUP_PARENT, |parent| parent.trim_matches('/'));
+    format!("{}/{}/{}", CGROUP_ROOT, parent, container_id)
+}
+
+/// The group a process runs in, read from its cgroup v2 entry.
+pub fn process_cgroup(pid: u32) -> Option<String> {
+    let content = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    let path = content.lines().find_map(|line| line.strip_prefix("0::"))?;
+    Some(format!("{}{}", CGROUP_ROOT, path))
+}
+
+/// A cgroup path as seen from the cgroup root, the way OCI runtimes take it.
+pub fn relative_path(path: &str) -> &str {
+    path.strip_prefix(CGROUP_ROOT).unwrap_or(path)
+}
+
+/// Checks a `--cgroup-parent` value, which must stay below the cgroup root.
+pub fn validate_parent(parent: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let trimmed = parent.trim_matches('/');
+    if trimmed.is_empty()
+        || trimmed
+            .split('/')
+            .any(|part| part.is_empty() || part == "..")
+    {
+        return Err(format!("Invalid cgroup parent: {}", parent).into());
+    }
+    Ok(())
+}
+
+/// Creates the container's cgroup at `cgroup_path` and applies its limits.
+pub fn create(
+    container_id: &str,
+    limits: &ResourceLimits,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = cgroup_path(container_id);
+    let parent = Path::new(&path).parent().ok_or("Invalid cgroup path")?;
+
+    // Controllers reach a group only when every ancestor delegates them.
+    let mut ancestor = Path::new(CGROUP_ROOT).to_path_buf();
+    enable_controllers(&ancestor)?;
+    for component in parent.strip_prefix(CGROUP_ROOT)?.components() {
+        ancestor.push(component);
+        fs::create_dir_all(&ancestor)?;
+        enable_controllers(&ancestor)?;
+    }
+
+    fs::create_dir_all(&path)?;
+    apply(container_id, limits)?;
+
+    let pids_max = format!("{}/pids.max", path);
+    if limits.pids_limit.is_none() && Path::new(&pids_max).exists() {
+        fs::write(&pids_max, DEFAULT_PIDS_LIMIT.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Writes the limits to the container's existing cgroup.
+pub fn apply(
+    container_id: &str,
+    limits: &ResourceLimits,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = cgroup_path(container_id);
+    for (file, value) in limits.files() {
+        fs::write(format!("{}/{}", path, file), &value)
+            .map_err(|e| format!("Failed to set {} to {}: {}", file, value, e))?;
+    }
+
+    Ok(())
+}
+
+/// PIDs of every process in the container's cgroup, and the groups below it
+/// a nested container creates, empty when it has none.
+pub fn pids(container_id: &str) -> Vec<u32> {
+    let mut pids = Vec::new();
+    collect_pids(Path::new(&cgroup_path(container_id)), &mut pids);
+    pids
+}
+
+fn collect_pids(path: &Path, pids: &mut Vec<u32>) {
+    if let Ok(procs) = fs::read_to_string(path.join("cgroup.procs")) {
+        pids.extend(procs.lines().filter_map(|pid| pid.parse::<u32>().ok()));
+    }
+    for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            collect_pids(&entry.path(), pids);
+        }
+    }
+}
+
+/// The leaf group a nested container's processes run in. The container's own
+/// group is left without processes, so it can delegate controllers to the
+/// groups the container creates next to the leaf.
+pub fn delegation_leaf(container_id: &str) -> String {
+    format!("{}/{}", cgroup_path(container_id), DELEGATION_LEAF)
+}
+
+/// The `cgroup.subtree_control` line enabling every controller a nested
+/// container can use in its own cgroups.
+pub fn delegated_controllers(container_id: &str) -> Result<String, Box<dyn std::error::Error>> {
+    controller_list(Path::new(&cgroup_path(container_id)))
+}
+
+/// How many processes in the container's cgroup the OOM killer has killed,
+/// from the `oom_kill` count in `memory.events`.
+pub fn oom_kills(container_id: &str) -> u64 {
+    fs::read_to_string(format!("{}/memory.events", cgroup_path(container_id)))
+        .ok()
+        .and_then(|events| {
+            events
+                .lines()
+                .find_map(|line| line.strip_prefix("oom_kill "))
+                .and_then(|count| count.trim().parse().ok())
+        })
+        .unwrap_or(0)
+}
+
+/// Kills every process in the container's cgroup, including ones forked while
+/// it happens. Returns false when the container has no cgroup.
+pub fn kill(container_id: &str) -> bool {
+    let path = cgroup_path(container_id);
+    if !Path::new(&path).exists() {
+        return false;
+    }
+
+    // cgroup.kill only exists since Linux 5.14; before that the members are
+    // killed one by one until no new ones appear.
+    if fs::write(format!("{}/cgroup.kill", path), "1").is_err() {
+        let deadline = Instant::now() + REMOVE_TIMEOUT;
+        loop {
+            let pids = pids(container_id);
+            if pids.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            for pid in pids {
+                unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    true
+}
+
+/// Removes the container's cgroup, giving killed processes a moment to exit
+/// since a cgroup with members cannot be removed.
+pub fn remove(container_id: &str) {
+    let path = cgroup_path(container_id);
+    if !Path::new(&path).exists() {
+        return;
+    }
+
+    let deadline = Instant::now() + REMOVE_TIMEOUT;
+    loop {
+        match remove_tree(Path::new(&path)) {
+            Ok(()) => return,
+            Err(e) if e.raw_os_error() == Some(libc::EBUSY) && Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) => {
+                println!("⚠️ Warning: Failed to remove cgroup {}: {}", path, e);
+                return;
+            }
+        }
+    }
+}
+
+/// Removes a group after the groups below it, which nested containers and
+/// their delegation leaf leave behind.
+fn remove_tree(path: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            remove_tree(&entry.path())?;
+        }
+    }
+    fs::remove_dir(path)
+}
+
+/// Whether the value is a cpuset list: comma separated numbers and ranges.
+fn is_cpu_list(list: &str) -> bool {
+    list.split(',').all(|item| {
+        let (start, end) = item.split_once('-').unwrap_or((item, item));
+        match (start.parse::<u32>(), end.parse::<u32>()) {
+            (Ok(start), Ok(end)) => start <= end,
+            _ => false,
+        }
+    })
+}
+
+/// Delegates every available controller to the group's children.
+fn enable_controllers(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let wanted = controller_list(path)?;
+    if !wanted.is_empty() {
+        fs::write(path.join("cgroup.subtree_control"), wanted)?;
+    }
+
+    Ok(())
+}
+
+/// The controllers available in a group that rustainer uses, as a
+/// `cgroup.subtree_control` line.
+fn controller_list(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let controllers = fs::read_to_string(path.join("cgroup.controllers"))?;
+    Ok(controllers
+        .split_whitespace()
+        .filter(|c| matches!(*c, "cpu" | "cpuset" | "io" | "memory" | "pids"))
+        .map(|c| format!("+{}", c))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::FileTypeExt;
+
+    /// Any block device of the host, tests needing one are skipped without.
+    fn block_device() -> Option<String> {
+        fs::read_dir("/dev")
+            .ok()?
+            .flatten()
+            .find(|entry| entry.file_type().is_ok_and(|t| t.is_block_device()))
+            .map(|entry| entry.path().display().to_string())
+    }
+
+    #[test]
+    fn validate_pids_limit() {
+        for limit in [-1, 0, 1, 4096] {
+            let limits = ResourceLimits {
+                pids_limit: Some(limit),
+                ..ResourceLimits::default()
+            };
+            assert!(limits.validate().is_ok(), "{} should be accepted", limit);
+        }
+        let limits = ResourceLimits {
+            pids_limit: Some(-2),
+            ..ResourceLimits::default()
+        };
+        assert!(limits.validate().is_err());
+    }
+
+    #[test]
+    fn pids_limit_of_zero_or_less_is_unlimited() {
+        for (limit, value) in [(-1, "max"), (0, "max"), (100, "100")] {
+            let limits = ResourceLimits {
+                pids_limit: Some(limit),
+                ..ResourceLimits::default()
+            };
+            assert!(limits.files().contains(&("pids.max", value.to_string())));
+        }
+    }
+    #[test]
+    fn device_rate_parse() {
+        let Some(device) = block_device() else {
+            return;
+        };
+        let rate = DeviceRate::parse(&format!("{}:10mb", device)).unwrap();
+        assert_eq!(rate.path, device);
+        assert_eq!(rate.rate, 10 * 1024 * 1024);
+    }
+
+    #[test]
+    fn device_rate_parse_rejects_invalid_specs() {
+        assert!(DeviceRate::parse("10mb").is_err());
+        assert!(DeviceRate::parse("/dev/null:10mb").is_err());
+        assert!(DeviceRate::parse("/nonexistent/device:10mb").is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    fs,
+    os::{fd::AsRawFd, unix::fs::MetadataExt, unix::process::CommandExt},
+    path::Path,
+    process::Command,
+};
+
+use crate::actions::{
+    cgroup::{self, ResourceLimits},
+    container, events, restart,
+    run::{self, ProcessSpec},
+};
+
+/// Key the container's network namespace is dumped under; it is created by
+/// rustainer, not by the container, so CRIU leaves it out of the images.
+const NETNS_KEY: &str = "extnet";
+/// The fd the network namespace is handed to `criu restore` on.
+const NETNS_FD: i32 = 3;
+
+/// Where a container's checkpoint images are kept.
+fn checkpoint_dir(container_id: &str) -> String {
+    format!("{}/checkpoint", container::container_dir(container_id))
+}
+
+/// Dumps the state of a running container's processes into its directory
+/// with CRIU. The container is stopped by the dump unless `leave_running`.
+pub async fn checkpoint_container(
+    reference: &str,
+    leave_running: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = container::resolve_container(reference)?;
+    let _lock = container::lock(&container_id)?;
+    if !container::is_running(&container_id) {
+        return Err(format!("Container {} is not running", container_id).into());
+    }
+    let metadata = container::load_metadata(&container_id)
+        .ok_or_else(|| format!("Container {} has no metadata", container_id))?;
+    let process: ProcessSpec = serde_json::from_value(metadata["process"].clone())?;
+    // CRIU dumps the contents of tmpfs mounts along with the processes.
+    if !process.secrets.is_empty() {
+        return Err(format!(
+            "Container {} has secrets, which a checkpoint would write to disk",
+            container_id
+        )
+        .into());
+    }
+    let pid = container::main_pid(&container_id)
+        .ok_or_else(|| format!("Container {} has no process to checkpoint", container_id))?;
+
+    let dir = checkpoint_dir(&container_id);
+    if Path::new(&dir).exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+
+    // The dump kills the container, which must not count as a reason to
+    // restart it.
+    if !leave_running {
+        container::update_metadata(&container_id, |metadata| {
+            metadata["stop_requested"] = true.into();
+        })?;
+    }
+
+    println!("📸 Checkpointing container {}", container_id);
+    let mut cmd = match &process.runtime {
+        Some(runtime) => {
+            let mut cmd = Command::new(runtime);
+            cmd.args(["checkpoint", "--image-path", &dir]);
+            if leave_running {
+                cmd.arg("--leave-running");
+            }
+            cmd.arg(&container_id);
+            cmd
+        }
+        None => {
+            let mut cmd = Command::new("criu");
+            cmd.args(["dump", "--tree", &pid.to_string(), "--images-dir", &dir])
+                .args(["--log-file", "dump.log"])
+                .args(common_criu_args());
+            if leave_running {
+                cmd.arg("--leave-running");
+            }
+            for target in external_mounts(&process).keys() {
+                cmd.arg("--ext-mount-map")
+                    .arg(format!("{}:{}", target, target));
+            }
+            if process.network.has_namespace() {
+                let netns = fs::metadata(format!("/run/netns/{}", container_id))?;
+                cmd.arg("--external")
+                    .arg(format!("net[{}]:{}", netns.ino(), NETNS_KEY));
+            }
+            cmd
+        }
+    };
+
+    let output = cmd.output();
+    let succeeded = output.as_ref().is_ok_and(|output| output.status.success());
+    if !succeeded && !leave_running {
+        container::update_metadata(&container_id, |metadata| {
+            metadata["stop_requested"] = false.into();
+        })?;
+    }
+    let output = output.map_err(|e| format!("Failed to run {:?}: {}", cmd.get_program(), e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to checkpoint container {} (see {}/dump.log): {}",
+            container_id,
+            dir,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    container::update_metadata(&container_id, |metadata| {
+        metadata["checkpointed_at"] = container::now_secs().into();
+    })?;
+    events::emit_container("checkpoint", &container_id, serde_json::json!({}));
+
+    println!("✅ Container {} checkpointed to {}", container_id, dir);
+    Ok(())
+}
+
+/// Starts a stopped container again from its last checkpoint, under a
+/// supervisor like any detached container.
+pub async fn restore_container(reference: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = container::resolve_container(reference)?;
+    let _lock = container::lock(&container_id)?;
+    if container::is_running(&container_id) {
+        return Err(format!("Container {} is already running", container_id).into());
+    }
+    let dir = checkpoint_dir(&container_id);
+    if !Path::new(&dir).is_dir() {
+        return Err(format!("Container {} has no checkpoint", container_id).into());
+    }
+
+    let metadata = container::load_metadata(&container_id)
+        .ok_or_else(|| format!("Container {} has no metadata", container_id))?;
+    let process: ProcessSpec = serde_json::from_value(metadata["process"].clone())?;
+    // A container migrated from another host arrives without its name and
+    // network, which are set up again as it was created with.
+    if let Some(name) = metadata.get("name").and_then(|v| v.as_str()) {
+        if container::resolve_container(name).ok().as_deref() != Some(container_id.as_str()) {
+            container::register_name(name, &container_id)?;
+        }
+    }
+    if process.network.has_namespace()
+        && !Path::new(&format!("/run/netns/{}", container_id)).exists()
+    {
+        let ports: Vec<String> =
+            serde_json::from_value(metadata["ports"].clone()).unwrap_or_default();
+        run::setup_container_networking(
+            &container_id,
+            process.network,
+            metadata.get("ip").and_then(|v| v.as_str()),
+            metadata.get("mac_address").and_then(|v| v.as_str()),
+            &ports,
+        )?;
+    }
+    if process.cgroup.is_some() {
+        let limits: ResourceLimits =
+            serde_json::from_value(metadata["resources"].clone()).unwrap_or_default();
+        cgroup::create(&container_id, &limits)?;
+    }
+
+    // The supervisor restores instead of starting the command on its first
+    // launch; started_at is cleared so the restore can be waited for.
+    container::update_metadata(&container_id, |metadata| {
+        metadata["restore"] = true.into();
+        metadata["started_at"] = serde_json::Value::Null;
+    })?;
+
+    println!("📸 Restoring container {}", container_id);
+    let mut supervisor = restart::spawn_supervisor(&container_id)?;
+    restart::wait_for_start(&container_id, &mut supervisor)?;
+    events::emit_container("restore", &container_id, serde_json::json!({}));
+
+    println!("✅ Container {} restored", container_id);
+    Ok(())
+}
+
+/// The command restoring the container's processes. Like the command that
+/// starts it, it stays in the foreground until the container exits.
+pub(crate) fn restore_command(
+    container_id: &str,
+    process: &ProcessSpec,
+) -> Result<Command, Box<dyn std::error::Error>> {
+    let dir = checkpoint_dir(container_id);
+    let bundle = fs::canonicalize(container::container_dir(container_id))?;
+
+    if let Some(runtime) = &process.runtime {
+        let mut cmd = Command::new(runtime);
+        cmd.args(["restore", "--image-path", &dir, "--bundle"])
+            .arg(&bundle)
+            .arg(container_id);
+        return Ok(cmd);
+    }
+
+    let mut cmd = Command::new("criu");
+    cmd.args(["restore", "--images-dir", &dir])
+        .args(["--log-file", "restore.log"])
+        .args(common_criu_args())
+        .arg("--root")
+        .arg(fs::canonicalize(bundle.join("rootfs"))?);
+    for (target, source) in external_mounts(process) {
+        cmd.arg("--ext-mount-map")
+            .arg(format!("{}:{}", target, source));
+    }
+    if process.network.has_namespace() {
+        let netns = fs::File::open(format!("/run/netns/{}", container_id))?;
+        cmd.arg("--inherit-fd")
+            .arg(format!("fd[{}]:{}", NETNS_FD, NETNS_KEY));
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::dup2(netns.as_raw_fd(), NETNS_FD) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    Ok(cmd)
+}
+
+fn common_criu_args() -> [&'static str; 4] {
+    [
+        "--manage-cgroups",
+        "--tcp-established",
+        "--file-locks",
+        "--ext-unix-sk",
+    ]
+}
+
+/// Mounts whose source lies outside the container, as target to source.
+/// CRIU cannot dump those and has to be told where to find them again.
+fn external_mounts(process: &ProcessSpec) -> std::collections::BTreeMap<String, String> {
+    process
+        .mounts
+        .iter()
+        .map(|mount| (mount.target.clone(), mount.source.clone()))
+        .chain(
+            process
+                .devices
+                .iter()
+                .map(|device| (device.clone(), device.clone())),
+        )
+        .collect()
+}
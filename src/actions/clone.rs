@@ -0,0 +1,154 @@
+use crate::actions::{
+    cgroup, container, dns, events,
+    network::{self, NetworkMode},
+    run::{self, ProcessSpec},
+    storage,
+    store::{self, LockMode},
+    utils,
+    volume::{self, Volume},
+};
+use std::fs;
+
+/// Metadata only the original's runs have, which the clone starts without.
+const RUN_STATE_KEYS: [&str; 8] = [
+    "health",
+    "supervisor_pid",
+    "pid_start_time",
+    "restore",
+    "checkpointed_at",
+    "stop_requested",
+    "timed_out",
+    "oom_killed",
+];
+
+/// Copies a container's rootfs and configuration into a new container that
+/// has not been started, returning its ID. The rootfs is committed with the
+/// container's snapshotter, so btrfs and zfs snapshot it rather than copy.
+/// Anonymous volumes are copied too, named volumes are shared.
+pub async fn clone_container(
+    reference: &str,
+    name: Option<&str>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let source_id = container::resolve_container(reference)?;
+    let _source_lock = container::lock(&source_id)?;
+    let mut metadata = container::load_metadata(&source_id)
+        .ok_or_else(|| format!("Container {} has no metadata", source_id))?;
+    // The rootfs of a bundle is not the container's to copy.
+    if metadata.get("bundle").is_some_and(|v| !v.is_null()) {
+        return Err("Containers run from an OCI bundle cannot be cloned".into());
+    }
+    if let Some(name) = name {
+        utils::validate_object_name("container", name)?;
+        container::check_name_available(name)?;
+    }
+    let mut process: ProcessSpec = serde_json::from_value(metadata["process"].clone())?;
+
+    let container_id = container::generate_container_id()?;
+    let _lock = store::lock_container(&container_id)?;
+    println!(
+        "📦 Cloning container {} to {}",
+        container::short_id(&source_id),
+        container::short_id(&container_id)
+    );
+    let source_path = container::container_dir(&source_id);
+    let container_path = container::container_dir(&container_id);
+
+    let cloned = {
+        // Until the clone's layers are recorded, pruning could remove them.
+        let _store_lock = store::lock_store(LockMode::Shared)?;
+        storage::clone_rootfs(&source_path, &container_path)
+    };
+    if let Err(e) = cloned {
+        storage::remove_rootfs(&container_id);
+        let _ = fs::remove_dir_all(&container_path);
+        return Err(e);
+    }
+
+    // What refers to the original is made to refer to the clone.
+    let source_short_id = container::short_id(&source_id).to_string();
+    if process.hostname == source_short_id {
+        process.hostname = container::short_id(&container_id).to_string();
+        run::write_hostname_file(&format!("{}/rootfs", container_path), &process.hostname)?;
+    }
+    let mut volumes: Vec<Volume> =
+        serde_json::from_value(metadata["volumes"].clone()).unwrap_or_default();
+    for volume in volumes.iter_mut().filter(|volume| volume.anonymous) {
+        let copy = volume::duplicate_volume(&volume.source)?;
+        // Mount sources are canonical paths.
+        let from = fs::canonicalize(volume::data_dir(&volume.source))?;
+        let to = fs::canonicalize(volume::data_dir(&copy))?;
+        for mount in &mut process.mounts {
+            if mount.source == from.display().to_string() {
+                mount.source = to.display().to_string();
+            }
+        }
+        volume.source = copy;
+    }
+    for mount in &mut process.mounts {
+        if let Some(rest) = mount.source.strip_prefix(&source_path) {
+            mount.source = format!("{}{}", container_path, rest);
+        }
+    }
+    if process.cgroup.is_some() {
+        let parent = metadata.get("cgroup_parent").and_then(|v| v.as_str());
+        process.cgroup = Some(cgroup::path_under(parent, &container_id));
+    }
+    let (network_lock, ip) = match process.network {
+        NetworkMode::Bridge => {
+            let lock = store::lock_network()?;
+            (Some(lock), Some(network::allocate_ip(None)?.to_string()))
+        }
+        NetworkMode::Host | NetworkMode::None => (None, None),
+    };
+
+    let extra_hosts: Vec<String> =
+        serde_json::from_value(metadata["extra_hosts"].clone()).unwrap_or_default();
+    dns::write_hosts_file(
+        &format!("{}/hosts", container_path),
+        &process.hostname,
+        ip.as_deref(),
+        &dns::parse_extra_hosts(&extra_hosts)?,
+    )?;
+    fs::copy(
+        format!("{}/resolv.conf", source_path),
+        format!("{}/resolv.conf", container_path),
+    )?;
+
+    if let Some(object) = metadata.as_object_mut() {
+        for key in RUN_STATE_KEYS {
+            object.remove(key);
+        }
+    }
+    metadata["name"] = name.into();
+    metadata["hostname"] = process.hostname.clone().into();
+    metadata["volumes"] = serde_json::to_value(&volumes)?;
+    metadata["process"] = serde_json::to_value(&process)?;
+    metadata["ip"] = ip.into();
+    // Two containers on the bridge cannot share an address.
+    metadata["mac_address"] = serde_json::Value::Null;
+    metadata["restart_count"] = 0.into();
+    metadata["created"] = container::now_secs().into();
+    metadata["cloned_from"] = source_id.clone().into();
+    metadata["state"] = "created".into();
+    metadata["pid"] = serde_json::Value::Null;
+    metadata["started_at"] = serde_json::Value::Null;
+    metadata["finished_at"] = serde_json::Value::Null;
+    metadata["exit_code"] = serde_json::Value::Null;
+    container::save_metadata(&container_id, &metadata)?;
+    drop(network_lock);
+    if let Some(name) = name {
+        container::register_name(name, &container_id)?;
+    }
+
+    events::emit_container(
+        "create",
+        &container_id,
+        serde_json::json!({ "cloned_from": source_id }),
+    );
+    println!(
+        "✅ Container {} cloned to {}",
+        container::short_id(&source_id),
+        container::short_id(&container_id)
+    );
+    Ok(container_id)
+}
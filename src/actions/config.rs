@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, io::Read, path::Path};
+
+use crate::actions::{container, events, store, utils};
+
+/// Where config objects are kept, each in a directory of its own.
+const CONFIGS_DIR: &str = "configs";
+
+/// A `--config NAME[:TARGET]` option, the config being bound read-only at
+/// `target`, `/NAME` by default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigMount {
+    pub name: String,
+    pub target: String,
+}
+
+impl ConfigMount {
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (name, target) = match spec.split_once(':') {
+            Some((name, target)) => (name, target.to_string()),
+            None => (spec, format!("/{}", spec)),
+        };
+        utils::validate_object_name("config", name)?;
+        if !target.starts_with('/') || target.ends_with('/') {
+            return Err(format!(
+                "Invalid config {}: the target must be an absolute path to a file",
+                spec
+            )
+            .into());
+        }
+
+        Ok(ConfigMount {
+            name: name.to_string(),
+            target,
+        })
+    }
+}
+
+/// What is recorded about a config next to its data.
+#[derive(Debug, Serialize, Deserialize)]
+struct ConfigMetadata {
+    name: String,
+    created_at: u64,
+}
+
+fn config_dir(name: &str) -> String {
+    format!("{}/{}", store::path(CONFIGS_DIR), name)
+}
+
+/// The file holding a config's contents, which is what gets mounted.
+pub fn data_path(name: &str) -> String {
+    format!("{}/data", config_dir(name))
+}
+
+fn exists(name: &str) -> bool {
+    Path::new(&format!("{}/metadata.json", config_dir(name))).exists()
+}
+
+/// Fails unless the config exists, so `run` can refuse before creating
+/// anything.
+pub fn check_exists(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !exists(name) {
+        return Err(format!("No such config: {}", name).into());
+    }
+    Ok(())
+}
+
+fn load_metadata(name: &str) -> Result<ConfigMetadata, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(format!("{}/metadata.json", config_dir(name)))
+        .map_err(|_| format!("No such config: {}", name))?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Stores the contents of `source`, a file or `-` for stdin, as a config.
+/// Configs cannot be changed once created, containers may be using them.
+pub fn create_config(name: &str, source: &str) -> Result<(), Box<dyn std::error::Error>> {
+    utils::validate_object_name("config", name)?;
+    if exists(name) {
+        return Err(format!("Config {} already exists", name).into());
+    }
+    let data = if source == "-" {
+        let mut data = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut data)?;
+        data
+    } else {
+        fs::read(source).map_err(|e| format!("Failed to read {}: {}", source, e))?
+    };
+
+    fs::create_dir_all(config_dir(name))?;
+    fs::write(data_path(name), data)?;
+    let metadata = ConfigMetadata {
+        name: name.to_string(),
+        created_at: container::now_secs(),
+    };
+    // Written last, a config only exists once its data does.
+    utils::write_atomic(
+        &format!("{}/metadata.json", config_dir(name)),
+        &serde_json::to_string_pretty(&metadata)?,
+    )?;
+    events::emit("config", "create", name, serde_json::json!({}));
+
+    Ok(())
+}
+
+/// Names of every config.
+fn list_names() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if !Path::new(&store::path(CONFIGS_DIR)).exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut names: Vec<String> = fs::read_dir(store::path(CONFIGS_DIR))?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str().map(String::from))
+        .filter(|name| exists(name))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// IDs of the containers the config is mounted into, running or not.
+fn users(name: &str) -> Vec<String> {
+    container::containers_using(|metadata| {
+        metadata
+            .get("configs")
+            .and_then(|configs| serde_json::from_value::<Vec<ConfigMount>>(configs.clone()).ok())
+            .is_some_and(|configs| configs.iter().any(|config| config.name == name))
+    })
+    .unwrap_or_default()
+}
+
+pub fn list_configs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rows = Vec::new();
+    for name in list_names()? {
+        let metadata = load_metadata(&name)?;
+        let size = fs::metadata(data_path(&name)).map(|m| m.len()).unwrap_or(0);
+        rows.push(vec![
+            name,
+            utils::format_size(size),
+            utils::format_timestamp(metadata.created_at),
+        ]);
+    }
+
+    utils::print_table(&["NAME", "SIZE", "CREATED"], &rows);
+    Ok(())
+}
+
+/// Removes configs, which no container may be using anymore.
+pub fn remove_configs(names: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    for name in names {
+        check_exists(name)?;
+        let users = users(name);
+        if !users.is_empty() {
+            return Err(format!(
+                "Config {} is in use by container(s) {}",
+                name,
+                users
+                    .iter()
+                    .map(|id| container::short_id(id))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+            .into());
+        }
+
+        fs::remove_dir_all(config_dir(name))?;
+        events::emit("config", "destroy", name, serde_json::json!({}));
+        println!("{}", name);
+    }
+
+    Ok(())
+}
+
+/// Prints configs with their contents and who uses them as JSON, in an
+/// array like `docker config inspect`.
+pub fn inspect_configs(names: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut documents = Vec::new();
+    for name in names {
+        let metadata = load_metadata(name)?;
+        let data = fs::read(data_path(name))?;
+        documents.push(serde_json::json!({
+            "Name": metadata.name,
+            "CreatedAt": utils::format_timestamp(metadata.created_at),
+            "Data": String::from_utf8_lossy(&data),
+            "UsedBy": users(name),
+        }));
+    }
+
+    println!("{}", serde_json::to_string_pretty(&documents)?);
+    Ok(())
+}
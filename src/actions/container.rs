@@ -0,0 +1,437 @@
+use crate::actions::{cgroup, namespaces, network::NetworkMode, store, utils};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::{self, File},
+    io::Read,
+    os::unix::fs::MetadataExt,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CONTAINERS_DIR: &str = "containers";
+const NAME_INDEX_FILE: &str = "names.json";
+
+pub fn container_dir(container_id: &str) -> String {
+    format!("{}/{}", store::path(CONTAINERS_DIR), container_id)
+}
+
+pub fn list_container_ids() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if fs::metadata(store::path(CONTAINERS_DIR)).is_err() {
+        return Ok(Vec::new());
+    }
+
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(store::path(CONTAINERS_DIR))?.flatten() {
+        if entry.path().is_dir() {
+            ids.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    ids.sort();
+
+    Ok(ids)
+}
+
+/// IDs of the containers, running or not, whose metadata `uses` accepts.
+pub fn containers_using(
+    uses: impl Fn(&serde_json::Value) -> bool,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    Ok(list_container_ids()?
+        .into_iter()
+        .filter(|container_id| load_metadata(container_id).is_some_and(|metadata| uses(&metadata)))
+        .collect())
+}
+
+/// Generates a random 64 character hex container ID.
+pub fn generate_container_id() -> Result<String, Box<dyn std::error::Error>> {
+    let mut bytes = [0u8; 32];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// The 12 character form shown in listings. IDs from before random hex IDs
+/// (`rustainer_<timestamp>`) are shown in full.
+pub fn short_id(container_id: &str) -> &str {
+    if is_hex_id(container_id) {
+        &container_id[..12]
+    } else {
+        container_id
+    }
+}
+
+/// Whether a name follows one of the container ID formats, which is also how
+/// network namespaces created by rustainer are recognized.
+pub fn looks_like_container_id(name: &str) -> bool {
+    is_hex_id(name) || name.starts_with("rustainer_")
+}
+
+fn is_hex_id(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Maps a container ID, name or unambiguous ID prefix to the container ID.
+pub fn resolve_container(reference: &str) -> Result<String, Box<dyn std::error::Error>> {
+    if reference.is_empty() {
+        return Err("Container reference cannot be empty".into());
+    }
+
+    if fs::metadata(container_dir(reference)).is_ok() {
+        return Ok(reference.to_string());
+    }
+
+    if let Some(container_id) = load_name_index().get(reference) {
+        if fs::metadata(container_dir(container_id)).is_ok() {
+            return Ok(container_id.clone());
+        }
+    }
+
+    let matches: Vec<String> = list_container_ids()?
+        .into_iter()
+        .filter(|id| id.starts_with(reference))
+        .collect();
+
+    match matches.len() {
+        0 => Err(format!("No such container: {}", reference).into()),
+        1 => Ok(matches.into_iter().next().unwrap()),
+        _ => Err(format!(
+            "Container prefix {} is ambiguous, it matches: {}",
+            reference,
+            matches
+                .iter()
+                .map(|id| short_id(id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into()),
+    }
+}
+
+/// Locks a container for a change other invocations must not interleave
+/// with, failing if it was removed while waiting for the lock.
+pub fn lock(container_id: &str) -> Result<store::Lock, Box<dyn std::error::Error>> {
+    let lock = store::lock_container(container_id)?;
+    if fs::metadata(container_dir(container_id)).is_err() {
+        return Err(format!("No such container: {}", container_id).into());
+    }
+    Ok(lock)
+}
+
+/// Fails when the name already belongs to a container that still exists.
+pub fn check_name_available(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(container_id) = load_name_index().get(name) {
+        if fs::metadata(container_dir(container_id)).is_ok() {
+            return Err(format!(
+                "The container name \"{}\" is already in use by container {}",
+                name, container_id
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn register_name(name: &str, container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let _lock = store::lock_names()?;
+    check_name_available(name)?;
+
+    let mut index = load_name_index();
+    index.insert(name.to_string(), container_id.to_string());
+    save_name_index(&index)
+}
+
+pub fn unregister_names(container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let _lock = store::lock_names()?;
+    let mut index = load_name_index();
+    let before = index.len();
+    index.retain(|_, id| id != container_id);
+
+    if index.len() != before {
+        save_name_index(&index)?;
+    }
+
+    Ok(())
+}
+
+/// Finds names in the index whose container is gone, and containers whose
+/// name is missing from it. With `repair` the index is fixed to match.
+pub fn check_name_index(repair: bool) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let _lock = store::lock_names()?;
+    let mut index = load_name_index();
+    let mut problems = Vec::new();
+
+    index.retain(|name, container_id| {
+        let exists = fs::metadata(container_dir(container_id)).is_ok();
+        if !exists {
+            problems.push(format!(
+                "Name {} belongs to container {}, which no longer exists",
+                name, container_id
+            ));
+        }
+        exists
+    });
+    for container_id in list_container_ids()? {
+        let Some(name) = load_metadata(&container_id)
+            .and_then(|metadata| metadata.get("name")?.as_str().map(String::from))
+        else {
+            continue;
+        };
+        if let Entry::Vacant(entry) = index.entry(name) {
+            problems.push(format!(
+                "Container {} is missing from the name index as {}",
+                container_id,
+                entry.key()
+            ));
+            entry.insert(container_id);
+        }
+    }
+
+    if repair && !problems.is_empty() {
+        save_name_index(&index)?;
+    }
+    Ok(problems)
+}
+
+fn load_name_index() -> HashMap<String, String> {
+    fs::read_to_string(format!(
+        "{}/{}",
+        store::path(CONTAINERS_DIR),
+        NAME_INDEX_FILE
+    ))
+    .ok()
+    .and_then(|content| serde_json::from_str(&content).ok())
+    .unwrap_or_default()
+}
+
+fn save_name_index(index: &HashMap<String, String>) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(store::path(CONTAINERS_DIR))?;
+    utils::write_atomic(
+        &format!("{}/{}", store::path(CONTAINERS_DIR), NAME_INDEX_FILE),
+        &serde_json::to_string_pretty(index)?,
+    )
+}
+
+pub fn load_metadata(container_id: &str) -> Option<serde_json::Value> {
+    let metadata_path = format!("{}/metadata.json", container_dir(container_id));
+    let content = fs::read_to_string(metadata_path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+pub fn save_metadata(
+    container_id: &str,
+    metadata: &serde_json::Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let metadata_path = format!("{}/metadata.json", container_dir(container_id));
+    utils::write_atomic(&metadata_path, &serde_json::to_string_pretty(metadata)?)
+}
+
+/// Changes the container's metadata under its metadata lock, so updates made
+/// meanwhile by the supervisor or another command are not lost. Does nothing
+/// when the container has no metadata.
+pub fn update_metadata(
+    container_id: &str,
+    update: impl FnOnce(&mut serde_json::Value),
+) -> Result<(), Box<dyn std::error::Error>> {
+    let _lock = store::lock_metadata(container_id)?;
+    let Some(mut metadata) = load_metadata(container_id) else {
+        return Ok(());
+    };
+    update(&mut metadata);
+    save_metadata(container_id, &metadata)
+}
+
+/// Records the process started for the container, with its start time so a
+/// recycled PID is never mistaken for it, and clears the previous exit.
+pub fn record_start(container_id: &str, pid: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let pid_start_time = process_start_time(pid);
+    update_metadata(container_id, |metadata| {
+        metadata["state"] = "running".into();
+        metadata["pid"] = pid.into();
+        metadata["pid_start_time"] = pid_start_time.into();
+        metadata["started_at"] = now_secs().into();
+        metadata["exit_code"] = serde_json::Value::Null;
+        metadata["finished_at"] = serde_json::Value::Null;
+        metadata["oom_killed"] = false.into();
+        metadata["stop_requested"] = false.into();
+        metadata["timed_out"] = false.into();
+    })
+}
+
+/// Records how the container's process ended, and whether the OOM killer
+/// killed anything in it while it ran.
+pub fn record_exit(
+    container_id: &str,
+    exit_code: i32,
+    oom_killed: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    update_metadata(container_id, |metadata| {
+        metadata["state"] = "exited".into();
+        metadata["pid"] = serde_json::Value::Null;
+        metadata["exit_code"] = exit_code.into();
+        metadata["oom_killed"] = oom_killed.into();
+        metadata["finished_at"] = now_secs().into();
+    })
+}
+
+/// The exit code of the container's last run, if it has exited.
+pub fn exit_code(container_id: &str) -> Option<i32> {
+    load_metadata(container_id)?
+        .get("exit_code")?
+        .as_i64()
+        .map(|code| code as i32)
+}
+
+/// Whether the OOM killer killed a process in the container's last run.
+pub fn oom_killed(container_id: &str) -> bool {
+    load_metadata(container_id)
+        .and_then(|metadata| metadata.get("oom_killed")?.as_bool())
+        .unwrap_or(false)
+}
+
+/// Whether the container was stopped for running longer than `--timeout`.
+pub fn timed_out(container_id: &str) -> bool {
+    load_metadata(container_id)
+        .and_then(|metadata| metadata.get("timed_out")?.as_bool())
+        .unwrap_or(false)
+}
+
+/// Creation time in seconds since the epoch, falling back to the timestamp
+/// embedded in the container ID when no metadata was written.
+pub fn created_at(container_id: &str) -> u64 {
+    load_metadata(container_id)
+        .and_then(|metadata| metadata.get("created").and_then(|v| v.as_u64()))
+        .unwrap_or_else(|| {
+            container_id
+                .strip_prefix("rustainer_")
+                .and_then(|ts| ts.parse::<u64>().ok())
+                .unwrap_or(0)
+        })
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+pub fn list_network_namespaces() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let output = Command::new("ip").args(["netns", "list"]).output()?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(String::from)
+        .collect())
+}
+
+/// A container is running while the process recorded at its start is alive.
+/// Containers from before PIDs were recorded are running while their network
+/// namespace still holds processes.
+pub fn is_running(container_id: &str) -> bool {
+    let Some(metadata) = load_metadata(container_id).filter(|m| m.get("pid").is_some()) else {
+        return !container_pids(container_id).is_empty();
+    };
+
+    metadata["pid"].as_u64().is_some_and(|pid| {
+        process_start_time(pid as u32)
+            .is_some_and(|started| Some(started) == metadata["pid_start_time"].as_u64())
+    })
+}
+
+/// `created` until the container's process first starts, then `running` or
+/// `exited`. A container whose process died unrecorded, with its supervisor
+/// killed for instance, is reported as exited.
+pub fn state(container_id: &str) -> &'static str {
+    if is_running(container_id) {
+        return "running";
+    }
+
+    let recorded = load_metadata(container_id)
+        .and_then(|metadata| metadata.get("state")?.as_str().map(String::from));
+    match recorded.as_deref() {
+        Some("created") => "created",
+        _ => "exited",
+    }
+}
+
+/// When the process started, in clock ticks after boot (`/proc/<pid>/stat`
+/// field 22), or `None` once it is gone.
+fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces, the fields after it do not.
+    stat.rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(19)?
+        .parse()
+        .ok()
+}
+
+/// The network mode recorded for the container, `bridge` for containers
+/// created before modes existed.
+pub fn network_mode(container_id: &str) -> NetworkMode {
+    load_metadata(container_id)
+        .and_then(|m| m.get("network").and_then(|v| v.as_str()).map(String::from))
+        .and_then(|mode| mode.parse().ok())
+        .unwrap_or_default()
+}
+
+/// PIDs of every process in the container, from its cgroup when it has one.
+/// Otherwise they are the processes in its network namespace, or for
+/// containers on the host network the ones with its rootfs as root directory.
+pub fn container_pids(container_id: &str) -> Vec<u32> {
+    if Path::new(&cgroup::cgroup_path(container_id)).exists() {
+        return cgroup::pids(container_id);
+    }
+
+    if !network_mode(container_id).has_namespace() {
+        let Some(rootfs) = rootfs_id(container_id) else {
+            return Vec::new();
+        };
+        let Ok(entries) = fs::read_dir("/proc") else {
+            return Vec::new();
+        };
+
+        return entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+            .filter(|pid| has_root(*pid, rootfs))
+            .collect();
+    }
+
+    let Some(netns) = namespaces::inode(&format!("/run/netns/{}", container_id)) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| namespaces::inode(&format!("/proc/{}/ns/net", pid)) == Some(netns))
+        .collect()
+}
+
+/// Host PID of the container's init process: the lowest PID in the network
+/// namespace whose root directory is the container rootfs.
+pub fn main_pid(container_id: &str) -> Option<u32> {
+    let rootfs = rootfs_id(container_id)?;
+
+    container_pids(container_id)
+        .into_iter()
+        .filter(|pid| has_root(*pid, rootfs))
+        .min()
+}
+
+/// Device and inode of the container's rootfs. Containers pivot into it, so
+/// their root reads as `/` through /proc and only compares by identity.
+fn rootfs_id(container_id: &str) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(format!("{}/rootfs", container_dir(container_id))).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+fn has_root(pid: u32, rootfs: (u64, u64)) -> bool {
+    fs::metadata(format!("/proc/{}/root", pid)).is_ok_and(|root| (root.dev(), root.ino()) == rootfs)
+}
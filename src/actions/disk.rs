@@ -0,0 +1,171 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use crate::actions::{
+    pull, storage,
+    store::{self, LockMode},
+    types::ImageManifest,
+    utils, wasm,
+};
+
+/// Room ext4 needs beyond the files, for its journal and inode tables.
+const EXT4_OVERHEAD: u64 = 32 * 1024 * 1024;
+
+/// The filesystem an image is exported as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskFormat {
+    /// Writable, for microVMs such as Firecracker.
+    Ext4,
+    /// Compressed and read-only, for embedded devices.
+    Squashfs,
+}
+
+impl FromStr for DiskFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "ext4" => Ok(DiskFormat::Ext4),
+            "squashfs" => Ok(DiskFormat::Squashfs),
+            _ => Err(format!(
+                "Invalid disk format: {}. Expected ext4 or squashfs",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DiskFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskFormat::Ext4 => write!(f, "ext4"),
+            DiskFormat::Squashfs => write!(f, "squashfs"),
+        }
+    }
+}
+
+/// Flattens an image's layers into a filesystem image at `output`. ext4
+/// images are sized to fit unless `size` is given.
+pub fn export_disk(
+    reference: &str,
+    format: DiskFormat,
+    output: &str,
+    size: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if format == DiskFormat::Squashfs && size.is_some() {
+        return Err(
+            "--size only applies to ext4, squashfs images are as large as their files".into(),
+        );
+    }
+    let (repository, tag) = pull::parse_image_tag(reference);
+    let image = pull::format_image_reference(&repository, &tag);
+
+    let staging = PathBuf::from(store::path(&format!(
+        "{}/disk-{}",
+        store::TMP_DIR,
+        std::process::id()
+    )));
+    let _ = fs::remove_dir_all(&staging);
+    let rootfs = staging.join("rootfs");
+    let result = flatten_image(&repository, &tag, &image, &rootfs)
+        .and_then(|_| make_filesystem(format, &rootfs, output, size));
+    let _ = fs::remove_dir_all(&staging);
+    result?;
+
+    println!("✅ Exported image {} to {} ({})", image, output, format);
+    Ok(())
+}
+
+fn flatten_image(
+    repository: &str,
+    tag: &str,
+    image: &str,
+    rootfs: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Until the layers are copied, pruning could remove them and pulling
+    // could replace the image.
+    let _store_lock = store::lock_store(LockMode::Shared)?;
+    let _image_lock = store::lock_image(repository, tag, LockMode::Shared)?;
+    let image_dir = store::image_dir(repository, tag);
+    let manifest: ImageManifest = fs::read_to_string(format!("{}/manifest.json", image_dir))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .ok_or_else(|| {
+            format!(
+                "Image {} not found locally. You need to pull it first.",
+                image
+            )
+        })?;
+    if wasm::is_wasm_image(&manifest, pull::image_platform(&image_dir).as_ref()) {
+        return Err(format!("Image {} is a Wasm module, it has no filesystem", image).into());
+    }
+    let layers: Vec<_> = manifest.layers.iter().collect();
+
+    let layer_dirs = storage::prepare_layers(&image_dir, &layers)?;
+    println!("📦 Flattening {} layer(s) of {}", layer_dirs.len(), image);
+    storage::flatten_layers(&layer_dirs, rootfs)?;
+    pull::mark_image_used(&image_dir);
+    Ok(())
+}
+
+fn make_filesystem(
+    format: DiskFormat,
+    rootfs: &Path,
+    output: &str,
+    size: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = match format {
+        DiskFormat::Ext4 => {
+            let used = utils::dir_size(rootfs);
+            let size = size.unwrap_or(used + used / 4 + EXT4_OVERHEAD);
+            if size < used {
+                return Err(format!(
+                    "The image needs {}, more than the {} asked for",
+                    utils::format_size(used),
+                    utils::format_size(size)
+                )
+                .into());
+            }
+            fs::File::create(output)
+                .and_then(|file| file.set_len(size))
+                .map_err(|e| format!("Failed to create {}: {}", output, e))?;
+
+            println!("💾 Making a {} ext4 filesystem", utils::format_size(size));
+            let mut cmd = Command::new("mkfs.ext4");
+            cmd.args(["-q", "-F", "-d"]).arg(rootfs).arg(output);
+            cmd
+        }
+        DiskFormat::Squashfs => {
+            println!("💾 Making a squashfs filesystem");
+            let mut cmd = Command::new("mksquashfs");
+            cmd.arg(rootfs)
+                .arg(output)
+                .args(["-noappend", "-quiet", "-no-progress"]);
+            cmd
+        }
+    };
+
+    let result = cmd
+        .output()
+        .map_err(|e| format!("Failed to run {:?}: {}", cmd.get_program(), e))
+        .and_then(|result| {
+            if result.status.success() {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{:?} failed: {}",
+                    cmd.get_program(),
+                    String::from_utf8_lossy(&result.stderr).trim()
+                ))
+            }
+        });
+    // A half-made filesystem is of no use.
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    Ok(result?)
+}
@@ -0,0 +1,188 @@
+use crate::actions::network::BRIDGE_GATEWAY;
+use std::{fs, net::IpAddr};
+
+/// `--add-host` alias that resolves to the bridge address on the host.
+const HOST_GATEWAY: &str = "host-gateway";
+
+/// Parses `--add-host host:ip` entries into `(host, ip)` pairs, resolving the
+/// `host-gateway` alias.
+pub fn parse_extra_hosts(
+    entries: &[String],
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    entries
+        .iter()
+        .map(|entry| {
+            let (host, ip) = entry
+                .split_once(':')
+                .filter(|(host, ip)| !host.is_empty() && !ip.is_empty())
+                .ok_or_else(|| format!("Invalid add-host: {}. Expected host:ip", entry))?;
+
+            let ip = if ip == HOST_GATEWAY {
+                BRIDGE_GATEWAY.to_string()
+            } else {
+                ip.parse::<IpAddr>()
+                    .map_err(|_| format!("Invalid IP address in add-host: {}", ip))?
+                    .to_string()
+            };
+
+            Ok((host.to_string(), ip))
+        })
+        .collect()
+}
+
+/// Writes the container's /etc/hosts to `path`.
+pub fn write_hosts_file(
+    path: &str,
+    hostname: &str,
+    container_ip: Option<&str>,
+    extra_hosts: &[(String, String)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut content = String::from(
+        "127.0.0.1\tlocalhost\n\
+         ::1\tlocalhost ip6-localhost ip6-loopback\n\
+         fe00::0\tip6-localnet\n\
+         ff00::0\tip6-mcastprefix\n\
+         ff02::1\tip6-allnodes\n\
+         ff02::2\tip6-allrouters\n",
+    );
+    for (host, ip) in extra_hosts {
+        content.push_str(&format!("{}\t{}\n", ip, host));
+    }
+    if let Some(container_ip) = container_ip {
+        content.push_str(&format!("{}\t{}\n", container_ip, hostname));
+    }
+
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Used when the host only lists loopback resolvers, which are unreachable
+/// from the container's network namespace.
+const FALLBACK_NAMESERVERS: [&str; 2] = ["8.8.8.8", "8.8.4.4"];
+const HOST_RESOLV_CONF: &str = "/etc/resolv.conf";
+/// Upstream servers behind systemd-resolved's 127.0.0.53 stub.
+const SYSTEMD_RESOLV_CONF: &str = "/run/systemd/resolve/resolv.conf";
+
+/// DNS settings given on the `run` command line. Each empty list falls back
+/// to the host's configuration.
+#[derive(Debug, Default)]
+pub struct DnsConfig {
+    pub nameservers: Vec<String>,
+    pub search: Vec<String>,
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct ResolvConf {
+    nameservers: Vec<String>,
+    search: Vec<String>,
+    options: Vec<String>,
+}
+
+impl ResolvConf {
+    fn parse(content: &str) -> Self {
+        let mut conf = ResolvConf::default();
+
+        for line in content.lines() {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("nameserver") => conf.nameservers.extend(fields.next().map(String::from)),
+                // The last search or domain line wins.
+                Some("search") | Some("domain") => conf.search = fields.map(String::from).collect(),
+                Some("options") => conf.options.extend(fields.map(String::from)),
+                _ => {}
+            }
+        }
+
+        conf
+    }
+}
+
+pub fn validate_dns(config: &DnsConfig) -> Result<(), Box<dyn std::error::Error>> {
+    for nameserver in &config.nameservers {
+        if nameserver.parse::<IpAddr>().is_err() {
+            return Err(format!("Invalid DNS server address: {}", nameserver).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the container's /etc/resolv.conf to `path`. Loopback resolvers are
+/// only kept for containers on the host network, which can reach them.
+pub fn write_resolv_conf(
+    path: &str,
+    config: &DnsConfig,
+    host_network: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let host = if host_network {
+        ResolvConf::parse(&fs::read_to_string(HOST_RESOLV_CONF).unwrap_or_default())
+    } else {
+        host_resolv_conf()
+    };
+
+    let nameservers = if config.nameservers.is_empty() && host_network {
+        host.nameservers
+    } else if config.nameservers.is_empty() {
+        let reachable: Vec<String> = host
+            .nameservers
+            .into_iter()
+            .filter(|ns| ns.parse::<IpAddr>().is_ok_and(|ip| !ip.is_loopback()))
+            .collect();
+        if reachable.is_empty() {
+            FALLBACK_NAMESERVERS
+                .iter()
+                .map(|ns| ns.to_string())
+                .collect()
+        } else {
+            reachable
+        }
+    } else {
+        config.nameservers.clone()
+    };
+    let search = if config.search.is_empty() {
+        host.search
+    } else {
+        config.search.clone()
+    };
+    let options = if config.options.is_empty() {
+        host.options
+    } else {
+        config.options.clone()
+    };
+
+    let mut content = String::new();
+    for nameserver in &nameservers {
+        content.push_str(&format!("nameserver {}\n", nameserver));
+    }
+    // `--dns-search .` clears the search list.
+    if !search.is_empty() && search != ["."] {
+        content.push_str(&format!("search {}\n", search.join(" ")));
+    }
+    if !options.is_empty() {
+        content.push_str(&format!("options {}\n", options.join(" ")));
+    }
+
+    fs::write(path, content)?;
+    Ok(())
+}
+
+fn host_resolv_conf() -> ResolvConf {
+    let conf = ResolvConf::parse(&fs::read_to_string(HOST_RESOLV_CONF).unwrap_or_default());
+
+    let only_loopback = conf
+        .nameservers
+        .iter()
+        .all(|ns| ns.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback()));
+    if only_loopback {
+        if let Ok(content) = fs::read_to_string(SYSTEMD_RESOLV_CONF) {
+            let upstream = ResolvConf::parse(&content);
+            return ResolvConf {
+                nameservers: upstream.nameservers,
+                ..conf
+            };
+        }
+    }
+
+    conf
+}
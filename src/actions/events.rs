@@ -0,0 +1,233 @@
+use crate::actions::{container, redact, store, utils};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    time::Duration,
+};
+
+const EVENTS_LOG: &str = "events.log";
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Event {
+    pub time: u64,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub action: String,
+    pub id: String,
+    #[serde(default)]
+    pub attributes: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Appends an event to the log. Failing to record an event never fails the
+/// operation that produced it.
+pub fn emit(event_type: &str, action: &str, id: &str, attributes: serde_json::Value) {
+    let event = Event {
+        time: container::now_secs(),
+        event_type: event_type.to_string(),
+        action: action.to_string(),
+        id: id.to_string(),
+        attributes: match attributes {
+            serde_json::Value::Object(map) => map,
+            _ => serde_json::Map::new(),
+        },
+    };
+
+    let result = serde_json::to_string(&event)
+        .map_err(|e| e.to_string())
+        .and_then(|line| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(store::path(EVENTS_LOG))
+                .and_then(|mut file| writeln!(file, "{}", line))
+                .map_err(|e| e.to_string())
+        });
+
+    if let Err(e) = result {
+        eprintln!("⚠️ Warning: Failed to record {} event: {}", action, e);
+    }
+}
+
+/// Emits a container event carrying the container's image and name alongside
+/// any extra attributes, in which its masked variables' values are masked.
+pub fn emit_container(action: &str, container_id: &str, mut extra: serde_json::Value) {
+    let mut attributes = serde_json::Map::new();
+
+    if let Some(metadata) = container::load_metadata(container_id) {
+        for key in ["image", "name"] {
+            if let Some(value) = metadata.get(key).filter(|v| !v.is_null()) {
+                attributes.insert(key.to_string(), value.clone());
+            }
+        }
+        redact::redact_value(&mut extra, &redact::masked_values(&metadata));
+    }
+    if let serde_json::Value::Object(extra) = extra {
+        attributes.extend(extra);
+    }
+
+    emit(
+        "container",
+        action,
+        container_id,
+        serde_json::Value::Object(attributes),
+    );
+}
+
+#[derive(Debug, Default)]
+pub struct EventFilter {
+    types: Vec<String>,
+    actions: Vec<String>,
+    containers: Vec<String>,
+}
+
+impl EventFilter {
+    /// Parses `--filter key=value` options, ORing values of the same key and
+    /// ANDing different keys.
+    pub fn parse(filters: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut filter = EventFilter::default();
+
+        for raw in filters {
+            let (key, value) = raw
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid filter: {}. Expected key=value", raw))?;
+
+            match key {
+                "type" => {
+                    if !matches!(
+                        value,
+                        "container" | "image" | "network" | "volume" | "secret" | "config"
+                    ) {
+                        return Err(format!(
+                            "Invalid type filter: {}. Expected container, image, network, volume, secret or config",
+                            value
+                        )
+                        .into());
+                    }
+                    filter.types.push(value.to_string());
+                }
+                "event" => filter.actions.push(value.to_string()),
+                "container" => {
+                    // Containers may be gone already, so fall back to the raw value.
+                    let container_id =
+                        container::resolve_container(value).unwrap_or_else(|_| value.to_string());
+                    filter.containers.push(container_id);
+                }
+                _ => {
+                    return Err(format!(
+                        "Unsupported filter: {}. Supported filters are type, event and container",
+                        key
+                    )
+                    .into())
+                }
+            }
+        }
+
+        Ok(filter)
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        let type_ok = self.types.is_empty() || self.types.contains(&event.event_type);
+        let action_ok = self.actions.is_empty() || self.actions.contains(&event.action);
+        let container_ok = self.containers.is_empty()
+            || self.containers.iter().any(|id| {
+                event.id == *id || event.attributes.get("name").and_then(|v| v.as_str()) == Some(id)
+            });
+
+        type_ok && action_ok && container_ok
+    }
+}
+
+/// Prints past events newer than `since` and, unless `until` is given, keeps
+/// following the log for new ones.
+pub async fn stream_events(
+    since: Option<u64>,
+    until: Option<u64>,
+    filter: &EventFilter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut offset = 0;
+
+    if let Ok(file) = fs::File::open(store::path(EVENTS_LOG)) {
+        let mut reader = BufReader::new(file);
+        offset = read_events(&mut reader, 0, |event| {
+            let after_since = since.is_some_and(|since| event.time >= since);
+            let before_until = until.is_none_or(|until| event.time <= until);
+            if after_since && before_until && filter.matches(event) {
+                print_event(event);
+            }
+        })?;
+    }
+
+    if until.is_some() {
+        return Ok(());
+    }
+
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        let file = match fs::File::open(store::path(EVENTS_LOG)) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+
+        let mut reader = BufReader::new(file);
+        offset = read_events(&mut reader, offset, |event| {
+            if filter.matches(event) {
+                print_event(event);
+            }
+        })?;
+    }
+}
+
+fn read_events<R, F>(
+    reader: &mut BufReader<R>,
+    offset: u64,
+    mut on_event: F,
+) -> Result<u64, Box<dyn std::error::Error>>
+where
+    R: std::io::Read + Seek,
+    F: FnMut(&Event),
+{
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut offset = offset;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        // Stop at a partially written line, it is picked up on the next poll.
+        if read == 0 || !line.ends_with('\n') {
+            return Ok(offset);
+        }
+        offset += read as u64;
+
+        if let Ok(event) = serde_json::from_str::<Event>(line.trim_end()) {
+            on_event(&event);
+        }
+    }
+}
+
+fn print_event(event: &Event) {
+    let attributes: Vec<String> = event
+        .attributes
+        .iter()
+        .map(|(key, value)| match value {
+            serde_json::Value::String(s) => format!("{}={}", key, s),
+            other => format!("{}={}", key, other),
+        })
+        .collect();
+
+    let mut line = format!(
+        "{} {} {} {}",
+        utils::format_timestamp(event.time),
+        event.event_type,
+        event.action,
+        event.id
+    );
+    if !attributes.is_empty() {
+        line.push_str(&format!(" ({})", attributes.join(", ")));
+    }
+
+    println!("{}", line);
+}
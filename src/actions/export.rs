@@ -0,0 +1,51 @@
+use crate::actions::{container, storage};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+};
+
+pub async fn export_container(
+    reference: &str,
+    output: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = container::resolve_container(reference)?;
+    let container_dir = container::container_dir(&container_id);
+    let rootfs_path = format!("{}/rootfs", container_dir);
+
+    if fs::metadata(&rootfs_path).is_err() {
+        return Err(format!("Container {} has no root filesystem", container_id).into());
+    }
+    // Gone after a host reboot, and an unmounted rootfs would export empty.
+    storage::mount_rootfs(&container_dir).map_err(|e| {
+        format!(
+            "Failed to mount the root filesystem of container {}: {}",
+            container_id, e
+        )
+    })?;
+
+    match output {
+        Some(output_path) => {
+            let file = File::create(output_path)?;
+            write_rootfs_archive(&rootfs_path, file)?;
+            println!("📦 Exported container {} to {}", container_id, output_path);
+        }
+        None => {
+            // Nothing else may be printed to stdout here, it carries the archive.
+            write_rootfs_archive(&rootfs_path, io::stdout().lock())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_rootfs_archive<W: Write>(
+    rootfs_path: &str,
+    writer: W,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", rootfs_path)?;
+    builder.into_inner()?.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::{fs, path::Path, process::Command};
+
+/// Device nodes every NVIDIA container needs besides the GPUs themselves.
+const CONTROL_DEVICES: [&str; 4] = [
+    "/dev/nvidiactl",
+    "/dev/nvidia-uvm",
+    "/dev/nvidia-uvm-tools",
+    "/dev/nvidia-modeset",
+];
+/// Driver library name prefixes, as listed by `ldconfig -p`.
+const DRIVER_LIBRARIES: [&str; 6] = [
+    "libcuda.so",
+    "libnvidia-",
+    "libnvcuvid.so",
+    "libnvoptix.so",
+    "libnvidia-ml.so",
+    "libOpenCL.so",
+];
+const DRIVER_BINARIES: [&str; 3] = ["nvidia-smi", "nvidia-debugdump", "nvidia-persistenced"];
+/// Where driver libraries are mounted inside the container.
+pub const LIBRARY_DIR: &str = "/usr/local/nvidia/lib64";
+
+/// What `--gpus` adds to a container.
+#[derive(Debug, Default)]
+pub struct GpuSetup {
+    /// `(host path, container path)` pairs to bind mount.
+    pub mounts: Vec<(String, String)>,
+    /// Value for NVIDIA_VISIBLE_DEVICES.
+    pub visible_devices: String,
+}
+
+/// Resolves `--gpus all`, `--gpus <count>` or `--gpus device=0,1` against the
+/// GPUs present on the host.
+pub fn resolve(spec: &str) -> Result<GpuSetup, Box<dyn std::error::Error>> {
+    let available = host_gpus()?;
+    if available.is_empty() {
+        return Err("No NVIDIA GPUs found on the host (no /dev/nvidia<N> devices)".into());
+    }
+
+    let spec = spec.trim_matches('"');
+    let selected: Vec<u32> = if spec == "all" {
+        available.clone()
+    } else if let Some(devices) = spec.strip_prefix("device=") {
+        devices
+            .split(',')
+            .map(|device| {
+                let index: u32 = device
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid GPU device: {}", device))?;
+                if !available.contains(&index) {
+                    return Err(format!("GPU device {} not found on the host", index));
+                }
+                Ok(index)
+            })
+            .collect::<Result<_, _>>()?
+    } else {
+        let count: usize = spec.parse().map_err(|_| {
+            format!(
+                "Invalid --gpus value: {}. Expected all, <count> or device=<ids>",
+                spec
+            )
+        })?;
+        if count == 0 || count > available.len() {
+            return Err(format!(
+                "Requested {} GPUs but only {} are available",
+                count,
+                available.len()
+            )
+            .into());
+        }
+        available[..count].to_vec()
+    };
+
+    let mut mounts: Vec<(String, String)> = selected
+        .iter()
+        .map(|index| format!("/dev/nvidia{}", index))
+        .chain(
+            CONTROL_DEVICES
+                .iter()
+                .filter(|device| Path::new(device).exists())
+                .map(|device| device.to_string()),
+        )
+        .map(|device| (device.clone(), device))
+        .collect();
+
+    for (soname, host_path) in driver_libraries() {
+        mounts.push((host_path, format!("{}/{}", LIBRARY_DIR, soname)));
+    }
+    for binary in DRIVER_BINARIES {
+        if let Some(host_path) = find_binary(binary) {
+            mounts.push((host_path, format!("/usr/bin/{}", binary)));
+        }
+    }
+
+    let visible_devices = selected
+        .iter()
+        .map(|index| index.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    Ok(GpuSetup {
+        mounts,
+        visible_devices,
+    })
+}
+
+/// Indexes of the host's /dev/nvidia<N> devices, sorted.
+fn host_gpus() -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+    let mut gpus: Vec<u32> = fs::read_dir("/dev")?
+        .flatten()
+        .filter_map(|entry| {
+            entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("nvidia")?
+                .parse()
+                .ok()
+        })
+        .collect();
+    gpus.sort();
+
+    Ok(gpus)
+}
+
+/// Driver libraries known to the host's dynamic linker, as `(soname, path)`.
+fn driver_libraries() -> Vec<(String, String)> {
+    let output = match Command::new("ldconfig").arg("-p").output() {
+        Ok(output) => output,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut libraries: Vec<(String, String)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            // e.g. "	libcuda.so.1 (libc6,x86-64) => /usr/lib/x86_64-linux-gnu/libcuda.so.1"
+            let (name, path) = line.trim().split_once(" => ")?;
+            let soname = name.split_whitespace().next()?;
+            DRIVER_LIBRARIES
+                .iter()
+                .any(|prefix| soname.starts_with(prefix))
+                .then(|| (soname.to_string(), path.to_string()))
+        })
+        .collect();
+    // ldconfig lists 32 and 64 bit variants, keep the first of each name.
+    libraries.dedup_by(|a, b| a.0 == b.0);
+
+    libraries
+}
+
+fn find_binary(name: &str) -> Option<String> {
+    std::env::var("PATH")
+        .ok()?
+        .split(':')
+        .map(|dir| format!("{}/{}", dir, name))
+        .find(|path| Path::new(path).exists())
+}
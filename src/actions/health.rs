@@ -0,0 +1,381 @@
+use crate::actions::{container, events, namespaces, run::ProcessSpec, store};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Read,
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_RETRIES: u32 = 3;
+/// How long the monitor waits for the container process to appear.
+const STARTUP_GRACE: Duration = Duration::from_secs(10);
+/// How often the monitor checks between probes that its run goes on.
+const RUN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_LOG_ENTRIES: usize = 5;
+const MAX_OUTPUT_LEN: usize = 4096;
+
+/// The `Healthcheck` section of an image config. Durations are nanoseconds.
+#[derive(Debug, Default, Deserialize)]
+pub struct ImageHealthcheck {
+    #[serde(rename = "Test", default)]
+    pub test: Vec<String>,
+    #[serde(rename = "Interval", default)]
+    pub interval: u64,
+    #[serde(rename = "Timeout", default)]
+    pub timeout: u64,
+    #[serde(rename = "StartPeriod", default)]
+    pub start_period: u64,
+    #[serde(rename = "Retries", default)]
+    pub retries: u32,
+}
+
+/// Healthcheck settings given on the `run` command line.
+#[derive(Debug, Default)]
+pub struct HealthOverrides {
+    pub cmd: Option<String>,
+    pub interval: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub retries: Option<u32>,
+    pub start_period: Option<Duration>,
+    pub disabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthConfig {
+    /// Probe command in image form: `["CMD", args...]` or `["CMD-SHELL", cmd]`.
+    pub test: Vec<String>,
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+    pub retries: u32,
+    pub start_period_secs: u64,
+}
+
+impl HealthConfig {
+    /// Combines the image healthcheck with command-line overrides, returning
+    /// `None` when no probe should run.
+    pub fn resolve(image: Option<&ImageHealthcheck>, overrides: &HealthOverrides) -> Option<Self> {
+        if overrides.disabled {
+            return None;
+        }
+
+        let test = match &overrides.cmd {
+            Some(cmd) => vec!["CMD-SHELL".to_string(), cmd.clone()],
+            None => image.map(|h| h.test.clone()).unwrap_or_default(),
+        };
+
+        if test.is_empty() || test[0] == "NONE" {
+            return None;
+        }
+
+        let from_nanos = |nanos: u64, default: Duration| {
+            if nanos == 0 {
+                default
+            } else {
+                Duration::from_nanos(nanos)
+            }
+        };
+
+        let interval = overrides
+            .interval
+            .unwrap_or_else(|| from_nanos(image.map_or(0, |h| h.interval), DEFAULT_INTERVAL));
+        let timeout = overrides
+            .timeout
+            .unwrap_or_else(|| from_nanos(image.map_or(0, |h| h.timeout), DEFAULT_TIMEOUT));
+        let start_period = overrides
+            .start_period
+            .unwrap_or_else(|| from_nanos(image.map_or(0, |h| h.start_period), Duration::ZERO));
+        let retries = overrides
+            .retries
+            .or_else(|| image.map(|h| h.retries).filter(|r| *r > 0))
+            .unwrap_or(DEFAULT_RETRIES);
+
+        Some(HealthConfig {
+            test,
+            interval_secs: interval.as_secs().max(1),
+            timeout_secs: timeout.as_secs().max(1),
+            retries,
+            start_period_secs: start_period.as_secs(),
+        })
+    }
+
+    fn probe_args(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        match self.test[0].as_str() {
+            "CMD" => Ok(self.test[1..].to_vec()),
+            "CMD-SHELL" => Ok(vec![
+                "/bin/sh".to_string(),
+                "-c".to_string(),
+                self.test[1..].join(" "),
+            ]),
+            other => Err(format!("Unsupported healthcheck test type: {}", other).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthState {
+    pub status: String,
+    pub failing_streak: u32,
+    pub log: Vec<HealthLogEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthLogEntry {
+    pub start: u64,
+    pub exit_code: i32,
+    pub output: String,
+}
+
+/// Reads the current health status (`starting`, `healthy` or `unhealthy`).
+pub fn health_status(container_id: &str) -> Option<String> {
+    let metadata = container::load_metadata(container_id)?;
+    let state: HealthState = serde_json::from_value(metadata.get("health")?.clone()).ok()?;
+    Some(state.status)
+}
+
+/// Starts `rustainer healthcheck <id>` detached from the CLI so probes keep
+/// running for detached containers.
+pub fn spawn_monitor(container_id: &str) -> Result<u32, Box<dyn std::error::Error>> {
+    let child = Command::new(std::env::current_exe()?)
+        .arg("--root")
+        .arg(store::root())
+        .args(["healthcheck", container_id])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+
+    Ok(child.id())
+}
+
+/// Starts a monitor for the container's current run when it has a
+/// healthcheck. Called each time the container starts, restarts included.
+pub fn start_monitor(container_id: &str) {
+    let has_healthcheck = container::load_metadata(container_id)
+        .is_some_and(|metadata| metadata.get("healthcheck").is_some_and(|v| !v.is_null()));
+    if has_healthcheck {
+        if let Err(e) = spawn_monitor(container_id) {
+            println!(
+                "⚠️ Warning: Failed to start the healthcheck of container {}: {}",
+                container_id, e
+            );
+        }
+    }
+}
+
+/// Probes one run of the container, the one current when the monitor gets
+/// the container's health monitor lock, and returns once that run ends.
+pub async fn monitor(container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let metadata = container::load_metadata(container_id)
+        .ok_or_else(|| format!("Container {} has no metadata", container_id))?;
+    let config: HealthConfig = serde_json::from_value(
+        metadata
+            .get("healthcheck")
+            .cloned()
+            .ok_or("Container has no healthcheck configured")?,
+    )?;
+    let probe_args = config.probe_args()?;
+    let process: ProcessSpec = serde_json::from_value(metadata["process"].clone())?;
+    if process.runtime.is_some() || process.wasm {
+        return Err("Healthchecks cannot be confined with an OCI runtime or in Wasm".into());
+    }
+
+    let id = container_id.to_string();
+    let _lock = tokio::task::spawn_blocking(move || {
+        store::lock_health_monitor(&id).map_err(|e| e.to_string())
+    })
+    .await??;
+
+    let started = Instant::now();
+    let run = loop {
+        if let Some(run) = current_run(container_id) {
+            break run;
+        }
+        if started.elapsed() > STARTUP_GRACE {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    };
+
+    let mut state = HealthState {
+        status: "starting".to_string(),
+        failing_streak: 0,
+        log: Vec::new(),
+    };
+    save_state(container_id, &state)?;
+
+    let start_period = Duration::from_secs(config.start_period_secs);
+    let timeout = Duration::from_secs(config.timeout_secs);
+
+    loop {
+        if !wait_in_run(container_id, run, Duration::from_secs(config.interval_secs)).await {
+            return Ok(());
+        }
+        let Some(pid) = container::main_pid(container_id) else {
+            return Ok(());
+        };
+
+        let probe_start = container::now_secs();
+        let (process, probe_args) = (process.clone(), probe_args.clone());
+        let (exit_code, output) =
+            tokio::task::spawn_blocking(move || run_probe(pid, &process, &probe_args, timeout))
+                .await
+                .unwrap_or_else(|e| (-1, format!("Failed to run healthcheck: {}", e)));
+        // A probe of a process that has since exited says nothing about
+        // the next run.
+        if current_run(container_id) != Some(run) {
+            return Ok(());
+        }
+
+        state.log.push(HealthLogEntry {
+            start: probe_start,
+            exit_code,
+            output,
+        });
+        if state.log.len() > MAX_LOG_ENTRIES {
+            state.log.remove(0);
+        }
+
+        let previous_status = state.status.clone();
+        if exit_code == 0 {
+            state.failing_streak = 0;
+            state.status = "healthy".to_string();
+        } else if started.elapsed() >= start_period {
+            state.failing_streak += 1;
+            if state.failing_streak >= config.retries {
+                state.status = "unhealthy".to_string();
+            }
+        }
+
+        save_state(container_id, &state)?;
+
+        if state.status != previous_status {
+            events::emit_container(
+                &format!("health_status: {}", state.status),
+                container_id,
+                serde_json::json!({}),
+            );
+        }
+    }
+}
+
+/// The PID and start time of the container's running process, which tell
+/// its runs apart.
+fn current_run(container_id: &str) -> Option<(u64, u64)> {
+    if !container::is_running(container_id) {
+        return None;
+    }
+    let metadata = container::load_metadata(container_id)?;
+    Some((
+        metadata.get("pid")?.as_u64()?,
+        metadata.get("pid_start_time")?.as_u64()?,
+    ))
+}
+
+/// Waits `interval`, returning false as soon as `run` is no longer the
+/// container's, leaving the next run to its own monitor.
+async fn wait_in_run(container_id: &str, run: (u64, u64), interval: Duration) -> bool {
+    let deadline = Instant::now() + interval;
+    loop {
+        if current_run(container_id) != Some(run) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        tokio::time::sleep(RUN_POLL_INTERVAL.min(deadline - now)).await;
+    }
+}
+
+/// Runs the probe inside the container's namespaces, confined like its
+/// process, returning its exit code (-1 when it could not run or timed out)
+/// and captured output.
+fn run_probe(
+    pid: u32,
+    process: &ProcessSpec,
+    probe_args: &[String],
+    timeout: Duration,
+) -> (i32, String) {
+    let Some((program, args)) = probe_args.split_first() else {
+        return (-1, "Healthcheck has no command".to_string());
+    };
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+        .env_clear()
+        .envs(&process.env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let child = namespaces::enter(pid, process, &mut cmd)
+        .map_err(|e| e.to_string())
+        .and_then(|()| cmd.spawn().map_err(|e| e.to_string()));
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => return (-1, format!("Failed to run healthcheck: {}", e)),
+    };
+    // Drained as the probe writes, so a chatty probe never blocks on a full
+    // pipe.
+    let stdout = child.stdout.take().map(read_output);
+    let stderr = child.stderr.take().map(read_output);
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() >= timeout => {
+                namespaces::kill_entered(child.id());
+                let _ = child.wait();
+                return (-1, format!("Health check exceeded timeout ({:?})", timeout));
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+            Err(e) => return (-1, format!("Failed to wait for healthcheck: {}", e)),
+        }
+    };
+
+    // Processes the probe left behind may hold the pipes open, so they are
+    // only waited on until the timeout.
+    let mut output = Vec::new();
+    for receiver in [stdout, stderr].into_iter().flatten() {
+        let remaining = timeout.saturating_sub(started.elapsed());
+        if let Ok(bytes) = receiver.recv_timeout(remaining) {
+            output.extend(bytes);
+        }
+    }
+    let mut text = String::from_utf8_lossy(&output).to_string();
+    text.truncate(text.floor_char_boundary(MAX_OUTPUT_LEN));
+    (status.code().unwrap_or(-1), text)
+}
+
+/// Reads `pipe` to its end on a thread of its own, keeping the first
+/// `MAX_OUTPUT_LEN` bytes.
+fn read_output(mut pipe: impl Read + Send + 'static) -> mpsc::Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let mut buffer = [0u8; 8192];
+        loop {
+            match pipe.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => {
+                    let keep = read.min(MAX_OUTPUT_LEN.saturating_sub(output.len()));
+                    output.extend_from_slice(&buffer[..keep]);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+        let _ = sender.send(output);
+    });
+    receiver
+}
+
+fn save_state(container_id: &str, state: &HealthState) -> Result<(), Box<dyn std::error::Error>> {
+    let state = serde_json::to_value(state)?;
+    container::update_metadata(container_id, |metadata| metadata["health"] = state)
+}
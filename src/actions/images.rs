@@ -1,26 +1,32 @@
-use crate::actions::types::ImageManifest;
+use crate::actions::{
+    container, events, mount, pull, storage,
+    store::{self, LockMode},
+    types::ImageManifest,
+    utils,
+};
 use std::{fs, path::Path, time::SystemTime};
 
 struct ImageInfo {
     repository: String,
     tag: String,
+    digest: Option<String>,
     image_id: String,
     created: String,
     size: u64,
     // layers: usize,
 }
 
-pub async fn list_images() -> Result<(), Box<dyn std::error::Error>> {
-    let images_dir = "./images";
+pub async fn list_images(show_digests: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let images_dir = store::path("images");
 
-    if !Path::new(images_dir).exists() {
+    if !Path::new(&images_dir).exists() {
         println!("No images found. Use 'rustainer pull <image>' to download images.");
         return Ok(());
     }
 
     let mut images = Vec::new();
 
-    for entry in fs::read_dir(images_dir)? {
+    for entry in fs::read_dir(&images_dir)? {
         let entry = entry?;
         let path = entry.path();
 
@@ -45,7 +51,7 @@ pub async fn list_images() -> Result<(), Box<dyn std::error::Error>> {
 
     images.sort_by(|a, b| a.repository.cmp(&b.repository));
 
-    print_images_table(&images);
+    print_images_table(&images, show_digests);
 
     Ok(())
 }
@@ -62,7 +68,6 @@ async fn parse_image_directory(
     let manifest_content = fs::read_to_string(&manifest_path)?;
     let manifest: ImageManifest = serde_json::from_str(&manifest_content)?;
 
-
     let repository_dir = path
         .parent()
         .and_then(|p| p.file_name())
@@ -93,15 +98,29 @@ async fn parse_image_directory(
         .take(12)
         .collect();
 
-    let tag = path
+    let reference = path
         .file_name()
         .and_then(|n| n.to_str())
         .unwrap_or("unknown")
         .to_string();
 
+    // Images pulled by digest are stored under the digest and have no tag.
+    let (tag, digest) = if pull::is_digest(&reference) {
+        ("<none>".to_string(), Some(reference))
+    } else {
+        let digest = path.to_str().and_then(|dir| {
+            pull::load_image_metadata(dir)
+                .get("digest")?
+                .as_str()
+                .map(String::from)
+        });
+        (reference, digest)
+    };
+
     Ok(Some(ImageInfo {
         repository,
         tag,
+        digest,
         image_id,
         created: created_str,
         size: total_size,
@@ -109,6 +128,156 @@ async fn parse_image_directory(
     }))
 }
 
+/// Evicts the least recently used images no container was made from and
+/// none mounted until images and extracted layers fit in
+/// `max_image_store_size`. The image at `keep`, just pulled or imported, is
+/// never evicted.
+pub fn enforce_size_limit(keep: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(limit) = store::max_image_store_size() else {
+        return Ok(());
+    };
+    let _lock = store::lock_store(LockMode::Exclusive)?;
+    let mut size = image_store_size();
+    if size <= limit {
+        return Ok(());
+    }
+
+    let mut in_use: Vec<String> = container::list_container_ids()?
+        .iter()
+        .filter_map(|container_id| {
+            let metadata = container::load_metadata(container_id)?;
+            let (repository, tag) = pull::parse_image_tag(metadata.get("image")?.as_str()?);
+            Some(store::image_dir(&repository, &tag))
+        })
+        .collect();
+    in_use.extend(mount::mounted_images());
+    let mut candidates = Vec::new();
+    for repository in fs::read_dir(store::path("images"))?.flatten() {
+        for tag in fs::read_dir(repository.path())?.flatten() {
+            let image_dir = tag.path().display().to_string();
+            if image_dir == keep || in_use.contains(&image_dir) {
+                continue;
+            }
+            let last_used = pull::load_image_metadata(&image_dir)
+                .get("last_used")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+            candidates.push((
+                last_used,
+                repository.file_name().to_string_lossy().replace('_', "/"),
+                tag.file_name().to_string_lossy().into_owned(),
+                image_dir,
+            ));
+        }
+    }
+    candidates.sort();
+
+    for (last_used, repository, tag, image_dir) in candidates {
+        if size <= limit {
+            break;
+        }
+        let _image_lock = store::lock_image(&repository, &tag, LockMode::Exclusive)?;
+        fs::remove_dir_all(&image_dir)?;
+        if let Some(repository_dir) = Path::new(&image_dir).parent() {
+            let _ = fs::remove_dir(repository_dir);
+        }
+        storage::prune_layers()?;
+
+        let freed = size.saturating_sub(image_store_size());
+        size -= freed;
+        let reference = pull::format_image_reference(&repository, &tag);
+        println!(
+            "🧹 Evicted image {} to stay within the image store limit, freeing {}",
+            reference,
+            format_size(freed)
+        );
+        events::emit(
+            "image",
+            "evict",
+            &reference,
+            serde_json::json!({ "last_used": last_used, "freed": freed }),
+        );
+    }
+
+    if size > limit {
+        println!(
+            "⚠️ Warning: The image store takes {}, over its limit of {}, but no other image can be evicted",
+            format_size(size),
+            format_size(limit)
+        );
+    }
+    Ok(())
+}
+
+/// Removes images and the extracted layers only they used. Images stopped
+/// containers were made from or that are mounted are only removed with
+/// `force`, those of running containers never: their layers stay for the
+/// containers either way.
+pub fn remove_images(references: &[String], force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    // Keeps containers from being made of the images meanwhile.
+    let _lock = store::lock_store(LockMode::Exclusive)?;
+    for reference in references {
+        let (repository, tag) = pull::parse_image_tag(reference);
+        let image = pull::format_image_reference(&repository, &tag);
+        let _image_lock = store::lock_image(&repository, &tag, LockMode::Exclusive)?;
+        let image_dir = store::image_dir(&repository, &tag);
+        if !Path::new(&image_dir).is_dir() {
+            return Err(format!("No such image: {}", image).into());
+        }
+
+        let users = container::containers_using(|metadata| {
+            metadata
+                .get("image")
+                .and_then(|image| image.as_str())
+                .is_some_and(|image| {
+                    pull::parse_image_tag(image) == (repository.clone(), tag.clone())
+                })
+        })?;
+        if let Some(running) = users.iter().find(|id| container::is_running(id)) {
+            return Err(format!(
+                "Image {} is used by running container {}, stop it first",
+                image,
+                container::short_id(running)
+            )
+            .into());
+        }
+        if !force && !users.is_empty() {
+            return Err(format!(
+                "Image {} is used by container(s) {}, remove them or use -f",
+                image,
+                users
+                    .iter()
+                    .map(|id| container::short_id(id))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+            .into());
+        }
+        if !force && mount::mounted_images().contains(&image_dir) {
+            return Err(format!("Image {} is mounted, unmount it or use -f", image).into());
+        }
+
+        fs::remove_dir_all(&image_dir)?;
+        if let Some(repository_dir) = Path::new(&image_dir).parent() {
+            let _ = fs::remove_dir(repository_dir);
+        }
+        println!("Untagged: {}", image);
+        // Layers other images, containers or mounts share stay.
+        for digest in storage::prune_layers()? {
+            println!("Deleted: {}", digest);
+        }
+        events::emit("image", "delete", &image, serde_json::json!({}));
+    }
+
+    Ok(())
+}
+
+/// The disk space images and their extracted layers take.
+fn image_store_size() -> u64 {
+    utils::dir_size(Path::new(&store::path("images")))
+        + utils::dir_size(Path::new(&store::path("layers")))
+}
+
 fn format_size(size: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
     let mut size = size as f64;
@@ -137,17 +306,28 @@ fn format_time(time: SystemTime) -> String {
     }
 }
 
-fn print_images_table(images: &[ImageInfo]) {
+fn print_images_table(images: &[ImageInfo], show_digests: bool) {
+    let digest_header = if show_digests {
+        format!("{:<72}", "DIGEST")
+    } else {
+        String::new()
+    };
     println!(
-        "{:<30} {:<10} {:<15} {:<15} {:<10}",
-        "REPOSITORY", "TAG", "IMAGE ID", "CREATED", "SIZE"
+        "{:<30} {:<10} {}{:<15} {:<15} {:<10}",
+        "REPOSITORY", "TAG", digest_header, "IMAGE ID", "CREATED", "SIZE"
     );
 
     for image in images {
+        let digest = if show_digests {
+            format!("{:<72}", image.digest.as_deref().unwrap_or("<none>"))
+        } else {
+            String::new()
+        };
         println!(
-            "{:<30} {:<10} {:<15} {:<15} {:<10}",
+            "{:<30} {:<10} {}{:<15} {:<15} {:<10}",
             image.repository,
             image.tag,
+            digest,
             image.image_id,
             image.created,
             format_size(image.size)
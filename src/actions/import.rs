@@ -0,0 +1,187 @@
+use crate::actions::{
+    self,
+    store::{self, LockMode},
+    types::{ImageManifest, Layer, Platform},
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Write},
+};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+struct DigestWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> DigestWriter<W> {
+    fn new(inner: W) -> Self {
+        DigestWriter {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    fn finish(self) -> (W, String, u64) {
+        let digest = format!("sha256:{:x}", self.hasher.finalize());
+        (self.inner, digest, self.size)
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub async fn import_image(source: &str, image_tag: &str) -> Result<(), Box<dyn std::error::Error>> {
+    println!("📥 Importing {} as {}", source, image_tag);
+
+    let (repository, tag) = actions::pull::parse_image_tag(image_tag);
+    if actions::pull::is_digest(&tag) {
+        return Err("Imported images cannot be referenced by digest, use a tag".into());
+    }
+    let store_lock = store::lock_store(LockMode::Shared)?;
+    let image_lock = store::lock_image(&repository, &tag, LockMode::Exclusive)?;
+    let image_dir = store::image_dir(&repository, &tag);
+    fs::create_dir_all(&image_dir)?;
+
+    let input: Box<dyn Read> = if source == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(source).map_err(|e| format!("Failed to open {}: {}", source, e))?)
+    };
+
+    let tmp_layer_path = format!("{}/layer.tmp", image_dir);
+    let (layer, diff_id) = match write_layer_blob(input, &tmp_layer_path) {
+        Ok(result) => result,
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_layer_path);
+            return Err(e);
+        }
+    };
+    fs::rename(
+        &tmp_layer_path,
+        format!("{}/{}", image_dir, layer.digest.replace("sha256:", "")),
+    )?;
+
+    let config_json = serde_json::to_vec_pretty(&generate_image_config(&diff_id))?;
+    let config_digest = format!("sha256:{:x}", Sha256::digest(&config_json));
+    fs::write(
+        format!("{}/{}", image_dir, config_digest.replace("sha256:", "")),
+        &config_json,
+    )?;
+
+    let manifest = ImageManifest {
+        schema_version: 2,
+        media_type: "application/vnd.docker.distribution.manifest.v2+json".to_string(),
+        config: Layer {
+            media_type: "application/vnd.docker.container.image.v1+json".to_string(),
+            size: config_json.len() as u64,
+            digest: config_digest.clone(),
+            annotations: HashMap::new(),
+        },
+        layers: vec![layer],
+    };
+
+    let manifest_path = format!("{}/manifest.json", image_dir);
+    fs::write(manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    actions::pull::save_image_metadata(
+        &image_dir,
+        &serde_json::json!({
+            "platform": Platform::host().to_string(),
+            "last_used": actions::container::now_secs(),
+        }),
+    )?;
+
+    actions::events::emit(
+        "image",
+        "import",
+        &format!("{}:{}", repository, tag),
+        serde_json::json!({ "digest": config_digest, "source": source }),
+    );
+
+    println!("✅ Successfully imported {}", image_tag);
+    println!("{}", config_digest);
+
+    drop(image_lock);
+    drop(store_lock);
+    actions::images::enforce_size_limit(&image_dir)
+}
+
+/// Stores the tarball as a gzip-compressed layer blob, returning the layer
+/// descriptor and the digest of the uncompressed tar stream (its diff ID).
+fn write_layer_blob(
+    input: Box<dyn Read>,
+    blob_path: &str,
+) -> Result<(Layer, String), Box<dyn std::error::Error>> {
+    let mut reader = BufReader::new(input);
+    let is_gzip = reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+
+    let file = File::create(blob_path)?;
+
+    let (layer_digest, layer_size, diff_id) = if is_gzip {
+        let mut blob_writer = DigestWriter::new(file);
+        io::copy(&mut reader, &mut blob_writer)?;
+        let (_, layer_digest, layer_size) = blob_writer.finish();
+
+        let mut diff_writer = DigestWriter::new(io::sink());
+        io::copy(
+            &mut GzDecoder::new(File::open(blob_path)?),
+            &mut diff_writer,
+        )?;
+        let (_, diff_id, _) = diff_writer.finish();
+
+        (layer_digest, layer_size, diff_id)
+    } else {
+        let encoder = GzEncoder::new(DigestWriter::new(file), Compression::default());
+        let mut diff_writer = DigestWriter::new(encoder);
+        io::copy(&mut reader, &mut diff_writer)?;
+        let (encoder, diff_id, _) = diff_writer.finish();
+        let (_, layer_digest, layer_size) = encoder.finish()?.finish();
+
+        (layer_digest, layer_size, diff_id)
+    };
+
+    let layer = Layer {
+        media_type: "application/vnd.docker.image.rootfs.diff.tar.gzip".to_string(),
+        size: layer_size,
+        digest: layer_digest,
+        annotations: HashMap::new(),
+    };
+
+    Ok((layer, diff_id))
+}
+
+fn generate_image_config(diff_id: &str) -> serde_json::Value {
+    let platform = Platform::host();
+
+    serde_json::json!({
+        "architecture": platform.architecture,
+        "os": platform.os,
+        "config": {
+            "Env": ["PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"],
+            "Cmd": ["/bin/sh"]
+        },
+        "rootfs": {
+            "type": "layers",
+            "diff_ids": [diff_id]
+        },
+        "history": [
+            { "created_by": "rustainer import" }
+        ]
+    })
+}
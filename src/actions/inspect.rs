@@ -0,0 +1,28 @@
+use crate::actions::{container, redact};
+
+/// Prints the container's recorded configuration with its current state as
+/// JSON, in an array like `docker inspect`. Masked variables print as `*****`.
+pub async fn inspect_container(reference: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = container::resolve_container(reference)?;
+    let metadata = container::load_metadata(&container_id)
+        .ok_or_else(|| format!("Container {} has no metadata", container_id))?;
+
+    let state = container::state(&container_id);
+    let document = serde_json::json!({
+        "Id": container_id,
+        "State": {
+            "Status": state,
+            "Running": state == "running",
+            "Pid": metadata.get("pid"),
+            "ExitCode": container::exit_code(&container_id),
+            "OOMKilled": container::oom_killed(&container_id),
+            "TimedOut": container::timed_out(&container_id),
+            "StartedAt": metadata.get("started_at"),
+            "FinishedAt": metadata.get("finished_at"),
+        },
+        "Config": redact::redact_metadata(&metadata),
+    });
+
+    println!("{}", serde_json::to_string_pretty(&[document])?);
+    Ok(())
+}
@@ -1,85 +1,397 @@
-use serde_json;
-use std::{
-    fs,
-    process::Command,
-    time::{SystemTime, UNIX_EPOCH},
-};
-
-pub async fn list_containers() -> Result<(), Box<dyn std::error::Error>> {
-    println!("CONTAINER ID\tIMAGE\t\tCOMMAND\t\tCREATED\t\tSTATUS\t\tPORTS");
-
-    let containers_dir = "./containers";
-    if !fs::metadata(containers_dir).is_ok() {
-        println!("No containers found");
-        return Ok(());
-    }
-
-    let entries = fs::read_dir(containers_dir)?;
-
-    for entry in entries {
-        if let Ok(entry) = entry {
-            let container_id = entry.file_name().to_string_lossy().to_string();
-
-            let status = Command::new("ip").args(&["netns", "list"]).output()?;
-
-            let netns_output = String::from_utf8_lossy(&status.stdout);
-            let is_running = netns_output.contains(&container_id);
-
-            let timestamp_part = container_id.strip_prefix("rustainer_").unwrap_or("0");
-            let timestamp = timestamp_part.parse::<u64>().unwrap_or(0);
-
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-            let elapsed_secs = now.saturating_sub(timestamp);
-            let created = if elapsed_secs < 60 {
-                format!("{}s ago", elapsed_secs)
-            } else if elapsed_secs < 3600 {
-                format!("{}m ago", elapsed_secs / 60)
-            } else if elapsed_secs < 86400 {
-                format!("{}h ago", elapsed_secs / 3600)
-            } else {
-                format!("{}d ago", elapsed_secs / 86400)
-            };
-            let mut image = "N/A".to_string();
-            let mut command = "N/A".to_string();
-            let mut ports = "N/A".to_string();
-
-            let metadata_path = format!("{}/{}/metadata.json", containers_dir, container_id);
-
-            let metadata_content = fs::read_to_string(&metadata_path);
-
-            if let Ok(content) = metadata_content {
-                if let Ok(metadata) = serde_json::from_str::<serde_json::Value>(&content) {
-                    if let Some(img) = metadata.get("image").and_then(|v| v.as_str()) {
-                        image = img.to_string();
-                    }
-                    if let Some(cmd) = metadata.get("command").and_then(|v| v.as_str()) {
-                        command = cmd.to_string();
-                    }
-                    if let Some(port_array) = metadata.get("ports").and_then(|v| v.as_array()) {
-                        if !port_array.is_empty() {
-                            let port_strs: Vec<String> = port_array
-                                .iter()
-                                .filter_map(|p| p.as_str().map(String::from))
-                                .collect();
-                            if !port_strs.is_empty() {
-                                ports = port_strs.join(", ");
-                            }
-                        }
+use crate::actions::{container, health, pull, redact, storage, utils};
+use std::path::Path;
+
+struct ContainerInfo {
+    id: String,
+    image: String,
+    image_digest: Option<String>,
+    command: String,
+    created: u64,
+    state: &'static str,
+    exit_code: Option<i32>,
+    oom_killed: bool,
+    timed_out: bool,
+    ports: Vec<String>,
+    name: String,
+    health: Option<String>,
+    size: Option<ContainerSize>,
+}
+
+struct ContainerSize {
+    /// Data written by the container on top of the image content.
+    writable: u64,
+    /// Everything under the container rootfs, image content included.
+    virtual_size: u64,
+}
+
+impl ContainerInfo {
+    fn status(&self) -> String {
+        match (&self.health, self.state) {
+            (Some(health), "running") => format!("Up ({})", health),
+            (None, "running") => "Up".to_string(),
+            (_, "created") => "Created".to_string(),
+            _ => {
+                let mut status = match self.exit_code {
+                    Some(exit_code) => format!("Exited ({})", exit_code),
+                    None => "Exited".to_string(),
+                };
+                if self.oom_killed {
+                    status.push_str(" OOMKilled");
+                }
+                if self.timed_out {
+                    status.push_str(" TimedOut");
+                }
+                status
+            }
+        }
+    }
+
+    fn size_str(&self) -> String {
+        match &self.size {
+            Some(size) => format!(
+                "{} (virtual {})",
+                utils::format_size(size.writable),
+                utils::format_size(size.virtual_size)
+            ),
+            None => String::new(),
+        }
+    }
+
+    fn ports_str(&self) -> String {
+        self.ports.join(", ")
+    }
+
+    /// Looks up a `{{.Field}}` placeholder used by `ps --format`.
+    fn field(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "ID" => container::short_id(&self.id).to_string(),
+            "FullID" => self.id.clone(),
+            "Image" => self.image.clone(),
+            "ImageDigest" => self.image_digest.clone().unwrap_or_default(),
+            "Command" => self.command.clone(),
+            "CreatedAt" => self.created.to_string(),
+            "RunningFor" => format_elapsed(self.created),
+            "Status" => self.status(),
+            "Health" => self.health.clone().unwrap_or_default(),
+            "State" => self.state.to_string(),
+            "OOMKilled" => self.oom_killed.to_string(),
+            "TimedOut" => self.timed_out.to_string(),
+            "Ports" => self.ports_str(),
+            "Names" => self.name.clone(),
+            "Size" => self.size_str(),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut value = serde_json::json!({
+            "ID": self.id,
+            "Image": self.image,
+            "ImageDigest": self.image_digest,
+            "Command": self.command,
+            "CreatedAt": self.created,
+            "RunningFor": format_elapsed(self.created),
+            "Status": self.status(),
+            "State": self.state,
+            "Ports": self.ports,
+            "Names": self.name,
+            "Health": self.health,
+            "ExitCode": self.exit_code,
+            "OOMKilled": self.oom_killed,
+            "TimedOut": self.timed_out,
+        });
+
+        if let Some(size) = &self.size {
+            value["Size"] = size.writable.into();
+            value["VirtualSize"] = size.virtual_size.into();
+        }
+
+        value
+    }
+}
+
+pub enum OutputFormat {
+    Table,
+    Json,
+    Template(String),
+}
+
+impl OutputFormat {
+    pub fn parse(format: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        match format {
+            None | Some("table") => Ok(OutputFormat::Table),
+            Some("json") => Ok(OutputFormat::Json),
+            Some(template) if template.contains("{{") => {
+                Ok(OutputFormat::Template(template.to_string()))
+            }
+            Some(other) => Err(format!(
+                "Invalid format: {}. Use table, json or a template such as '{{{{.ID}}}} {{{{.Names}}}}'",
+                other
+            )
+            .into()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ContainerFilter {
+    statuses: Vec<String>,
+    ancestors: Vec<String>,
+    names: Vec<String>,
+}
+
+impl ContainerFilter {
+    /// Parses `--filter key=value` options. Values for the same key are ORed,
+    /// different keys are ANDed.
+    pub fn parse(filters: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut filter = ContainerFilter::default();
+
+        for raw in filters {
+            let (key, value) = raw
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid filter: {}. Expected key=value", raw))?;
+
+            match key {
+                "status" => {
+                    if !matches!(value, "created" | "running" | "exited") {
+                        return Err(format!(
+                            "Invalid status filter: {}. Expected created, running or exited",
+                            value
+                        )
+                        .into());
                     }
+                    filter.statuses.push(value.to_string());
+                }
+                "ancestor" => filter.ancestors.push(value.to_string()),
+                "name" => filter.names.push(value.to_string()),
+                _ => {
+                    return Err(format!(
+                        "Unsupported filter: {}. Supported filters are status, ancestor and name",
+                        key
+                    )
+                    .into())
                 }
             }
+        }
+
+        Ok(filter)
+    }
+
+    fn has_status(&self) -> bool {
+        !self.statuses.is_empty()
+    }
+
+    fn matches(&self, info: &ContainerInfo) -> bool {
+        let status_ok = self.statuses.is_empty() || self.statuses.iter().any(|s| s == info.state);
+
+        let ancestor_ok = self.ancestors.is_empty()
+            || self.ancestors.iter().any(|ancestor| {
+                pull::parse_image_tag(ancestor) == pull::parse_image_tag(&info.image)
+            });
+
+        let name_ok = self.names.is_empty()
+            || self
+                .names
+                .iter()
+                .any(|pattern| info.name.contains(pattern.as_str()));
+
+        status_ok && ancestor_ok && name_ok
+    }
+}
+
+pub async fn list_containers(
+    all: bool,
+    filter: &ContainerFilter,
+    format: &OutputFormat,
+    with_size: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut containers = Vec::new();
+    for container_id in container::list_container_ids()? {
+        let mut info = load_container_info(&container_id);
+
+        // A status filter selects stopped containers on its own, like `-a`.
+        if !all && !filter.has_status() && info.state != "running" {
+            continue;
+        }
+
+        if filter.matches(&info) {
+            if with_size {
+                info.size = Some(measure_container_size(&container_id));
+            }
+            containers.push(info);
+        }
+    }
 
-            let status_str = if is_running { "Up" } else { "Exited" };
+    containers.sort_by_key(|info| std::cmp::Reverse(info.created));
 
-            println!(
-                "{}\t{}\t{}\t{}\t{}\t{}",
-                container_id, image, command, created, status_str, ports
-            );
+    match format {
+        OutputFormat::Table => print_containers_table(&containers, with_size),
+        OutputFormat::Json => {
+            for info in &containers {
+                println!("{}", serde_json::to_string(&info.to_json())?);
+            }
+        }
+        OutputFormat::Template(template) => {
+            for info in &containers {
+                println!("{}", render_template(template, info)?);
+            }
         }
     }
 
     Ok(())
 }
+
+fn print_containers_table(containers: &[ContainerInfo], with_size: bool) {
+    let rows: Vec<Vec<String>> = containers
+        .iter()
+        .map(|info| {
+            let mut row = vec![
+                container::short_id(&info.id).to_string(),
+                info.image.clone(),
+                format!("\"{}\"", utils::truncate(&info.command, 20)),
+                format_elapsed(info.created),
+                info.status(),
+                info.ports_str(),
+                info.name.clone(),
+            ];
+            if with_size {
+                row.push(info.size_str());
+            }
+            row
+        })
+        .collect();
+
+    let mut headers = vec![
+        "CONTAINER ID",
+        "IMAGE",
+        "COMMAND",
+        "CREATED",
+        "STATUS",
+        "PORTS",
+        "NAMES",
+    ];
+    if with_size {
+        headers.push("SIZE");
+    }
+
+    utils::print_table(&headers, &rows);
+}
+
+/// An overlay rootfs keeps the container's writes apart. Otherwise the image
+/// content size is recorded in metadata right after the layers are
+/// extracted, so anything beyond it was written by the container.
+fn measure_container_size(container_id: &str) -> ContainerSize {
+    let container_dir = container::container_dir(container_id);
+    let rootfs_path = format!("{}/rootfs", container_dir);
+    // Gone after a host reboot, when the rootfs would measure empty.
+    if let Err(e) = storage::mount_rootfs(&container_dir) {
+        eprintln!(
+            "⚠️ Warning: Failed to mount the rootfs of container {}: {}",
+            container::short_id(container_id),
+            e
+        );
+    }
+    let virtual_size = utils::dir_size(Path::new(&rootfs_path));
+
+    if let Some(upper) = storage::upper_dir(container_id) {
+        return ContainerSize {
+            writable: utils::dir_size(&upper),
+            virtual_size,
+        };
+    }
+
+    let image_size = container::load_metadata(container_id)
+        .and_then(|metadata| metadata.get("image_size").and_then(|v| v.as_u64()))
+        .unwrap_or(virtual_size);
+
+    ContainerSize {
+        writable: virtual_size.saturating_sub(image_size),
+        virtual_size,
+    }
+}
+
+fn render_template(
+    template: &str,
+    info: &ContainerInfo,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let template = template.replace("\\t", "\t").replace("\\n", "\n");
+    let mut output = String::new();
+    let mut rest = template.as_str();
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| format!("Unclosed placeholder in format: {}", template))?;
+
+        let placeholder = rest[start + 2..start + end].trim();
+        let field = placeholder.strip_prefix('.').unwrap_or(placeholder);
+        let value = info
+            .field(field)
+            .ok_or_else(|| format!("Unknown format field: {}", placeholder))?;
+        output.push_str(&value);
+
+        rest = &rest[start + end + 2..];
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+fn load_container_info(container_id: &str) -> ContainerInfo {
+    let mut info = ContainerInfo {
+        id: container_id.to_string(),
+        image: "N/A".to_string(),
+        image_digest: None,
+        command: String::new(),
+        created: container::created_at(container_id),
+        state: container::state(container_id),
+        exit_code: container::exit_code(container_id),
+        oom_killed: container::oom_killed(container_id),
+        timed_out: container::timed_out(container_id),
+        ports: Vec::new(),
+        name: String::new(),
+        health: health::health_status(container_id),
+        size: None,
+    };
+
+    if let Some(metadata) = container::load_metadata(container_id) {
+        if let Some(img) = metadata.get("image").and_then(|v| v.as_str()) {
+            info.image = img.to_string();
+        }
+        info.image_digest = metadata
+            .get("image_digest")
+            .and_then(|v| v.as_str())
+            .map(String::from);
+        if let Some(container_name) = metadata.get("name").and_then(|v| v.as_str()) {
+            info.name = container_name.to_string();
+        }
+        if let Some(cmd) = metadata.get("command").and_then(|v| v.as_str()) {
+            info.command = redact::redact_text(cmd, &redact::masked_values(&metadata));
+        }
+        if let Some(port_array) = metadata.get("ports").and_then(|v| v.as_array()) {
+            info.ports = port_array
+                .iter()
+                .filter_map(|p| p.as_str().map(String::from))
+                .collect();
+        }
+    }
+
+    info
+}
+
+fn format_elapsed(timestamp: u64) -> String {
+    let elapsed_secs = container::now_secs().saturating_sub(timestamp);
+
+    if elapsed_secs < 60 {
+        format!("{}s ago", elapsed_secs)
+    } else if elapsed_secs < 3600 {
+        format!("{}m ago", elapsed_secs / 60)
+    } else if elapsed_secs < 86400 {
+        format!("{}h ago", elapsed_secs / 3600)
+    } else {
+        format!("{}d ago", elapsed_secs / 86400)
+    }
+}
@@ -0,0 +1,129 @@
+use std::process::Command;
+
+use crate::actions::{checkpoint, container, events, rm};
+
+/// Moves a running container to `destination`, an SSH `[user@]host` with
+/// rustainer installed, whose storage root is `remote_dir` there. The
+/// container directory is copied while the container still runs, so only
+/// what changed since and the checkpoint are sent while it is stopped.
+pub async fn migrate_container(
+    reference: &str,
+    destination: &str,
+    remote_dir: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = container::resolve_container(reference)?;
+    if !container::is_running(&container_id) {
+        return Err(format!("Container {} is not running", container_id).into());
+    }
+    let metadata = container::load_metadata(&container_id)
+        .ok_or_else(|| format!("Container {} has no metadata", container_id))?;
+    // The rootfs of a bundle is not the container's to copy.
+    if metadata.get("bundle").is_some_and(|v| !v.is_null()) {
+        return Err("Containers run from an OCI bundle cannot be migrated".into());
+    }
+
+    let local_dir = format!("{}/", container::container_dir(&container_id));
+    let remote_path = format!("{}/containers/{}/", remote_dir, container_id);
+    ssh(
+        destination,
+        &format!("mkdir -p {}", shell_quote(&remote_path)),
+    )?;
+
+    println!(
+        "🚚 Copying container {} to {}",
+        container::short_id(&container_id),
+        destination
+    );
+    sync(&local_dir, destination, &remote_path)?;
+
+    checkpoint::checkpoint_container(&container_id, false).await?;
+
+    println!("🚚 Sending the changes since and the checkpoint");
+    let restored = sync(&local_dir, destination, &remote_path).and_then(|_| {
+        ssh(
+            destination,
+            &format!(
+                "rustainer --root {} restore {}",
+                shell_quote(remote_dir),
+                container_id
+            ),
+        )
+    });
+    if let Err(e) = restored {
+        println!(
+            "⚠️ Warning: Failed to restore container on {}, restoring it here: {}",
+            destination, e
+        );
+        checkpoint::restore_container(&container_id).await?;
+        return Err(format!("Failed to migrate container {}: {}", container_id, e).into());
+    }
+
+    events::emit_container(
+        "migrate",
+        &container_id,
+        serde_json::json!({ "destination": destination }),
+    );
+    rm::remove_container(&container_id, true, false).await?;
+
+    println!("✅ Container {} migrated to {}", container_id, destination);
+    Ok(())
+}
+
+/// Brings the remote copy of a container directory up to date, keeping
+/// ownership as is for user namespaced containers.
+fn sync(
+    local_dir: &str,
+    destination: &str,
+    remote_path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output = Command::new("rsync")
+        .args(["-aHAX", "--numeric-ids", "--delete", "--sparse"])
+        .args(["--exclude", "supervisor.log"])
+        // The layers of the rootfs may be missing there, so it is sent as a
+        // plain directory instead.
+        .args([
+            "--exclude",
+            "/upper",
+            "--exclude",
+            "/work",
+            "--exclude",
+            "/empty",
+        ])
+        .args(["--exclude", "/lower", "--exclude", "/driver"])
+        .arg(local_dir)
+        .arg(format!("{}:{}", destination, remote_path))
+        .output()
+        .map_err(|e| format!("Failed to run rsync: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to copy container to {}: {}",
+            destination,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+fn ssh(destination: &str, command: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let output = Command::new("ssh")
+        .args(["-o", "BatchMode=yes", destination, command])
+        .output()
+        .map_err(|e| format!("Failed to run ssh: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Command on {} failed: {}",
+            destination,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Quotes a value for the remote shell ssh runs commands with.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
@@ -1,6 +1,48 @@
+pub mod archive;
+pub mod capabilities;
+pub mod cgroup;
+pub mod checkpoint;
+pub mod clone;
+pub mod config;
+pub mod container;
+pub mod disk;
+pub mod dns;
+pub mod events;
+pub mod export;
+pub mod gpu;
+pub mod health;
 pub mod images;
+pub mod import;
+pub mod inspect;
 pub mod ls;
+pub mod migrate;
+pub mod mount;
+pub mod namespaces;
+pub mod network;
+pub mod oci;
+pub mod prune;
 pub mod pull;
+pub mod quota;
+pub mod redact;
+pub mod restart;
 pub mod rm;
 pub mod run;
+pub mod seccomp;
+pub mod secret;
+pub mod security;
+pub mod stats;
+pub mod stop;
+pub mod storage;
+pub mod store;
+pub mod sysctl;
+pub mod tty;
 mod types;
+pub mod update;
+pub mod user;
+pub mod userns;
+pub mod utils;
+pub mod verify;
+pub mod vm;
+pub mod volume;
+pub mod wait;
+pub mod wasm;
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    ffi::CString,
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::actions::{
+    container, pull, storage,
+    store::{self, LockMode},
+    types::ImageManifest,
+    utils, wasm,
+};
+
+/// Where image mounts are recorded, each in a directory named after its
+/// mount point, along with the layers they keep in use.
+pub const MOUNTS_DIR: &str = "mounts";
+
+/// What is recorded about an image mount.
+#[derive(Debug, Serialize, Deserialize)]
+struct ImageMount {
+    image: String,
+    /// The image's directory in the store.
+    image_dir: String,
+    target: PathBuf,
+    created_at: u64,
+}
+
+fn mount_dir(target: &Path) -> PathBuf {
+    let digest = format!("{:x}", Sha256::digest(target.as_os_str().as_bytes()));
+    Path::new(&store::path(MOUNTS_DIR)).join(&digest[..32])
+}
+
+/// Whether something is mounted at `path`. Unlike comparing devices this
+/// also sees bind mounts from the same filesystem.
+fn is_mounted(path: &Path) -> bool {
+    // Mount points are the fifth field, with spaces and the like escaped.
+    let escaped = path
+        .display()
+        .to_string()
+        .replace('\\', "\\134")
+        .replace(' ', "\\040")
+        .replace('\t', "\\011")
+        .replace('\n', "\\012");
+    fs::read_to_string("/proc/self/mountinfo")
+        .unwrap_or_default()
+        .lines()
+        .any(|line| line.split(' ').nth(4) == Some(escaped.as_str()))
+}
+
+/// The recorded mounts, leaving out those gone since, as after a reboot.
+fn list_mounts() -> Vec<ImageMount> {
+    let mut mounts: Vec<ImageMount> = fs::read_dir(store::path(MOUNTS_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let content = fs::read_to_string(entry.path().join("metadata.json")).ok()?;
+            serde_json::from_str::<ImageMount>(&content).ok()
+        })
+        .filter(|mount| is_mounted(&mount.target))
+        .collect();
+    mounts.sort_by(|a, b| a.target.cmp(&b.target));
+    mounts
+}
+
+/// Directories of the images mounted somewhere, which must stay.
+pub fn mounted_images() -> Vec<String> {
+    list_mounts()
+        .into_iter()
+        .map(|mount| mount.image_dir)
+        .collect()
+}
+
+/// Mounts an image's layers read-only at `target` for inspection, without
+/// making a container of it.
+pub fn mount_image(reference: &str, target: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (repository, tag) = pull::parse_image_tag(reference);
+    let image = pull::format_image_reference(&repository, &tag);
+    fs::create_dir_all(target)
+        .map_err(|e| format!("Failed to create mount point {}: {}", target, e))?;
+    let target = fs::canonicalize(target)?;
+    let dir = mount_dir(&target);
+    if is_mounted(&target) {
+        return Err(format!("Something is mounted at {} already", target.display()).into());
+    }
+    // Left by a mount that is gone, as after a reboot.
+    let _ = fs::remove_dir_all(&dir);
+
+    // Until the mount's layers are recorded, pruning could remove them and
+    // pulling could replace the image.
+    let _store_lock = store::lock_store(LockMode::Shared)?;
+    let _image_lock = store::lock_image(&repository, &tag, LockMode::Shared)?;
+    let image_dir = store::image_dir(&repository, &tag);
+    let manifest: ImageManifest = fs::read_to_string(format!("{}/manifest.json", image_dir))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .ok_or_else(|| {
+            format!(
+                "Image {} not found locally. You need to pull it first.",
+                image
+            )
+        })?;
+    let layers: Vec<_> = manifest
+        .layers
+        .iter()
+        .filter(|layer| !wasm::is_module_layer(layer))
+        .collect();
+
+    println!("📦 Mounting image {} at {}", image, target.display());
+    let layer_dirs = storage::prepare_layers(&image_dir, &layers)?;
+    fs::create_dir_all(&dir)?;
+    storage::record_layers(&dir, &layers)?;
+    let mount = ImageMount {
+        image: image.clone(),
+        image_dir: image_dir.clone(),
+        target: target.clone(),
+        created_at: container::now_secs(),
+    };
+    utils::write_atomic(
+        &dir.join("metadata.json").display().to_string(),
+        &serde_json::to_string_pretty(&mount)?,
+    )?;
+    if let Err(e) = storage::mount_layers_read_only(&dir, &layer_dirs, &target) {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(format!("Failed to mount image {}: {}", image, e).into());
+    }
+    pull::mark_image_used(&image_dir);
+
+    println!(
+        "✅ Image {} mounted read-only at {}",
+        image,
+        target.display()
+    );
+    Ok(())
+}
+
+/// Unmounts an image mounted with `mount_image`.
+pub fn unmount_image(target: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let target =
+        fs::canonicalize(target).map_err(|e| format!("Failed to resolve {}: {}", target, e))?;
+    let dir = mount_dir(&target);
+    if !dir.join("metadata.json").exists() {
+        return Err(format!("No image is mounted at {}", target.display()).into());
+    }
+
+    if is_mounted(&target) {
+        let target_c = CString::new(target.as_os_str().as_bytes())?;
+        if unsafe { libc::umount2(target_c.as_ptr(), 0) } != 0 {
+            return Err(format!(
+                "Failed to unmount {}: {}",
+                target.display(),
+                std::io::Error::last_os_error()
+            )
+            .into());
+        }
+    }
+    fs::remove_dir_all(&dir)?;
+
+    println!("✅ Unmounted {}", target.display());
+    Ok(())
+}
+
+/// Lists the images mounted with `mount_image`.
+pub fn list_image_mounts() -> Result<(), Box<dyn std::error::Error>> {
+    let rows: Vec<Vec<String>> = list_mounts()
+        .into_iter()
+        .map(|mount| {
+            vec![
+                mount.image,
+                mount.target.display().to_string(),
+                utils::format_timestamp(mount.created_at),
+            ]
+        })
+        .collect();
+
+    utils::print_table(&["IMAGE", "MOUNTPOINT", "CREATED"], &rows);
+    Ok(())
+}
@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::CString,
+    fmt,
+    fs::{self, File},
+    os::{fd::AsRawFd, unix::fs::MetadataExt, unix::process::CommandExt},
+    process::Command,
+    str::FromStr,
+};
+
+use crate::actions::{
+    capabilities, cgroup,
+    run::{wait_and_exit, write_file, ProcessSpec},
+    seccomp,
+};
+
+/// Whether a container gets its own PID, IPC or UTS namespace or shares the
+/// host's, as selected with `--pid`, `--ipc` and `--uts`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamespaceMode {
+    #[default]
+    Private,
+    Host,
+}
+
+impl FromStr for NamespaceMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "" | "private" => Ok(NamespaceMode::Private),
+            "host" => Ok(NamespaceMode::Host),
+            _ => Err(format!(
+                "Invalid namespace mode: {}. Expected private or host",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for NamespaceMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NamespaceMode::Private => write!(f, "private"),
+            NamespaceMode::Host => write!(f, "host"),
+        }
+    }
+}
+
+impl NamespaceMode {
+    pub fn is_host(&self) -> bool {
+        *self == NamespaceMode::Host
+    }
+}
+
+/// Namespaces `enter` joins, in the order they are joined. The user
+/// namespace comes last: joining the others needs the host's capabilities.
+const ENTERED_NAMESPACES: [(&str, libc::c_int); 7] = [
+    ("cgroup", libc::CLONE_NEWCGROUP),
+    ("uts", libc::CLONE_NEWUTS),
+    ("ipc", libc::CLONE_NEWIPC),
+    ("net", libc::CLONE_NEWNET),
+    ("pid", libc::CLONE_NEWPID),
+    ("mnt", libc::CLONE_NEWNS),
+    ("user", libc::CLONE_NEWUSER),
+];
+
+/// Makes `cmd` run inside the namespaces of the process `pid`, with its root
+/// and working directory, like `nsenter --all --root --wd`. The command runs
+/// in a fork to be part of the PID namespace, confined like the container's
+/// own process: in its cgroup, with its label, seccomp filter, capabilities
+/// and ids.
+pub fn enter(
+    pid: u32,
+    process: &ProcessSpec,
+    cmd: &mut Command,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = namespace_leader(pid);
+    let open =
+        |path: String| File::open(&path).map_err(|e| format!("Failed to open {}: {}", path, e));
+    // Those shared with the host are already ours, and the kernel refuses
+    // rejoining the user namespace.
+    let namespaces = ENTERED_NAMESPACES
+        .iter()
+        .filter(|(name, _)| {
+            inode(&format!("/proc/{}/ns/{}", pid, name))
+                != inode(&format!("/proc/self/ns/{}", name))
+        })
+        .map(|(name, flag)| Ok((open(format!("/proc/{}/ns/{}", pid, name))?, *flag)))
+        .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+    let root = open(format!("/proc/{}/root", pid))?;
+    let cwd = open(format!("/proc/{}/cwd", pid))?;
+
+    // Prepared up front, allocating after fork is not safe.
+    // That of the process itself, which for nested containers is a leaf
+    // below the container's group.
+    let cgroup_procs = process
+        .cgroup
+        .as_ref()
+        .map(|path| cgroup::process_cgroup(pid).unwrap_or_else(|| path.clone()))
+        .map(|path| CString::new(format!("{}/cgroup.procs", path)))
+        .transpose()?;
+    let caps_to_drop = process
+        .capabilities
+        .as_deref()
+        .map(capabilities::to_drop)
+        .unwrap_or_default();
+    let seccomp_filter = process
+        .seccomp
+        .as_ref()
+        .map(|profile| seccomp::compile(profile, process.capabilities.as_deref()))
+        .transpose()?;
+    let label_attr = process.label.as_ref().map(|label| {
+        let (paths, value) = label.exec_attr();
+        let paths: Vec<CString> = paths
+            .iter()
+            .filter_map(|path| CString::new(*path).ok())
+            .collect();
+        (paths, value)
+    });
+    let (uid, gid) = (process.uid, process.gid);
+    let groups: Vec<libc::gid_t> = process.additional_gids.clone();
+
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(cgroup_procs) = &cgroup_procs {
+                write_file(cgroup_procs, b"0")?;
+            }
+            for (namespace, flag) in &namespaces {
+                if libc::setns(namespace.as_raw_fd(), *flag) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                // Becomes the namespace's root, as the container's process
+                // did, rather than the unmapped host root.
+                if *flag == libc::CLONE_NEWUSER
+                    && (libc::setresgid(0, 0, 0) != 0 || libc::setresuid(0, 0, 0) != 0)
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if libc::fchdir(root.as_raw_fd()) != 0
+                || libc::chroot(c".".as_ptr()) != 0
+                || libc::fchdir(cwd.as_raw_fd()) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            match libc::fork() {
+                -1 => return Err(std::io::Error::last_os_error()),
+                0 => {}
+                child => wait_and_exit(child),
+            }
+            // Killing the waiting parent kills the command, and `kill_entered`
+            // whatever it started.
+            libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+            libc::setpgid(0, 0);
+
+            // In the same order as for the container's process. The label is
+            // written through the container's /proc, now this process's.
+            if let Some((paths, value)) = &label_attr {
+                let fd = paths
+                    .iter()
+                    .map(|path| libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC))
+                    .find(|fd| *fd >= 0)
+                    .ok_or_else(std::io::Error::last_os_error)?;
+                let written = libc::write(fd, value.as_ptr() as *const libc::c_void, value.len());
+                libc::close(fd);
+                if written != value.len() as isize {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(filter) = &seccomp_filter {
+                seccomp::install(filter)?;
+            }
+            if capabilities::apply(&caps_to_drop).is_err() {
+                return Err(std::io::Error::last_os_error());
+            }
+            if libc::setgroups(groups.len(), groups.as_ptr()) != 0
+                || libc::setgid(gid) != 0
+                || libc::setuid(uid) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+/// The process whose namespaces a container's are. With a PID namespace of
+/// its own, the container's first process waits outside it for the one it
+/// forked inside, which is entered instead.
+fn namespace_leader(pid: u32) -> u32 {
+    if inode(&format!("/proc/{}/ns/pid", pid))
+        == inode(&format!("/proc/{}/ns/pid_for_children", pid))
+    {
+        return pid;
+    }
+    first_child(pid).unwrap_or(pid)
+}
+
+/// Kills a command run with `enter`, given the process spawned for it, along
+/// with every process it started.
+pub fn kill_entered(pid: u32) {
+    if let Some(command) = first_child(pid) {
+        unsafe { libc::kill(-(command as libc::pid_t), libc::SIGKILL) };
+    }
+    unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+}
+
+fn first_child(pid: u32) -> Option<u32> {
+    fs::read_to_string(format!("/proc/{}/task/{}/children", pid, pid))
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Inode of a namespace, which tells processes sharing it apart.
+pub fn inode(path: &str) -> Option<u64> {
+    fs::metadata(path).ok().map(|metadata| metadata.ino())
+}
@@ -0,0 +1,131 @@
+use crate::actions::container;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fmt, net::Ipv4Addr, str::FromStr};
+
+/// How a container is attached to the network.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkMode {
+    /// Own network namespace attached to the rustainer0 bridge.
+    #[default]
+    Bridge,
+    /// Shares the host's network namespace.
+    Host,
+    /// Own network namespace with only a loopback interface.
+    None,
+}
+
+impl FromStr for NetworkMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "bridge" => Ok(NetworkMode::Bridge),
+            "host" => Ok(NetworkMode::Host),
+            "none" => Ok(NetworkMode::None),
+            _ => Err(format!(
+                "Invalid network mode: {}. Expected bridge, host or none",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for NetworkMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkMode::Bridge => write!(f, "bridge"),
+            NetworkMode::Host => write!(f, "host"),
+            NetworkMode::None => write!(f, "none"),
+        }
+    }
+}
+
+impl NetworkMode {
+    /// Whether the container gets a network namespace of its own.
+    pub fn has_namespace(&self) -> bool {
+        *self != NetworkMode::Host
+    }
+}
+
+/// Address of the rustainer0 bridge, which is every bridged container's gateway.
+pub const BRIDGE_GATEWAY: &str = "172.19.0.1";
+pub const BRIDGE_SUBNET: &str = "172.19.0.0/16";
+const SUBNET_BASE: Ipv4Addr = Ipv4Addr::new(172, 19, 0, 0);
+const SUBNET_PREFIX_LEN: u32 = 16;
+
+/// Checks that a requested static IP is a usable host address on the bridge
+/// subnet.
+fn validate_static_ip(ip: &str) -> Result<Ipv4Addr, Box<dyn std::error::Error>> {
+    let ip: Ipv4Addr = ip
+        .parse()
+        .map_err(|_| format!("Invalid IPv4 address: {}", ip))?;
+
+    let mask = u32::MAX << (32 - SUBNET_PREFIX_LEN);
+    let base = u32::from(SUBNET_BASE);
+    let host = u32::from(ip);
+
+    if host & mask != base {
+        return Err(format!("IP {} is not in the bridge subnet {}", ip, BRIDGE_SUBNET).into());
+    }
+    if host == base || host == base | !mask || ip.to_string() == BRIDGE_GATEWAY {
+        return Err(format!("IP {} is reserved on the bridge subnet", ip).into());
+    }
+
+    Ok(ip)
+}
+
+/// Checks a `--mac-address` value: six colon separated hex octets forming a
+/// unicast address, which is all a veth interface accepts.
+pub fn validate_mac_address(mac_address: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let octets: Vec<&str> = mac_address.split(':').collect();
+    let parsed: Vec<u8> = octets
+        .iter()
+        .filter(|octet| octet.len() == 2)
+        .filter_map(|octet| u8::from_str_radix(octet, 16).ok())
+        .collect();
+
+    if octets.len() != 6 || parsed.len() != 6 {
+        return Err(format!("Invalid MAC address: {}", mac_address).into());
+    }
+    if parsed[0] & 1 != 0 {
+        return Err(format!("MAC address {} is a multicast address", mac_address).into());
+    }
+
+    Ok(())
+}
+
+/// Picks the container's bridge IP: the requested one if it is free, otherwise
+/// the lowest address not held by another container. Callers hold
+/// `store::lock_network` until the container's metadata records the IP.
+pub fn allocate_ip(requested: Option<&str>) -> Result<Ipv4Addr, Box<dyn std::error::Error>> {
+    let in_use = addresses_in_use()?;
+
+    if let Some(requested) = requested {
+        let ip = validate_static_ip(requested)?;
+        if in_use.contains(&ip) {
+            return Err(format!("IP {} is already in use by another container", ip).into());
+        }
+        return Ok(ip);
+    }
+
+    let base = u32::from(SUBNET_BASE);
+    let broadcast = base | !(u32::MAX << (32 - SUBNET_PREFIX_LEN));
+    // .0.0 is the network address and .0.1 the gateway.
+    (base + 2..broadcast)
+        .map(Ipv4Addr::from)
+        .find(|ip| !in_use.contains(ip))
+        .ok_or_else(|| format!("No free IP addresses left in {}", BRIDGE_SUBNET).into())
+}
+
+/// Addresses of containers whose network namespace still exists.
+fn addresses_in_use() -> Result<HashSet<Ipv4Addr>, Box<dyn std::error::Error>> {
+    let namespaces: HashSet<String> = container::list_network_namespaces()?.into_iter().collect();
+
+    Ok(container::list_container_ids()?
+        .iter()
+        .filter(|id| namespaces.contains(*id))
+        .filter_map(|id| container::load_metadata(id))
+        .filter_map(|metadata| metadata.get("ip")?.as_str()?.parse().ok())
+        .collect())
+}
@@ -0,0 +1,397 @@
+use std::{
+    fs,
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::actions::{
+    cgroup, container,
+    run::{ProcessSpec, DEFAULT_SHM_SIZE},
+    seccomp,
+    security::ProcessLabel,
+    userns::IdMapping,
+};
+
+/// The version of the runtime spec the generated config.json follows.
+const OCI_VERSION: &str = "1.0.2";
+/// Devices runtimes create in every container on their own.
+const RUNTIME_DEVICES: [&str; 6] = [
+    "/dev/null",
+    "/dev/zero",
+    "/dev/full",
+    "/dev/random",
+    "/dev/urandom",
+    "/dev/tty",
+];
+
+/// A pre-built OCI bundle run in place of an image.
+#[derive(Debug)]
+pub struct Bundle {
+    pub path: PathBuf,
+    pub rootfs: PathBuf,
+    pub spec: BundleSpec,
+}
+
+/// The parts of a bundle's config.json rustainer runs it with, the rest is
+/// set up from its own options.
+#[derive(Debug, Deserialize)]
+pub struct BundleSpec {
+    #[serde(default)]
+    pub process: BundleProcess,
+    pub root: BundleRoot,
+    #[serde(default)]
+    pub hostname: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BundleProcess {
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub cwd: String,
+    #[serde(default)]
+    pub user: BundleUser,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleUser {
+    #[serde(default)]
+    pub uid: u32,
+    #[serde(default)]
+    pub gid: u32,
+    #[serde(default)]
+    pub additional_gids: Vec<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BundleRoot {
+    pub path: String,
+    #[serde(default)]
+    pub readonly: bool,
+}
+
+/// Reads the bundle at `path`, a directory with a config.json and the
+/// rootfs it points to.
+pub fn load_bundle(path: &str) -> Result<Bundle, Box<dyn std::error::Error>> {
+    let path =
+        fs::canonicalize(path).map_err(|e| format!("Failed to open bundle {}: {}", path, e))?;
+    let config_path = path.join("config.json");
+    let content = fs::read_to_string(&config_path)
+        .map_err(|e| format!("Failed to read {}: {}", config_path.display(), e))?;
+    let spec: BundleSpec = serde_json::from_str(&content)
+        .map_err(|e| format!("Invalid bundle config {}: {}", config_path.display(), e))?;
+
+    // Relative root paths are relative to the bundle.
+    let rootfs = path.join(&spec.root.path);
+    if !rootfs.is_dir() {
+        return Err(format!("Bundle rootfs {} is not a directory", rootfs.display()).into());
+    }
+
+    Ok(Bundle { path, rootfs, spec })
+}
+
+/// Checks that `runtime` is an OCI runtime that can be run, such as runc or
+/// crun.
+pub fn check_runtime(runtime: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let output = Command::new(runtime)
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("OCI runtime {} not found: {}", runtime, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "OCI runtime {} failed: {}",
+            runtime,
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Writes the container's config.json next to its rootfs, making the
+/// container directory an OCI bundle, and returns the command running it
+/// with `runtime`. The runtime stays in the foreground like the container
+/// process itself would, so supervision and signal forwarding are the same.
+pub fn runtime_command(
+    runtime: &str,
+    container_id: &str,
+    rootfs_path: &str,
+    process: &ProcessSpec,
+) -> Result<Command, Box<dyn std::error::Error>> {
+    let bundle = fs::canonicalize(
+        Path::new(rootfs_path)
+            .parent()
+            .ok_or("The rootfs has no parent directory")?,
+    )?;
+    let spec = generate_spec(container_id, process)?;
+    fs::write(
+        bundle.join("config.json"),
+        serde_json::to_string_pretty(&spec)?,
+    )?;
+
+    let mut cmd = Command::new(runtime);
+    cmd.arg("run")
+        .arg("--bundle")
+        .arg(&bundle)
+        .arg(container_id);
+    Ok(cmd)
+}
+
+/// Removes what the runtime keeps about a container it was killed in the
+/// middle of running.
+pub fn delete(container_id: &str) {
+    let runtime = container::load_metadata(container_id).and_then(|metadata| {
+        metadata
+            .get("process")?
+            .get("runtime")?
+            .as_str()
+            .map(String::from)
+    });
+    if let Some(runtime) = runtime {
+        let _ = Command::new(runtime)
+            .args(["delete", "--force", container_id])
+            .output();
+    }
+}
+
+/// The OCI runtime spec equivalent of how rustainer itself sets up the
+/// process.
+fn generate_spec(
+    container_id: &str,
+    process: &ProcessSpec,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let capabilities = match &process.capabilities {
+        Some(capabilities) => capabilities.clone(),
+        None => caps::all().iter().map(|cap| cap.to_string()).collect(),
+    };
+    let mut process_spec = json!({
+        "terminal": false,
+        "user": {
+            "uid": process.uid,
+            "gid": process.gid,
+            "additionalGids": process.additional_gids,
+        },
+        "args": process.args,
+        "env": process
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>(),
+        "cwd": process.cwd,
+        "capabilities": {
+            "bounding": capabilities,
+            "effective": capabilities,
+            "permitted": capabilities,
+        },
+    });
+    if let Some(adj) = process.oom_score_adj {
+        process_spec["oomScoreAdj"] = json!(adj);
+    }
+    match &process.label {
+        Some(ProcessLabel::AppArmor(profile)) => process_spec["apparmorProfile"] = json!(profile),
+        Some(ProcessLabel::SELinux(context)) => process_spec["selinuxLabel"] = json!(context),
+        None => {}
+    }
+
+    let mut namespaces = vec![json!({ "type": "mount" })];
+    for (namespace, mode) in [
+        ("pid", process.pid_mode),
+        ("ipc", process.ipc_mode),
+        ("uts", process.uts_mode),
+    ] {
+        if !mode.is_host() {
+            namespaces.push(json!({ "type": namespace }));
+        }
+    }
+    if process.network.has_namespace() {
+        namespaces.push(json!({
+            "type": "network",
+            "path": format!("/run/netns/{}", container_id),
+        }));
+    }
+    let cgroup_namespace = process.cgroup.is_some() && !process.privileged;
+    if cgroup_namespace {
+        namespaces.push(json!({ "type": "cgroup" }));
+    }
+
+    let mut linux = json!({
+        "namespaces": namespaces,
+        // rustainer does not restrict devices, only what is bound into /dev.
+        "resources": { "devices": [{ "allow": true, "access": "rwm" }] },
+        "sysctl": process
+            .sysctls
+            .iter()
+            .map(|sysctl| (sysctl.key.clone(), json!(sysctl.value)))
+            .collect::<serde_json::Map<_, _>>(),
+    });
+    if let Some(mappings) = &process.userns {
+        linux["namespaces"]
+            .as_array_mut()
+            .ok_or("Invalid namespaces")?
+            .push(json!({ "type": "user" }));
+        linux["uidMappings"] = id_mappings(&mappings.uids);
+        linux["gidMappings"] = id_mappings(&mappings.gids);
+    }
+    if let Some(path) = &process.cgroup {
+        linux["cgroupsPath"] = json!(cgroup::relative_path(path));
+    }
+    if let Some(profile) = &process.seccomp {
+        linux["seccomp"] = seccomp::to_oci(profile, process.capabilities.as_deref());
+    }
+    let devices = process
+        .devices
+        .iter()
+        .filter(|device| !RUNTIME_DEVICES.contains(&device.as_str()))
+        .map(|device| device_spec(device))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !devices.is_empty() {
+        linux["devices"] = json!(devices);
+    }
+
+    let mut spec = json!({
+        "ociVersion": OCI_VERSION,
+        "process": process_spec,
+        "root": { "path": "rootfs", "readonly": process.read_only },
+        "mounts": mounts(process, cgroup_namespace),
+        "linux": linux,
+    });
+    if !process.uts_mode.is_host() {
+        spec["hostname"] = json!(process.hostname);
+    }
+
+    Ok(spec)
+}
+
+/// The same mounts the built-in runtime sets up, in the same order.
+fn mounts(process: &ProcessSpec, cgroup_namespace: bool) -> Vec<Value> {
+    let mut mounts = vec![json!({
+        "destination": "/proc",
+        "type": "proc",
+        "source": "proc",
+        "options": ["nosuid", "noexec", "nodev"],
+    })];
+    if !process.privileged {
+        mounts.push(json!({
+            "destination": "/dev",
+            "type": "tmpfs",
+            "source": "tmpfs",
+            "options": ["nosuid", "strictatime", "mode=755", "size=65536k"],
+        }));
+        mounts.push(json!({
+            "destination": "/dev/pts",
+            "type": "devpts",
+            "source": "devpts",
+            "options": ["nosuid", "noexec", "newinstance", "ptmxmode=0666", "mode=0620"],
+        }));
+        if process.ipc_mode.is_host() {
+            mounts.push(json!({
+                "destination": "/dev/shm",
+                "type": "bind",
+                "source": "/dev/shm",
+                "options": ["rbind"],
+            }));
+        } else {
+            mounts.push(json!({
+                "destination": "/dev/shm",
+                "type": "tmpfs",
+                "source": "shm",
+                "options": [
+                    "nosuid",
+                    "noexec",
+                    "nodev",
+                    "mode=1777",
+                    format!("size={}", process.shm_size.unwrap_or(DEFAULT_SHM_SIZE)),
+                ],
+            }));
+        }
+        mounts.push(json!({
+            "destination": "/sys",
+            "type": "sysfs",
+            "source": "sysfs",
+            "options": ["nosuid", "noexec", "nodev", "ro"],
+        }));
+    }
+    if cgroup_namespace {
+        mounts.push(json!({
+            "destination": "/sys/fs/cgroup",
+            "type": "cgroup2",
+            "source": "cgroup",
+            "options": ["nosuid", "noexec", "nodev", "ro"],
+        }));
+    }
+    for mount in &process.mounts {
+        let mut options = vec![
+            if mount.recursive { "rbind" } else { "bind" }.to_string(),
+            mount
+                .propagation
+                .map_or("rprivate".to_string(), |propagation| {
+                    propagation.to_string()
+                }),
+        ];
+        if mount.readonly {
+            options.push("ro".to_string());
+        }
+        mounts.push(json!({
+            "destination": mount.target,
+            "type": "bind",
+            "source": mount.source,
+            "options": options,
+        }));
+    }
+    for tmpfs in &process.tmpfs {
+        let mut options = vec!["nosuid", "nodev"];
+        options.extend(tmpfs.options.split(',').filter(|option| !option.is_empty()));
+        mounts.push(json!({
+            "destination": tmpfs.target,
+            "type": "tmpfs",
+            "source": "tmpfs",
+            "options": options,
+        }));
+    }
+
+    mounts
+}
+
+fn id_mappings(mappings: &[IdMapping]) -> Value {
+    mappings
+        .iter()
+        .map(|mapping| {
+            json!({
+                "containerID": mapping.container_id,
+                "hostID": mapping.host_id,
+                "size": mapping.size,
+            })
+        })
+        .collect()
+}
+
+/// A host device node as an entry of `linux.devices`.
+fn device_spec(path: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    let metadata =
+        fs::metadata(path).map_err(|e| format!("Failed to read device {}: {}", path, e))?;
+    let device_type = if metadata.file_type().is_char_device() {
+        "c"
+    } else if metadata.file_type().is_block_device() {
+        "b"
+    } else {
+        return Err(format!("{} is not a device", path).into());
+    };
+
+    Ok(json!({
+        "path": path,
+        "type": device_type,
+        "major": libc::major(metadata.rdev()),
+        "minor": libc::minor(metadata.rdev()),
+        "fileMode": metadata.mode() & 0o777,
+        "uid": metadata.uid(),
+        "gid": metadata.gid(),
+    }))
+}
@@ -0,0 +1,55 @@
+use crate::actions::{
+    container, rm, run, storage,
+    store::{self, LockMode},
+};
+use std::time::Duration;
+
+pub async fn prune_containers(until: Option<Duration>) -> Result<(), Box<dyn std::error::Error>> {
+    let now = container::now_secs();
+    let container_ids = container::list_container_ids()?;
+    let mut removed = Vec::new();
+
+    for container_id in &container_ids {
+        if container::is_running(container_id) {
+            continue;
+        }
+
+        if let Some(until) = until {
+            let age = now.saturating_sub(container::created_at(container_id));
+            if age < until.as_secs() {
+                continue;
+            }
+        }
+
+        rm::remove_container(container_id, false, false).await?;
+        removed.push(container_id.clone());
+    }
+
+    for namespace in container::list_network_namespaces()? {
+        if container::looks_like_container_id(&namespace) && !container_ids.contains(&namespace) {
+            run::cleanup_container_networking(&namespace)?;
+        }
+    }
+
+    if removed.is_empty() {
+        println!("No stopped containers to prune");
+    } else {
+        println!("🧹 Deleted {} container(s):", removed.len());
+        for container_id in removed {
+            println!("{}", container::short_id(&container_id));
+        }
+    }
+
+    let layers = {
+        let _lock = store::lock_store(LockMode::Exclusive)?;
+        storage::prune_layers()?
+    };
+    if !layers.is_empty() {
+        println!("🧹 Deleted {} unused layer(s):", layers.len());
+        for digest in layers {
+            println!("{}", digest);
+        }
+    }
+
+    Ok(())
+}
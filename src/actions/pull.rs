@@ -1,18 +1,86 @@
-use crate::actions::types::{AuthToken, ImageManifest, ManifestResponse};
+use crate::actions::{
+    container, images, storage,
+    store::{self, LockMode},
+    types::{AuthToken, ImageManifest, Layer, ManifestResponse, Platform},
+    utils, wasm,
+};
 use reqwest::Client;
-use std::fs;
-use tokio::io::AsyncWriteExt;
+use sha2::{Digest, Sha256};
+use std::{fmt, fs, io::Read, str::FromStr, sync::Arc};
+use tokio::{io::AsyncWriteExt, sync::Semaphore, task::JoinHandle};
+
+/// Layers downloaded at once, as many as registries comfortably serve.
+const MAX_CONCURRENT_DOWNLOADS: usize = 3;
+/// Per-image bookkeeping kept next to the manifest, such as its platform.
+pub const IMAGE_METADATA_FILE: &str = "metadata.json";
+
+/// When `run` pulls the image before creating the container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PullPolicy {
+    Always,
+    Missing,
+    Never,
+}
+
+impl FromStr for PullPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "always" => Ok(PullPolicy::Always),
+            "missing" => Ok(PullPolicy::Missing),
+            "never" => Ok(PullPolicy::Never),
+            _ => Err(format!(
+                "Invalid pull policy: {}. Expected always, missing or never",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PullPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PullPolicy::Always => write!(f, "always"),
+            PullPolicy::Missing => write!(f, "missing"),
+            PullPolicy::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// Pulls `image_tag` for `platform` (`os/arch[/variant]`), defaulting to the
+/// host platform.
+pub async fn pull_image(
+    image_tag: &str,
+    platform: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let wanted = match platform {
+        Some(platform) => platform.parse::<Platform>()?,
+        None => Platform::host(),
+    };
+    let explicit_platform = platform.is_some();
 
-pub async fn pull_image(image_tag: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("🔄 Pulling image: {}", image_tag);
 
     let (repository, tag) = parse_image_tag(image_tag);
+    if is_digest(&tag) {
+        validate_digest(&tag)?;
+    }
 
     let client = Client::new();
 
     let token = get_auth_token(&client, &repository).await?;
 
-    let manifest_response = get_manifest(&client, &repository, &tag, &token).await?;
+    let (manifest_response, manifest_digest) =
+        get_manifest(&client, &repository, &tag, &token).await?;
+    // Content addressed pulls must get exactly the content that was asked for.
+    if is_digest(&tag) && manifest_digest != tag {
+        return Err(format!(
+            "Manifest digest mismatch: requested {}, registry returned {}",
+            tag, manifest_digest
+        )
+        .into());
+    }
 
     let image_manifest = match manifest_response {
         ManifestResponse::V2(manifest) => {
@@ -22,40 +90,61 @@ pub async fn pull_image(image_tag: &str) -> Result<(), Box<dyn std::error::Error
         ManifestResponse::List(manifest_list) => {
             println!("📋 Found manifest list, selecting platform...");
 
+            // Wasm builds run anywhere, so they do when there is no native one.
             let selected_manifest = manifest_list
                 .manifests
                 .iter()
-                .find(|m| {
-                    if let Some(platform) = &m.platform {
-                        platform.os == "linux" && platform.architecture == "amd64"
-                    } else {
-                        false
-                    }
+                .find(|m| m.platform.as_ref().is_some_and(|p| p.satisfies(&wanted)))
+                .or_else(|| {
+                    manifest_list
+                        .manifests
+                        .iter()
+                        .filter(|_| !explicit_platform)
+                        .find(|m| m.platform.as_ref().is_some_and(Platform::is_wasm))
                 })
-                .or_else(|| manifest_list.manifests.first())
-                .ok_or("No suitable manifest found in manifest list")?;
+                .ok_or_else(|| {
+                    format!(
+                        "No matching manifest for {} in the manifest list entries",
+                        wanted
+                    )
+                })?;
 
             println!(
-                "📋 Selected platform: {}/{}",
+                "📋 Selected platform: {}",
                 selected_manifest
                     .platform
                     .as_ref()
-                    .map(|p| p.os.as_str())
-                    .unwrap_or("unknown"),
-                selected_manifest
-                    .platform
-                    .as_ref()
-                    .map(|p| p.architecture.as_str())
-                    .unwrap_or("unknown")
+                    .map(|p| p.to_string())
+                    .unwrap_or_else(|| "unknown".to_string())
             );
 
             get_manifest_by_digest(&client, &repository, &selected_manifest.digest, &token).await?
         }
     };
 
-    let image_dir = format!("./images/{}/{}", repository.replace('/', "_"), tag);
+    for layer in &image_manifest.layers {
+        if !wasm::is_module_layer(layer) {
+            storage::check_decompressor(layer)?;
+        }
+    }
+
+    let store_lock = store::lock_store(LockMode::Shared)?;
+    let image_lock = store::lock_image(&repository, &tag, LockMode::Exclusive)?;
+    let image_dir = store::image_dir(&repository, &tag);
     fs::create_dir_all(&image_dir)?;
 
+    // With stargz-store, eStargz layers are fetched as containers read them.
+    let lazy_reference = format!("docker.io/{}", format_image_reference(&repository, &tag));
+    let mut lazy = Vec::new();
+    if store::config().lazy_layer_store.is_some() {
+        for layer in image_manifest.layers.iter().filter(|l| l.is_estargz()) {
+            match storage::link_lazy_layer(&lazy_reference, layer) {
+                Ok(_) => lazy.push(layer.digest.clone()),
+                Err(e) => println!("⚠️ Warning: {}, downloading it instead", e),
+            }
+        }
+    }
+
     println!("📥 Downloading config...");
     download_blob(
         &client,
@@ -66,45 +155,221 @@ pub async fn pull_image(image_tag: &str) -> Result<(), Box<dyn std::error::Error
     )
     .await?;
 
-    for (i, layer) in image_manifest.layers.iter().enumerate() {
-        println!(
-            "📥 Downloading layer {}/{} ({})",
-            i + 1,
-            image_manifest.layers.len(),
-            format_size(layer.size)
-        );
-        download_blob(&client, &repository, &layer.digest, &token, &image_dir).await?;
+    // Layers download side by side, and each is extracted into the layer
+    // cache once it and those below it are in, while the rest download.
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_DOWNLOADS));
+    let layer_count = image_manifest.layers.len();
+    let downloads: Vec<_> = image_manifest
+        .layers
+        .iter()
+        .enumerate()
+        .map(|(i, layer)| {
+            let client = client.clone();
+            let repository = repository.clone();
+            let token = token.clone();
+            let image_dir = image_dir.clone();
+            let layer = layer.clone();
+            let semaphore = semaphore.clone();
+            let lazy = lazy.contains(&layer.digest);
+            tokio::spawn(async move {
+                if lazy {
+                    println!("⚡ Layer {}/{} is pulled lazily", i + 1, layer_count);
+                    return Ok(());
+                }
+                let _permit = semaphore.acquire_owned().await.map_err(|e| e.to_string())?;
+                println!(
+                    "📥 Downloading layer {}/{} ({})",
+                    i + 1,
+                    layer_count,
+                    format_size(layer.size)
+                );
+                download_blob(&client, &repository, &layer.digest, &token, &image_dir)
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+        })
+        .collect();
+
+    let mut extraction: Option<JoinHandle<Result<(), String>>> = None;
+    for (i, (layer, download)) in image_manifest.layers.iter().zip(downloads).enumerate() {
+        download.await??;
+        if wasm::is_module_layer(layer) || lazy.contains(&layer.digest) {
+            continue;
+        }
+        verify_compression(&image_dir, layer)?;
+
+        if let Some(previous) = extraction.take() {
+            previous.await??;
+        }
+        println!("📦 Extracting layer {}/{}", i + 1, layer_count);
+        let image_dir = image_dir.clone();
+        let layer = layer.clone();
+        extraction = Some(tokio::task::spawn_blocking(move || {
+            storage::prepare_layer(&image_dir, &layer)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }));
+    }
+    if let Some(previous) = extraction {
+        previous.await??;
     }
 
     let manifest_path = format!("{}/manifest.json", image_dir);
     let manifest_json = serde_json::to_string_pretty(&image_manifest)?;
     fs::write(manifest_path, manifest_json)?;
 
+    // Single-platform images only reveal their platform in the config.
+    let platform =
+        config_platform(&image_dir, &image_manifest.config.digest).unwrap_or(wanted.clone());
+    if !platform.satisfies(&wanted) && (explicit_platform || !platform.is_wasm()) {
+        println!(
+            "⚠️ Warning: The image's platform ({}) does not match the requested platform ({})",
+            platform, wanted
+        );
+    }
+    let mut metadata = serde_json::json!({
+        "platform": platform.to_string(),
+        "digest": manifest_digest,
+        "last_used": container::now_secs(),
+    });
+    if !lazy.is_empty() {
+        metadata["lazy"] = serde_json::json!({
+            "reference": lazy_reference,
+            "layers": lazy,
+        });
+    }
+    save_image_metadata(&image_dir, &metadata)?;
+
+    crate::actions::events::emit(
+        "image",
+        "pull",
+        &format_image_reference(&repository, &tag),
+        serde_json::json!({ "digest": image_manifest.config.digest }),
+    );
+
     println!("✅ Successfully pulled {}", image_tag);
-    Ok(())
+    println!("Digest: {}", manifest_digest);
+
+    drop(image_lock);
+    drop(store_lock);
+    images::enforce_size_limit(&image_dir)
 }
 
-pub fn parse_image_tag(image_tag: &str) -> (String, String) {
-    if let Some(pos) = image_tag.rfind(':') {
-        let repository = image_tag[..pos].to_string();
-        let tag = image_tag[pos + 1..].to_string();
+fn config_platform(image_dir: &str, config_digest: &str) -> Option<Platform> {
+    let config_path = format!("{}/{}", image_dir, config_digest.replace("sha256:", ""));
+    serde_json::from_str(&fs::read_to_string(config_path).ok()?).ok()
+}
+
+/// Reads an image's metadata file, an empty object if it has none.
+pub fn load_image_metadata(image_dir: &str) -> serde_json::Value {
+    fs::read_to_string(format!("{}/{}", image_dir, IMAGE_METADATA_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_else(|| serde_json::json!({}))
+}
+
+pub fn save_image_metadata(
+    image_dir: &str,
+    metadata: &serde_json::Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    utils::write_atomic(
+        &format!("{}/{}", image_dir, IMAGE_METADATA_FILE),
+        &serde_json::to_string_pretty(metadata)?,
+    )
+}
+
+/// The layers of an image pulled lazily, which have no blob.
+pub fn lazy_layers(image_dir: &str) -> Vec<String> {
+    serde_json::from_value(load_image_metadata(image_dir)["lazy"]["layers"].clone())
+        .unwrap_or_default()
+}
 
-        let full_repository = if repository.contains('/') {
-            repository
-        } else {
-            format!("library/{}", repository)
-        };
+/// The reference stargz-store serves a lazily pulled layer of the image
+/// under, `None` for layers pulled whole.
+pub fn lazy_reference(image_dir: &str, digest: &str) -> Option<String> {
+    let metadata = load_image_metadata(image_dir);
+    let lazy = &metadata["lazy"];
+    if !lazy["layers"]
+        .as_array()?
+        .iter()
+        .any(|layer| layer.as_str() == Some(digest))
+    {
+        return None;
+    }
+    lazy["reference"].as_str().map(String::from)
+}
+
+/// Records that a container was made from the image, which keeps it from
+/// being evicted before images used less recently. Failing to record it
+/// never fails the run.
+pub fn mark_image_used(image_dir: &str) {
+    let mut metadata = load_image_metadata(image_dir);
+    metadata["last_used"] = container::now_secs().into();
+    let _ = save_image_metadata(image_dir, &metadata);
+}
 
-        return (full_repository, tag);
+/// The platform of a stored image. Images stored before platforms were
+/// recorded fall back to the platform in their config.
+pub fn image_platform(image_dir: &str) -> Option<Platform> {
+    if let Some(platform) = load_image_metadata(image_dir)
+        .get("platform")
+        .and_then(|v| v.as_str())
+    {
+        return platform.parse().ok();
     }
 
-    let full_repository = if image_tag.contains('/') {
-        image_tag.to_string()
+    let manifest: ImageManifest =
+        serde_json::from_str(&fs::read_to_string(format!("{}/manifest.json", image_dir)).ok()?)
+            .ok()?;
+    config_platform(image_dir, &manifest.config.digest)
+}
+
+/// Splits an image reference into its full repository and either its tag or,
+/// for `name@sha256:...` references, its digest.
+pub fn parse_image_tag(image_tag: &str) -> (String, String) {
+    let (name, digest) = match image_tag.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (image_tag, None),
+    };
+
+    let (repository, tag) = match name.rfind(':') {
+        Some(pos) => (&name[..pos], &name[pos + 1..]),
+        None => (name, "latest"),
+    };
+
+    let full_repository = if repository.contains('/') {
+        repository.to_string()
     } else {
-        format!("library/{}", image_tag)
+        format!("library/{}", repository)
     };
 
-    (full_repository, "latest".to_string())
+    // A digest pins the content, so any tag next to it is informational.
+    (full_repository, digest.unwrap_or(tag).to_string())
+}
+
+/// Whether a reference returned by `parse_image_tag` is a digest.
+pub fn is_digest(reference: &str) -> bool {
+    reference.starts_with("sha256:")
+}
+
+pub fn format_image_reference(repository: &str, reference: &str) -> String {
+    if is_digest(reference) {
+        format!("{}@{}", repository, reference)
+    } else {
+        format!("{}:{}", repository, reference)
+    }
+}
+
+fn validate_digest(digest: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let hex = digest.strip_prefix("sha256:").unwrap_or_default();
+    if hex.len() != 64
+        || !hex
+            .chars()
+            .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+    {
+        return Err(format!("Invalid image digest: {}", digest).into());
+    }
+    Ok(())
 }
 
 async fn get_auth_token(
@@ -126,7 +391,7 @@ async fn get_manifest(
     repository: &str,
     tag: &str,
     token: &str,
-) -> Result<ManifestResponse, Box<dyn std::error::Error>> {
+) -> Result<(ManifestResponse, String), Box<dyn std::error::Error>> {
     let manifest_url = format!(
         "https://registry-1.docker.io/v2/{}/manifests/{}",
         repository, tag
@@ -137,7 +402,7 @@ async fn get_manifest(
         .header("Authorization", format!("Bearer {}", token))
         .header(
             "Accept",
-            "application/vnd.docker.distribution.manifest.v2+json,application/vnd.docker.distribution.manifest.list.v2+json",
+            "application/vnd.docker.distribution.manifest.v2+json,application/vnd.docker.distribution.manifest.list.v2+json,application/vnd.oci.image.manifest.v1+json,application/vnd.oci.image.index.v1+json",
         )
         .send()
         .await?;
@@ -146,8 +411,11 @@ async fn get_manifest(
         return Err(format!("Failed to get manifest: {}", response.status()).into());
     }
 
-    let manifest: ManifestResponse = response.json().await?;
-    Ok(manifest)
+    // The digest is computed over the exact bytes the registry served.
+    let body = response.bytes().await?;
+    let digest = format!("sha256:{:x}", Sha256::digest(&body));
+    let manifest: ManifestResponse = serde_json::from_slice(&body)?;
+    Ok((manifest, digest))
 }
 
 async fn get_manifest_by_digest(
@@ -166,7 +434,7 @@ async fn get_manifest_by_digest(
         .header("Authorization", format!("Bearer {}", token))
         .header(
             "Accept",
-            "application/vnd.docker.distribution.manifest.v2+json",
+            "application/vnd.docker.distribution.manifest.v2+json,application/vnd.oci.image.manifest.v1+json",
         )
         .send()
         .await?;
@@ -211,6 +479,24 @@ async fn download_blob(
     Ok(())
 }
 
+/// Checks that a downloaded layer is compressed the way its media type says,
+/// so it won't fail only once a container is created from it.
+fn verify_compression(image_dir: &str, layer: &Layer) -> Result<(), Box<dyn std::error::Error>> {
+    let compression = layer.compression()?;
+    let mut magic = vec![0; compression.magic().len()];
+    let path = format!("{}/{}", image_dir, layer.digest.replace("sha256:", ""));
+    fs::File::open(path)?.read_exact(&mut magic)?;
+    if magic != compression.magic() {
+        return Err(format!(
+            "Layer {} is not {} as its media type {} says",
+            layer.digest, compression, layer.media_type
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
 fn format_size(size: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
     let mut size = size as f64;
@@ -0,0 +1,107 @@
+use std::{fs, io, os::fd::AsRawFd, path::Path};
+
+use crate::actions::container;
+
+/// Project ids handed out to containers start here, above those set up by
+/// hand in /etc/projid.
+const FIRST_PROJECT_ID: u32 = 100_000;
+/// Project quotas, missing from libc.
+const PRJQUOTA: libc::c_int = 2;
+const FS_IOC_FSGETXATTR: libc::c_ulong = 0x801c581f;
+const FS_IOC_FSSETXATTR: libc::c_ulong = 0x401c5820;
+/// Files created in a directory with it join the directory's project.
+const FS_XFLAG_PROJINHERIT: u32 = 0x200;
+/// The unit of block limits in `dqblk`.
+const QUOTA_BLOCK_SIZE: u64 = 1024;
+
+/// `struct fsxattr` of linux/fs.h.
+#[repr(C)]
+#[derive(Default)]
+struct FsXattr {
+    xflags: u32,
+    extsize: u32,
+    nextents: u32,
+    projid: u32,
+    cowextsize: u32,
+    pad: [u8; 8],
+}
+
+/// Limits what is written under `dir` to `bytes`, making it a project of
+/// its own with a block limit. The filesystem needs project quotas, as XFS
+/// mounted with `pquota` or ext4 with `prjquota` have.
+pub fn limit(dir: &Path, bytes: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let project_id = next_project_id();
+    let file = fs::File::open(dir)?;
+
+    let mut attr = FsXattr::default();
+    if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FSGETXATTR, &mut attr) } != 0 {
+        return Err(unsupported(dir, io::Error::last_os_error()));
+    }
+    attr.projid = project_id;
+    attr.xflags |= FS_XFLAG_PROJINHERIT;
+    if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FSSETXATTR, &attr) } != 0 {
+        return Err(unsupported(dir, io::Error::last_os_error()));
+    }
+
+    let blocks = bytes.div_ceil(QUOTA_BLOCK_SIZE);
+    let mut quota = libc::dqblk {
+        dqb_bhardlimit: blocks,
+        dqb_bsoftlimit: blocks,
+        dqb_curspace: 0,
+        dqb_ihardlimit: 0,
+        dqb_isoftlimit: 0,
+        dqb_curinodes: 0,
+        dqb_btime: 0,
+        dqb_itime: 0,
+        dqb_valid: libc::QIF_BLIMITS,
+    };
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_quotactl_fd,
+            file.as_raw_fd(),
+            libc::QCMD(libc::Q_SETQUOTA, PRJQUOTA),
+            project_id,
+            &mut quota,
+        )
+    };
+    if result != 0 {
+        return Err(unsupported(dir, io::Error::last_os_error()));
+    }
+
+    Ok(())
+}
+
+/// The project id after those of the existing containers' writable
+/// directories.
+fn next_project_id() -> u32 {
+    let container_ids = container::list_container_ids().unwrap_or_default();
+    container_ids
+        .iter()
+        .flat_map(|container_id| {
+            let container_dir = container::container_dir(container_id);
+            [
+                format!("{}/upper", container_dir),
+                format!("{}/rootfs", container_dir),
+            ]
+        })
+        .filter_map(|dir| project_id(Path::new(&dir)))
+        .filter(|id| *id >= FIRST_PROJECT_ID)
+        .max()
+        .map_or(FIRST_PROJECT_ID, |id| id + 1)
+}
+
+fn project_id(dir: &Path) -> Option<u32> {
+    let file = fs::File::open(dir).ok()?;
+    let mut attr = FsXattr::default();
+    (unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FSGETXATTR, &mut attr) } == 0)
+        .then_some(attr.projid)
+}
+
+fn unsupported(dir: &Path, e: io::Error) -> Box<dyn std::error::Error> {
+    format!(
+        "Failed to set a size limit on {}: {}. Project quotas need XFS mounted with pquota, or ext4 with prjquota",
+        dir.display(),
+        e
+    )
+    .into()
+}
@@ -0,0 +1,211 @@
+use crate::actions::store;
+use std::collections::HashMap;
+
+/// What sensitive values are printed as.
+pub const MASK: &str = "*****";
+/// Names of environment variables masked unless the config file's
+/// `mask_env` lists others. `*` matches any run of characters.
+const DEFAULT_PATTERNS: [&str; 7] = [
+    "*PASSWORD*",
+    "*PASSWD*",
+    "*SECRET*",
+    "*TOKEN*",
+    "*API_KEY*",
+    "*PRIVATE_KEY*",
+    "*CREDENTIAL*",
+];
+/// Shorter values are only masked as whole variables, masking them wherever
+/// they appear would garble unrelated text.
+const MIN_INLINE_LEN: usize = 4;
+
+/// The variables of `env` whose values are masked: those named with
+/// `--mask-env` and those matching the patterns, case-insensitively.
+pub fn masked_keys(env: &HashMap<String, String>, mask_env: &[String]) -> Vec<String> {
+    let configured = store::config().mask_env.as_ref();
+    let mut keys: Vec<String> = env
+        .keys()
+        .filter(|key| {
+            mask_env.contains(key)
+                || match configured {
+                    Some(patterns) => patterns.iter().any(|pattern| matches(pattern, key)),
+                    None => DEFAULT_PATTERNS.iter().any(|pattern| matches(pattern, key)),
+                }
+        })
+        .cloned()
+        .collect();
+    keys.sort();
+    keys
+}
+
+fn matches(pattern: &str, key: &str) -> bool {
+    let pattern = pattern.to_ascii_uppercase();
+    let key = key.to_ascii_uppercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = key.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`, the whole name has to match.
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// The values a container's metadata records as masked.
+pub fn masked_values(metadata: &serde_json::Value) -> Vec<String> {
+    let env = &metadata["process"]["env"];
+    let mut values: Vec<String> = metadata["masked_env"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|key| env.get(key.as_str()?)?.as_str())
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+        .collect();
+    // Longest first, so no value is left half masked by one it contains.
+    values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    values.dedup();
+    values
+}
+
+/// Masks `values` wherever they appear in `text`.
+pub fn redact_text(text: &str, values: &[String]) -> String {
+    values
+        .iter()
+        .filter(|value| value.len() >= MIN_INLINE_LEN)
+        .fold(text.to_string(), |text, value| {
+            text.replace(value.as_str(), MASK)
+        })
+}
+
+/// A container's metadata as it is shown: masked variables print as
+/// `*****` and their values are masked in the rest, such as the command or
+/// health check output, too.
+pub fn redact_metadata(metadata: &serde_json::Value) -> serde_json::Value {
+    let values = masked_values(metadata);
+    let keys: Vec<String> = metadata["masked_env"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|key| key.as_str().map(String::from))
+        .collect();
+
+    let mut redacted = metadata.clone();
+    if let Some(env) = redacted
+        .get_mut("process")
+        .and_then(|process| process.get_mut("env"))
+        .and_then(|env| env.as_object_mut())
+    {
+        for (key, value) in env.iter_mut() {
+            if keys.contains(key) {
+                *value = MASK.into();
+            }
+        }
+    }
+    redact_value(&mut redacted, &values);
+    redacted
+}
+
+/// Masks `values` in every string of a JSON value.
+pub fn redact_value(value: &mut serde_json::Value, values: &[String]) {
+    match value {
+        serde_json::Value::String(text) => *text = redact_text(text, values),
+        serde_json::Value::Array(items) => {
+            items.iter_mut().for_each(|item| redact_value(item, values))
+        }
+        serde_json::Value::Object(map) => {
+            map.values_mut().for_each(|item| redact_value(item, values))
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards_case_insensitively() {
+        assert!(matches("*PASSWORD*", "DB_PASSWORD"));
+        assert!(matches("*PASSWORD*", "password_file"));
+        assert!(matches("*PASSWORD*", "PASSWORD"));
+        assert!(matches("AWS_*", "aws_region"));
+        assert!(matches("*_KEY", "API_KEY"));
+        assert!(matches("A*B*C", "AXXBYYC"));
+        assert!(!matches("*PASSWORD*", "PASSWD"));
+        assert!(!matches("*_KEY", "KEY_ID"));
+        assert!(!matches("A*B*C", "AXXCYYB"));
+    }
+
+    #[test]
+    fn matches_without_wildcard_needs_the_whole_name() {
+        assert!(matches("TOKEN", "token"));
+        assert!(!matches("TOKEN", "TOKENS"));
+        assert!(!matches("TOKEN", "MY_TOKEN"));
+    }
+
+    #[test]
+    fn matches_does_not_reuse_the_prefix_for_the_suffix() {
+        assert!(!matches("AB*AB", "AB"));
+        assert!(matches("AB*AB", "ABAB"));
+    }
+
+    #[test]
+    fn masked_values_are_those_of_masked_keys_longest_first() {
+        let metadata = serde_json::json!({
+            "masked_env": ["SHORT", "LONG", "EMPTY", "MISSING"],
+            "process": {
+                "env": {
+                    "SHORT": "abcd",
+                    "LONG": "abcdefgh",
+                    "EMPTY": "",
+                    "PLAIN": "visible",
+                },
+            },
+        });
+
+        assert_eq!(masked_values(&metadata), vec!["abcdefgh", "abcd"]);
+    }
+
+    #[test]
+    fn masked_values_without_masked_env_is_empty() {
+        let metadata = serde_json::json!({ "process": { "env": { "A": "value" } } });
+        assert!(masked_values(&metadata).is_empty());
+    }
+
+    #[test]
+    fn redact_text_masks_every_occurrence() {
+        let values = vec!["hunter22".to_string()];
+        assert_eq!(
+            redact_text("login hunter22 then hunter22", &values),
+            format!("login {} then {}", MASK, MASK)
+        );
+    }
+
+    #[test]
+    fn redact_text_leaves_short_values() {
+        let values = vec!["abc".to_string()];
+        assert_eq!(redact_text("abc abcd", &values), "abc abcd");
+    }
+
+    #[test]
+    fn redact_text_masks_longer_values_first() {
+        let metadata = serde_json::json!({
+            "masked_env": ["A", "B"],
+            "process": { "env": { "A": "secret", "B": "secret-token" } },
+        });
+        let values = masked_values(&metadata);
+        assert_eq!(
+            redact_text("x secret-token y", &values),
+            format!("x {} y", MASK)
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use crate::actions::{
+    cgroup, checkpoint, container, events, health,
+    run::{self, ProcessSpec},
+    stop, storage, store,
+};
+use std::{
+    fmt,
+    fs::File,
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Child, Command, ExitStatus, Stdio},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A container that stays up this long is considered healthy again and its
+/// backoff starts over.
+const BACKOFF_RESET_AFTER: Duration = Duration::from_secs(10);
+const START_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// PID of the container command `run_with_restarts` is waiting on, 0
+/// between runs. Read from signal handlers.
+pub static RUNNING_PID: AtomicI32 = AtomicI32::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    No,
+    OnFailure { max_retries: Option<u32> },
+    Always,
+    UnlessStopped,
+}
+
+impl FromStr for RestartPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            None => match value {
+                "no" => Ok(RestartPolicy::No),
+                "on-failure" => Ok(RestartPolicy::OnFailure { max_retries: None }),
+                "always" => Ok(RestartPolicy::Always),
+                "unless-stopped" => Ok(RestartPolicy::UnlessStopped),
+                _ => Err(format!(
+                    "Invalid restart policy: {}. Expected no, on-failure[:max], always or unless-stopped",
+                    value
+                )),
+            },
+            Some(("on-failure", max)) => {
+                let max_retries = max
+                    .parse()
+                    .map_err(|_| format!("Invalid maximum retry count: {}", max))?;
+                Ok(RestartPolicy::OnFailure {
+                    max_retries: Some(max_retries),
+                })
+            }
+            Some(_) => Err(format!(
+                "Only the on-failure restart policy accepts a maximum retry count, got {}",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestartPolicy::No => write!(f, "no"),
+            RestartPolicy::OnFailure { max_retries: None } => write!(f, "on-failure"),
+            RestartPolicy::OnFailure {
+                max_retries: Some(max),
+            } => write!(f, "on-failure:{}", max),
+            RestartPolicy::Always => write!(f, "always"),
+            RestartPolicy::UnlessStopped => write!(f, "unless-stopped"),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Stopping a container kills its supervisor first, so `always` and
+    /// `unless-stopped` only differ once rustainer gains a daemon that
+    /// restarts containers at boot.
+    pub fn should_restart(&self, status: &ExitStatus, restart_count: u32) -> bool {
+        match self {
+            RestartPolicy::No => false,
+            RestartPolicy::OnFailure { max_retries } => {
+                !status.success() && max_retries.is_none_or(|max| restart_count < max)
+            }
+            RestartPolicy::Always | RestartPolicy::UnlessStopped => true,
+        }
+    }
+}
+
+/// Runs the container command until it exits in a way the policy does not
+/// restart, returning the final exit status. The container's cgroup is kept
+/// for restarts and removed after the last exit.
+pub fn run_with_restarts<F>(
+    container_id: &str,
+    policy: RestartPolicy,
+    build_command: F,
+) -> Result<ExitStatus, Box<dyn std::error::Error>>
+where
+    F: FnMut() -> Result<Command, Box<dyn std::error::Error>>,
+{
+    let finished = Arc::new(AtomicBool::new(false));
+    if let Some(timeout) = container::load_metadata(container_id)
+        .and_then(|metadata| metadata.get("timeout_ms")?.as_u64())
+        .map(Duration::from_millis)
+    {
+        let (container_id, finished) = (container_id.to_string(), finished.clone());
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            if !finished.load(Ordering::Relaxed) {
+                time_out(&container_id, timeout);
+            }
+        });
+    }
+
+    let result = restart_loop(container_id, policy, build_command);
+    finished.store(true, Ordering::Relaxed);
+    cgroup::remove(container_id);
+    result
+}
+
+/// Stops a container that outlived its `--timeout`, marking it as timed out.
+fn time_out(container_id: &str, timeout: Duration) {
+    let _ = container::update_metadata(container_id, |metadata| {
+        metadata["timed_out"] = true.into();
+    });
+    events::emit_container("timeout", container_id, serde_json::json!({}));
+    println!(
+        "⏱️ Container {} timed out after {:?}, stopping it",
+        container_id, timeout
+    );
+
+    if let Err(e) = stop::stop(container_id, None) {
+        println!(
+            "⚠️ Warning: Failed to stop container {}: {}",
+            container_id, e
+        );
+    }
+}
+
+fn restart_loop<F>(
+    container_id: &str,
+    policy: RestartPolicy,
+    mut build_command: F,
+) -> Result<ExitStatus, Box<dyn std::error::Error>>
+where
+    F: FnMut() -> Result<Command, Box<dyn std::error::Error>>,
+{
+    let mut restart_count = 0;
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        let started = Instant::now();
+        // The cgroup's count covers earlier runs too.
+        let oom_kills = cgroup::oom_kills(container_id);
+        let mut child = run::spawn_container_command(build_command()?)?;
+        RUNNING_PID.store(child.id() as i32, Ordering::Relaxed);
+        container::record_start(container_id, child.id())?;
+        events::emit_container("start", container_id, serde_json::json!({}));
+        health::start_monitor(container_id);
+        let status = child.wait();
+        RUNNING_PID.store(0, Ordering::Relaxed);
+        let status = status?;
+        let oom_killed = cgroup::oom_kills(container_id) > oom_kills;
+        if oom_killed {
+            events::emit_container("oom", container_id, serde_json::json!({}));
+        }
+        container::record_exit(container_id, exit_code(&status), oom_killed)?;
+        events::emit_container(
+            "die",
+            container_id,
+            serde_json::json!({ "exitCode": exit_code(&status) }),
+        );
+
+        if !policy.should_restart(&status, restart_count) || stop::was_requested(container_id) {
+            return Ok(status);
+        }
+
+        if started.elapsed() >= BACKOFF_RESET_AFTER {
+            backoff = INITIAL_BACKOFF;
+        }
+
+        restart_count += 1;
+        container::update_metadata(container_id, |metadata| {
+            metadata["restart_count"] = restart_count.into();
+        })?;
+
+        println!(
+            "🔁 Container {} exited with code {}, restarting in {:?} (restart #{})",
+            container_id,
+            exit_code(&status),
+            backoff,
+            restart_count
+        );
+
+        std::thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        if stop::was_requested(container_id) {
+            return Ok(status);
+        }
+    }
+}
+
+/// The exit code a shell would report: the process's own, or 128 plus the
+/// signal that killed it.
+pub fn exit_code(status: &ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+/// Launches `rustainer supervise <id>` in a session of its own so it
+/// outlives the CLI invocation, and the terminal, that started a detached
+/// container. Its errors go to `supervisor.log` in the container directory.
+pub fn spawn_supervisor(container_id: &str) -> Result<Child, Box<dyn std::error::Error>> {
+    let log = File::create(supervisor_log(container_id))?;
+    let mut cmd = Command::new(std::env::current_exe()?);
+    cmd.arg("--root")
+        .arg(store::root())
+        .args(["supervise", container_id])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(log);
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    Ok(cmd.spawn()?)
+}
+
+/// Waits until the supervisor has started the container process, failing
+/// with what the supervisor reported if it exits before that.
+pub fn wait_for_start(
+    container_id: &str,
+    supervisor: &mut Child,
+) -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+        let started = container::load_metadata(container_id)
+            .is_some_and(|metadata| metadata.get("started_at").is_some_and(|v| !v.is_null()));
+        if started {
+            return Ok(());
+        }
+
+        if let Some(status) = supervisor.try_wait()? {
+            let log = std::fs::read_to_string(supervisor_log(container_id)).unwrap_or_default();
+            return Err(format!(
+                "Container {} failed to start ({}): {}",
+                container::short_id(container_id),
+                status,
+                log.trim()
+            )
+            .into());
+        }
+
+        std::thread::sleep(START_POLL_INTERVAL);
+    }
+}
+
+fn supervisor_log(container_id: &str) -> String {
+    format!("{}/supervisor.log", container::container_dir(container_id))
+}
+
+pub async fn supervise(container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // Recorded here rather than by the CLI, which would race the container
+    // start for the metadata file.
+    let mut restore = false;
+    container::update_metadata(container_id, |metadata| {
+        metadata["supervisor_pid"] = std::process::id().into();
+        // Set by `restore`, only the first launch restores the checkpoint.
+        restore = metadata
+            .as_object_mut()
+            .and_then(|metadata| metadata.remove("restore"))
+            .is_some_and(|v| v.as_bool() == Some(true));
+    })?;
+    let metadata = container::load_metadata(container_id)
+        .ok_or_else(|| format!("Container {} has no metadata", container_id))?;
+
+    let policy: RestartPolicy = metadata
+        .get("restart_policy")
+        .and_then(|v| v.as_str())
+        .unwrap_or("no")
+        .parse()?;
+
+    let process: ProcessSpec = serde_json::from_value(metadata["process"].clone())?;
+    let rootfs_path = format!("{}/rootfs", container::container_dir(container_id));
+    // Gone after a host reboot, for containers restarting with it.
+    storage::mount_rootfs(&container::container_dir(container_id))?;
+
+    run_with_restarts(container_id, policy, || {
+        let mut cmd = if std::mem::take(&mut restore) {
+            checkpoint::restore_command(container_id, &process)?
+        } else {
+            run::build_container_command(container_id, &rootfs_path, &process)?
+        };
+        cmd.stdin(run::detached_stdin(container_id, &process)?);
+        cmd.stdout(Stdio::null());
+        cmd.stderr(Stdio::null());
+        Ok(cmd)
+    })?;
+
+    Ok(())
+}
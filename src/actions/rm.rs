@@ -1,28 +1,63 @@
+use crate::actions::{
+    cgroup, container, events, oci, run, stop, storage, store,
+    volume::{self, Volume},
+};
 use std::{fs, process::Command};
 
-pub async fn remove_container(container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let container_dir = format!("./containers/{}", container_id);
-
-    if !fs::metadata(&container_dir).is_ok() {
-        return Err(format!("Container {} does not exist", container_id).into());
+/// Removes a container, stopping it first if it still runs: with its stop
+/// signal and timeout, or with `force` by killing it right away. With
+/// `remove_volumes` its anonymous volumes go with it.
+pub async fn remove_container(
+    reference: &str,
+    force: bool,
+    remove_volumes: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = &container::resolve_container(reference)?;
+    let _lock = container::lock(container_id)?;
+    let container_dir = container::container_dir(container_id);
+    let anonymous_volumes: Vec<String> = container::load_metadata(container_id)
+        .and_then(|metadata| {
+            serde_json::from_value::<Vec<Volume>>(metadata.get("volumes")?.clone()).ok()
+        })
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|volume| volume.anonymous)
+        .map(|volume| volume.source)
+        .collect();
+
+    if !force {
+        stop::stop(container_id, None)?;
     }
-
     stop_container(container_id)?;
+    cgroup::remove(container_id);
+
+    // Emitted before the metadata is deleted so the event keeps image and name.
+    events::emit_container("destroy", container_id, serde_json::json!({}));
 
+    storage::remove_rootfs(container_id);
     fs::remove_dir_all(&container_dir)?;
+    container::unregister_names(container_id)?;
+    store::remove_container_lock(container_id);
 
     println!("Container {} removed", container_id);
 
+    if remove_volumes {
+        for name in anonymous_volumes {
+            if let Err(e) = volume::remove_volume(&name) {
+                println!("⚠️ Warning: Failed to remove volume {}: {}", name, e);
+            }
+        }
+    }
+
     Ok(())
 }
 
 fn stop_container(container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("Stopping container {}", container_id);
 
-    let mut process_to_kill = None;
-
-    let metadata_path = format!("./containers/{}/metadata.json", container_id);
+    let metadata_path = format!("{}/metadata.json", container::container_dir(container_id));
     let mut ports = Vec::new();
+    let mut container_ip = None;
 
     if let Ok(metadata_content) = fs::read_to_string(&metadata_path) {
         if let Ok(metadata) = serde_json::from_str::<serde_json::Value>(&metadata_content) {
@@ -33,84 +68,33 @@ fn stop_container(container_id: &str) -> Result<(), Box<dyn std::error::Error>>
                     }
                 }
             }
+            container_ip = metadata
+                .get("ip")
+                .and_then(|v| v.as_str())
+                .map(String::from);
+
+            // The supervisor would restart the container as soon as it is killed.
+            if let Some(supervisor_pid) = metadata.get("supervisor_pid").and_then(|v| v.as_u64()) {
+                println!("Stopping supervisor with PID {}", supervisor_pid);
+                unsafe { libc::kill(supervisor_pid as libc::pid_t, libc::SIGKILL) };
+            }
         }
     }
 
-    for port_mapping in &ports {
-        let parts: Vec<&str> = port_mapping.split(':').collect();
-        if parts.len() == 2 {
-            let host_port = parts[0];
-
-            let _ = Command::new("iptables")
-                .args(&[
-                    "-t",
-                    "nat",
-                    "-D",
-                    "PREROUTING",
-                    "-p",
-                    "tcp",
-                    "--dport",
-                    host_port,
-                    "-j",
-                    "DNAT",
-                ])
-                .output();
-
-            let _ = Command::new("iptables")
-                .args(&[
-                    "-t", "nat", "-D", "OUTPUT", "-p", "tcp", "--dport", host_port, "-j", "DNAT",
-                ])
-                .output();
-        }
+    if let Some(container_ip) = &container_ip {
+        run::remove_port_mapping(container_ip, &ports);
     }
 
-    let output = Command::new("ip").args(&["netns", "list"]).output()?;
-
-    if String::from_utf8_lossy(&output.stdout).contains(container_id) {
-        println!("Killing all processes in container namespace");
-
-        let _ = Command::new("nsenter")
-            .args(&[
-                "--net=/var/run/netns/",
-                container_id,
-                "--",
-                "killall5",
-                "-9",
-            ])
-            .output();
-
-        let ps_output = Command::new("ps").args(&["-ef"]).output()?;
-        let ps_str = String::from_utf8_lossy(&ps_output.stdout);
-
-        for line in ps_str.lines() {
-            if line.contains(container_id) && (line.contains("chroot") || line.contains("unshare"))
-            {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() > 1 {
-                    process_to_kill = Some(parts[1].to_string());
-                    break;
-                }
-            }
-        }
-
-        if let Some(pid) = &process_to_kill {
-            println!("Killing process with PID {}", pid);
-
-            let _ = Command::new("kill")
-                .args(&["-9", "-", pid]) 
-                .output()?;
-
-            let _ = Command::new("kill").args(&["-9", pid]).output()?;
-        }
+    stop::kill(container_id);
+    oci::delete(container_id);
 
-        std::thread::sleep(std::time::Duration::from_millis(500));
+    let output = Command::new("ip").args(["netns", "list"]).output()?;
 
+    if String::from_utf8_lossy(&output.stdout).contains(container_id) {
         let _ = Command::new("ip")
-            .args(&["netns", "delete", container_id])
+            .args(["netns", "delete", container_id])
             .output();
     }
 
-    let _ = Command::new("iptables").args(&["-F", "FORWARD"]).output();
-
     Ok(())
 }
@@ -1,26 +1,392 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    ffi::CString,
     fs,
-    path::Path,
+    os::{fd::AsRawFd, unix::process::CommandExt},
+    path::{Path, PathBuf},
     process::{Command, Stdio},
 };
 
-use crate::actions::{self, types::ImageManifest};
+use crate::actions::{
+    self,
+    cgroup::{self, ResourceLimits},
+    config::ConfigMount,
+    dns::DnsConfig,
+    health::{HealthConfig, HealthOverrides, ImageHealthcheck},
+    namespaces::NamespaceMode,
+    network::{self, NetworkMode, BRIDGE_GATEWAY, BRIDGE_SUBNET},
+    oci,
+    pull::PullPolicy,
+    redact,
+    restart::{self, RestartPolicy},
+    seccomp::{self, SeccompProfile},
+    secret,
+    security::{self, ProcessLabel},
+    storage::StorageOpt,
+    store::{self, LockMode},
+    sysctl::Sysctl,
+    tty,
+    types::{ImageManifest, Platform},
+    userns::{self, IdMappings, UsernsMode},
+    volume::{self, MountSpec, Propagation, Volume},
+};
+
+const OOM_SCORE_ADJ_MIN: i32 = -1000;
+const OOM_SCORE_ADJ_MAX: i32 = 1000;
+/// Host directories a `--privileged` container sees, along with the mounts
+/// below them such as /sys/fs/cgroup.
+const PRIVILEGED_HOST_DIRS: [&str; 2] = ["/dev", "/sys"];
+/// Host device nodes every unprivileged container gets in its /dev.
+const STANDARD_DEVICES: [&str; 6] = [
+    "/dev/null",
+    "/dev/zero",
+    "/dev/full",
+    "/dev/random",
+    "/dev/urandom",
+    "/dev/tty",
+];
+/// Symlinks in the container's /dev, as `(name, target)`.
+const DEV_SYMLINKS: [(&str, &str); 5] = [
+    ("fd", "/proc/self/fd"),
+    ("stdin", "/proc/self/fd/0"),
+    ("stdout", "/proc/self/fd/1"),
+    ("stderr", "/proc/self/fd/2"),
+    ("ptmx", "pts/ptmx"),
+];
+/// Where the kernel filesystems every container gets are mounted.
+const CONTAINER_MOUNT_POINTS: [&str; 3] = ["/proc", "/dev", "/sys"];
+pub(crate) const DEFAULT_SHM_SIZE: u64 = 64 * 1024 * 1024;
+/// Signals passed on to the container's PID 1 rather than acted on by the
+/// processes standing between it and the user.
+const FORWARDED_SIGNALS: [libc::c_int; 6] = [
+    libc::SIGINT,
+    libc::SIGTERM,
+    libc::SIGHUP,
+    libc::SIGQUIT,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+];
+/// Directories that stay writable in a `--read-only` container.
+const READ_ONLY_TMPFS_DIRS: [&str; 3] = ["/tmp", "/run", "/var/tmp"];
+/// Mount flags a tmpfs takes among its options; `exec`, `suid`, `dev` and
+/// `rw` clear the flags of their counterparts.
+const TMPFS_FLAGS: [(&str, libc::c_ulong); 11] = [
+    ("ro", libc::MS_RDONLY),
+    ("rw", 0),
+    ("noexec", libc::MS_NOEXEC),
+    ("exec", 0),
+    ("nosuid", libc::MS_NOSUID),
+    ("suid", 0),
+    ("nodev", libc::MS_NODEV),
+    ("dev", 0),
+    ("noatime", libc::MS_NOATIME),
+    ("nodiratime", libc::MS_NODIRATIME),
+    ("relatime", libc::MS_RELATIME),
+];
+/// Options tmpfs itself understands, such as `size=256m` or `mode=1777`.
+const TMPFS_DATA_OPTIONS: [&str; 6] = ["size", "nr_blocks", "nr_inodes", "mode", "uid", "gid"];
+/// Devices a nested container needs on top of the standard ones, for its
+/// networking and for fuse-overlayfs.
+const NESTED_DEVICES: [&str; 2] = ["/dev/net/tun", "/dev/fuse"];
+/// Where `--share-images` puts the host's image store, that of a nested
+/// rustainer with the default storage root.
+const NESTED_IMAGES_DIR: &str = "/var/lib/rustainer/images";
 
 #[derive(Debug)]
 pub struct RunConfig {
     pub image: String,
     pub name: Option<String>,
     pub detach: bool,
+    pub auto_remove: bool,
+    pub restart_policy: RestartPolicy,
     pub interactive: bool,
     pub tty: bool,
+    pub detach_keys: Vec<u8>,
     pub env_vars: Vec<String>,
-    pub volumes: Vec<String>,
+    pub env_files: Vec<String>,
+    /// Variables masked when shown on top of those matching the patterns.
+    pub mask_env: Vec<String>,
+    pub volumes: Vec<Volume>,
+    pub tmpfs: Vec<TmpfsMount>,
+    /// `--mount` options, added to `volumes` and `tmpfs` as the run starts.
+    pub mounts: Vec<MountSpec>,
+    pub secrets: Vec<String>,
+    pub configs: Vec<ConfigMount>,
     pub ports: Vec<String>,
     pub command: Option<Vec<String>>,
+    pub workdir: Option<String>,
+    pub user: Option<String>,
+    pub hostname: Option<String>,
+    pub extra_hosts: Vec<String>,
+    pub dns: DnsConfig,
+    pub network: NetworkMode,
+    pub ip: Option<String>,
+    pub mac_address: Option<String>,
+    pub cidfile: Option<String>,
+    pub pull_policy: PullPolicy,
+    pub platform: Option<String>,
+    pub resources: ResourceLimits,
+    pub gpus: Option<String>,
+    pub privileged: bool,
+    pub cap_add: Vec<String>,
+    pub cap_drop: Vec<String>,
+    pub security_opts: Vec<String>,
+    pub read_only: bool,
+    pub userns: UsernsMode,
+    pub pid_mode: NamespaceMode,
+    pub ipc_mode: NamespaceMode,
+    pub uts_mode: NamespaceMode,
+    pub shm_size: Option<u64>,
+    pub oom_score_adj: Option<i32>,
+    pub oom_kill_disable: bool,
+    pub stop_signal: Option<String>,
+    pub stop_timeout: Option<u64>,
+    pub sysctls: Vec<Sysctl>,
+    pub storage_opts: Vec<StorageOpt>,
+    pub cgroup_parent: Option<String>,
+    pub group_add: Vec<String>,
+    pub timeout: Option<std::time::Duration>,
+    pub init: bool,
+    pub runtime: Option<String>,
+    /// An OCI bundle directory to run instead of `image`.
+    pub bundle: Option<String>,
+    pub nested: bool,
+    pub share_images: bool,
+    pub health: HealthOverrides,
+}
+
+/// Everything needed to (re)launch the container process, persisted in the
+/// container metadata so supervisors can restart it.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ProcessSpec {
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub cwd: String,
+    #[serde(default)]
+    pub uid: u32,
+    #[serde(default)]
+    pub gid: u32,
+    /// Supplementary groups: the user's in the image, then `--group-add`.
+    #[serde(default)]
+    pub additional_gids: Vec<u32>,
+    #[serde(default)]
+    pub hostname: String,
+    #[serde(default)]
+    pub mounts: Vec<BindMount>,
+    #[serde(default)]
+    pub network: NetworkMode,
+    /// The cgroup the process joins, if cgroups are in use.
+    #[serde(default)]
+    pub cgroup: Option<String>,
+    /// Keeps every capability and gets the host's devices and /sys.
+    #[serde(default)]
+    pub privileged: bool,
+    /// The bounding set the command runs with, all capabilities if unset.
+    #[serde(default)]
+    pub capabilities: Option<Vec<String>>,
+    /// The seccomp profile applied before exec, unconfined if unset.
+    #[serde(default)]
+    pub seccomp: Option<SeccompProfile>,
+    /// The AppArmor profile or SELinux context the command runs under.
+    #[serde(default)]
+    pub label: Option<ProcessLabel>,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub tmpfs: Vec<TmpfsMount>,
+    /// Secrets decrypted onto a tmpfs at /run/secrets each time the command
+    /// starts, by name so their contents are never written to disk.
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    /// Mappings of the user namespace the command runs in, if it has one.
+    #[serde(default)]
+    pub userns: Option<IdMappings>,
+    /// Whether the rootfs is shown through an idmapped mount rather than
+    /// having been chowned to the mapped IDs.
+    #[serde(default)]
+    pub idmapped_rootfs: bool,
+    /// Host device nodes bound into the container's own /dev, which every
+    /// unprivileged container gets.
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Size of the /dev shared memory tmpfs, 64MB if unset.
+    #[serde(default)]
+    pub shm_size: Option<u64>,
+    /// Runs the command under a minimal init that reaps zombies and
+    /// forwards signals.
+    #[serde(default)]
+    pub init: bool,
+    /// Whether the command's stdio is a terminal it should take as its
+    /// controlling terminal.
+    #[serde(default)]
+    pub tty: bool,
+    /// Keeps stdin open when no client is attached, through a FIFO in the
+    /// container directory.
+    #[serde(default)]
+    pub open_stdin: bool,
+    #[serde(default)]
+    pub pid_mode: NamespaceMode,
+    /// With `host`, the host's /dev/shm is bound in as well.
+    #[serde(default)]
+    pub ipc_mode: NamespaceMode,
+    #[serde(default)]
+    pub uts_mode: NamespaceMode,
+    /// Written to the command's oom_score_adj, -1000 keeping the OOM
+    /// killer away from it entirely.
+    #[serde(default)]
+    pub oom_score_adj: Option<i32>,
+    /// Settings of the container's network and IPC namespaces.
+    #[serde(default)]
+    pub sysctls: Vec<Sysctl>,
+    /// The OCI runtime, such as runc or crun, running the container instead
+    /// of rustainer itself.
+    #[serde(default)]
+    pub runtime: Option<String>,
+    /// Runs `args[0]`, a Wasm module in the rootfs, with a Wasm runtime
+    /// instead of as a Linux process in namespaces.
+    #[serde(default)]
+    pub wasm: bool,
+    /// Set up for running containers itself: its cgroup is delegated to it
+    /// and mounted writable.
+    #[serde(default)]
+    pub nested: bool,
+}
+
+/// A host file or directory bind mounted at `target` inside the rootfs.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BindMount {
+    pub source: String,
+    pub target: String,
+    #[serde(default)]
+    pub readonly: bool,
+    /// Also binds the mounts below `source`.
+    #[serde(default)]
+    pub recursive: bool,
+    /// `rprivate` when unset.
+    #[serde(default)]
+    pub propagation: Option<Propagation>,
+}
+
+/// A tmpfs mounted at `target` inside the rootfs, `options` being its mount
+/// options such as `size=64m,mode=1777,noexec`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TmpfsMount {
+    pub target: String,
+    pub options: String,
+}
+
+impl TmpfsMount {
+    /// Parses a `--tmpfs PATH[:OPTIONS]`, which is mounted noexec unless
+    /// `exec` is among its options.
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (target, options) = spec.split_once(':').unwrap_or((spec, ""));
+        if !target.starts_with('/')
+            || target == "/"
+            || Path::new(target)
+                .components()
+                .any(|component| component == std::path::Component::ParentDir)
+        {
+            return Err(format!(
+                "Invalid tmpfs {}: the path must be absolute, other than / and without ..",
+                spec
+            )
+            .into());
+        }
+
+        let mut options: Vec<&str> = options
+            .split(',')
+            .filter(|option| !option.is_empty())
+            .collect();
+        for option in &options {
+            let key = option.split('=').next().unwrap_or(option);
+            if !TMPFS_FLAGS.iter().any(|(flag, _)| flag == option)
+                && !TMPFS_DATA_OPTIONS.contains(&key)
+            {
+                return Err(format!("Invalid tmpfs option {} in {}", option, spec).into());
+            }
+        }
+        if !options.contains(&"exec") {
+            options.push("noexec");
+        }
+
+        Ok(TmpfsMount {
+            target: target.trim_end_matches('/').to_string(),
+            options: options.join(","),
+        })
+    }
+
+    /// The mount flags and the data passed on to tmpfs its options make up.
+    fn mount_options(&self) -> (libc::c_ulong, String) {
+        let mut flags = libc::MS_NOSUID | libc::MS_NODEV;
+        let mut data = Vec::new();
+        for option in self.options.split(',').filter(|option| !option.is_empty()) {
+            match option {
+                "exec" => flags &= !libc::MS_NOEXEC,
+                "suid" => flags &= !libc::MS_NOSUID,
+                "dev" => flags &= !libc::MS_NODEV,
+                "rw" => flags &= !libc::MS_RDONLY,
+                _ => match TMPFS_FLAGS.iter().find(|(flag, _)| *flag == option) {
+                    Some((_, flag)) => flags |= flag,
+                    None => data.push(option),
+                },
+            }
+        }
+        (flags, data.join(","))
+    }
+}
+
+/// What the container's rootfs is made from.
+enum RootfsSource {
+    Image {
+        path: String,
+        manifest: ImageManifest,
+    },
+    Bundle(oci::Bundle),
+}
+
+/// Undoes the setup of a container that failed before its metadata was
+/// written, which would otherwise leave its rootfs, network namespace, port
+/// rules and name behind with nothing for rm to find them by.
+struct SetupRollback<'a> {
+    container_id: &'a str,
+    network: NetworkMode,
+    container_ip: Option<&'a str>,
+    ports: &'a [String],
+    done: bool,
+}
+
+impl SetupRollback<'_> {
+    /// Keeps the container once it is fully set up.
+    fn commit(mut self) {
+        self.done = true;
+    }
 }
 
+impl Drop for SetupRollback<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if let Some(container_ip) = self.container_ip {
+            remove_port_mapping(container_ip, self.ports);
+        }
+        if self.network != NetworkMode::Host {
+            let _ = cleanup_container_networking(self.container_id);
+        }
+        let _ = actions::container::unregister_names(self.container_id);
+        actions::storage::remove_rootfs(self.container_id);
+        let _ = fs::remove_dir_all(actions::container::container_dir(self.container_id));
+    }
+}
+
+/// The image config blob; the runtime settings live under its `config` key.
 #[derive(Debug, serde::Deserialize)]
+struct ImageConfigFile {
+    #[serde(default)]
+    config: ImageConfig,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
 struct ImageConfig {
     #[serde(rename = "Env", default)]
     env: Vec<String>,
@@ -32,50 +398,738 @@ struct ImageConfig {
     working_dir: String,
     #[serde(rename = "User", default)]
     user: String,
+    #[serde(rename = "Healthcheck", default)]
+    healthcheck: Option<ImageHealthcheck>,
+    #[serde(rename = "StopSignal", default)]
+    stop_signal: String,
+    /// Paths the image keeps its data in, each getting an anonymous volume.
+    #[serde(rename = "Volumes", default)]
+    volumes: Option<BTreeMap<String, serde_json::Value>>,
 }
 
-pub async fn run_container(config: RunConfig) -> Result<(), Box<dyn std::error::Error>> {
-    let (repository, tag) = actions::pull::parse_image_tag(&config.image);
-    let image_path = find_local_image(&repository, &tag)?;
+/// Creates and starts a container, returning the exit code of its process
+/// when it runs in the foreground and 0 once started when detached.
+pub async fn run_container(mut config: RunConfig) -> Result<i32, Box<dyn std::error::Error>> {
+    for mount in &config.mounts {
+        match mount.clone() {
+            MountSpec::Volume(volume) => config.volumes.push(volume),
+            MountSpec::Tmpfs(mount) => config.tmpfs.push(mount),
+        }
+    }
+    // The bundle's own settings apply unless overridden on the command line.
+    let bundle = config.bundle.as_deref().map(oci::load_bundle).transpose()?;
+    if let Some(bundle) = &bundle {
+        config.read_only |= bundle.spec.root.readonly;
+        if config.hostname.is_none()
+            && !config.uts_mode.is_host()
+            && !bundle.spec.hostname.is_empty()
+        {
+            config.hostname = Some(bundle.spec.hostname.clone());
+        }
+        config.group_add.extend(
+            bundle
+                .spec
+                .process
+                .user
+                .additional_gids
+                .iter()
+                .map(|gid| gid.to_string()),
+        );
+    }
+    if let Some(name) = &config.name {
+        actions::utils::validate_object_name("container", name)?;
+        actions::container::check_name_available(name)?;
+    }
+    if let Some(hostname) = &config.hostname {
+        validate_hostname(hostname)?;
+        if config.uts_mode.is_host() {
+            return Err("Conflicting options: --hostname and --uts=host".into());
+        }
+    }
+    // The container's /proc could not be mounted, the PID namespace belongs
+    // to the host's user namespace.
+    if config.pid_mode.is_host() && config.userns != UsernsMode::Host {
+        return Err("--pid=host cannot be combined with a user namespace".into());
+    }
+    if let Some(cidfile) = &config.cidfile {
+        if fs::symlink_metadata(cidfile).is_ok() {
+            return Err(format!(
+                "Container ID file found, make sure the other container isn't running or delete {}",
+                cidfile
+            )
+            .into());
+        }
+    }
+    config.resources.validate()?;
+    if config.bundle.is_some() && !config.storage_opts.is_empty() {
+        return Err("--storage-opt cannot be used with --bundle".into());
+    }
+    if config.shm_size == Some(0) {
+        return Err("--shm-size must be greater than 0".into());
+    }
+    for sysctl in &config.sysctls {
+        if sysctl.is_network() && !config.network.has_namespace() {
+            return Err(format!("Sysctl {} cannot be set on the host network", sysctl.key).into());
+        }
+        if sysctl.is_ipc() && config.ipc_mode.is_host() {
+            return Err(format!("Sysctl {} cannot be set with --ipc=host", sysctl.key).into());
+        }
+    }
+    if let Some(signal) = &config.stop_signal {
+        actions::stop::parse_signal(signal)?;
+    }
+    match config.runtime.as_deref() {
+        Some(actions::vm::RUNTIME) => {
+            actions::vm::check(config.network)?;
+            if config.init || config.userns != UsernsMode::Host {
+                return Err("MicroVMs cannot be combined with --init or --userns".into());
+            }
+        }
+        Some(runtime) => {
+            oci::check_runtime(runtime)?;
+            if config.init {
+                return Err("--init is not supported with an OCI runtime".into());
+            }
+        }
+        None => {}
+    }
+    if let Some(adj) = config.oom_score_adj {
+        if !(OOM_SCORE_ADJ_MIN..=OOM_SCORE_ADJ_MAX).contains(&adj) {
+            return Err(format!(
+                "Invalid --oom-score-adj {}, it must be between {} and {}",
+                adj, OOM_SCORE_ADJ_MIN, OOM_SCORE_ADJ_MAX
+            )
+            .into());
+        }
+        if config.oom_kill_disable {
+            return Err("Conflicting options: --oom-score-adj and --oom-kill-disable".into());
+        }
+    }
+    // Without a limit only the host running out of memory would stop the
+    // container, which could take the host down with it.
+    if config.oom_kill_disable && config.resources.memory.is_none() {
+        return Err("--oom-kill-disable requires a memory limit set with --memory".into());
+    }
+    if !config.resources.is_empty() && !cgroup::is_available() {
+        return Err("Resource limits require cgroup v2 mounted at /sys/fs/cgroup".into());
+    }
+    if let Some(parent) = &config.cgroup_parent {
+        cgroup::validate_parent(parent)?;
+        if !cgroup::is_available() {
+            return Err("--cgroup-parent requires cgroup v2 mounted at /sys/fs/cgroup".into());
+        }
+    }
+    let gpu_setup = config
+        .gpus
+        .as_deref()
+        .map(actions::gpu::resolve)
+        .transpose()?;
+    // Containers running containers need every capability and syscall to
+    // set them up, but not the host's devices that --privileged brings.
+    let unconfined = config.privileged || config.nested;
+    if config.nested && !cgroup::is_available() {
+        println!("⚠️ Warning: cgroup v2 is not available, nested containers will run without a delegated cgroup");
+    }
+    let capabilities = if unconfined {
+        None
+    } else {
+        Some(actions::capabilities::resolve(
+            &config.cap_add,
+            &config.cap_drop,
+        )?)
+    };
+    let seccomp = seccomp::resolve(
+        security_opt(&config.security_opts, "seccomp")?
+            .last()
+            .copied(),
+        unconfined,
+    )?;
+    if let Some(profile) = &seccomp {
+        seccomp::compile(profile, capabilities.as_deref())?;
+    }
+    if config.privileged && config.userns != UsernsMode::Host {
+        return Err("Privileged mode is incompatible with user namespaces".into());
+    }
+    let id_mappings = userns::allocate(config.userns)?;
+    let label = security::resolve(
+        security_opt(&config.security_opts, "apparmor")?
+            .last()
+            .copied(),
+        &security_opt(&config.security_opts, "label")?,
+        unconfined,
+    )?;
+    volume::validate(&config.volumes, &config.tmpfs)?;
+    for (index, name) in config.secrets.iter().enumerate() {
+        secret::check_exists(name)?;
+        if config.secrets[..index].contains(name) {
+            return Err(format!("Secret {} is given more than once", name).into());
+        }
+    }
+    for config_mount in &config.configs {
+        actions::config::check_exists(&config_mount.name)?;
+    }
+    // Only rustainer itself can fill the secrets tmpfs as the container starts.
+    if !config.secrets.is_empty() && config.runtime.is_some() {
+        return Err("Secrets cannot be used with --runtime".into());
+    }
+    let extra_hosts = actions::dns::parse_extra_hosts(&config.extra_hosts)?;
+    actions::dns::validate_dns(&config.dns)?;
+    if config.network != NetworkMode::Bridge && config.ip.is_some() {
+        return Err(format!(
+            "A static IP requires the bridge network, not {}",
+            config.network
+        )
+        .into());
+    }
+    if let Some(mac_address) = &config.mac_address {
+        if config.network != NetworkMode::Bridge {
+            return Err(format!(
+                "A MAC address requires the bridge network, not {}",
+                config.network
+            )
+            .into());
+        }
+        network::validate_mac_address(mac_address)?;
+    }
+    if config.network != NetworkMode::Bridge && !config.ports.is_empty() {
+        return Err(format!(
+            "Publishing ports requires the bridge network, not {}",
+            config.network
+        )
+        .into());
+    }
 
-    let manifest = load_image_manifest(&image_path)?;
-    let image_config = load_image_config(&image_path, &manifest.config.digest)?;
+    let mut wasm = false;
+    let (source, image_config) = match bundle {
+        Some(bundle) => {
+            let image_config = ImageConfig {
+                env: bundle.spec.process.env.clone(),
+                cmd: bundle.spec.process.args.clone(),
+                working_dir: bundle.spec.process.cwd.clone(),
+                user: format!(
+                    "{}:{}",
+                    bundle.spec.process.user.uid, bundle.spec.process.user.gid
+                ),
+                ..ImageConfig::default()
+            };
+            (RootfsSource::Bundle(bundle), image_config)
+        }
+        None => {
+            // Archives are loaded on the fly, the container then runs from
+            // the image they hold like from any other local image.
+            if let Some(archive) = actions::archive::ArchiveReference::parse(&config.image) {
+                config.image =
+                    actions::archive::load_archive(&archive, config.platform.as_deref())?;
+                config.pull_policy = PullPolicy::Never;
+            }
+            let (image_path, manifest) = resolve_image(&config).await?;
+            let mut image_config = load_image_config(&image_path, &manifest.config.digest)?;
+            if actions::wasm::is_wasm_image(
+                &manifest,
+                actions::pull::image_platform(&image_path).as_ref(),
+            ) {
+                if config.runtime.is_some()
+                    || config.init
+                    || !config.secrets.is_empty()
+                    || !config.tmpfs.is_empty()
+                    || !config.mounts.is_empty()
+                    || config.user.is_some()
+                    || !config.ports.is_empty()
+                {
+                    return Err("Wasm images cannot be run with --runtime, --init, --secret, --tmpfs, --mount, --user or -p".into());
+                }
+                // Volumes are mapped into the sandbox as writable directories.
+                for volume in &config.volumes {
+                    if volume.readonly {
+                        return Err(format!(
+                            "Wasm images cannot mount {} read-only",
+                            volume.target
+                        )
+                        .into());
+                    }
+                    if !volume.named && !Path::new(&volume.source).is_dir() {
+                        return Err(format!(
+                            "Wasm images can only mount directories, not {}",
+                            volume.source
+                        )
+                        .into());
+                    }
+                }
+                actions::wasm::check_runtime()?;
+                // Artifacts carry no command, they run their module.
+                if image_config.cmd.is_empty() && image_config.entrypoint.is_empty() {
+                    image_config.cmd = vec![actions::wasm::MODULE_PATH.to_string()];
+                }
+                wasm = true;
+            }
+            (
+                RootfsSource::Image {
+                    path: image_path,
+                    manifest,
+                },
+                image_config,
+            )
+        }
+    };
+
+    let healthcheck = HealthConfig::resolve(image_config.healthcheck.as_ref(), &config.health);
+    // Probes are run like the container's own process, which only rustainer
+    // itself starts that way.
+    if healthcheck.is_some() && (config.runtime.is_some() || wasm) {
+        return Err(
+            "Healthchecks cannot be confined with --runtime or in Wasm images, disable them with --no-healthcheck"
+                .into(),
+        );
+    }
 
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+    for path in image_config.volumes.iter().flatten().map(|(path, _)| path) {
+        let anonymous = Volume::anonymous(path)
+            .map_err(|e| format!("Invalid volume in the image config: {}", e))?;
+        let covered = config
+            .volumes
+            .iter()
+            .map(|volume| &volume.target)
+            .chain(config.tmpfs.iter().map(|mount| &mount.target))
+            .any(|target| *target == anonymous.target);
+        if !covered {
+            config.volumes.push(anonymous);
+        }
+    }
+
+    // Allocated before anything is created so a taken IP leaves nothing behind,
+    // and kept from concurrent runs until the container is set up.
+    let (network_lock, container_ip) = match config.network {
+        NetworkMode::Bridge => {
+            let lock = store::lock_network()?;
+            let ip = network::allocate_ip(config.ip.as_deref())?;
+            (Some(lock), Some(ip.to_string()))
+        }
+        NetworkMode::Host | NetworkMode::None => (None, None),
+    };
+
+    let container_id = actions::container::generate_container_id()?;
+    // Keeps rm from removing the container while it is set up.
+    let container_lock = store::lock_container(&container_id)?;
+    println!(
+        "📦 Creating container {}",
+        actions::container::short_id(&container_id)
+    );
+    let container_path = match &source {
+        RootfsSource::Image { path, manifest } => {
+            // Until the container's layers are recorded, pruning could
+            // remove them and pulling could replace the image.
+            let (repository, tag) = actions::pull::parse_image_tag(&config.image);
+            let _store_lock = store::lock_store(LockMode::Shared)?;
+            let _image_lock = store::lock_image(&repository, &tag, LockMode::Shared)?;
+            actions::pull::mark_image_used(path);
+            create_container_filesystem(&container_id, path, manifest, &config.storage_opts).await?
+        }
+        RootfsSource::Bundle(bundle) => {
+            let container_path = actions::container::container_dir(&container_id);
+            fs::create_dir_all(&container_path)?;
+            // Run in place, the way OCI runtimes run bundles.
+            std::os::unix::fs::symlink(&bundle.rootfs, format!("{}/rootfs", container_path))?;
+            container_path
+        }
+    };
+    let rollback = SetupRollback {
+        container_id: &container_id,
+        network: config.network,
+        container_ip: container_ip.as_deref(),
+        ports: &config.ports,
+        done: false,
+    };
+    // Checked before anything else is set up, since the image decides
+    // whether the targets are files.
+    let config_mounts = config_mounts(&container_path, &config.configs)?;
+    let image_size = actions::utils::dir_size(Path::new(&format!("{}/rootfs", container_path)));
+
+    if let Some(name) = &config.name {
+        actions::container::register_name(name, &container_id)?;
+    }
 
-    let container_id = format!("rustainer_{}", timestamp);
-    let container_path = create_container_filesystem(&container_id, &image_path, &manifest).await?;
+    setup_container_networking(
+        &container_id,
+        config.network,
+        container_ip.as_deref(),
+        config.mac_address.as_deref(),
+        &config.ports,
+    )?;
+
+    let mut file_envs = Vec::new();
+    for env_file in &config.env_files {
+        file_envs.extend(read_env_file(env_file)?);
+    }
 
-    setup_container_networking(&container_id, &config.ports)?;
+    let mut env_vars = prepare_environment(&config.env_vars, &file_envs, &image_config.env);
 
-    let env_vars = prepare_environment(&config.env_vars, &image_config.env);
+    // keep-id runs as the invoking user unless told otherwise.
+    let keep_id_user = (config.userns == UsernsMode::KeepId && config.user.is_none()).then(|| {
+        let (uid, gid) = userns::invoking_ids();
+        format!("{}:{}", uid, gid)
+    });
+    let user_spec = keep_id_user
+        .as_deref()
+        .or(config.user.as_deref())
+        .unwrap_or(&image_config.user);
+    let user = actions::user::resolve_user(&format!("{}/rootfs", container_path), user_spec)?;
+    let mut additional_gids = user.groups.clone();
+    for group in &config.group_add {
+        let gid = actions::user::resolve_group(&format!("{}/rootfs", container_path), group)?;
+        if !additional_gids.contains(&gid) {
+            additional_gids.push(gid);
+        }
+    }
+    if let Some(home) = &user.home {
+        env_vars
+            .entry("HOME".to_string())
+            .or_insert_with(|| home.clone());
+    }
     let cmd = prepare_command(&config.command, &image_config.cmd, &image_config.entrypoint);
+    let hostname = match &config.hostname {
+        Some(hostname) => hostname.clone(),
+        None if config.uts_mode.is_host() => fs::read_to_string("/proc/sys/kernel/hostname")?
+            .trim()
+            .to_string(),
+        None => actions::container::short_id(&container_id).to_string(),
+    };
+    write_hostname_file(&format!("{}/rootfs", container_path), &hostname)?;
+
+    let hosts_path = format!("{}/hosts", container_path);
+    actions::dns::write_hosts_file(
+        &hosts_path,
+        &hostname,
+        container_ip.as_deref(),
+        &extra_hosts,
+    )?;
+    let resolv_conf_path = format!("{}/resolv.conf", container_path);
+    actions::dns::write_resolv_conf(
+        &resolv_conf_path,
+        &config.dns,
+        config.network == NetworkMode::Host,
+    )?;
+    let mut mounts = vec![
+        bind_container_file(&container_path, &hosts_path, "/etc/hosts")?,
+        bind_container_file(&container_path, &resolv_conf_path, "/etc/resolv.conf")?,
+    ];
+
+    if config.privileged {
+        for dir in PRIVILEGED_HOST_DIRS {
+            fs::create_dir_all(format!("{}/rootfs{}", container_path, dir))?;
+            mounts.push(BindMount {
+                source: dir.to_string(),
+                target: dir.to_string(),
+                readonly: false,
+                recursive: true,
+                propagation: None,
+            });
+        }
+    }
+
+    let mut tmpfs = Vec::new();
+    for mount in &config.tmpfs {
+        let target = volume::resolve_in_rootfs(
+            Path::new(&format!("{}/rootfs", container_path)),
+            &mount.target,
+        )?;
+        fs::create_dir_all(format!("{}/rootfs{}", container_path, target.display()))?;
+        tmpfs.push(TmpfsMount {
+            target: target.display().to_string(),
+            options: mount.options.clone(),
+        });
+    }
+    if config.read_only {
+        for dir in READ_ONLY_TMPFS_DIRS {
+            if config.tmpfs.iter().any(|mount| mount.target == dir) {
+                continue;
+            }
+            fs::create_dir_all(format!("{}/rootfs{}", container_path, dir))?;
+            tmpfs.push(TmpfsMount {
+                target: dir.to_string(),
+                options: "mode=1777".to_string(),
+            });
+        }
+    }
+
+    let mut devices: Vec<String> = if config.privileged {
+        Vec::new()
+    } else {
+        STANDARD_DEVICES
+            .iter()
+            .map(|device| device.to_string())
+            .collect()
+    };
+
+    if config.nested && !config.privileged {
+        devices.extend(
+            NESTED_DEVICES
+                .iter()
+                .filter(|device| Path::new(device).exists())
+                .map(|device| device.to_string()),
+        );
+    }
+    if config.share_images {
+        fs::create_dir_all(store::path("images"))?;
+        fs::create_dir_all(format!("{}/rootfs{}", container_path, NESTED_IMAGES_DIR))?;
+        mounts.push(BindMount {
+            source: store::path("images"),
+            target: NESTED_IMAGES_DIR.to_string(),
+            readonly: false,
+            recursive: false,
+            propagation: None,
+        });
+    }
+
+    mounts.extend(volume::bind_mounts(
+        &format!("{}/rootfs", container_path),
+        &config.volumes,
+        label.as_ref(),
+    )?);
+    mounts.extend(config_mounts);
+
+    if let Some(gpu_setup) = &gpu_setup {
+        for (source, target) in &gpu_setup.mounts {
+            if target.starts_with("/dev/") {
+                // Privileged containers already see the host's /dev.
+                if !config.privileged {
+                    devices.push(target.clone());
+                }
+                continue;
+            }
+            let mut mount = bind_container_file(&container_path, source, target)?;
+            mount.readonly = true;
+            mounts.push(mount);
+        }
+
+        env_vars.insert(
+            "NVIDIA_VISIBLE_DEVICES".to_string(),
+            gpu_setup.visible_devices.clone(),
+        );
+        let library_path = match env_vars.get("LD_LIBRARY_PATH") {
+            Some(existing) if !existing.is_empty() => {
+                format!("{}:{}", actions::gpu::LIBRARY_DIR, existing)
+            }
+            _ => actions::gpu::LIBRARY_DIR.to_string(),
+        };
+        env_vars.insert("LD_LIBRARY_PATH".to_string(), library_path);
+    }
+
+    let cwd = prepare_workdir(
+        &format!("{}/rootfs", container_path),
+        config.workdir.as_deref(),
+        &image_config.working_dir,
+    )?;
+    // Last, so files created above are covered too.
+    let idmapped_rootfs = match &id_mappings {
+        Some(mappings) => {
+            // Bind mounted as they are, so they need the container's root
+            // as owner either way.
+            for path in [&hosts_path, &resolv_conf_path] {
+                userns::shift_ownership(Path::new(path), mappings)?;
+            }
+            let rootfs_path = format!("{}/rootfs", container_path);
+            // The OCI runtime sets up the rootfs itself, without idmapping it.
+            if config.runtime.is_some() {
+                userns::shift_ownership(Path::new(&rootfs_path), mappings)?;
+                false
+            } else {
+                userns::prepare_rootfs(&rootfs_path, mappings)?
+            }
+        }
+        None => false,
+    };
+    let cgroup_path = cgroup::is_available()
+        .then(|| cgroup::path_under(config.cgroup_parent.as_deref(), &container_id));
+    let stop_signal = match &config.stop_signal {
+        Some(signal) => signal.clone(),
+        None if image_config.stop_signal.is_empty() => {
+            actions::stop::DEFAULT_STOP_SIGNAL.to_string()
+        }
+        None => match actions::stop::parse_signal(&image_config.stop_signal) {
+            Ok(_) => image_config.stop_signal.clone(),
+            Err(e) => {
+                println!("⚠️ Warning: Ignoring the image's stop signal: {}", e);
+                actions::stop::DEFAULT_STOP_SIGNAL.to_string()
+            }
+        },
+    };
+
+    let masked_env = redact::masked_keys(&env_vars, &config.mask_env);
+    let process = ProcessSpec {
+        args: cmd.clone(),
+        env: env_vars,
+        cwd,
+        uid: user.uid,
+        gid: user.gid,
+        additional_gids,
+        hostname: hostname.clone(),
+        mounts,
+        network: config.network,
+        cgroup: cgroup_path,
+        privileged: config.privileged,
+        capabilities,
+        seccomp,
+        label,
+        read_only: config.read_only,
+        tmpfs,
+        secrets: config.secrets.clone(),
+        userns: id_mappings,
+        idmapped_rootfs,
+        devices,
+        shm_size: config.shm_size,
+        init: config.init,
+        tty: config.tty && !config.detach,
+        open_stdin: config.interactive,
+        pid_mode: config.pid_mode,
+        ipc_mode: config.ipc_mode,
+        uts_mode: config.uts_mode,
+        // cgroup v2 cannot turn off the OOM killer, the lowest score keeps
+        // it from choosing the container's processes instead.
+        oom_score_adj: if config.oom_kill_disable {
+            Some(OOM_SCORE_ADJ_MIN)
+        } else {
+            config.oom_score_adj
+        },
+        sysctls: config.sysctls.clone(),
+        runtime: config.runtime.clone(),
+        wasm,
+        nested: config.nested,
+    };
 
     let metadata = serde_json::json!({
         "image": config.image,
+        "image_digest": match &source {
+            RootfsSource::Image { path, .. } => actions::pull::load_image_metadata(path).get("digest").cloned(),
+            RootfsSource::Bundle(_) => None,
+        },
+        "bundle": match &source {
+            RootfsSource::Bundle(bundle) => Some(bundle.path.display().to_string()),
+            RootfsSource::Image { .. } => None,
+        },
+        "name": config.name,
+        "hostname": hostname,
+        "extra_hosts": config.extra_hosts,
+        "dns": config.dns.nameservers,
+        "dns_search": config.dns.search,
+        "dns_options": config.dns.options,
         "command": cmd.join(" "),
+        "masked_env": masked_env,
         "ports": config.ports,
-        "created": SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+        "volumes": config.volumes,
+        "configs": config.configs,
+        "network": config.network,
+        "userns": config.userns,
+        "ip": container_ip,
+        "mac_address": config.mac_address,
+        "process": process,
+        "resources": config.resources,
+        "cgroup_parent": config.cgroup_parent,
+        "timeout_ms": config.timeout.map(|timeout| timeout.as_millis() as u64),
+        "restart_policy": config.restart_policy.to_string(),
+        "restart_count": 0,
+        "image_size": image_size,
+        "healthcheck": healthcheck,
+        "stop_signal": stop_signal,
+        "stop_timeout": config
+            .stop_timeout
+            .unwrap_or(actions::stop::DEFAULT_STOP_TIMEOUT),
+        "created": actions::container::now_secs(),
+        "state": "created",
+        "pid": null,
+        "started_at": null,
+        "finished_at": null,
+        "exit_code": null
     });
 
     let metadata_path = format!("{}/metadata.json", &container_path);
     fs::write(metadata_path, serde_json::to_string_pretty(&metadata)?)?;
+    rollback.commit();
+    drop(network_lock);
+    actions::events::emit_container("create", &container_id, serde_json::json!({}));
+    if let Some(cidfile) = &config.cidfile {
+        write_cidfile(cidfile, &container_id)?;
+    }
+    if let Some(container_ip) = &container_ip {
+        actions::events::emit(
+            "network",
+            "connect",
+            "rustainer0",
+            serde_json::json!({ "container": container_id, "ip": container_ip }),
+        );
+    }
 
-    execute_container(&container_id, &container_path, cmd, env_vars, &config).await?;
+    drop(container_lock);
 
-    Ok(())
+    let result = execute_container(&container_id, &container_path, &process, &config).await;
+
+    if config.auto_remove {
+        if let Err(e) = actions::rm::remove_container(&container_id, true, true).await {
+            println!("⚠️ Warning: Failed to remove container: {}", e);
+        }
+    }
+
+    result
+}
+
+/// Finds the image to run locally, pulling it as the pull policy says, and
+/// returns its path and manifest.
+async fn resolve_image(
+    config: &RunConfig,
+) -> Result<(String, ImageManifest), Box<dyn std::error::Error>> {
+    let (repository, tag) = actions::pull::parse_image_tag(&config.image);
+    let wanted_platform = config
+        .platform
+        .as_deref()
+        .map(str::parse::<Platform>)
+        .transpose()?;
+    // A local image for another platform counts as missing.
+    let local_image = find_local_image(&repository, &tag)
+        .ok()
+        .filter(|image_path| {
+            wanted_platform.as_ref().is_none_or(|wanted| {
+                actions::pull::image_platform(image_path).is_some_and(|p| p.satisfies(wanted))
+            })
+        });
+    let pull = match config.pull_policy {
+        PullPolicy::Always => true,
+        PullPolicy::Missing => local_image.is_none(),
+        PullPolicy::Never => false,
+    };
+    if pull {
+        actions::pull::pull_image(&config.image, config.platform.as_deref()).await?;
+    } else if local_image.is_none() {
+        if let Some(wanted) = &wanted_platform {
+            if find_local_image(&repository, &tag).is_ok() {
+                return Err(format!(
+                    "Image {} was found but does not match the specified platform {}",
+                    actions::pull::format_image_reference(&repository, &tag),
+                    wanted
+                )
+                .into());
+            }
+        }
+    }
+    let image_path = find_local_image(&repository, &tag)?;
+    if let Some(platform) = actions::pull::image_platform(&image_path) {
+        if !platform.satisfies(&Platform::host()) && !platform.is_wasm() {
+            println!(
+                "⚠️ Warning: The image's platform ({}) does not match the host platform ({})",
+                platform,
+                Platform::host()
+            );
+        }
+    }
+
+    let manifest = load_image_manifest(&image_path)?;
+    Ok((image_path, manifest))
 }
 
 fn find_local_image(repository: &str, tag: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let image_path = format!("./images/{}/{}", repository.replace('/', "_"), tag);
+    let image_path = store::image_dir(repository, tag);
     if !Path::new(&image_path).exists() {
         return Err(format!(
-            "Image {}:{} not found locally. You need to pull it first.",
-            repository, tag
+            "Image {} not found locally. You need to pull it first.",
+            actions::pull::format_image_reference(repository, tag)
         )
         .into());
     }
@@ -98,67 +1152,56 @@ fn load_image_config(
     let config_path = format!("{}/{}", image_path, config_filename);
 
     let config_content = fs::read_to_string(config_path)?;
-    let config: ImageConfig = serde_json::from_str(&config_content)?;
-    Ok(config)
+    let config_file: ImageConfigFile = serde_json::from_str(&config_content)?;
+    Ok(config_file.config)
 }
 
 async fn create_container_filesystem(
     container_id: &str,
     image_path: &str,
     manifest: &ImageManifest,
+    storage_opts: &[StorageOpt],
 ) -> Result<String, Box<dyn std::error::Error>> {
-    let container_path = format!("./containers/{}", container_id);
-    let rootfs_path = format!("{}/rootfs", container_path);
-
-    fs::create_dir_all(&rootfs_path)?;
+    let container_path = actions::container::container_dir(container_id);
 
     println!("Creating container filesystem");
-
-    for (i, layer) in manifest.layers.iter().enumerate() {
-        println!(
-            "Etracting layer {}/{}: {}",
-            i + 1,
-            manifest.layers.len(),
-            layer.digest
-        );
-
-        extract_layer(image_path, &layer.digest, &rootfs_path).await?;
+    if let Err(e) =
+        actions::storage::create_rootfs(&container_path, image_path, manifest, storage_opts).await
+    {
+        actions::storage::remove_rootfs(container_id);
+        let _ = fs::remove_dir_all(&container_path);
+        return Err(e);
     }
 
     Ok(container_path)
 }
 
-async fn extract_layer(
-    image_path: &str,
-    layer_digest: &str,
-    rootfs_path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let layer_filename = layer_digest.replace("sha256:", "");
-    let layer_path = format!("{}/{}", image_path, layer_filename);
-
-    let output = Command::new("tar")
-        .args(["-xzf", &layer_path, "-C", rootfs_path])
-        .output()?;
-
-    if !output.status.success() {
-        return Err(format!(
-            "Failed to extract layer: {}",
-            String::from_utf8_lossy(&output.stderr)
-        )
-        .into());
-    }
-
-    Ok(())
-}
-
-fn setup_container_networking(
+/// `container_ip` is the allocated bridge IP, only used in bridge mode.
+pub(crate) fn setup_container_networking(
     container_id: &str,
+    network: NetworkMode,
+    container_ip: Option<&str>,
+    mac_address: Option<&str>,
     ports: &[String],
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let container_ip = match network {
+        NetworkMode::Host => {
+            println!("🌐 Using the host network");
+            return Ok(());
+        }
+        NetworkMode::None => {
+            println!("🌐 Creating isolated network namespace...");
+            create_container_namespace(container_id)?;
+            bring_up_loopback(container_id)?;
+            return Ok(());
+        }
+        NetworkMode::Bridge => container_ip.ok_or("No IP allocated for the bridge network")?,
+    };
+
     println!("🌐 Setting up container networking...");
 
     let output = Command::new("sysctl")
-        .args(&["-w", "net.ipv4.ip_forward=1"])
+        .args(["-w", "net.ipv4.ip_forward=1"])
         .output()?;
     if !output.status.success() {
         return Err(format!(
@@ -172,20 +1215,20 @@ fn setup_container_networking(
 
     create_host_switch("rustainer0")?;
 
-    let (veth_container, _) = create_bridge(container_id)?;
+    let (veth_container, _) = create_bridge(container_id, mac_address)?;
 
-    let container_ip = add_ip_to_network(container_id, &veth_container)?;
+    add_ip_to_network(container_id, &veth_container, container_ip)?;
 
     add_routing_rules(container_id)?;
 
-    setup_port_mapping(&container_ip, ports)?;
+    setup_port_mapping(container_ip, ports)?;
 
     Ok(())
 }
 
 fn create_container_namespace(container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
     let output = Command::new("ip")
-        .args(&["netns", "add", container_id])
+        .args(["netns", "add", container_id])
         .output()?;
 
     if !output.status.success() {
@@ -203,7 +1246,7 @@ fn create_host_switch(host_name: &str) -> Result<(), Box<dyn std::error::Error>>
     let host_name = host_name.trim();
 
     let check_output = Command::new("ip")
-        .args(&["link", "show", host_name])
+        .args(["link", "show", host_name])
         .output()?;
 
     if check_output.status.success() {
@@ -211,7 +1254,7 @@ fn create_host_switch(host_name: &str) -> Result<(), Box<dyn std::error::Error>>
     }
 
     let output = Command::new("ip")
-        .args(&["link", "add", host_name, "type", "bridge"])
+        .args(["link", "add", host_name, "type", "bridge"])
         .output()?;
 
     if !output.status.success() {
@@ -223,7 +1266,7 @@ fn create_host_switch(host_name: &str) -> Result<(), Box<dyn std::error::Error>>
     }
 
     let output = Command::new("ip")
-        .args(&["link", "set", "dev", host_name, "up"])
+        .args(["link", "set", "dev", host_name, "up"])
         .output()?;
 
     if !output.status.success() {
@@ -237,7 +1280,10 @@ fn create_host_switch(host_name: &str) -> Result<(), Box<dyn std::error::Error>>
     Ok(())
 }
 
-fn create_bridge(container_id: &str) -> Result<(String, String), Box<dyn std::error::Error>> {
+fn create_bridge(
+    container_id: &str,
+    mac_address: Option<&str>,
+) -> Result<(String, String), Box<dyn std::error::Error>> {
     use std::time::{SystemTime, UNIX_EPOCH};
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -253,18 +1299,13 @@ fn create_bridge(container_id: &str) -> Result<(String, String), Box<dyn std::er
     let container_veth = format!("veth{}c{}", short_id, timestamp % 10000);
     let host_veth = format!("veth{}h{}", short_id, timestamp % 10000);
 
-    let output = Command::new("ip")
-        .args(&[
-            "link",
-            "add",
-            &container_veth,
-            "type",
-            "veth",
-            "peer",
-            "name",
-            &host_veth,
-        ])
-        .output()?;
+    let mut args = vec!["link", "add", &container_veth];
+    if let Some(mac_address) = mac_address {
+        args.extend(["address", mac_address]);
+    }
+    args.extend(["type", "veth", "peer", "name", &host_veth]);
+
+    let output = Command::new("ip").args(&args).output()?;
 
     if !output.status.success() {
         return Err(format!(
@@ -275,7 +1316,7 @@ fn create_bridge(container_id: &str) -> Result<(String, String), Box<dyn std::er
     }
 
     let output = Command::new("ip")
-        .args(&["link", "set", &container_veth, "netns", container_id])
+        .args(["link", "set", &container_veth, "netns", container_id])
         .output()?;
 
     if !output.status.success() {
@@ -287,7 +1328,7 @@ fn create_bridge(container_id: &str) -> Result<(String, String), Box<dyn std::er
     }
 
     let output = Command::new("ip")
-        .args(&["link", "set", &host_veth, "master", "rustainer0"])
+        .args(["link", "set", &host_veth, "master", "rustainer0"])
         .output()?;
 
     if !output.status.success() {
@@ -299,7 +1340,7 @@ fn create_bridge(container_id: &str) -> Result<(String, String), Box<dyn std::er
     }
 
     let output = Command::new("ip")
-        .args(&["link", "set", &host_veth, "up"])
+        .args(["link", "set", &host_veth, "up"])
         .output()?;
 
     if !output.status.success() {
@@ -321,14 +1362,21 @@ fn create_bridge(container_id: &str) -> Result<(String, String), Box<dyn std::er
 fn add_ip_to_network(
     container_id: &str,
     veth_container: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
+    container_ip: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
     let check_ip = Command::new("ip")
-        .args(&["addr", "show", "dev", "rustainer0"])
+        .args(["addr", "show", "dev", "rustainer0"])
         .output()?;
 
-    if !String::from_utf8_lossy(&check_ip.stdout).contains("172.19.0.1/16") {
+    if !String::from_utf8_lossy(&check_ip.stdout).contains(&format!("{}/16", BRIDGE_GATEWAY)) {
         let output = Command::new("ip")
-            .args(&["addr", "add", "172.19.0.1/16", "dev", "rustainer0"])
+            .args([
+                "addr",
+                "add",
+                &format!("{}/16", BRIDGE_GATEWAY),
+                "dev",
+                "rustainer0",
+            ])
             .output()?;
 
         if !output.status.success() {
@@ -340,15 +1388,10 @@ fn add_ip_to_network(
         }
     }
 
-    let ip_suffix = (container_id.len() % 254) + 2;
-    let container_ip = format!("172.19.0.{}", ip_suffix);
-
     let output = Command::new("ip")
-        .args(&[
-            "netns",
-            "exec",
+        .args([
+            "-n",
             container_id,
-            "ip",
             "addr",
             "add",
             &format!("{}/16", container_ip),
@@ -366,16 +1409,7 @@ fn add_ip_to_network(
     }
 
     let output = Command::new("ip")
-        .args(&[
-            "netns",
-            "exec",
-            container_id,
-            "ip",
-            "link",
-            "set",
-            veth_container,
-            "up",
-        ])
+        .args(["-n", container_id, "link", "set", veth_container, "up"])
         .output()?;
     if !output.status.success() {
         return Err(format!(
@@ -386,16 +1420,14 @@ fn add_ip_to_network(
     }
 
     let output = Command::new("ip")
-        .args(&[
-            "netns",
-            "exec",
+        .args([
+            "-n",
             container_id,
-            "ip",
             "route",
             "add",
             "default",
             "via",
-            "172.19.0.1",
+            BRIDGE_GATEWAY,
         ])
         .output()?;
     if !output.status.success() {
@@ -408,23 +1440,12 @@ fn add_ip_to_network(
 
     println!("🖥️ Container {} IP: {}", container_id, container_ip);
 
-    Ok(container_ip)
+    Ok(())
 }
 
-fn add_routing_rules(container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-    println!("🌐 Adding routing rules for container: {}", container_id);
-
+fn bring_up_loopback(container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
     let output = Command::new("ip")
-        .args(&[
-            "netns",
-            "exec",
-            container_id,
-            "ip",
-            "link",
-            "set",
-            "lo",
-            "up",
-        ])
+        .args(["-n", container_id, "link", "set", "lo", "up"])
         .output()?;
 
     if !output.status.success() {
@@ -435,14 +1456,22 @@ fn add_routing_rules(container_id: &str) -> Result<(), Box<dyn std::error::Error
         .into());
     }
 
+    Ok(())
+}
+
+fn add_routing_rules(container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    println!("🌐 Adding routing rules for container: {}", container_id);
+
+    bring_up_loopback(container_id)?;
+
     let output = Command::new("iptables")
-        .args(&[
+        .args([
             "-t",
             "nat",
             "-A",
             "POSTROUTING",
             "-s",
-            "172.19.0.0/16",
+            BRIDGE_SUBNET,
             "!",
             "-o",
             "rustainer0",
@@ -462,10 +1491,13 @@ fn add_routing_rules(container_id: &str) -> Result<(), Box<dyn std::error::Error
     Ok(())
 }
 
-fn setup_port_mapping(
+/// The iptables rules publishing `ports` on the container's IP, as the
+/// arguments following `-A`/`-D`, the table first where not `filter`.
+fn port_mapping_rules(
     container_ip: &str,
     ports: &[String],
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>> {
+    let mut rules = Vec::new();
     for port_mapping in ports {
         let parts: Vec<&str> = port_mapping.split(':').collect();
         if parts.len() != 2 {
@@ -477,12 +1509,12 @@ fn setup_port_mapping(
         }
         let host_port = parts[0];
         let container_port = parts[1];
+        let destination = format!("{}:{}", container_ip, container_port);
 
-        let output = Command::new("iptables")
-            .args(&[
+        let port_rules: [&[&str]; 6] = [
+            &[
                 "-t",
                 "nat",
-                "-A",
                 "PREROUTING",
                 "-p",
                 "tcp",
@@ -491,23 +1523,11 @@ fn setup_port_mapping(
                 "-j",
                 "DNAT",
                 "--to-destination",
-                &format!("{}:{}", container_ip, container_port),
-            ])
-            .output()?;
-        if !output.status.success() {
-            return Err(format!(
-                "Error configuring DNAT (PREROUTING) for port {}: {}",
-                host_port,
-                String::from_utf8_lossy(&output.stderr)
-            )
-            .into());
-        }
-
-        let output = Command::new("iptables")
-            .args(&[
+                &destination,
+            ],
+            &[
                 "-t",
                 "nat",
-                "-A",
                 "OUTPUT",
                 "-p",
                 "tcp",
@@ -516,21 +1536,9 @@ fn setup_port_mapping(
                 "-j",
                 "DNAT",
                 "--to-destination",
-                &format!("{}:{}", container_ip, container_port),
-            ])
-            .output()?;
-        if !output.status.success() {
-            return Err(format!(
-                "Error configuring DNAT (OUTPUT) for port {}: {}",
-                host_port,
-                String::from_utf8_lossy(&output.stderr)
-            )
-            .into());
-        }
-
-        let output = Command::new("iptables")
-            .args(&[
-                "-A",
+                &destination,
+            ],
+            &[
                 "FORWARD",
                 "-i",
                 "rustainer0",
@@ -539,19 +1547,8 @@ fn setup_port_mapping(
                 "rustainer0",
                 "-j",
                 "ACCEPT",
-            ])
-            .output()?;
-        if !output.status.success() {
-            return Err(format!(
-                "Error allowing FORWARD for host to container: {}",
-                String::from_utf8_lossy(&output.stderr)
-            )
-            .into());
-        }
-
-        let output = Command::new("iptables")
-            .args(&[
-                "-A",
+            ],
+            &[
                 "FORWARD",
                 "-o",
                 "rustainer0",
@@ -561,19 +1558,8 @@ fn setup_port_mapping(
                 "RELATED,ESTABLISHED",
                 "-j",
                 "ACCEPT",
-            ])
-            .output()?;
-        if !output.status.success() {
-            return Err(format!(
-                "Erro ao permitir FORWARD para conexões estabelecidas: {}",
-                String::from_utf8_lossy(&output.stderr)
-            )
-            .into());
-        }
-
-        let output = Command::new("iptables")
-            .args(&[
-                "-A",
+            ],
+            &[
                 "FORWARD",
                 "-d",
                 container_ip,
@@ -585,19 +1571,8 @@ fn setup_port_mapping(
                 "rustainer0",
                 "-j",
                 "ACCEPT",
-            ])
-            .output()?;
-        if !output.status.success() {
-            return Err(format!(
-                "Erro ao permitir FORWARD para o container: {}",
-                String::from_utf8_lossy(&output.stderr)
-            )
-            .into());
-        }
-
-        let output = Command::new("iptables")
-            .args(&[
-                "-A",
+            ],
+            &[
                 "FORWARD",
                 "-o",
                 "rustainer0",
@@ -607,12 +1582,43 @@ fn setup_port_mapping(
                 "RELATED,ESTABLISHED",
                 "-j",
                 "ACCEPT",
-            ])
-            .output()?;
+            ],
+        ];
+        rules.extend(
+            port_rules
+                .iter()
+                .map(|rule| rule.iter().map(|arg| arg.to_string()).collect()),
+        );
+    }
+    Ok(rules)
+}
+
+/// Runs iptables with `action` (`-A` or `-D`) inserted before the chain of
+/// `rule`.
+fn iptables_rule(action: &str, rule: &[String]) -> std::io::Result<std::process::Output> {
+    let chain_at = if rule.first().map(String::as_str) == Some("-t") {
+        2
+    } else {
+        0
+    };
+    Command::new("iptables")
+        .args(&rule[..chain_at])
+        .arg(action)
+        .args(&rule[chain_at..])
+        .output()
+}
+
+fn setup_port_mapping(
+    container_ip: &str,
+    ports: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for rule in port_mapping_rules(container_ip, ports)? {
+        let output = iptables_rule("-A", &rule)?;
         if !output.status.success() {
             return Err(format!(
-                "Erro ao permitir FORWARD para conexões estabelecidas (container): {}",
-                String::from_utf8_lossy(&output.stderr)
+                "Error adding iptables rule {}: {}",
+                rule.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
             )
             .into());
         }
@@ -621,7 +1627,54 @@ fn setup_port_mapping(
     Ok(())
 }
 
-fn prepare_environment(user_envs: &[String], image_envs: &[String]) -> HashMap<String, String> {
+/// Deletes the rules `setup_port_mapping` added for the container, one
+/// instance of each, leaving those of other containers and the host alone.
+pub fn remove_port_mapping(container_ip: &str, ports: &[String]) {
+    for rule in port_mapping_rules(container_ip, ports).unwrap_or_default() {
+        let _ = iptables_rule("-D", &rule);
+    }
+}
+
+/// Reads `KEY=VALUE` lines, skipping blanks and `#` comments. A bare `KEY`
+/// takes its value from the host environment and is dropped if unset there.
+fn read_env_file(path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read env file {}: {}", path, e))?;
+
+    let mut envs = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((key, _)) if key.trim().is_empty() || key.contains(char::is_whitespace) => {
+                return Err(format!(
+                    "Invalid variable name in {} line {}: {}",
+                    path,
+                    line_number + 1,
+                    line
+                )
+                .into());
+            }
+            Some(_) => envs.push(line.to_string()),
+            None => {
+                if let Ok(value) = std::env::var(line) {
+                    envs.push(format!("{}={}", line, value));
+                }
+            }
+        }
+    }
+
+    Ok(envs)
+}
+
+fn prepare_environment(
+    user_envs: &[String],
+    file_envs: &[String],
+    image_envs: &[String],
+) -> HashMap<String, String> {
     let mut env_map = HashMap::new();
 
     // USER=root -> USER : root
@@ -633,7 +1686,7 @@ fn prepare_environment(user_envs: &[String], image_envs: &[String]) -> HashMap<S
         }
     }
 
-    for env_var in user_envs {
+    for env_var in file_envs.iter().chain(user_envs) {
         if let Some(pos) = env_var.find('=') {
             let key = env_var[..pos].to_string();
             let value = env_var[pos + 1..].to_string();
@@ -665,84 +1718,1126 @@ fn prepare_command(
     }
 }
 
+/// Resolves the working directory (`--workdir`, then the image `WorkingDir`,
+/// then `/`) and creates it in the rootfs when the image lacks it.
+fn prepare_workdir(
+    rootfs_path: &str,
+    user_workdir: Option<&str>,
+    image_workdir: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let workdir = match user_workdir {
+        Some(dir) => dir,
+        None if !image_workdir.is_empty() => image_workdir,
+        None => "/",
+    };
+
+    if !workdir.starts_with('/') {
+        return Err(format!(
+            "The working directory must be an absolute path, got {}",
+            workdir
+        )
+        .into());
+    }
+    if Path::new(workdir)
+        .components()
+        .any(|component| component == std::path::Component::ParentDir)
+    {
+        return Err(format!(
+            "The working directory must not contain '..', got {}",
+            workdir
+        )
+        .into());
+    }
+
+    // Create the directory where the container will see it, never through a
+    // symlink pointing out of the rootfs.
+    let resolved = volume::resolve_in_rootfs(Path::new(rootfs_path), workdir)?;
+    let host_path = Path::new(rootfs_path).join(resolved.strip_prefix("/")?);
+    if fs::symlink_metadata(&host_path).is_err() {
+        fs::create_dir_all(&host_path)?;
+    }
+
+    Ok(workdir.to_string())
+}
+
+pub(crate) fn build_container_command(
+    container_id: &str,
+    rootfs_path: &str,
+    process: &ProcessSpec,
+) -> Result<Command, Box<dyn std::error::Error>> {
+    match process.runtime.as_deref() {
+        Some(actions::vm::RUNTIME) => {
+            return actions::vm::command(container_id, rootfs_path, process)
+        }
+        Some(runtime) => return oci::runtime_command(runtime, container_id, rootfs_path, process),
+        None => {}
+    }
+
+    if process.wasm {
+        let mut cmd = actions::wasm::command(rootfs_path, process)?;
+        if let Some(path) = &process.cgroup {
+            let cgroup_procs = CString::new(format!("{}/cgroup.procs", path))?;
+            unsafe {
+                cmd.pre_exec(move || write_file(&cgroup_procs, b"0"));
+            }
+        }
+        return Ok(cmd);
+    }
+
+    let mut cmd = Command::new(&process.args[0]);
+    cmd.args(&process.args[1..]);
+
+    for (key, value) in &process.env {
+        cmd.env(key, value);
+    }
+
+    for dir in CONTAINER_MOUNT_POINTS {
+        fs::create_dir_all(format!("{}{}", rootfs_path, dir))?;
+    }
+
+    // Paths are converted up front, allocating after fork is not safe.
+    let cgroup_procs = process
+        .cgroup
+        .as_ref()
+        .and_then(|path| CString::new(format!("{}/cgroup.procs", path)).ok());
+    let netns = if process.network.has_namespace() {
+        let path = format!("/run/netns/{}", container_id);
+        Some(
+            fs::File::open(&path)
+                .map_err(|e| format!("Failed to open network namespace {}: {}", path, e))?,
+        )
+    } else {
+        None
+    };
+    let userns = process
+        .userns
+        .as_ref()
+        .map(userns::create_namespace)
+        .transpose()?;
+    let idmapped_root = match &userns {
+        Some(userns) if process.idmapped_rootfs => Some(
+            userns::idmapped_mount(rootfs_path, userns)
+                .map_err(|e| format!("Failed to create idmapped rootfs mount: {}", e))?,
+        ),
+        _ => None,
+    };
+    let mounts: Vec<(CString, CString, libc::c_ulong, libc::c_ulong, bool)> = process
+        .mounts
+        .iter()
+        .filter_map(|mount| {
+            let flags = if mount.recursive {
+                libc::MS_BIND | libc::MS_REC
+            } else {
+                libc::MS_BIND
+            };
+            Some((
+                CString::new(mount.source.as_str()).ok()?,
+                CString::new(format!("{}{}", rootfs_path, mount.target)).ok()?,
+                flags,
+                mount.propagation.unwrap_or(Propagation::Rprivate).flags(),
+                mount.readonly,
+            ))
+        })
+        .collect();
+    let tmpfs: Vec<(CString, libc::c_ulong, CString)> = process
+        .tmpfs
+        .iter()
+        .filter_map(|tmpfs| {
+            let (flags, data) = tmpfs.mount_options();
+            Some((
+                CString::new(format!("{}{}", rootfs_path, tmpfs.target)).ok()?,
+                flags,
+                CString::new(data).ok()?,
+            ))
+        })
+        .collect();
+    // Decrypted now, the rootfs only gets the directory they appear in.
+    let secrets = if process.secrets.is_empty() {
+        None
+    } else {
+        let dir = volume::resolve_in_rootfs(Path::new(rootfs_path), secret::MOUNT_DIR)?;
+        let dir = format!("{}{}", rootfs_path, dir.display());
+        fs::create_dir_all(&dir)?;
+        let files = process
+            .secrets
+            .iter()
+            .map(|name| {
+                Ok((
+                    CString::new(format!("{}/{}", dir, name))?,
+                    secret::read_secret(name)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+        Some((CString::new(dir)?, files))
+    };
+    let dev = (!process.privileged)
+        .then(|| CString::new(format!("{}/dev", rootfs_path)).unwrap_or_default());
+    let dev_pts = CString::new(format!("{}/dev/pts", rootfs_path)).unwrap_or_default();
+    let dev_shm = CString::new(format!("{}/dev/shm", rootfs_path)).unwrap_or_default();
+    let sys = (!process.privileged)
+        .then(|| CString::new(format!("{}/sys", rootfs_path)).unwrap_or_default());
+    // Containers only see their own cgroup, through a cgroup namespace.
+    let cgroup_mount = process
+        .cgroup
+        .as_ref()
+        .filter(|_| !process.privileged)
+        .map(|_| CString::new(format!("{}/sys/fs/cgroup", rootfs_path)).unwrap_or_default());
+    let cgroup_mount_flags = if process.nested {
+        libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC
+    } else {
+        libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC
+    };
+    // Moved into the leaf once the cgroup namespace is rooted at the
+    // container's own group, which then delegates its controllers.
+    let delegation = match &process.cgroup {
+        Some(_) if process.nested && cgroup_mount.is_some() => {
+            let leaf = cgroup::delegation_leaf(container_id);
+            fs::create_dir_all(&leaf)?;
+            Some((
+                CString::new(format!("{}/cgroup.procs", leaf))?,
+                CString::new(format!(
+                    "{}/cgroup.subtree_control",
+                    cgroup::cgroup_path(container_id)
+                ))?,
+                cgroup::delegated_controllers(container_id)?,
+            ))
+        }
+        _ => None,
+    };
+    let devices: Vec<(CString, CString)> = process
+        .devices
+        .iter()
+        .filter_map(|device| {
+            Some((
+                CString::new(device.as_str()).ok()?,
+                CString::new(format!("{}{}", rootfs_path, device)).ok()?,
+            ))
+        })
+        .collect();
+    // Directories below /dev holding devices, such as /dev/net, parents first.
+    let device_dirs: Vec<CString> = process
+        .devices
+        .iter()
+        .flat_map(|device| Path::new(device).ancestors().skip(1))
+        .filter(|dir| dir.starts_with("/dev") && *dir != Path::new("/dev"))
+        .map(|dir| dir.display().to_string())
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .filter_map(|dir| CString::new(format!("{}{}", rootfs_path, dir)).ok())
+        .collect();
+    let dev_symlinks: Vec<(CString, CString)> = DEV_SYMLINKS
+        .iter()
+        .filter_map(|(name, target)| {
+            Some((
+                CString::new(format!("{}/dev/{}", rootfs_path, name)).ok()?,
+                CString::new(*target).ok()?,
+            ))
+        })
+        .collect();
+    let shm_options = CString::new(format!(
+        "mode=1777,size={}",
+        process.shm_size.unwrap_or(DEFAULT_SHM_SIZE)
+    ))
+    .unwrap_or_default();
+    let read_only = process.read_only;
+    let root = CString::new(rootfs_path).unwrap_or_default();
+    let proc_path = CString::new(format!("{}/proc", rootfs_path)).unwrap_or_default();
+    let cwd = CString::new(process.cwd.as_str()).unwrap_or_default();
+    let caps_to_drop = process
+        .capabilities
+        .as_deref()
+        .map(actions::capabilities::to_drop)
+        .unwrap_or_default();
+    let seccomp_filter = process
+        .seccomp
+        .as_ref()
+        .map(|profile| seccomp::compile(profile, process.capabilities.as_deref()))
+        .transpose()?;
+    let label_attr = process.label.as_ref().map(|label| {
+        let (paths, value) = label.exec_attr();
+        let paths: Vec<CString> = paths
+            .iter()
+            .filter_map(|path| CString::new(*path).ok())
+            .collect();
+        (paths, value)
+    });
+    let (uid, gid) = (process.uid, process.gid);
+    let groups: Vec<libc::gid_t> = process.additional_gids.clone();
+    let oom_score_adj = process
+        .oom_score_adj
+        .map(|adj| CString::new(adj.to_string()).unwrap_or_default());
+    let sysctl = |network: bool| -> Vec<(CString, CString)> {
+        process
+            .sysctls
+            .iter()
+            .filter(|sysctl| sysctl.is_network() == network)
+            .filter_map(|sysctl| {
+                Some((
+                    CString::new(sysctl.proc_path()).ok()?,
+                    CString::new(sysctl.value.as_str()).ok()?,
+                ))
+            })
+            .collect()
+    };
+    let (net_sysctls, ipc_sysctls) = (sysctl(true), sysctl(false));
+    let init = process.init;
+    let tty = process.tty;
+    // With the host's UTS namespace the host name is left alone.
+    let hostname = if process.uts_mode.is_host() {
+        String::new()
+    } else {
+        process.hostname.clone()
+    };
+    let mut namespaces = libc::CLONE_NEWNS;
+    for (mode, flag) in [
+        (process.pid_mode, libc::CLONE_NEWPID),
+        (process.ipc_mode, libc::CLONE_NEWIPC),
+        (process.uts_mode, libc::CLONE_NEWUTS),
+    ] {
+        if !mode.is_host() {
+            namespaces |= flag;
+        }
+    }
+    if cgroup_mount.is_some() {
+        namespaces |= libc::CLONE_NEWCGROUP;
+    }
+    let ipc_host = process.ipc_mode.is_host();
+
+    unsafe {
+        cmd.pre_exec(move || {
+            // Writing 0 moves the writing process, everything it starts
+            // stays in the cgroup.
+            if let Some(cgroup_procs) = &cgroup_procs {
+                let fd = libc::open(cgroup_procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+                if fd < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                let written = libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+                libc::close(fd);
+                if written != 1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+
+            // Lowering the score needs privileges in the host's user
+            // namespace, so it is set before joining the container's.
+            if let Some(oom_score_adj) = &oom_score_adj {
+                write_file(c"/proc/self/oom_score_adj", oom_score_adj.as_bytes())?;
+            }
+
+            if let Some(netns) = &netns {
+                if libc::setns(netns.as_raw_fd(), libc::CLONE_NEWNET) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            // /proc/sys shows the writer's namespaces. The network namespace
+            // belongs to the host's user namespace, so its settings are made
+            // before joining the container's.
+            for (path, value) in &net_sysctls {
+                write_file(path, value.as_bytes())?;
+            }
+
+            // Joined after the network namespace, which needs the host's
+            // capabilities. Everything unshared below belongs to it, and the
+            // process becomes the namespace's root.
+            if let Some(userns) = &userns {
+                if libc::setns(userns.as_raw_fd(), libc::CLONE_NEWUSER) != 0
+                    || libc::setresgid(0, 0, 0) != 0
+                    || libc::setresuid(0, 0, 0) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+
+            if libc::unshare(namespaces) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            if let Some((leaf_procs, subtree_control, controllers)) = &delegation {
+                write_file(leaf_procs, b"0")?;
+                if !controllers.is_empty() {
+                    write_file(subtree_control, controllers.as_bytes())?;
+                }
+            }
+            for (path, value) in &ipc_sysctls {
+                write_file(path, value.as_bytes())?;
+            }
+            if !hostname.is_empty()
+                && libc::sethostname(hostname.as_ptr() as *const libc::c_char, hostname.len()) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            // Mounts made on the host still show up in the container, none
+            // made in the container reach the host; bind mounts pick their
+            // own propagation below.
+            if libc::mount(
+                std::ptr::null(),
+                c"/".as_ptr(),
+                std::ptr::null(),
+                libc::MS_REC | libc::MS_SLAVE,
+                std::ptr::null(),
+            ) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+            if let Some(tree) = &idmapped_root {
+                if libc::syscall(
+                    libc::SYS_move_mount,
+                    tree.as_raw_fd(),
+                    c"".as_ptr(),
+                    libc::AT_FDCWD,
+                    root.as_ptr(),
+                    libc::MOVE_MOUNT_F_EMPTY_PATH,
+                ) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            // The rootfs is a plain directory, binding it onto itself makes
+            // it a mount pivot_root can switch to and that can be remounted
+            // read-only below.
+            if idmapped_root.is_none()
+                && libc::mount(
+                    root.as_ptr(),
+                    root.as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_BIND | libc::MS_REC,
+                    std::ptr::null(),
+                ) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+            // A fresh /dev with only the listed host devices, bound rather
+            // than created as mknod is refused in a user namespace.
+            if let Some(dev) = &dev {
+                if libc::mount(
+                    c"tmpfs".as_ptr(),
+                    dev.as_ptr(),
+                    c"tmpfs".as_ptr(),
+                    libc::MS_NOSUID | libc::MS_STRICTATIME,
+                    c"mode=755,size=65536k".as_ptr() as *const libc::c_void,
+                ) != 0
+                    || libc::mkdir(dev_pts.as_ptr(), 0o755) != 0
+                    || libc::mount(
+                        c"devpts".as_ptr(),
+                        dev_pts.as_ptr(),
+                        c"devpts".as_ptr(),
+                        libc::MS_NOSUID | libc::MS_NOEXEC,
+                        c"newinstance,ptmxmode=0666,mode=0620".as_ptr() as *const libc::c_void,
+                    ) != 0
+                    || libc::mkdir(dev_shm.as_ptr(), 0o1777) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+                // Shared memory in the host's IPC namespace is the host's
+                // /dev/shm.
+                let shm_mounted = if ipc_host {
+                    libc::mount(
+                        c"/dev/shm".as_ptr(),
+                        dev_shm.as_ptr(),
+                        std::ptr::null(),
+                        libc::MS_BIND | libc::MS_REC,
+                        std::ptr::null(),
+                    )
+                } else {
+                    libc::mount(
+                        c"shm".as_ptr(),
+                        dev_shm.as_ptr(),
+                        c"tmpfs".as_ptr(),
+                        libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                        shm_options.as_ptr() as *const libc::c_void,
+                    )
+                };
+                if shm_mounted != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                for dir in &device_dirs {
+                    if libc::mkdir(dir.as_ptr(), 0o755) != 0
+                        && std::io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST)
+                    {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                for (source, target) in &devices {
+                    let fd = libc::open(
+                        target.as_ptr(),
+                        libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC,
+                        0o666,
+                    );
+                    if fd < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    libc::close(fd);
+                    if libc::mount(
+                        source.as_ptr(),
+                        target.as_ptr(),
+                        std::ptr::null(),
+                        libc::MS_BIND,
+                        std::ptr::null(),
+                    ) != 0
+                    {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                for (link, target) in &dev_symlinks {
+                    if libc::symlink(target.as_ptr(), link.as_ptr()) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+            }
+            // sysfs can only be mounted by the network namespace's owner, so
+            // a user namespace gets a bind of the host's instead. It has to
+            // include the mounts below, which the kernel locks together.
+            if let Some(sys) = &sys {
+                let mounted = if userns.is_some() {
+                    libc::mount(
+                        c"/sys".as_ptr(),
+                        sys.as_ptr(),
+                        std::ptr::null(),
+                        libc::MS_BIND | libc::MS_REC,
+                        std::ptr::null(),
+                    ) == 0
+                        && libc::mount(
+                            std::ptr::null(),
+                            sys.as_ptr(),
+                            std::ptr::null(),
+                            libc::MS_BIND
+                                | libc::MS_REMOUNT
+                                | libc::MS_RDONLY
+                                | libc::MS_NOSUID
+                                | libc::MS_NODEV
+                                | libc::MS_NOEXEC,
+                            std::ptr::null(),
+                        ) == 0
+                } else {
+                    libc::mount(
+                        c"sysfs".as_ptr(),
+                        sys.as_ptr(),
+                        c"sysfs".as_ptr(),
+                        libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                        std::ptr::null(),
+                    ) == 0
+                };
+                if !mounted {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(cgroup_mount) = &cgroup_mount {
+                if libc::mount(
+                    c"cgroup".as_ptr(),
+                    cgroup_mount.as_ptr(),
+                    c"cgroup2".as_ptr(),
+                    cgroup_mount_flags,
+                    std::ptr::null(),
+                ) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            for (source, target, flags, propagation, readonly) in &mounts {
+                if libc::mount(
+                    source.as_ptr(),
+                    target.as_ptr(),
+                    std::ptr::null(),
+                    *flags,
+                    std::ptr::null(),
+                ) != 0
+                    || libc::mount(
+                        std::ptr::null(),
+                        target.as_ptr(),
+                        std::ptr::null(),
+                        *propagation,
+                        std::ptr::null(),
+                    ) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+                // Bind mounts only become read-only through a remount.
+                if *readonly
+                    && libc::mount(
+                        std::ptr::null(),
+                        target.as_ptr(),
+                        std::ptr::null(),
+                        libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY,
+                        std::ptr::null(),
+                    ) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            for (target, flags, data) in &tmpfs {
+                if libc::mount(
+                    c"tmpfs".as_ptr(),
+                    target.as_ptr(),
+                    c"tmpfs".as_ptr(),
+                    *flags,
+                    data.as_ptr() as *const libc::c_void,
+                ) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            // Read-only once filled, like the secrets themselves. Made again
+            // in case a tmpfs such as that of --read-only covers /run.
+            if let Some((dir, files)) = &secrets {
+                let flags = libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC;
+                if libc::mkdir(dir.as_ptr(), 0o755) != 0
+                    && std::io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST)
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if libc::mount(
+                    c"tmpfs".as_ptr(),
+                    dir.as_ptr(),
+                    c"tmpfs".as_ptr(),
+                    flags,
+                    c"mode=0755".as_ptr() as *const libc::c_void,
+                ) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+                for (path, contents) in files {
+                    create_file(path, contents, 0o444)?;
+                }
+                if libc::mount(
+                    std::ptr::null(),
+                    dir.as_ptr(),
+                    std::ptr::null(),
+                    flags | libc::MS_REMOUNT | libc::MS_RDONLY,
+                    std::ptr::null(),
+                ) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            // Mounts on top of the rootfs keep their own flags.
+            if read_only
+                && libc::mount(
+                    std::ptr::null(),
+                    root.as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY,
+                    std::ptr::null(),
+                ) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            // Only children join a new PID namespace, so the command runs in
+            // a fork while this process waits and passes on its status.
+            match libc::fork() {
+                -1 => return Err(std::io::Error::last_os_error()),
+                0 => {}
+                pid => wait_and_exit(pid),
+            }
+
+            // Mounted by the child to show its PID namespace, and before the
+            // pivot: in a user namespace the kernel only allows it while the
+            // host's /proc is still visible.
+            if libc::mount(
+                c"proc".as_ptr(),
+                proc_path.as_ptr(),
+                c"proc".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                std::ptr::null(),
+            ) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+            // Stacking the old root under the new one and detaching it saves
+            // needing a directory for it in the rootfs, which may be
+            // read-only. Host mounts are gone from the namespace after.
+            if libc::chdir(root.as_ptr()) != 0
+                || libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), c".".as_ptr()) != 0
+                || libc::umount2(c".".as_ptr(), libc::MNT_DETACH) != 0
+                || libc::chdir(cwd.as_ptr()) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            // The label takes effect on exec. Written through the container's
+            // own /proc, which refers to this process.
+            if let Some((paths, value)) = &label_attr {
+                let fd = paths
+                    .iter()
+                    .map(|path| libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC))
+                    .find(|fd| *fd >= 0)
+                    .ok_or_else(std::io::Error::last_os_error)?;
+                let written = libc::write(fd, value.as_ptr() as *const libc::c_void, value.len());
+                libc::close(fd);
+                if written != value.len() as isize {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+
+            // Installed while all capabilities are still held, which spares
+            // setting no_new_privs; the filter allows what follows.
+            if let Some(filter) = &seccomp_filter {
+                seccomp::install(filter)?;
+            }
+            if actions::capabilities::apply(&caps_to_drop).is_err() {
+                return Err(std::io::Error::last_os_error());
+            }
+            // Even as root, so none of the launcher's own groups are kept.
+            if libc::setgroups(groups.len(), groups.as_ptr()) != 0
+                || libc::setgid(gid) != 0
+                || libc::setuid(uid) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            // Forked last so the init runs with the same restrictions as
+            // the command it stays behind for.
+            if init {
+                match libc::fork() {
+                    -1 => return Err(std::io::Error::last_os_error()),
+                    0 => {}
+                    pid => run_init(pid),
+                }
+            }
+
+            if tty && (libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) != 0) {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    Ok(cmd)
+}
+
+/// Copies rustainer's stdin to the container for a foreground `run -i`. The
+/// pipe closes at EOF, so the container sees the end of input too.
+fn pipe_stdin() -> Result<std::io::PipeReader, Box<dyn std::error::Error>> {
+    let (reader, mut writer) = std::io::pipe()?;
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut std::io::stdin().lock(), &mut writer);
+    });
+
+    Ok(reader)
+}
+
+/// Stdin for a container nobody is attached to: empty, or with `-i` a FIFO
+/// in the container directory that stays open. It is opened for writing as
+/// well, so the container never reads EOF from it.
+pub(crate) fn detached_stdin(
+    container_id: &str,
+    process: &ProcessSpec,
+) -> Result<Stdio, Box<dyn std::error::Error>> {
+    if !process.open_stdin {
+        return Ok(Stdio::null());
+    }
+
+    let path = format!("{}/stdin", actions::container::container_dir(container_id));
+    if !Path::new(&path).exists() {
+        let c_path = CString::new(path.as_str())?;
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+            return Err(format!(
+                "Failed to create stdin FIFO {}: {}",
+                path,
+                std::io::Error::last_os_error()
+            )
+            .into());
+        }
+    }
+
+    let fifo = fs::OpenOptions::new().read(true).write(true).open(&path)?;
+    Ok(fifo.into())
+}
+
+/// Starts a command from `build_container_command`, naming the command
+/// when it cannot be run.
+pub(crate) fn spawn_container_command(
+    mut cmd: Command,
+) -> Result<std::process::Child, Box<dyn std::error::Error>> {
+    cmd.spawn().map_err(|e| {
+        format!(
+            "Failed to start container command {:?}: {}",
+            cmd.get_program(),
+            e
+        )
+        .into()
+    })
+}
+
+/// Whether a signal was sent by a process rather than raised by the kernel,
+/// as the terminal does for Ctrl+C. Those reach the whole foreground process
+/// group, the container included, so passing them on would repeat them.
+fn sent_by_process(info: &libc::siginfo_t) -> bool {
+    info.si_code != libc::SI_KERNEL
+}
+
+/// Makes signals sent to a foreground `run` reach the container's PID 1
+/// instead of ending rustainer before it cleans up.
+fn forward_signals() {
+    extern "C" fn forward(
+        signal: libc::c_int,
+        info: *mut libc::siginfo_t,
+        _context: *mut libc::c_void,
+    ) {
+        let pid = restart::RUNNING_PID.load(std::sync::atomic::Ordering::Relaxed);
+        if pid > 0 && unsafe { sent_by_process(&*info) } {
+            unsafe { libc::kill(pid, signal) };
+        }
+    }
+
+    for signal in FORWARDED_SIGNALS {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = forward as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Writes a whole value to a file, without allocating so it can run between
+/// fork and exec.
+pub(crate) unsafe fn write_file(path: &std::ffi::CStr, value: &[u8]) -> std::io::Result<()> {
+    let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let written = libc::write(fd, value.as_ptr() as *const libc::c_void, value.len());
+    libc::close(fd);
+    if written != value.len() as isize {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Creates a file with `contents`, for use after fork.
+unsafe fn create_file(
+    path: &std::ffi::CStr,
+    contents: &[u8],
+    mode: libc::mode_t,
+) -> std::io::Result<()> {
+    let fd = libc::open(
+        path.as_ptr(),
+        libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC,
+        mode,
+    );
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let written = libc::write(fd, contents.as_ptr() as *const libc::c_void, contents.len());
+    libc::close(fd);
+    if written != contents.len() as isize {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Waits for the container's PID 1 and exits the same way, for the process
+/// left outside the container's PID namespace. Signals sent to it are
+/// passed on to PID 1.
+pub(crate) unsafe fn wait_and_exit(pid: libc::pid_t) -> ! {
+    // Nothing here reads the spawn error pipe, holding it open would stall
+    // the caller until the container exits.
+    libc::syscall(libc::SYS_close_range, 3, libc::c_uint::MAX, 0);
+
+    let mut signals: libc::sigset_t = std::mem::zeroed();
+    libc::sigemptyset(&mut signals);
+    for signal in FORWARDED_SIGNALS {
+        libc::sigaddset(&mut signals, signal);
+    }
+    libc::sigaddset(&mut signals, libc::SIGCHLD);
+    libc::sigprocmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+
+    let mut status = 0;
+    loop {
+        match libc::waitpid(pid, &mut status, libc::WNOHANG) {
+            -1 => libc::_exit(1),
+            0 => {}
+            _ => break,
+        }
+        // An exit while not waiting here leaves SIGCHLD pending.
+        let mut info: libc::siginfo_t = std::mem::zeroed();
+        let signal = libc::sigwaitinfo(&signals, &mut info);
+        if signal > 0 && signal != libc::SIGCHLD && sent_by_process(&info) {
+            libc::kill(pid, signal);
+        }
+    }
+
+    if libc::WIFSIGNALED(status) {
+        let signal = libc::WTERMSIG(status);
+        libc::signal(signal, libc::SIG_DFL);
+        libc::sigprocmask(libc::SIG_UNBLOCK, &signals, std::ptr::null_mut());
+        libc::kill(libc::getpid(), signal);
+        libc::_exit(128 + signal);
+    }
+    libc::_exit(libc::WEXITSTATUS(status))
+}
+
+/// The container's PID 1 with `--init`: forwards every signal it can catch
+/// to the command, reaps whatever gets orphaned to it, and exits as the
+/// command does.
+unsafe fn run_init(child: libc::pid_t) -> ! {
+    libc::syscall(libc::SYS_close_range, 3, libc::c_uint::MAX, 0);
+
+    // Blocked signals reach PID 1 even without handlers.
+    let mut signals: libc::sigset_t = std::mem::zeroed();
+    libc::sigfillset(&mut signals);
+    libc::sigprocmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+
+    loop {
+        let mut info: libc::siginfo_t = std::mem::zeroed();
+        let signal = libc::sigwaitinfo(&signals, &mut info);
+        if signal < 0 {
+            continue;
+        }
+        if signal != libc::SIGCHLD {
+            if sent_by_process(&info) {
+                libc::kill(child, signal);
+            }
+            continue;
+        }
+
+        let mut status = 0;
+        loop {
+            match libc::waitpid(-1, &mut status, libc::WNOHANG) {
+                pid if pid == child => {
+                    if libc::WIFSIGNALED(status) {
+                        libc::_exit(128 + libc::WTERMSIG(status));
+                    }
+                    libc::_exit(libc::WEXITSTATUS(status));
+                }
+                pid if pid > 0 => {}
+                _ => break,
+            }
+        }
+    }
+}
+
+/// Security options `--security-opt` accepts.
+const SECURITY_OPTS: [&str; 3] = ["seccomp", "apparmor", "label"];
+
+/// The values of every `--security-opt <key>=<value>` for `key`, after
+/// checking that every option is known.
+fn security_opt<'a>(
+    opts: &'a [String],
+    key: &str,
+) -> Result<Vec<&'a str>, Box<dyn std::error::Error>> {
+    let mut found = Vec::new();
+    for opt in opts {
+        // Docker also still accepts the older `key:value` form.
+        let (name, value) = opt
+            .split_once('=')
+            .or_else(|| opt.split_once(':'))
+            .ok_or_else(|| format!("Invalid security option: {}", opt))?;
+        if !SECURITY_OPTS.contains(&name) {
+            return Err(format!("Unsupported security option: {}", name).into());
+        }
+        if name == key {
+            found.push(value);
+        }
+    }
+
+    Ok(found)
+}
+
+pub fn validate_hostname(hostname: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let valid = !hostname.is_empty()
+        && hostname.len() <= 64
+        && hostname.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+
+    if !valid {
+        return Err(format!("Invalid hostname: {}", hostname).into());
+    }
+
+    Ok(())
+}
+
+pub(crate) fn write_hostname_file(
+    rootfs_path: &str,
+    hostname: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let hostname_path = volume::resolve_file_in_rootfs(Path::new(rootfs_path), "/etc/hostname")?;
+    if let Some(parent) = hostname_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Never follow a symlink planted in the image out of the rootfs.
+    if fs::symlink_metadata(&hostname_path).is_ok_and(|m| m.file_type().is_symlink()) {
+        fs::remove_file(&hostname_path)?;
+    }
+    fs::write(hostname_path, format!("{}\n", hostname))?;
+
+    Ok(())
+}
+
+/// Writes the container ID to `path` without ever exposing a partially written
+/// file, failing if `path` already exists.
+fn write_cidfile(path: &str, container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let temp_path = format!("{}.{}.tmp", path, std::process::id());
+    fs::write(&temp_path, container_id)?;
+
+    // Unlike rename, linking refuses to replace an existing file.
+    let result = fs::hard_link(&temp_path, path);
+    let _ = fs::remove_file(&temp_path);
+    result.map_err(|e| format!("Failed to create container ID file {}: {}", path, e))?;
+
+    Ok(())
+}
+
+/// Prepares a bind mount of a host file over `target` in the rootfs, creating
+/// an empty mount point if needed.
+fn bind_container_file(
+    container_path: &str,
+    source: &str,
+    target: &str,
+) -> Result<BindMount, Box<dyn std::error::Error>> {
+    let rootfs_path = PathBuf::from(format!("{}/rootfs", container_path));
+    let target_path = volume::resolve_file_in_rootfs(&rootfs_path, target)?;
+    if let Some(parent) = target_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // A symlinked mount point would be resolved against the host.
+    if fs::symlink_metadata(&target_path).is_ok_and(|m| m.file_type().is_symlink()) {
+        fs::remove_file(&target_path)?;
+    }
+    if !target_path.exists() {
+        fs::write(&target_path, "")?;
+    }
+
+    Ok(BindMount {
+        source: fs::canonicalize(source)?.to_string_lossy().to_string(),
+        target: Path::new("/")
+            .join(target_path.strip_prefix(&rootfs_path)?)
+            .to_string_lossy()
+            .to_string(),
+        readonly: false,
+        recursive: false,
+        propagation: None,
+    })
+}
+
+/// Prepares read-only bind mounts of configs over their targets.
+fn config_mounts(
+    container_path: &str,
+    configs: &[ConfigMount],
+) -> Result<Vec<BindMount>, Box<dyn std::error::Error>> {
+    let rootfs_path = format!("{}/rootfs", container_path);
+    let mut mounts = Vec::new();
+    for config_mount in configs {
+        let target = volume::resolve_in_rootfs(Path::new(&rootfs_path), &config_mount.target)?;
+        if Path::new(&format!("{}{}", rootfs_path, target.display())).is_dir() {
+            return Err(format!(
+                "Cannot mount config {} at {}, which is a directory",
+                config_mount.name, config_mount.target
+            )
+            .into());
+        }
+        let mut mount = bind_container_file(
+            container_path,
+            &actions::config::data_path(&config_mount.name),
+            &target.display().to_string(),
+        )?;
+        mount.readonly = true;
+        mounts.push(mount);
+    }
+    Ok(mounts)
+}
+
 async fn execute_container(
     container_id: &str,
     container_path: &str,
-    command: Vec<String>,
-    env_vars: HashMap<String, String>,
+    process: &ProcessSpec,
     config: &RunConfig,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<i32, Box<dyn std::error::Error>> {
     let rootfs_path = format!("{}/rootfs", container_path);
 
-    if command.is_empty() {
+    if process.args.is_empty() {
         return Err("No command specified to run in the container".into());
     }
 
-    let mut cmd = Command::new("ip");
-    cmd.args(&[
-        "netns",
-        "exec",
-        container_id,
-        "unshare",
-        "--mount",
-        "--uts",
-        "--ipc",
-        "--pid",
-        "--fork",
-        "--mount-proc",
-        "chroot",
-        &rootfs_path,
-    ]);
-    cmd.args(&command);
-
-    for (key, value) in env_vars {
-        cmd.env(key, value);
+    if process.cgroup.is_some() {
+        cgroup::create(container_id, &config.resources)?;
     }
 
-    if config.detach {
-        cmd.stdin(Stdio::null());
-        cmd.stdout(Stdio::null());
-        cmd.stderr(Stdio::null());
-    } else {
-        cmd.stdin(Stdio::inherit());
-        cmd.stdout(Stdio::inherit());
-        cmd.stderr(Stdio::inherit());
-    }
-
-    println!("🏃 Executing in network namespace: ip netns exec {} unshare --mount --uts --ipc --pid --fork --mount-proc chroot {} {:?}", 
-             container_id, rootfs_path, command);
+    let masked_values = actions::container::load_metadata(container_id)
+        .map(|metadata| redact::masked_values(&metadata))
+        .unwrap_or_default();
+    println!(
+        "🏃 Executing: {} in {} (workdir {})",
+        redact::redact_text(&format!("{:?}", process.args), &masked_values),
+        rootfs_path,
+        process.cwd
+    );
 
     if config.detach {
-        let child = cmd.spawn()?;
+        let mut supervisor = restart::spawn_supervisor(container_id)?;
+        restart::wait_for_start(container_id, &mut supervisor)?;
+
         println!(
-            "🔧 Container running in background with PID: {}",
-            child.id()
+            "🔧 Container supervised in background with restart policy {} (supervisor PID: {})",
+            config.restart_policy,
+            supervisor.id()
         );
-
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-
         println!("✅ Container started successfully");
-        return Ok(());
+        Ok(0)
     } else {
-        let status = cmd.status()?;
-
-        if let Err(e) = cleanup_container_networking(container_id) {
-            println!("⚠️ Warning: Failed to cleanup networking: {}", e);
+        let terminal = if process.tty {
+            Some(tty::Terminal::open(
+                container_id,
+                config.interactive,
+                &config.detach_keys,
+            )?)
+        } else {
+            None
+        };
+        let stdin = if config.interactive && terminal.is_none() {
+            Some(pipe_stdin()?)
+        } else {
+            None
+        };
+        forward_signals();
+        let status = restart::run_with_restarts(container_id, config.restart_policy, || {
+            let mut cmd = build_container_command(container_id, &rootfs_path, process)?;
+            match &terminal {
+                Some(terminal) => {
+                    cmd.stdin(terminal.stdio()?);
+                    cmd.stdout(terminal.stdio()?);
+                    cmd.stderr(terminal.stdio()?);
+                }
+                None => {
+                    match &stdin {
+                        Some(stdin) => cmd.stdin(stdin.try_clone()?),
+                        None => cmd.stdin(Stdio::null()),
+                    };
+                    cmd.stdout(Stdio::inherit());
+                    cmd.stderr(Stdio::inherit());
+                }
+            }
+            Ok(cmd)
+        });
+        if let Some(terminal) = terminal {
+            terminal.finish();
         }
+        let status = status?;
 
-        if !status.success() {
-            return Err(format!("Container exited with code: {:?}", status.code()).into());
+        if process.network.has_namespace() {
+            if let Err(e) = cleanup_container_networking(container_id) {
+                println!("⚠️ Warning: Failed to cleanup networking: {}", e);
+            }
         }
-    }
 
-    Ok(())
+        Ok(restart::exit_code(&status))
+    }
 }
 
 pub fn cleanup_container_networking(container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("🧹 Cleaning up networking for container: {}", container_id);
 
     let output = Command::new("ip")
-        .args(&["netns", "delete", container_id])
+        .args(["netns", "delete", container_id])
         .output()?;
 
     if !output.status.success() {
@@ -754,3 +2849,220 @@ pub fn cleanup_container_networking(container_id: &str) -> Result<(), Box<dyn st
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tmpfs_parse_defaults_to_noexec() {
+        let mount = TmpfsMount::parse("/tmp/").unwrap();
+        assert_eq!(mount.target, "/tmp");
+        assert_eq!(mount.options, "noexec");
+    }
+
+    #[test]
+    fn tmpfs_parse_keeps_options() {
+        let mount = TmpfsMount::parse("/run:size=64m,mode=1777,exec").unwrap();
+        assert_eq!(mount.target, "/run");
+        assert_eq!(mount.options, "size=64m,mode=1777,exec");
+    }
+
+    #[test]
+    fn tmpfs_parse_rejects_invalid_specs() {
+        for spec in ["tmp", "/", "/tmp/../etc", "/tmp:bogus"] {
+            assert!(
+                TmpfsMount::parse(spec).is_err(),
+                "{} should be rejected",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn tmpfs_mount_options_split_flags_from_data() {
+        let mount = TmpfsMount::parse("/tmp:ro,size=1m,mode=700").unwrap();
+        let (flags, data) = mount.mount_options();
+        assert_ne!(flags & libc::MS_RDONLY, 0);
+        assert_ne!(flags & libc::MS_NOEXEC, 0);
+        assert_ne!(flags & libc::MS_NOSUID, 0);
+        assert_eq!(data, "size=1m,mode=700");
+    }
+
+    /// Creates an empty scratch directory for a test.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rustainer-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn hostname_is_written_into_the_rootfs() {
+        let rootfs = scratch_dir("hostname");
+        write_hostname_file(rootfs.to_str().unwrap(), "web").unwrap();
+        assert_eq!(
+            fs::read_to_string(rootfs.join("etc/hostname")).unwrap(),
+            "web\n"
+        );
+        fs::remove_dir_all(&rootfs).unwrap();
+    }
+
+    #[test]
+    fn hostname_does_not_follow_symlinks_out_of_the_rootfs() {
+        let dir = scratch_dir("hostname-escape");
+        let rootfs = dir.join("rootfs");
+        let outside = dir.join("outside");
+        fs::create_dir_all(&rootfs).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+
+        // An absolute symlink for /etc and a relative one climbing out.
+        std::os::unix::fs::symlink(&outside, rootfs.join("etc")).unwrap();
+        write_hostname_file(rootfs.to_str().unwrap(), "web").unwrap();
+        assert!(!outside.join("hostname").exists());
+        let inside = rootfs.join(outside.strip_prefix("/").unwrap());
+        assert_eq!(
+            fs::read_to_string(inside.join("hostname")).unwrap(),
+            "web\n"
+        );
+
+        fs::remove_file(rootfs.join("etc")).unwrap();
+        std::os::unix::fs::symlink("../../../../../../outside", rootfs.join("etc")).unwrap();
+        write_hostname_file(rootfs.to_str().unwrap(), "web").unwrap();
+        assert!(!outside.join("hostname").exists());
+        assert!(rootfs.join("outside/hostname").exists());
+
+        // A symlinked hostname file is replaced rather than written through.
+        fs::remove_file(rootfs.join("etc")).unwrap();
+        fs::create_dir(rootfs.join("etc")).unwrap();
+        std::os::unix::fs::symlink(outside.join("hostname"), rootfs.join("etc/hostname")).unwrap();
+        write_hostname_file(rootfs.to_str().unwrap(), "web").unwrap();
+        assert!(!outside.join("hostname").exists());
+        assert!(!fs::symlink_metadata(rootfs.join("etc/hostname"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bind_container_file_resolves_its_target_inside_the_rootfs() {
+        let dir = scratch_dir("bind-file");
+        let rootfs = dir.join("rootfs");
+        let outside = dir.join("outside");
+        fs::create_dir_all(rootfs.join("run")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, rootfs.join("etc")).unwrap();
+
+        let source = dir.join("hosts");
+        fs::write(&source, "127.0.0.1 localhost\n").unwrap();
+        let mount = bind_container_file(
+            dir.to_str().unwrap(),
+            source.to_str().unwrap(),
+            "/etc/hosts",
+        )
+        .unwrap();
+
+        assert_eq!(mount.target, outside.join("hosts").to_str().unwrap());
+        assert!(!outside.join("hosts").exists());
+        assert!(rootfs.join(mount.target.trim_start_matches('/')).is_file());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn workdir_defaults_to_the_image_then_root() {
+        let rootfs = scratch_dir("workdir");
+        let rootfs_path = rootfs.to_str().unwrap();
+        assert_eq!(prepare_workdir(rootfs_path, None, "").unwrap(), "/");
+        assert_eq!(prepare_workdir(rootfs_path, None, "/app").unwrap(), "/app");
+        assert_eq!(
+            prepare_workdir(rootfs_path, Some("/srv/data"), "/app").unwrap(),
+            "/srv/data"
+        );
+        assert!(rootfs.join("app").is_dir());
+        assert!(rootfs.join("srv/data").is_dir());
+        fs::remove_dir_all(&rootfs).unwrap();
+    }
+
+    #[test]
+    fn workdir_rejects_relative_and_parent_paths() {
+        let rootfs = scratch_dir("workdir-invalid");
+        let rootfs_path = rootfs.to_str().unwrap();
+        for workdir in ["app", "/../../../tmp/x", "/app/../.."] {
+            assert!(
+                prepare_workdir(rootfs_path, Some(workdir), "").is_err(),
+                "{} should be rejected",
+                workdir
+            );
+        }
+        assert!(prepare_workdir(rootfs_path, None, "/../x").is_err());
+        fs::remove_dir_all(&rootfs).unwrap();
+    }
+
+    #[test]
+    fn workdir_is_created_through_symlinks_inside_the_rootfs() {
+        let dir = scratch_dir("workdir-escape");
+        let rootfs = dir.join("rootfs");
+        let outside = dir.join("outside");
+        fs::create_dir_all(&rootfs).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, rootfs.join("app")).unwrap();
+        std::os::unix::fs::symlink("../../../../../outside", rootfs.join("srv")).unwrap();
+
+        let rootfs_path = rootfs.to_str().unwrap();
+        assert_eq!(
+            prepare_workdir(rootfs_path, None, "/app/src").unwrap(),
+            "/app/src"
+        );
+        prepare_workdir(rootfs_path, Some("/srv/data"), "").unwrap();
+
+        assert!(!outside.join("src").exists());
+        assert!(!outside.join("data").exists());
+        assert!(rootfs
+            .join(outside.strip_prefix("/").unwrap())
+            .join("src")
+            .is_dir());
+        assert!(rootfs.join("outside/data").is_dir());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn port_mapping_rules_target_the_container() {
+        let ports = vec!["8080:80".to_string(), "8443:443".to_string()];
+        let rules = port_mapping_rules("10.0.0.5", &ports).unwrap();
+        assert_eq!(rules.len(), 12);
+
+        let dnat: Vec<&Vec<String>> = rules
+            .iter()
+            .filter(|rule| rule.contains(&"DNAT".to_string()))
+            .collect();
+        assert_eq!(dnat.len(), 4);
+        assert_eq!(dnat[0][2], "PREROUTING");
+        assert_eq!(dnat[1][2], "OUTPUT");
+        assert!(dnat[0].ends_with(&["--to-destination".to_string(), "10.0.0.5:80".to_string()]));
+        assert!(dnat[2].ends_with(&["--to-destination".to_string(), "10.0.0.5:443".to_string()]));
+        assert!(dnat[2].contains(&"8443".to_string()));
+
+        assert!(rules.iter().any(|rule| rule.starts_with(&[
+            "FORWARD".to_string(),
+            "-d".to_string(),
+            "10.0.0.5".to_string(),
+        ]) && rule.contains(&"443".to_string())));
+    }
+
+    #[test]
+    fn port_mapping_rules_reject_invalid_mappings() {
+        for port in ["80", "1:2:3"] {
+            assert!(
+                port_mapping_rules("10.0.0.5", &[port.to_string()]).is_err(),
+                "{} should be rejected",
+                port
+            );
+        }
+        assert!(port_mapping_rules("10.0.0.5", &[]).unwrap().is_empty());
+    }
+}
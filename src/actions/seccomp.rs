@@ -0,0 +1,514 @@
+use libc::sock_filter;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+/// Set on x32 ABI syscall numbers, which would slip past a filter written
+/// for x86_64 numbers.
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+const LOAD: u16 = (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16;
+const JEQ: u16 = (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16;
+const JGE: u16 = (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16;
+const AND: u16 = (libc::BPF_ALU | libc::BPF_AND | libc::BPF_K) as u16;
+const RET: u16 = (libc::BPF_RET | libc::BPF_K) as u16;
+
+// Offsets into struct seccomp_data.
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+const ARGS_OFFSET: u32 = 16;
+
+/// Blocked for every container unless the profile is overridden.
+const BLOCKED_SYSCALLS: [&str; 21] = [
+    "acct",
+    "add_key",
+    "bpf",
+    "create_module",
+    "get_kernel_syms",
+    "ioperm",
+    "iopl",
+    "kexec_file_load",
+    "kexec_load",
+    "keyctl",
+    "lookup_dcookie",
+    "open_by_handle_at",
+    "perf_event_open",
+    "query_module",
+    "request_key",
+    "swapoff",
+    "swapon",
+    "_sysctl",
+    "sysfs",
+    "userfaultfd",
+    "uselib",
+];
+/// Blocked unless the container keeps the capability guarding them, so
+/// `--cap-add` is enough to use them.
+const CAPABILITY_GUARDED_SYSCALLS: [(&str, &[&str]); 4] = [
+    (
+        "CAP_SYS_ADMIN",
+        &[
+            "fsconfig",
+            "fsmount",
+            "fsopen",
+            "fspick",
+            "mount",
+            "move_mount",
+            "open_tree",
+            "pivot_root",
+            "quotactl",
+            "setns",
+            "syslog",
+            "umount2",
+            "unshare",
+        ],
+    ),
+    (
+        "CAP_SYS_MODULE",
+        &["delete_module", "finit_module", "init_module"],
+    ),
+    ("CAP_SYS_BOOT", &["reboot"]),
+    (
+        "CAP_SYS_TIME",
+        &["clock_adjtime", "clock_settime", "settimeofday"],
+    ),
+];
+
+/// A seccomp profile in the JSON format Docker uses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeccompProfile {
+    pub default_action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_errno_ret: Option<u32>,
+    #[serde(default)]
+    pub syscalls: Vec<SyscallRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyscallRule {
+    #[serde(default)]
+    pub names: Vec<String>,
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errno_ret: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<ArgCondition>,
+    #[serde(default, skip_serializing_if = "RuleFilter::is_empty")]
+    pub includes: RuleFilter,
+    #[serde(default, skip_serializing_if = "RuleFilter::is_empty")]
+    pub excludes: RuleFilter,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArgCondition {
+    pub index: u32,
+    pub value: u64,
+    #[serde(default)]
+    pub value_two: u64,
+    pub op: String,
+}
+
+/// Limits a rule to containers with all (for includes) or none (for
+/// excludes) of the given capabilities.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleFilter {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub caps: Vec<String>,
+}
+
+impl RuleFilter {
+    fn is_empty(&self) -> bool {
+        self.caps.is_empty()
+    }
+}
+
+impl SyscallRule {
+    fn applies(&self, capabilities: Option<&[String]>) -> bool {
+        let has = |cap: &String| capabilities.is_none_or(|kept| kept.contains(cap));
+        self.includes.caps.iter().all(has) && !self.excludes.caps.iter().any(has)
+    }
+}
+
+/// The profile containers get without `--security-opt seccomp=`: everything
+/// is allowed except syscalls that reach outside the container.
+pub fn default_profile() -> SeccompProfile {
+    let errno_rule = |names: &[&str]| SyscallRule {
+        names: names.iter().map(|name| name.to_string()).collect(),
+        action: "SCMP_ACT_ERRNO".to_string(),
+        errno_ret: None,
+        args: Vec::new(),
+        includes: RuleFilter::default(),
+        excludes: RuleFilter::default(),
+    };
+
+    let mut syscalls = vec![errno_rule(&BLOCKED_SYSCALLS)];
+    for (capability, names) in CAPABILITY_GUARDED_SYSCALLS {
+        syscalls.push(SyscallRule {
+            excludes: RuleFilter {
+                caps: vec![capability.to_string()],
+            },
+            ..errno_rule(names)
+        });
+    }
+
+    SeccompProfile {
+        default_action: "SCMP_ACT_ALLOW".to_string(),
+        default_errno_ret: None,
+        syscalls,
+    }
+}
+
+/// Resolves a `--security-opt seccomp=` value: `unconfined` or the path of a
+/// JSON profile. Privileged containers are unconfined.
+pub fn resolve(
+    value: Option<&str>,
+    privileged: bool,
+) -> Result<Option<SeccompProfile>, Box<dyn std::error::Error>> {
+    match value {
+        Some("unconfined") => Ok(None),
+        Some(path) => {
+            let content = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read seccomp profile {}: {}", path, e))?;
+            let profile = serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid seccomp profile {}: {}", path, e))?;
+            Ok(Some(profile))
+        }
+        None if privileged => Ok(None),
+        None => Ok(Some(default_profile())),
+    }
+}
+
+/// Compiles a profile into a BPF program for the native architecture.
+/// Syscalls this architecture lacks are skipped, as profiles usually cover
+/// several.
+pub fn compile(
+    profile: &SeccompProfile,
+    capabilities: Option<&[String]>,
+) -> Result<Vec<sock_filter>, Box<dyn std::error::Error>> {
+    let default_action = action_value(&profile.default_action, profile.default_errno_ret)?;
+
+    let mut program = vec![
+        stmt(LOAD, ARCH_OFFSET),
+        jump(JEQ, AUDIT_ARCH, 1, 0),
+        stmt(RET, libc::SECCOMP_RET_KILL_PROCESS),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    program.extend([
+        stmt(LOAD, NR_OFFSET),
+        jump(JGE, X32_SYSCALL_BIT, 0, 1),
+        stmt(RET, libc::SECCOMP_RET_KILL_PROCESS),
+    ]);
+
+    for rule in &profile.syscalls {
+        if !rule.applies(capabilities) {
+            continue;
+        }
+        let action = action_value(&rule.action, rule.errno_ret.or(profile.default_errno_ret))?;
+        for name in &rule.names {
+            if let Some(nr) = syscall_number(name) {
+                program.extend(rule_block(nr, &rule.args, action)?);
+            }
+        }
+    }
+    program.push(stmt(RET, default_action));
+
+    if program.len() > libc::BPF_MAXINSNS as usize {
+        return Err(format!(
+            "Seccomp profile is too large ({} instructions, at most {})",
+            program.len(),
+            libc::BPF_MAXINSNS
+        )
+        .into());
+    }
+
+    Ok(program)
+}
+
+/// The profile as the `linux.seccomp` section of an OCI runtime spec, with
+/// the rules `capabilities` select already picked out.
+pub fn to_oci(profile: &SeccompProfile, capabilities: Option<&[String]>) -> serde_json::Value {
+    let syscalls: Vec<serde_json::Value> = profile
+        .syscalls
+        .iter()
+        .filter(|rule| rule.applies(capabilities))
+        .map(|rule| {
+            let mut syscall = serde_json::json!({
+                "names": rule.names,
+                "action": rule.action,
+                "args": rule.args,
+            });
+            if let Some(errno) = rule.errno_ret.or(profile.default_errno_ret) {
+                syscall["errnoRet"] = errno.into();
+            }
+            syscall
+        })
+        .collect();
+
+    let mut seccomp = serde_json::json!({
+        "defaultAction": profile.default_action,
+        "syscalls": syscalls,
+    });
+    if let Some(errno) = profile.default_errno_ret {
+        seccomp["defaultErrnoRet"] = errno.into();
+    }
+    seccomp
+}
+
+/// Installs a compiled filter on the calling thread, for use between fork
+/// and exec.
+pub fn install(program: &[sock_filter]) -> std::io::Result<()> {
+    let prog = libc::sock_fprog {
+        len: program.len() as u16,
+        filter: program.as_ptr() as *mut sock_filter,
+    };
+    let result = unsafe {
+        libc::prctl(
+            libc::PR_SET_SECCOMP,
+            libc::SECCOMP_MODE_FILTER,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// One syscall's check: returns `action` when the syscall number and every
+/// argument condition match, otherwise falls through to the next block.
+fn rule_block(
+    nr: i64,
+    args: &[ArgCondition],
+    action: u32,
+) -> Result<Vec<sock_filter>, Box<dyn std::error::Error>> {
+    let mut block = vec![stmt(LOAD, NR_OFFSET), jump(JEQ, nr as u32, 0, 0)];
+    // Jumps leaving the block, as (index, on match), patched once the
+    // block's length is known.
+    let mut exits = vec![(1, false)];
+
+    for arg in args {
+        if arg.index > 5 {
+            return Err(format!("Invalid seccomp argument index: {}", arg.index).into());
+        }
+        let low = ARGS_OFFSET + arg.index * 8;
+        let high = low + 4;
+        let (value_high, value_low) = ((arg.value >> 32) as u32, arg.value as u32);
+
+        match arg.op.as_str() {
+            "SCMP_CMP_EQ" => {
+                block.push(stmt(LOAD, high));
+                exits.push((block.len(), false));
+                block.push(jump(JEQ, value_high, 0, 0));
+                block.push(stmt(LOAD, low));
+                exits.push((block.len(), false));
+                block.push(jump(JEQ, value_low, 0, 0));
+            }
+            "SCMP_CMP_NE" => {
+                // Differing high words already match, skip the low word.
+                block.push(stmt(LOAD, high));
+                block.push(jump(JEQ, value_high, 0, 2));
+                block.push(stmt(LOAD, low));
+                exits.push((block.len(), true));
+                block.push(jump(JEQ, value_low, 0, 0));
+            }
+            "SCMP_CMP_MASKED_EQ" => {
+                let (mask_high, mask_low) = ((arg.value >> 32) as u32, arg.value as u32);
+                let (value_high, value_low) = ((arg.value_two >> 32) as u32, arg.value_two as u32);
+                block.push(stmt(LOAD, high));
+                block.push(stmt(AND, mask_high));
+                exits.push((block.len(), false));
+                block.push(jump(JEQ, value_high, 0, 0));
+                block.push(stmt(LOAD, low));
+                block.push(stmt(AND, mask_low));
+                exits.push((block.len(), false));
+                block.push(jump(JEQ, value_low, 0, 0));
+            }
+            op => return Err(format!("Unsupported seccomp operator: {}", op).into()),
+        }
+    }
+    block.push(stmt(RET, action));
+
+    for (index, on_match) in exits {
+        let offset = u8::try_from(block.len() - index - 1)
+            .map_err(|_| "Seccomp rule has too many argument conditions")?;
+        if on_match {
+            block[index].jt = offset;
+        } else {
+            block[index].jf = offset;
+        }
+    }
+
+    Ok(block)
+}
+
+fn action_value(action: &str, errno: Option<u32>) -> Result<u32, Box<dyn std::error::Error>> {
+    match action {
+        "SCMP_ACT_ALLOW" => Ok(libc::SECCOMP_RET_ALLOW),
+        "SCMP_ACT_ERRNO" => {
+            let errno = errno.unwrap_or(libc::EPERM as u32);
+            Ok(libc::SECCOMP_RET_ERRNO | (errno & libc::SECCOMP_RET_DATA))
+        }
+        "SCMP_ACT_KILL" | "SCMP_ACT_KILL_THREAD" => Ok(libc::SECCOMP_RET_KILL_THREAD),
+        "SCMP_ACT_KILL_PROCESS" => Ok(libc::SECCOMP_RET_KILL_PROCESS),
+        "SCMP_ACT_TRAP" => Ok(libc::SECCOMP_RET_TRAP),
+        "SCMP_ACT_LOG" => Ok(libc::SECCOMP_RET_LOG),
+        _ => Err(format!("Unsupported seccomp action: {}", action).into()),
+    }
+}
+
+fn stmt(code: u16, k: u32) -> sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter { code, jt, jf, k }
+}
+
+fn syscall_number(name: &str) -> Option<i64> {
+    SYSCALLS
+        .iter()
+        .find(|(sys, _)| sys.strip_prefix("SYS_") == Some(name))
+        .map(|(_, nr)| *nr)
+}
+
+macro_rules! syscall_table {
+    ($($sys:ident)*) => {
+        &[$((stringify!($sys), libc::$sys)),*]
+    };
+}
+
+// Long obsolete syscalls are still listed so profiles can block them.
+#[cfg(target_arch = "x86_64")]
+#[allow(deprecated)]
+const SYSCALLS: &[(&str, i64)] = syscall_table! {
+    SYS_accept SYS_accept4 SYS_acct SYS_add_key SYS_adjtimex SYS_bind SYS_bpf SYS_brk
+    SYS_capget SYS_capset SYS_chdir SYS_chroot SYS_clock_adjtime SYS_clock_getres
+    SYS_clock_gettime SYS_clock_nanosleep SYS_clock_settime SYS_clone SYS_clone3 SYS_close
+    SYS_close_range SYS_connect SYS_copy_file_range SYS_delete_module SYS_dup SYS_dup3
+    SYS_epoll_create1 SYS_epoll_ctl SYS_epoll_pwait SYS_epoll_pwait2 SYS_eventfd2
+    SYS_execve SYS_execveat SYS_exit SYS_exit_group SYS_faccessat SYS_faccessat2
+    SYS_fallocate SYS_fanotify_init SYS_fanotify_mark SYS_fchdir SYS_fchmod SYS_fchmodat
+    SYS_fchown SYS_fchownat SYS_fcntl SYS_fdatasync SYS_fgetxattr SYS_finit_module
+    SYS_flistxattr SYS_flock SYS_fremovexattr SYS_fsconfig SYS_fsetxattr SYS_fsmount
+    SYS_fsopen SYS_fspick SYS_fstat SYS_fstatfs SYS_fsync SYS_ftruncate SYS_futex
+    SYS_futex_waitv SYS_get_mempolicy SYS_get_robust_list SYS_getcpu SYS_getcwd
+    SYS_getdents64 SYS_getegid SYS_geteuid SYS_getgid SYS_getgroups SYS_getitimer
+    SYS_getpeername SYS_getpgid SYS_getpid SYS_getppid SYS_getpriority SYS_getrandom
+    SYS_getresgid SYS_getresuid SYS_getrusage SYS_getsid SYS_getsockname SYS_getsockopt
+    SYS_gettid SYS_gettimeofday SYS_getuid SYS_getxattr SYS_init_module
+    SYS_inotify_add_watch SYS_inotify_init1 SYS_inotify_rm_watch SYS_io_cancel
+    SYS_io_destroy SYS_io_getevents SYS_io_setup SYS_io_submit SYS_io_uring_enter
+    SYS_io_uring_register SYS_io_uring_setup SYS_ioctl SYS_ioprio_get SYS_ioprio_set
+    SYS_kcmp SYS_kexec_file_load SYS_kexec_load SYS_keyctl SYS_kill SYS_landlock_add_rule
+    SYS_landlock_create_ruleset SYS_landlock_restrict_self SYS_lgetxattr SYS_linkat
+    SYS_listen SYS_listxattr SYS_llistxattr SYS_lookup_dcookie SYS_lremovexattr SYS_lseek
+    SYS_lsetxattr SYS_madvise SYS_mbind SYS_membarrier SYS_memfd_create SYS_memfd_secret
+    SYS_migrate_pages SYS_mincore SYS_mkdirat SYS_mknodat SYS_mlock SYS_mlock2 SYS_mlockall
+    SYS_mmap SYS_mount SYS_mount_setattr SYS_move_mount SYS_move_pages SYS_mprotect
+    SYS_mq_getsetattr SYS_mq_notify SYS_mq_open SYS_mq_timedreceive SYS_mq_timedsend
+    SYS_mq_unlink SYS_mremap SYS_mseal SYS_msgctl SYS_msgget SYS_msgrcv SYS_msgsnd
+    SYS_msync SYS_munlock SYS_munlockall SYS_munmap SYS_name_to_handle_at SYS_nanosleep
+    SYS_newfstatat SYS_nfsservctl SYS_open_by_handle_at SYS_open_tree SYS_openat
+    SYS_openat2 SYS_perf_event_open SYS_personality SYS_pidfd_getfd SYS_pidfd_open
+    SYS_pidfd_send_signal SYS_pipe2 SYS_pivot_root SYS_pkey_alloc SYS_pkey_free
+    SYS_pkey_mprotect SYS_ppoll SYS_prctl SYS_pread64 SYS_preadv SYS_preadv2 SYS_prlimit64
+    SYS_process_madvise SYS_process_mrelease SYS_process_vm_readv SYS_process_vm_writev
+    SYS_pselect6 SYS_ptrace SYS_pwrite64 SYS_pwritev SYS_pwritev2 SYS_quotactl
+    SYS_quotactl_fd SYS_read SYS_readahead SYS_readlinkat SYS_readv SYS_reboot SYS_recvfrom
+    SYS_recvmmsg SYS_recvmsg SYS_remap_file_pages SYS_removexattr SYS_renameat2
+    SYS_request_key SYS_restart_syscall SYS_rseq SYS_rt_sigaction SYS_rt_sigpending
+    SYS_rt_sigprocmask SYS_rt_sigqueueinfo SYS_rt_sigreturn SYS_rt_sigsuspend
+    SYS_rt_sigtimedwait SYS_rt_tgsigqueueinfo SYS_sched_get_priority_max
+    SYS_sched_get_priority_min SYS_sched_getaffinity SYS_sched_getattr SYS_sched_getparam
+    SYS_sched_getscheduler SYS_sched_rr_get_interval SYS_sched_setaffinity
+    SYS_sched_setattr SYS_sched_setparam SYS_sched_setscheduler SYS_sched_yield SYS_seccomp
+    SYS_semctl SYS_semget SYS_semop SYS_semtimedop SYS_sendmmsg SYS_sendmsg SYS_sendto
+    SYS_set_mempolicy SYS_set_mempolicy_home_node SYS_set_robust_list SYS_set_tid_address
+    SYS_setdomainname SYS_setfsgid SYS_setfsuid SYS_setgid SYS_setgroups SYS_sethostname
+    SYS_setitimer SYS_setns SYS_setpgid SYS_setpriority SYS_setregid SYS_setresgid
+    SYS_setresuid SYS_setreuid SYS_setsid SYS_setsockopt SYS_settimeofday SYS_setuid
+    SYS_setxattr SYS_shmat SYS_shmctl SYS_shmdt SYS_shmget SYS_shutdown SYS_sigaltstack
+    SYS_signalfd4 SYS_socket SYS_socketpair SYS_splice SYS_statfs SYS_statx SYS_swapoff
+    SYS_swapon SYS_symlinkat SYS_sync SYS_syncfs SYS_sysinfo SYS_syslog SYS_tee SYS_tgkill
+    SYS_timer_create SYS_timer_delete SYS_timer_getoverrun SYS_timer_gettime
+    SYS_timer_settime SYS_timerfd_create SYS_timerfd_gettime SYS_timerfd_settime SYS_times
+    SYS_tkill SYS_truncate SYS_umask SYS_umount2 SYS_uname SYS_unlinkat SYS_unshare
+    SYS_userfaultfd SYS_utimensat SYS_vhangup SYS_vmsplice SYS_wait4 SYS_waitid SYS_write
+    SYS_writev
+    SYS__sysctl SYS_access SYS_afs_syscall SYS_alarm SYS_arch_prctl SYS_chmod SYS_chown
+    SYS_creat SYS_create_module SYS_dup2 SYS_epoll_create SYS_epoll_ctl_old SYS_epoll_wait
+    SYS_epoll_wait_old SYS_eventfd SYS_fadvise64 SYS_fchmodat2 SYS_fork SYS_futimesat
+    SYS_get_kernel_syms SYS_get_thread_area SYS_getdents SYS_getpgrp SYS_getpmsg
+    SYS_getrlimit SYS_inotify_init SYS_ioperm SYS_iopl SYS_lchown SYS_link SYS_lstat
+    SYS_mkdir SYS_mknod SYS_modify_ldt SYS_open SYS_pause SYS_pipe SYS_poll SYS_putpmsg
+    SYS_query_module SYS_readlink SYS_rename SYS_renameat SYS_rmdir SYS_security SYS_select
+    SYS_sendfile SYS_set_thread_area SYS_setrlimit SYS_signalfd SYS_stat SYS_symlink
+    SYS_sync_file_range SYS_sysfs SYS_time SYS_tuxcall SYS_unlink SYS_uselib SYS_ustat
+    SYS_utime SYS_utimes SYS_vfork SYS_vserver
+};
+
+#[cfg(target_arch = "aarch64")]
+const SYSCALLS: &[(&str, i64)] = syscall_table! {
+    SYS_accept SYS_accept4 SYS_acct SYS_add_key SYS_adjtimex SYS_bind SYS_bpf SYS_brk
+    SYS_capget SYS_capset SYS_chdir SYS_chroot SYS_clock_adjtime SYS_clock_getres
+    SYS_clock_gettime SYS_clock_nanosleep SYS_clock_settime SYS_clone SYS_clone3 SYS_close
+    SYS_close_range SYS_connect SYS_copy_file_range SYS_delete_module SYS_dup SYS_dup3
+    SYS_epoll_create1 SYS_epoll_ctl SYS_epoll_pwait SYS_epoll_pwait2 SYS_eventfd2
+    SYS_execve SYS_execveat SYS_exit SYS_exit_group SYS_faccessat SYS_faccessat2
+    SYS_fallocate SYS_fanotify_init SYS_fanotify_mark SYS_fchdir SYS_fchmod SYS_fchmodat
+    SYS_fchown SYS_fchownat SYS_fcntl SYS_fdatasync SYS_fgetxattr SYS_finit_module
+    SYS_flistxattr SYS_flock SYS_fremovexattr SYS_fsconfig SYS_fsetxattr SYS_fsmount
+    SYS_fsopen SYS_fspick SYS_fstat SYS_fstatfs SYS_fsync SYS_ftruncate SYS_futex
+    SYS_futex_waitv SYS_get_mempolicy SYS_get_robust_list SYS_getcpu SYS_getcwd
+    SYS_getdents64 SYS_getegid SYS_geteuid SYS_getgid SYS_getgroups SYS_getitimer
+    SYS_getpeername SYS_getpgid SYS_getpid SYS_getppid SYS_getpriority SYS_getrandom
+    SYS_getresgid SYS_getresuid SYS_getrusage SYS_getsid SYS_getsockname SYS_getsockopt
+    SYS_gettid SYS_gettimeofday SYS_getuid SYS_getxattr SYS_init_module
+    SYS_inotify_add_watch SYS_inotify_init1 SYS_inotify_rm_watch SYS_io_cancel
+    SYS_io_destroy SYS_io_getevents SYS_io_setup SYS_io_submit SYS_io_uring_enter
+    SYS_io_uring_register SYS_io_uring_setup SYS_ioctl SYS_ioprio_get SYS_ioprio_set
+    SYS_kcmp SYS_kexec_file_load SYS_kexec_load SYS_keyctl SYS_kill SYS_landlock_add_rule
+    SYS_landlock_create_ruleset SYS_landlock_restrict_self SYS_lgetxattr SYS_linkat
+    SYS_listen SYS_listxattr SYS_llistxattr SYS_lookup_dcookie SYS_lremovexattr SYS_lseek
+    SYS_lsetxattr SYS_madvise SYS_mbind SYS_membarrier SYS_memfd_create SYS_memfd_secret
+    SYS_migrate_pages SYS_mincore SYS_mkdirat SYS_mknodat SYS_mlock SYS_mlock2 SYS_mlockall
+    SYS_mmap SYS_mount SYS_mount_setattr SYS_move_mount SYS_move_pages SYS_mprotect
+    SYS_mq_getsetattr SYS_mq_notify SYS_mq_open SYS_mq_timedreceive SYS_mq_timedsend
+    SYS_mq_unlink SYS_mremap SYS_mseal SYS_msgctl SYS_msgget SYS_msgrcv SYS_msgsnd
+    SYS_msync SYS_munlock SYS_munlockall SYS_munmap SYS_name_to_handle_at SYS_nanosleep
+    SYS_newfstatat SYS_nfsservctl SYS_open_by_handle_at SYS_open_tree SYS_openat
+    SYS_openat2 SYS_perf_event_open SYS_personality SYS_pidfd_getfd SYS_pidfd_open
+    SYS_pidfd_send_signal SYS_pipe2 SYS_pivot_root SYS_pkey_alloc SYS_pkey_free
+    SYS_pkey_mprotect SYS_ppoll SYS_prctl SYS_pread64 SYS_preadv SYS_preadv2 SYS_prlimit64
+    SYS_process_madvise SYS_process_mrelease SYS_process_vm_readv SYS_process_vm_writev
+    SYS_pselect6 SYS_ptrace SYS_pwrite64 SYS_pwritev SYS_pwritev2 SYS_quotactl
+    SYS_quotactl_fd SYS_read SYS_readahead SYS_readlinkat SYS_readv SYS_reboot SYS_recvfrom
+    SYS_recvmmsg SYS_recvmsg SYS_remap_file_pages SYS_removexattr SYS_renameat2
+    SYS_request_key SYS_restart_syscall SYS_rseq SYS_rt_sigaction SYS_rt_sigpending
+    SYS_rt_sigprocmask SYS_rt_sigqueueinfo SYS_rt_sigreturn SYS_rt_sigsuspend
+    SYS_rt_sigtimedwait SYS_rt_tgsigqueueinfo SYS_sched_get_priority_max
+    SYS_sched_get_priority_min SYS_sched_getaffinity SYS_sched_getattr SYS_sched_getparam
+    SYS_sched_getscheduler SYS_sched_rr_get_interval SYS_sched_setaffinity
+    SYS_sched_setattr SYS_sched_setparam SYS_sched_setscheduler SYS_sched_yield SYS_seccomp
+    SYS_semctl SYS_semget SYS_semop SYS_semtimedop SYS_sendmmsg SYS_sendmsg SYS_sendto
+    SYS_set_mempolicy SYS_set_mempolicy_home_node SYS_set_robust_list SYS_set_tid_address
+    SYS_setdomainname SYS_setfsgid SYS_setfsuid SYS_setgid SYS_setgroups SYS_sethostname
+    SYS_setitimer SYS_setns SYS_setpgid SYS_setpriority SYS_setregid SYS_setresgid
+    SYS_setresuid SYS_setreuid SYS_setsid SYS_setsockopt SYS_settimeofday SYS_setuid
+    SYS_setxattr SYS_shmat SYS_shmctl SYS_shmdt SYS_shmget SYS_shutdown SYS_sigaltstack
+    SYS_signalfd4 SYS_socket SYS_socketpair SYS_splice SYS_statfs SYS_statx SYS_swapoff
+    SYS_swapon SYS_symlinkat SYS_sync SYS_syncfs SYS_sysinfo SYS_syslog SYS_tee SYS_tgkill
+    SYS_timer_create SYS_timer_delete SYS_timer_getoverrun SYS_timer_gettime
+    SYS_timer_settime SYS_timerfd_create SYS_timerfd_gettime SYS_timerfd_settime SYS_times
+    SYS_tkill SYS_truncate SYS_umask SYS_umount2 SYS_uname SYS_unlinkat SYS_unshare
+    SYS_userfaultfd SYS_utimensat SYS_vhangup SYS_vmsplice SYS_wait4 SYS_waitid SYS_write
+    SYS_writev
+};
@@ -0,0 +1,298 @@
+use crate::actions::{container, events, run::ProcessSpec, store, utils};
+use openssl::symm::{self, Cipher};
+use std::{
+    fs,
+    io::{ErrorKind, Read, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+const SECRETS_DIR: &str = "secrets";
+/// The AES-256 key secrets are encrypted with, readable by its owner only.
+const KEY_FILE: &str = "secrets.key";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+/// Secrets are for passwords, keys and certificates, not data.
+const MAX_SECRET_SIZE: usize = 500 * 1024;
+/// Where containers find their secrets, on a tmpfs of their own.
+pub const MOUNT_DIR: &str = "/run/secrets";
+
+fn secret_path(name: &str) -> String {
+    format!("{}/{}", store::path(SECRETS_DIR), name)
+}
+
+/// Fails unless the secret exists, so `run` can refuse before creating
+/// anything.
+pub fn check_exists(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    utils::validate_object_name("secret", name)?;
+    if !Path::new(&secret_path(name)).is_file() {
+        return Err(format!("No such secret: {}", name).into());
+    }
+    Ok(())
+}
+
+/// Stores the contents of `source`, a file or `-` for stdin, as a secret,
+/// encrypted with AES-256-GCM under the store's secrets key.
+pub fn create_secret(name: &str, source: &str) -> Result<(), Box<dyn std::error::Error>> {
+    utils::validate_object_name("secret", name)?;
+    let mut data = Vec::new();
+    let reader: Box<dyn Read> = if source == "-" {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(fs::File::open(source).map_err(|e| format!("Failed to open {}: {}", source, e))?)
+    };
+    reader
+        .take(MAX_SECRET_SIZE as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.is_empty() {
+        return Err("A secret cannot be empty".into());
+    }
+    if data.len() > MAX_SECRET_SIZE {
+        return Err(format!(
+            "Secrets can be at most {}",
+            utils::format_size(MAX_SECRET_SIZE as u64)
+        )
+        .into());
+    }
+
+    let key = load_key()?;
+    let mut nonce = [0u8; NONCE_LEN];
+    openssl::rand::rand_bytes(&mut nonce)?;
+    let mut tag = [0u8; TAG_LEN];
+    // The name is authenticated too, so one secret cannot pass for another.
+    let ciphertext = symm::encrypt_aead(
+        Cipher::aes_256_gcm(),
+        &key,
+        Some(&nonce),
+        name.as_bytes(),
+        &data,
+        &mut tag,
+    )?;
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(secret_path(name))
+        .map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => format!("Secret {} already exists", name),
+            _ => format!("Failed to create secret {}: {}", name, e),
+        })?;
+    file.write_all(&[&nonce[..], &tag, &ciphertext].concat())?;
+    file.sync_all()?;
+    events::emit("secret", "create", name, serde_json::json!({}));
+
+    Ok(())
+}
+
+/// Decrypts a secret for a container about to start.
+pub fn read_secret(name: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let content = fs::read(secret_path(name)).map_err(|_| format!("No such secret: {}", name))?;
+    if content.len() < NONCE_LEN + TAG_LEN {
+        return Err(format!("Secret {} is corrupted", name).into());
+    }
+    let (nonce, rest) = content.split_at(NONCE_LEN);
+    let (tag, ciphertext) = rest.split_at(TAG_LEN);
+
+    symm::decrypt_aead(
+        Cipher::aes_256_gcm(),
+        &load_key()?,
+        Some(nonce),
+        name.as_bytes(),
+        ciphertext,
+        tag,
+    )
+    .map_err(|_| {
+        format!(
+            "Failed to decrypt secret {}, it or the secrets key is corrupted",
+            name
+        )
+        .into()
+    })
+}
+
+/// Reads the secrets key, generating it the first time a secret is stored.
+fn load_key() -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(store::path(SECRETS_DIR))?;
+    read_or_create_key(&store::path(KEY_FILE))
+}
+
+fn read_or_create_key(key_path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match fs::read(key_path) {
+        Ok(key) => return check_key(key_path, key),
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            return Err(format!("Failed to read the secrets key {}: {}", key_path, e).into())
+        }
+        Err(_) => {}
+    }
+
+    let mut key = vec![0u8; KEY_LEN];
+    openssl::rand::rand_bytes(&mut key)?;
+
+    // Write the key aside and link it into place once it is on disk, so no
+    // reader ever sees a partial key, even after a crash.
+    let mut suffix = [0u8; 8];
+    openssl::rand::rand_bytes(&mut suffix)?;
+    let temp_path = format!("{}.{:x}.tmp", key_path, u64::from_ne_bytes(suffix));
+    let result = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(&key)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::hard_link(&temp_path, key_path));
+    let _ = fs::remove_file(&temp_path);
+
+    match result {
+        Ok(()) => Ok(key),
+        // Another process created the key first.
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            let key = fs::read(key_path)
+                .map_err(|e| format!("Failed to read the secrets key {}: {}", key_path, e))?;
+            check_key(key_path, key)
+        }
+        Err(e) => Err(format!("Failed to create the secrets key {}: {}", key_path, e).into()),
+    }
+}
+
+fn check_key(key_path: &str, key: Vec<u8>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if key.len() != KEY_LEN {
+        return Err(format!("The secrets key {} is corrupted", key_path).into());
+    }
+    Ok(key)
+}
+
+fn list_names() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if !Path::new(&store::path(SECRETS_DIR)).exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut names: Vec<String> = fs::read_dir(store::path(SECRETS_DIR))?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str().map(String::from))
+        .filter(|name| utils::validate_object_name("secret", name).is_ok())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// IDs of the containers given the secret, running or not.
+fn users(name: &str) -> Vec<String> {
+    container::containers_using(|metadata| {
+        metadata
+            .get("process")
+            .and_then(|process| serde_json::from_value::<ProcessSpec>(process.clone()).ok())
+            .is_some_and(|process| process.secrets.iter().any(|secret| secret == name))
+    })
+    .unwrap_or_default()
+}
+
+/// Lists secrets by name, their contents staying where they are.
+pub fn list_secrets() -> Result<(), Box<dyn std::error::Error>> {
+    let rows: Vec<Vec<String>> = list_names()?
+        .into_iter()
+        .map(|name| {
+            let created = fs::metadata(secret_path(&name))
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|age| utils::format_timestamp(age.as_secs()))
+                .unwrap_or_default();
+            vec![name, created]
+        })
+        .collect();
+
+    utils::print_table(&["NAME", "CREATED"], &rows);
+    Ok(())
+}
+
+/// Removes secrets, which no container may be given anymore.
+pub fn remove_secrets(names: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    for name in names {
+        check_exists(name)?;
+        let users = users(name);
+        if !users.is_empty() {
+            return Err(format!(
+                "Secret {} is in use by container(s) {}",
+                name,
+                users
+                    .iter()
+                    .map(|id| container::short_id(id))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+            .into());
+        }
+
+        fs::remove_file(secret_path(name))?;
+        events::emit("secret", "destroy", name, serde_json::json!({}));
+        println!("{}", name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rustainer-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn key_is_created_once_and_owner_only() {
+        let dir = scratch_dir("secrets-key");
+        let key_path = dir.join("secrets.key").to_string_lossy().to_string();
+
+        let key = read_or_create_key(&key_path).unwrap();
+        assert_eq!(key.len(), KEY_LEN);
+        let mode = fs::metadata(&key_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(read_or_create_key(&key_path).unwrap(), key);
+
+        // Only the key is left behind.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn corrupted_key_is_rejected() {
+        let dir = scratch_dir("secrets-key-corrupted");
+        let key_path = dir.join("secrets.key").to_string_lossy().to_string();
+        fs::write(&key_path, "").unwrap();
+        assert!(read_or_create_key(&key_path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_key_creation_agrees_on_one_key() {
+        let dir = scratch_dir("secrets-key-race");
+        let key_path = dir.join("secrets.key").to_string_lossy().to_string();
+        let keys: Vec<Vec<u8>> = (0..8)
+            .map(|_| {
+                let key_path = key_path.clone();
+                std::thread::spawn(move || read_or_create_key(&key_path).unwrap())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        assert!(keys.iter().all(|key| *key == keys[0]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
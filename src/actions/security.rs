@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+const APPARMOR_ENABLED: &str = "/sys/module/apparmor/parameters/enabled";
+const APPARMOR_PROFILES: &str = "/sys/kernel/security/apparmor/profiles";
+const SELINUX_ENFORCE: &str = "/sys/fs/selinux/enforce";
+const DEFAULT_APPARMOR_PROFILE: &str = "rustainer-default";
+/// The type of files containers may use, which relabeled volumes get.
+const CONTAINER_FILE_CONTEXT: &str = "system_u:object_r:container_file_t";
+/// Host directories relabeling would break the host with.
+const SYSTEM_DIRS: [&str; 12] = [
+    "/", "/bin", "/boot", "/dev", "/etc", "/home", "/lib", "/proc", "/root", "/sys", "/usr", "/var",
+];
+
+/// Loaded on first use when the host runs AppArmor. Modeled on Docker's
+/// default profile: containers may do anything except write to the host's
+/// kernel interfaces, mount, or ptrace outside their own profile.
+const DEFAULT_APPARMOR_POLICY: &str = r#"#include <tunables/global>
+
+profile rustainer-default flags=(attach_disconnected,mediate_deleted) {
+  #include <abstractions/base>
+
+  network,
+  capability,
+  file,
+  umount,
+  signal (receive) peer=unconfined,
+  signal (send,receive) peer=rustainer-default,
+
+  deny @{PROC}/* w,
+  deny @{PROC}/{[^1-9],[^1-9][^0-9],[^1-9s][^0-9y][^0-9s],[^1-9][^0-9][^0-9][^0-9/]*}/** w,
+  deny @{PROC}/sys/[^k]** w,
+  deny @{PROC}/sys/kernel/{?,??,[^s][^h][^m]**} w,
+  deny @{PROC}/sysrq-trigger rwklx,
+  deny @{PROC}/kcore rwklx,
+
+  deny mount,
+
+  deny /sys/[^f]*/** wklx,
+  deny /sys/f[^s]*/** wklx,
+  deny /sys/fs/[^c]*/** wklx,
+  deny /sys/fs/c[^g]*/** wklx,
+  deny /sys/fs/cg[^r]*/** wklx,
+  deny /sys/firmware/** rwklx,
+  deny /sys/kernel/security/** rwklx,
+
+  ptrace (trace,read,tracedby,readby) peer=rustainer-default,
+}
+"#;
+
+/// The security label the container process is confined with, set by
+/// writing it to the LSM's exec attribute right before exec.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "lsm", content = "label", rename_all = "lowercase")]
+pub enum ProcessLabel {
+    AppArmor(String),
+    SELinux(String),
+}
+
+impl ProcessLabel {
+    /// The /proc/self attribute files to try, most specific first, and the
+    /// value to write to them.
+    pub fn exec_attr(&self) -> (&'static [&'static str], String) {
+        match self {
+            ProcessLabel::AppArmor(profile) => (
+                &["/proc/self/attr/apparmor/exec", "/proc/self/attr/exec"],
+                format!("exec {}", profile),
+            ),
+            ProcessLabel::SELinux(context) => (&["/proc/self/attr/exec"], context.clone()),
+        }
+    }
+}
+
+/// Works out the container's label from `--security-opt apparmor=` and
+/// `label=` values. Without either, containers get the default AppArmor
+/// profile on hosts running AppArmor; privileged ones stay unconfined.
+pub fn resolve(
+    apparmor: Option<&str>,
+    labels: &[&str],
+    privileged: bool,
+) -> Result<Option<ProcessLabel>, Box<dyn std::error::Error>> {
+    if apparmor.is_some() && !labels.is_empty() {
+        return Err("Conflicting options: apparmor and label cannot both be set".into());
+    }
+
+    if !labels.is_empty() {
+        return selinux_label(labels);
+    }
+
+    match apparmor {
+        Some("unconfined") => Ok(None),
+        Some(profile) => {
+            if !apparmor_enabled() {
+                return Err("AppArmor is not enabled on this host".into());
+            }
+            if !apparmor_profile_loaded(profile) {
+                return Err(format!("AppArmor profile {} is not loaded", profile).into());
+            }
+            Ok(Some(ProcessLabel::AppArmor(profile.to_string())))
+        }
+        None if privileged || !apparmor_enabled() => Ok(None),
+        None => match load_default_apparmor_profile() {
+            Ok(()) => Ok(Some(ProcessLabel::AppArmor(
+                DEFAULT_APPARMOR_PROFILE.to_string(),
+            ))),
+            Err(e) => {
+                println!(
+                    "⚠️ Warning: Failed to load AppArmor profile {}, running unconfined: {}",
+                    DEFAULT_APPARMOR_PROFILE, e
+                );
+                Ok(None)
+            }
+        },
+    }
+}
+
+/// Builds an SELinux context from `label=user:`, `role:`, `type:` and
+/// `level:` parts over Docker's container defaults, or none for
+/// `label=disable`.
+fn selinux_label(labels: &[&str]) -> Result<Option<ProcessLabel>, Box<dyn std::error::Error>> {
+    if labels.contains(&"disable") {
+        return Ok(None);
+    }
+    if !Path::new(SELINUX_ENFORCE).exists() {
+        return Err("SELinux is not enabled on this host".into());
+    }
+
+    let (mut user, mut role, mut kind, mut level) = ("system_u", "system_r", "container_t", "s0");
+    for label in labels {
+        match label.split_once(':') {
+            Some(("user", value)) => user = value,
+            Some(("role", value)) => role = value,
+            Some(("type", value)) => kind = value,
+            Some(("level", value)) => level = value,
+            _ => return Err(format!("Invalid label option: {}", label).into()),
+        }
+    }
+
+    Ok(Some(ProcessLabel::SELinux(format!(
+        "{}:{}:{}:{}",
+        user, role, kind, level
+    ))))
+}
+
+/// Relabels a volume's content for containers: shared between all of them,
+/// or with `private` at the level of the container's `label` only. Nothing
+/// needs relabeling when SELinux is not enabled.
+pub fn relabel(
+    path: &Path,
+    private: bool,
+    label: Option<&ProcessLabel>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !Path::new(SELINUX_ENFORCE).exists() {
+        return Ok(());
+    }
+    if SYSTEM_DIRS.iter().any(|dir| path == Path::new(dir)) {
+        return Err(format!("Relabeling {} is not allowed", path.display()).into());
+    }
+
+    let level = match label {
+        Some(ProcessLabel::SELinux(context)) if private => {
+            context.splitn(4, ':').nth(3).unwrap_or("s0")
+        }
+        _ => "s0",
+    };
+    let output = Command::new("chcon")
+        .arg("-R")
+        .arg(format!("{}:{}", CONTAINER_FILE_CONTEXT, level))
+        .arg(path)
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to relabel {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+fn apparmor_enabled() -> bool {
+    fs::read_to_string(APPARMOR_ENABLED).is_ok_and(|enabled| enabled.trim() == "Y")
+}
+
+fn apparmor_profile_loaded(profile: &str) -> bool {
+    // Lines look like "rustainer-default (enforce)".
+    fs::read_to_string(APPARMOR_PROFILES).is_ok_and(|profiles| {
+        profiles
+            .lines()
+            .any(|line| line.rsplit_once(' ').map_or(line, |(name, _)| name) == profile)
+    })
+}
+
+fn load_default_apparmor_profile() -> Result<(), Box<dyn std::error::Error>> {
+    if apparmor_profile_loaded(DEFAULT_APPARMOR_PROFILE) {
+        return Ok(());
+    }
+
+    let mut child = Command::new("apparmor_parser")
+        .args(["--replace", "--skip-cache"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(DEFAULT_APPARMOR_POLICY.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .to_string()
+            .into());
+    }
+
+    Ok(())
+}
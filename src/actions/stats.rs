@@ -0,0 +1,217 @@
+use crate::actions::{container, utils};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+/// Kernel clock ticks per second as exposed in /proc (USER_HZ), which is 100
+/// on every architecture Linux supports.
+const USER_HZ: f64 = 100.0;
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct Sample {
+    cpu_ticks: u64,
+    memory: u64,
+    net_rx: u64,
+    net_tx: u64,
+    block_read: u64,
+    block_write: u64,
+    pids: usize,
+}
+
+struct ContainerStats {
+    container_id: String,
+    name: String,
+    cpu_percent: f64,
+    sample: Sample,
+}
+
+pub async fn show_stats(
+    references: &[String],
+    no_stream: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let container_ids = if references.is_empty() {
+        container::list_container_ids()?
+            .into_iter()
+            .filter(|id| container::is_running(id))
+            .collect()
+    } else {
+        references
+            .iter()
+            .map(|reference| container::resolve_container(reference))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    let memory_limit = host_memory_total();
+    let mut previous: HashMap<String, (Instant, u64)> = HashMap::new();
+    for container_id in &container_ids {
+        previous.insert(
+            container_id.clone(),
+            (Instant::now(), collect_sample(container_id).cpu_ticks),
+        );
+    }
+
+    loop {
+        tokio::time::sleep(REFRESH_INTERVAL).await;
+
+        let mut stats = Vec::new();
+        for container_id in &container_ids {
+            let sample = collect_sample(container_id);
+            let now = Instant::now();
+
+            let cpu_percent = match previous.get(container_id) {
+                Some((last_time, last_ticks)) => {
+                    let elapsed = now.duration_since(*last_time).as_secs_f64();
+                    let used = sample.cpu_ticks.saturating_sub(*last_ticks) as f64 / USER_HZ;
+                    if elapsed > 0.0 {
+                        used / elapsed * 100.0
+                    } else {
+                        0.0
+                    }
+                }
+                None => 0.0,
+            };
+            previous.insert(container_id.clone(), (now, sample.cpu_ticks));
+
+            let name = container::load_metadata(container_id)
+                .and_then(|m| m.get("name").and_then(|v| v.as_str()).map(String::from))
+                .unwrap_or_else(|| "--".to_string());
+
+            stats.push(ContainerStats {
+                container_id: container_id.clone(),
+                name,
+                cpu_percent,
+                sample,
+            });
+        }
+
+        if !no_stream {
+            // Clear the screen and move the cursor home before redrawing.
+            print!("\x1b[2J\x1b[H");
+        }
+        print_stats_table(&stats, memory_limit);
+        std::io::stdout().flush()?;
+
+        if no_stream {
+            return Ok(());
+        }
+    }
+}
+
+fn print_stats_table(stats: &[ContainerStats], memory_limit: u64) {
+    let rows: Vec<Vec<String>> = stats
+        .iter()
+        .map(|s| {
+            let mem_percent = if memory_limit > 0 {
+                s.sample.memory as f64 / memory_limit as f64 * 100.0
+            } else {
+                0.0
+            };
+
+            vec![
+                container::short_id(&s.container_id).to_string(),
+                s.name.clone(),
+                format!("{:.2}%", s.cpu_percent),
+                format!(
+                    "{} / {}",
+                    utils::format_size(s.sample.memory),
+                    utils::format_size(memory_limit)
+                ),
+                format!("{:.2}%", mem_percent),
+                format!(
+                    "{} / {}",
+                    utils::format_size(s.sample.net_rx),
+                    utils::format_size(s.sample.net_tx)
+                ),
+                format!(
+                    "{} / {}",
+                    utils::format_size(s.sample.block_read),
+                    utils::format_size(s.sample.block_write)
+                ),
+                s.sample.pids.to_string(),
+            ]
+        })
+        .collect();
+
+    utils::print_table(
+        &[
+            "CONTAINER ID",
+            "NAME",
+            "CPU %",
+            "MEM USAGE / LIMIT",
+            "MEM %",
+            "NET I/O",
+            "BLOCK I/O",
+            "PIDS",
+        ],
+        &rows,
+    );
+}
+
+fn collect_sample(container_id: &str) -> Sample {
+    let pids = container::container_pids(container_id);
+    let mut sample = Sample {
+        pids: pids.len(),
+        ..Default::default()
+    };
+
+    for pid in &pids {
+        if let Ok(stat) = fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            // The command name may contain spaces, fields are counted after it.
+            if let Some((_, fields)) = stat.rsplit_once(')') {
+                let fields: Vec<&str> = fields.split_whitespace().collect();
+                let utime: u64 = fields.get(11).and_then(|v| v.parse().ok()).unwrap_or(0);
+                let stime: u64 = fields.get(12).and_then(|v| v.parse().ok()).unwrap_or(0);
+                sample.cpu_ticks += utime + stime;
+            }
+        }
+
+        if let Ok(status) = fs::read_to_string(format!("/proc/{}/status", pid)) {
+            sample.memory += read_field(&status, "VmRSS:") * 1024;
+        }
+
+        if let Ok(io) = fs::read_to_string(format!("/proc/{}/io", pid)) {
+            sample.block_read += read_field(&io, "read_bytes:");
+            sample.block_write += read_field(&io, "write_bytes:");
+        }
+    }
+
+    // Every process shares the container's network namespace.
+    if let Some(pid) = pids.first() {
+        if let Ok(net_dev) = fs::read_to_string(format!("/proc/{}/net/dev", pid)) {
+            for line in net_dev.lines().skip(2) {
+                if let Some((interface, counters)) = line.split_once(':') {
+                    if interface.trim() == "lo" {
+                        continue;
+                    }
+                    let counters: Vec<u64> = counters
+                        .split_whitespace()
+                        .filter_map(|v| v.parse().ok())
+                        .collect();
+                    sample.net_rx += counters.first().copied().unwrap_or(0);
+                    sample.net_tx += counters.get(8).copied().unwrap_or(0);
+                }
+            }
+        }
+    }
+
+    sample
+}
+
+fn host_memory_total() -> u64 {
+    fs::read_to_string("/proc/meminfo")
+        .map(|meminfo| read_field(&meminfo, "MemTotal:") * 1024)
+        .unwrap_or(0)
+}
+
+fn read_field(content: &str, key: &str) -> u64 {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix(key))
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0)
+}
@@ -0,0 +1,146 @@
+use crate::actions::{cgroup, container};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_STOP_SIGNAL: &str = "SIGTERM";
+/// Seconds a container gets to exit after its stop signal.
+pub const DEFAULT_STOP_TIMEOUT: u64 = 10;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long killed processes get to disappear.
+const KILL_TIMEOUT: Duration = Duration::from_secs(2);
+
+const SIGNALS: [(&str, libc::c_int); 14] = [
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ABRT", libc::SIGABRT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("WINCH", libc::SIGWINCH),
+    ("PWR", libc::SIGPWR),
+];
+
+/// Parses a signal given by number or by name, with or without the `SIG`
+/// prefix, including real-time signals such as systemd's `SIGRTMIN+3`.
+pub fn parse_signal(value: &str) -> Result<libc::c_int, Box<dyn std::error::Error>> {
+    let invalid = || format!("Invalid signal: {}", value);
+
+    if let Ok(number) = value.parse::<libc::c_int>() {
+        if !(1..=libc::SIGRTMAX()).contains(&number) {
+            return Err(invalid().into());
+        }
+        return Ok(number);
+    }
+
+    let upper = value.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    if let Some(offset) = name.strip_prefix("RTMIN") {
+        let offset = match offset.strip_prefix('+') {
+            Some(offset) => offset.parse::<libc::c_int>().map_err(|_| invalid())?,
+            None if offset.is_empty() => 0,
+            None => return Err(invalid().into()),
+        };
+        let signal = libc::SIGRTMIN() + offset;
+        if signal > libc::SIGRTMAX() {
+            return Err(invalid().into());
+        }
+        return Ok(signal);
+    }
+
+    SIGNALS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, signal)| *signal)
+        .ok_or_else(|| invalid().into())
+}
+
+/// Stops a container for the `stop` command and prints its ID.
+pub async fn stop_container(
+    reference: &str,
+    timeout: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = container::resolve_container(reference)?;
+    let _lock = container::lock(&container_id)?;
+    stop(&container_id, timeout)?;
+    println!("{}", container_id);
+    Ok(())
+}
+
+/// Sends the container its stop signal and kills it when it is still running
+/// after the timeout, by default the one recorded for it. Restart policies do
+/// not bring it back.
+pub fn stop(container_id: &str, timeout: Option<u64>) -> Result<(), Box<dyn std::error::Error>> {
+    container::update_metadata(container_id, |metadata| {
+        metadata["stop_requested"] = true.into();
+    })?;
+    let metadata = container::load_metadata(container_id);
+    if !container::is_running(container_id) {
+        return Ok(());
+    }
+
+    let signal_name = metadata
+        .as_ref()
+        .and_then(|m| m.get("stop_signal")?.as_str().map(String::from))
+        .unwrap_or_else(|| DEFAULT_STOP_SIGNAL.to_string());
+    let timeout = timeout
+        .or_else(|| metadata.as_ref()?.get("stop_timeout")?.as_u64())
+        .unwrap_or(DEFAULT_STOP_TIMEOUT);
+
+    // The container's PID 1 decides what the signal means for the others.
+    if let Some(pid) = container::main_pid(container_id) {
+        let signal = parse_signal(&signal_name)?;
+        println!("Sending {} to container {}", signal_name, container_id);
+        unsafe { libc::kill(pid as libc::pid_t, signal) };
+    }
+    if wait_stopped(container_id, Duration::from_secs(timeout)) {
+        return Ok(());
+    }
+
+    println!(
+        "Container {} did not exit within {}s, killing it",
+        container_id, timeout
+    );
+    kill(container_id);
+    wait_stopped(container_id, KILL_TIMEOUT);
+    Ok(())
+}
+
+/// Whether a stop was requested since the container last started.
+pub fn was_requested(container_id: &str) -> bool {
+    container::load_metadata(container_id)
+        .and_then(|metadata| metadata.get("stop_requested")?.as_bool())
+        .unwrap_or(false)
+}
+
+/// Kills every process of the container with SIGKILL. Without cgroup v2
+/// there is no cgroup to kill, the processes are then found through the
+/// container's namespace or root directory.
+pub fn kill(container_id: &str) {
+    if cgroup::kill(container_id) {
+        return;
+    }
+
+    let pids = container::container_pids(container_id);
+    if !pids.is_empty() {
+        println!("Killing all processes in container");
+    }
+    for pid in pids {
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+    }
+}
+
+fn wait_stopped(container_id: &str, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while container::is_running(container_id) {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    true
+}
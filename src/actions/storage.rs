@@ -0,0 +1,1464 @@
+use std::{
+    collections::HashSet,
+    ffi::{CStr, CString},
+    fs,
+    io::{self, BufReader, Read},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileTypeExt, MetadataExt, PermissionsExt},
+    },
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+
+use crate::actions::{
+    container, mount, pull, quota, store,
+    types::{Compression, ImageManifest, Layer},
+    utils, wasm,
+};
+
+/// Where each image layer is extracted once, shared by every container
+/// whose image has it.
+const LAYERS_DIR: &str = "layers";
+/// The container's layer digests, bottom first.
+const LOWER_FILE: &str = "lower";
+/// Where the btrfs and zfs snapshotters keep their layer snapshots.
+const SNAPSHOTS_DIR: &str = "snapshots";
+/// The name of the snapshotter the container's rootfs was made with.
+const DRIVER_FILE: &str = "driver";
+/// Prefixes the layers containers are committed to when cloned, named after
+/// the clone.
+const CLONE_LAYER_PREFIX: &str = "clone-";
+/// Marks a file deleted by a layer, the `.wh.` prefix of its name.
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// Marks a directory whose lower layers' content a layer deletes.
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+/// How overlayfs marks a directory opaque.
+const OPAQUE_XATTR: &CStr = c"trusted.overlay.opaque";
+/// Selects the snapshotter for new containers, over `storage_driver` of the
+/// config file. Without either overlayfs is used where it can be mounted,
+/// and plain copies elsewhere.
+const DRIVER_ENV: &str = "RUSTAINER_STORAGE_DRIVER";
+
+/// A way of making a container rootfs from extracted image layers.
+pub trait Snapshotter {
+    /// The name recorded with containers and accepted in the configuration.
+    fn name(&self) -> &'static str;
+    /// Where the container's writes end up.
+    fn writable_dir(&self, container_path: &Path) -> PathBuf;
+    /// Makes `<container_path>/rootfs` from `layers`, bottom first.
+    fn prepare(
+        &self,
+        container_path: &Path,
+        layers: &[PathBuf],
+    ) -> Result<(), Box<dyn std::error::Error>>;
+    /// Makes a prepared rootfs usable again, as after a host reboot.
+    fn mount(
+        &self,
+        container_path: &Path,
+        layers: &[PathBuf],
+    ) -> Result<(), Box<dyn std::error::Error>>;
+    /// Saves the rootfs as a layer in `dest`, returning the layers, bottom
+    /// first, that make it up together.
+    fn commit(
+        &self,
+        container_path: &Path,
+        layers: &[PathBuf],
+        dest: &Path,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>>;
+    /// Releases the rootfs before the container directory is removed.
+    fn remove(&self, container_path: &Path);
+}
+
+/// The layers mounted read-only under a writable `upper` directory.
+pub struct Overlay;
+
+impl Snapshotter for Overlay {
+    fn name(&self) -> &'static str {
+        "overlay"
+    }
+
+    fn writable_dir(&self, container_path: &Path) -> PathBuf {
+        container_path.join("upper")
+    }
+
+    fn prepare(
+        &self,
+        container_path: &Path,
+        layers: &[PathBuf],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.mount(container_path, layers)
+    }
+
+    fn mount(
+        &self,
+        container_path: &Path,
+        layers: &[PathBuf],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let rootfs_path = container_path.join("rootfs");
+        if is_mountpoint(&rootfs_path) {
+            return Ok(());
+        }
+
+        let upper = self.writable_dir(container_path);
+        let work = container_path.join("work");
+        fs::create_dir_all(&upper)?;
+        fs::create_dir_all(&work)?;
+
+        // overlayfs lists the topmost layer first.
+        let mut lowerdirs = Vec::new();
+        for dir in layers.iter().rev() {
+            lowerdirs.push(overlay_path(&fs::canonicalize(dir)?));
+        }
+        // An image without layers still needs a lower directory.
+        if lowerdirs.is_empty() {
+            let empty = container_path.join("empty");
+            fs::create_dir_all(&empty)?;
+            lowerdirs.push(fs::canonicalize(empty)?.display().to_string());
+        }
+
+        let data = format!(
+            "lowerdir={},upperdir={},workdir={}",
+            lowerdirs.join(":"),
+            fs::canonicalize(&upper)?.display(),
+            fs::canonicalize(&work)?.display()
+        );
+        let source = CString::new("overlay")?;
+        let target = CString::new(rootfs_path.as_os_str().as_bytes())?;
+        let data = CString::new(data)?;
+        let result = unsafe {
+            libc::mount(
+                source.as_ptr(),
+                target.as_ptr(),
+                source.as_ptr(),
+                0,
+                data.as_ptr() as *const libc::c_void,
+            )
+        };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    }
+
+    fn commit(
+        &self,
+        container_path: &Path,
+        layers: &[PathBuf],
+        dest: &Path,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        fs::create_dir_all(dest)?;
+        copy_dir(&self.writable_dir(container_path), dest)?;
+        Ok(layers.iter().cloned().chain([dest.to_path_buf()]).collect())
+    }
+
+    fn remove(&self, container_path: &Path) {
+        let rootfs_path = container_path.join("rootfs");
+        if !is_mountpoint(&rootfs_path) {
+            return;
+        }
+        let Ok(target) = CString::new(rootfs_path.as_os_str().as_bytes()) else {
+            return;
+        };
+        // Lazily, in case a process still has its cwd in there.
+        unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) };
+    }
+}
+
+/// The layers copied one over the other into a plain directory, which works
+/// on any filesystem.
+pub struct Vfs;
+
+impl Snapshotter for Vfs {
+    fn name(&self) -> &'static str {
+        "vfs"
+    }
+
+    fn writable_dir(&self, container_path: &Path) -> PathBuf {
+        container_path.join("rootfs")
+    }
+
+    fn prepare(
+        &self,
+        container_path: &Path,
+        layers: &[PathBuf],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let rootfs_path = container_path.join("rootfs");
+        for layer in layers {
+            apply_layer(layer, &rootfs_path)?;
+        }
+        Ok(())
+    }
+
+    fn mount(&self, _: &Path, _: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn commit(
+        &self,
+        container_path: &Path,
+        _: &[PathBuf],
+        dest: &Path,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        fs::create_dir_all(dest)?;
+        copy_dir(&container_path.join("rootfs"), dest)?;
+        Ok(vec![dest.to_path_buf()])
+    }
+
+    fn remove(&self, _: &Path) {}
+}
+
+/// A btrfs subvolume snapshot for each stack of layers images start with,
+/// and one of the topmost for each container.
+pub struct Btrfs;
+
+impl Snapshotter for Btrfs {
+    fn name(&self) -> &'static str {
+        "btrfs"
+    }
+
+    fn writable_dir(&self, container_path: &Path) -> PathBuf {
+        container_path.join("rootfs")
+    }
+
+    fn prepare(
+        &self,
+        container_path: &Path,
+        layers: &[PathBuf],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let rootfs_path = container_path.join("rootfs");
+        // The snapshot takes the place of the empty directory.
+        let _ = fs::remove_dir(&rootfs_path);
+        match self.chain(layers)? {
+            Some(top) => run_tool("btrfs", &["subvolume", "snapshot"], &[&top, &rootfs_path])?,
+            None => run_tool("btrfs", &["subvolume", "create"], &[&rootfs_path])?,
+        };
+        Ok(())
+    }
+
+    fn mount(&self, _: &Path, _: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn commit(
+        &self,
+        container_path: &Path,
+        _: &[PathBuf],
+        dest: &Path,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        run_tool(
+            "btrfs",
+            &["subvolume", "snapshot", "-r"],
+            &[&container_path.join("rootfs"), dest],
+        )?;
+        // Stacked on nothing it is the whole rootfs, so preparing from it
+        // snapshots it rather than copying.
+        let layers = vec![dest.to_path_buf()];
+        let dir = Path::new(&store::path(SNAPSHOTS_DIR)).join(self.name());
+        fs::create_dir_all(&dir)?;
+        run_tool(
+            "btrfs",
+            &["subvolume", "snapshot"],
+            &[dest, &dir.join(chain_id(&layers))],
+        )?;
+        Ok(layers)
+    }
+
+    fn remove(&self, container_path: &Path) {
+        let _ = run_tool(
+            "btrfs",
+            &["subvolume", "delete"],
+            &[&container_path.join("rootfs")],
+        );
+    }
+}
+
+impl Btrfs {
+    /// The snapshot of all `layers` stacked, snapshotting the one below and
+    /// adding a layer for each stack not made before.
+    fn chain(&self, layers: &[PathBuf]) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+        let dir = Path::new(&store::path(SNAPSHOTS_DIR)).join(self.name());
+        fs::create_dir_all(&dir)?;
+
+        let mut below: Option<PathBuf> = None;
+        for depth in 1..=layers.len() {
+            let snapshot = dir.join(chain_id(&layers[..depth]));
+            if !snapshot.exists() {
+                let partial = PathBuf::from(format!(
+                    "{}.{}.partial",
+                    snapshot.display(),
+                    std::process::id()
+                ));
+                let _ = run_tool("btrfs", &["subvolume", "delete"], &[&partial]);
+                match &below {
+                    Some(below) => {
+                        run_tool("btrfs", &["subvolume", "snapshot"], &[below, &partial])?
+                    }
+                    None => run_tool("btrfs", &["subvolume", "create"], &[&partial])?,
+                };
+                apply_layer(&layers[depth - 1], &partial)?;
+                // Another invocation may have made it meanwhile.
+                if fs::rename(&partial, &snapshot).is_err() {
+                    run_tool("btrfs", &["subvolume", "delete"], &[&partial])?;
+                }
+            }
+            below = Some(snapshot);
+        }
+
+        Ok(below)
+    }
+}
+
+/// A ZFS dataset snapshotted for each stack of layers images start with,
+/// and a clone of the topmost for each container. The datasets go under
+/// the one holding the storage root.
+pub struct Zfs;
+
+impl Snapshotter for Zfs {
+    fn name(&self) -> &'static str {
+        "zfs"
+    }
+
+    fn writable_dir(&self, container_path: &Path) -> PathBuf {
+        container_path.join("rootfs")
+    }
+
+    fn prepare(
+        &self,
+        container_path: &Path,
+        layers: &[PathBuf],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let dataset = self.container_dataset(container_path)?;
+        let mountpoint = format!(
+            "mountpoint={}",
+            fs::canonicalize(container_path.join("rootfs"))?.display()
+        );
+        match self.chain(layers)? {
+            Some(top) => run_tool(
+                "zfs",
+                &[
+                    "clone",
+                    "-p",
+                    "-o",
+                    &mountpoint,
+                    &format!("{}@layer", top),
+                    &dataset,
+                ],
+                &[],
+            )?,
+            None => run_tool("zfs", &["create", "-p", "-o", &mountpoint, &dataset], &[])?,
+        };
+        Ok(())
+    }
+
+    fn mount(
+        &self,
+        container_path: &Path,
+        _: &[PathBuf],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !is_mountpoint(&container_path.join("rootfs")) {
+            run_tool(
+                "zfs",
+                &["mount", &self.container_dataset(container_path)?],
+                &[],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn commit(
+        &self,
+        container_path: &Path,
+        _: &[PathBuf],
+        dest: &Path,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let snapshot = format!(
+            "{}@{}",
+            self.container_dataset(container_path)?,
+            dest.file_name().unwrap_or_default().to_string_lossy()
+        );
+        // Named as the stack of it alone, so preparing from it clones it
+        // rather than copying.
+        let layers = vec![dest.to_path_buf()];
+        let clone = format!("{}/rustainer-layers/{}", zfs_root()?, chain_id(&layers));
+        fs::create_dir_all(dest)?;
+        let mountpoint = format!("mountpoint={}", fs::canonicalize(dest)?.display());
+        run_tool("zfs", &["snapshot", &snapshot], &[])?;
+        run_tool(
+            "zfs",
+            &["clone", "-p", "-o", &mountpoint, &snapshot, &clone],
+            &[],
+        )?;
+        run_tool("zfs", &["snapshot", &format!("{}@layer", clone)], &[])?;
+        Ok(layers)
+    }
+
+    fn remove(&self, container_path: &Path) {
+        if let Ok(dataset) = self.container_dataset(container_path) {
+            // With the snapshots layers were committed from.
+            let _ = run_tool("zfs", &["destroy", "-r", &dataset], &[]);
+        }
+    }
+}
+
+impl Zfs {
+    fn container_dataset(
+        &self,
+        container_path: &Path,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let container_id = container_path
+            .file_name()
+            .ok_or("The container directory has no name")?;
+        Ok(format!(
+            "{}/rustainer-containers/{}",
+            zfs_root()?,
+            container_id.to_string_lossy()
+        ))
+    }
+
+    /// The dataset holding all `layers` stacked, cloning the snapshot of the
+    /// one below and adding a layer for each stack not made before. Its
+    /// `@layer` snapshot is only taken once complete.
+    fn chain(&self, layers: &[PathBuf]) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let root = zfs_root()?;
+        let dir = Path::new(&store::path(SNAPSHOTS_DIR)).join(self.name());
+
+        let mut below: Option<String> = None;
+        for depth in 1..=layers.len() {
+            let id = chain_id(&layers[..depth]);
+            let dataset = format!("{}/rustainer-layers/{}", root, id);
+            if run_tool(
+                "zfs",
+                &["list", "-H", "-o", "name", &format!("{}@layer", dataset)],
+                &[],
+            )
+            .is_err()
+            {
+                let _ = run_tool("zfs", &["destroy", "-r", &dataset], &[]);
+                let mountpoint_dir = dir.join(&id);
+                fs::create_dir_all(&mountpoint_dir)?;
+                let mountpoint = format!(
+                    "mountpoint={}",
+                    fs::canonicalize(&mountpoint_dir)?.display()
+                );
+                match &below {
+                    Some(below) => run_tool(
+                        "zfs",
+                        &[
+                            "clone",
+                            "-p",
+                            "-o",
+                            &mountpoint,
+                            &format!("{}@layer", below),
+                            &dataset,
+                        ],
+                        &[],
+                    )?,
+                    None => run_tool("zfs", &["create", "-p", "-o", &mountpoint, &dataset], &[])?,
+                };
+                apply_layer(&layers[depth - 1], &mountpoint_dir)?;
+                run_tool("zfs", &["snapshot", &format!("{}@layer", dataset)], &[])?;
+            }
+            below = Some(dataset);
+        }
+
+        Ok(below)
+    }
+}
+
+/// The dataset holding the storage root, under which rustainer makes its
+/// own.
+fn zfs_root() -> Result<String, Box<dyn std::error::Error>> {
+    fs::create_dir_all(store::root())?;
+    Ok(
+        run_tool("zfs", &["list", "-H", "-o", "name"], &[store::root()])?
+            .trim()
+            .to_string(),
+    )
+}
+
+/// Names a stack of layers, bottom first, by their digests.
+fn chain_id(layers: &[PathBuf]) -> String {
+    let mut hasher = Sha256::new();
+    for layer in layers {
+        hasher.update(layer.file_name().unwrap_or_default().as_bytes());
+        hasher.update(b"\n");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Copies a layer over `dir`, deleting what its whiteouts hide.
+fn apply_layer(layer: &Path, dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    apply_whiteouts(layer, dir)?;
+    copy_dir(layer, dir)?;
+    remove_whiteouts(layer, dir)
+}
+
+/// Applies extracted layers, bottom first, one over another into `dest`,
+/// as the vfs snapshotter makes a rootfs.
+pub fn flatten_layers(layers: &[PathBuf], dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(dest)?;
+    for layer in layers {
+        apply_layer(layer, dest)?;
+    }
+    Ok(())
+}
+
+/// Runs a storage tool with `args` and then `paths`, returning its output.
+fn run_tool(
+    program: &str,
+    args: &[&str],
+    paths: &[&Path],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new(program)
+        .args(args)
+        .args(paths)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// A `--storage-opt` setting.
+#[derive(Debug, Clone, Copy)]
+pub enum StorageOpt {
+    /// The most the container may write, in bytes.
+    Size(u64),
+}
+
+impl StorageOpt {
+    /// Parses `KEY=VALUE`, `size=10g` being the one option there is.
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (key, value) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid storage option: {}. Expected KEY=VALUE", spec))?;
+        match key {
+            "size" => match utils::parse_size(value)? {
+                0 => Err("The storage size must be greater than 0".into()),
+                size => Ok(StorageOpt::Size(size)),
+            },
+            _ => Err(format!("Unknown storage option {}. Expected size", key).into()),
+        }
+    }
+}
+
+/// The snapshotter called `name`.
+pub fn snapshotter(name: &str) -> Result<Box<dyn Snapshotter>, Box<dyn std::error::Error>> {
+    match name {
+        "overlay" => Ok(Box::new(Overlay)),
+        "vfs" => Ok(Box::new(Vfs)),
+        "btrfs" => Ok(Box::new(Btrfs)),
+        "zfs" => Ok(Box::new(Zfs)),
+        _ => Err(format!(
+            "Unknown storage driver {}. Expected overlay, vfs, btrfs or zfs",
+            name
+        )
+        .into()),
+    }
+}
+
+/// Assembles the rootfs of a new container at `<container_path>/rootfs`
+/// with the configured snapshotter, extracting the image layers no
+/// container needed before.
+pub async fn create_rootfs(
+    container_path: &str,
+    image_path: &str,
+    manifest: &ImageManifest,
+    options: &[StorageOpt],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let configured = match std::env::var(DRIVER_ENV) {
+        Ok(name) => Some(snapshotter(&name)?),
+        Err(_) => match &store::config().storage_driver {
+            Some(name) => Some(snapshotter(name)?),
+            None => None,
+        },
+    };
+    let container_path = Path::new(container_path);
+    let rootfs_path = container_path.join("rootfs");
+    fs::create_dir_all(&rootfs_path)?;
+
+    let (module_layers, layers): (Vec<_>, Vec<_>) = manifest
+        .layers
+        .iter()
+        .partition(|layer| wasm::is_module_layer(layer));
+
+    let layer_dirs = prepare_layers(image_path, &layers)?;
+    record_layers(container_path, &layers)?;
+
+    let driver = match configured {
+        Some(driver) => {
+            limit_size(driver.as_ref(), container_path, options)?;
+            driver
+                .prepare(container_path, &layer_dirs)
+                .map_err(|e| format!("Failed to prepare the {} rootfs: {}", driver.name(), e))?;
+            driver
+        }
+        None => {
+            limit_size(&Overlay, container_path, options)?;
+            match Overlay.prepare(container_path, &layer_dirs) {
+                Ok(()) => Box::new(Overlay),
+                Err(e) => {
+                    println!(
+                        "⚠️ Warning: Failed to mount overlayfs, copying the layers instead: {}",
+                        e
+                    );
+                    limit_size(&Vfs, container_path, options)?;
+                    Vfs.prepare(container_path, &layer_dirs)?;
+                    Box::new(Vfs) as Box<dyn Snapshotter>
+                }
+            }
+        }
+    };
+    fs::write(container_path.join(DRIVER_FILE), driver.name())?;
+
+    for layer in module_layers {
+        verify_blob(image_path, &layer.digest)?;
+        fs::copy(
+            format!("{}/{}", image_path, layer.digest.replace("sha256:", "")),
+            format!("{}{}", rootfs_path.display(), wasm::MODULE_PATH),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Extracts the layers no container or mount needed before, side by side,
+/// returning their directories bottom first.
+pub fn prepare_layers(
+    image_path: &str,
+    layers: &[&Layer],
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    // An image may list a layer twice.
+    let mut unique: Vec<&Layer> = Vec::new();
+    for layer in layers {
+        if !unique.iter().any(|other| other.digest == layer.digest) {
+            unique.push(layer);
+        }
+    }
+    std::thread::scope(|scope| {
+        let extractions: Vec<_> = unique
+            .iter()
+            .enumerate()
+            .map(|(i, layer)| {
+                println!(
+                    "Preparing layer {}/{}: {}",
+                    i + 1,
+                    unique.len(),
+                    layer.digest
+                );
+                scope.spawn(move || {
+                    prepare_layer(image_path, layer)
+                        .map(|_| ())
+                        .map_err(|e| e.to_string())
+                })
+            })
+            .collect();
+        extractions.into_iter().try_for_each(|extraction| {
+            extraction
+                .join()
+                .unwrap_or(Err("Layer extraction panicked".into()))
+        })
+    })?;
+    Ok(layers
+        .iter()
+        .map(|layer| layer_dir(&layer.digest))
+        .collect())
+}
+
+/// Applies `--storage-opt size` to the driver's writable directory before
+/// anything is written to it.
+fn limit_size(
+    driver: &dyn Snapshotter,
+    container_path: &Path,
+    options: &[StorageOpt],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for option in options {
+        match option {
+            StorageOpt::Size(size) => {
+                let dir = driver.writable_dir(container_path);
+                fs::create_dir_all(&dir)?;
+                quota::limit(&dir, *size)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A directory as overlayfs options take it. Lazily pulled layers live in
+/// directories named after their digest, whose `:` would split the list.
+fn overlay_path(path: &Path) -> String {
+    path.display()
+        .to_string()
+        .replace('\\', "\\\\")
+        .replace(':', "\\:")
+        .replace(',', "\\,")
+}
+
+/// The snapshotter a container's rootfs was made with; none for a rootfs
+/// from an OCI bundle or made before snapshotters.
+fn container_snapshotter(container_path: &Path) -> Option<Box<dyn Snapshotter>> {
+    let name = fs::read_to_string(container_path.join(DRIVER_FILE)).ok()?;
+    snapshotter(name.trim()).ok()
+}
+
+/// The extracted layers of a container's rootfs, bottom first.
+fn container_layers(container_path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let lower = fs::read_to_string(container_path.join(LOWER_FILE)).unwrap_or_default();
+    let mut layers = Vec::new();
+    for digest in lower.lines().filter(|line| !line.is_empty()) {
+        let dir = layer_dir(digest);
+        if !dir.is_dir() {
+            return Err(format!(
+                "Layer {} is missing from {}",
+                digest,
+                store::path(LAYERS_DIR)
+            )
+            .into());
+        }
+        layers.push(dir);
+    }
+    Ok(layers)
+}
+
+/// The digests of a container's layers missing from the layer store.
+pub fn missing_layers(container_path: &Path) -> Vec<String> {
+    fs::read_to_string(container_path.join(LOWER_FILE))
+        .unwrap_or_default()
+        .lines()
+        .filter(|digest| !digest.is_empty() && !layer_dir(digest).is_dir())
+        .map(String::from)
+        .collect()
+}
+
+/// Mounts a container's rootfs again when it needs it, as after a host
+/// reboot.
+pub fn mount_rootfs(container_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let container_path = Path::new(container_path);
+    match container_snapshotter(container_path) {
+        Some(driver) => driver.mount(container_path, &container_layers(container_path)?),
+        None => Ok(()),
+    }
+}
+
+/// Mounts `layers`, bottom first, read-only at `target`: as an overlay of
+/// them, or where overlayfs cannot be mounted as a copy made in
+/// `<state_dir>/rootfs` and bound there. Nothing set-user-ID or a device
+/// node in an image works through it on the host.
+pub fn mount_layers_read_only(
+    state_dir: &Path,
+    layers: &[PathBuf],
+    target: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let flags = libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV;
+    let target_c = CString::new(target.as_os_str().as_bytes())?;
+
+    // overlayfs lists the topmost layer first, and without an upper
+    // directory needs two lower ones at least.
+    let mut lowerdirs = Vec::new();
+    for dir in layers.iter().rev() {
+        lowerdirs.push(overlay_path(&fs::canonicalize(dir)?));
+    }
+    if lowerdirs.len() < 2 {
+        let empty = state_dir.join("empty");
+        fs::create_dir_all(&empty)?;
+        lowerdirs.push(fs::canonicalize(empty)?.display().to_string());
+    }
+    let data = CString::new(format!("lowerdir={}", lowerdirs.join(":")))?;
+    let result = unsafe {
+        libc::mount(
+            c"overlay".as_ptr(),
+            target_c.as_ptr(),
+            c"overlay".as_ptr(),
+            flags,
+            data.as_ptr() as *const libc::c_void,
+        )
+    };
+    if result == 0 {
+        return Ok(());
+    }
+    println!(
+        "⚠️ Warning: Failed to mount overlayfs, copying the layers instead: {}",
+        std::io::Error::last_os_error()
+    );
+
+    let rootfs_path = state_dir.join("rootfs");
+    fs::create_dir_all(&rootfs_path)?;
+    Vfs.prepare(state_dir, layers)?;
+    let source = CString::new(rootfs_path.as_os_str().as_bytes())?;
+    unsafe {
+        if libc::mount(
+            source.as_ptr(),
+            target_c.as_ptr(),
+            std::ptr::null(),
+            libc::MS_BIND,
+            std::ptr::null(),
+        ) != 0
+        {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        // Bind mounts only become read-only through a remount.
+        if libc::mount(
+            std::ptr::null(),
+            target_c.as_ptr(),
+            std::ptr::null(),
+            libc::MS_BIND | libc::MS_REMOUNT | flags,
+            std::ptr::null(),
+        ) != 0
+        {
+            let error = std::io::Error::last_os_error();
+            libc::umount2(target_c.as_ptr(), libc::MNT_DETACH);
+            return Err(error.into());
+        }
+    }
+    Ok(())
+}
+
+/// Records the layers, bottom first, a container or image mount in `dir` is
+/// made of, so pruning leaves them.
+pub fn record_layers(dir: &Path, layers: &[&Layer]) -> Result<(), Box<dyn std::error::Error>> {
+    let digests: Vec<&str> = layers.iter().map(|layer| layer.digest.as_str()).collect();
+    fs::write(dir.join(LOWER_FILE), digests.join("\n"))?;
+    Ok(())
+}
+
+/// Releases a container's rootfs so its directory can be removed.
+pub fn remove_rootfs(container_id: &str) {
+    let container_path = container::container_dir(container_id);
+    let container_path = Path::new(&container_path);
+    if let Some(driver) = container_snapshotter(container_path) {
+        driver.remove(container_path);
+    }
+}
+
+/// The directory holding what the container wrote over its image, when its
+/// rootfs is an overlay.
+pub fn upper_dir(container_id: &str) -> Option<PathBuf> {
+    let container_path = PathBuf::from(container::container_dir(container_id));
+    let driver = container_snapshotter(&container_path)?;
+    (driver.name() == Overlay.name()).then(|| container_path.join("upper"))
+}
+
+/// Removes the extracted layers that no container uses and no image has any
+/// more, returning their digests. The rest stay cached for the next
+/// container of an image sharing them.
+pub fn prune_layers() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let Ok(entries) = fs::read_dir(store::path(LAYERS_DIR)) else {
+        return Ok(Vec::new());
+    };
+
+    let mut in_use = HashSet::new();
+    for container_id in container::list_container_ids()? {
+        let lower = fs::read_to_string(format!(
+            "{}/{}",
+            container::container_dir(&container_id),
+            LOWER_FILE
+        ))
+        .unwrap_or_default();
+        in_use.extend(lower.lines().map(|digest| digest.replace("sha256:", "")));
+    }
+    for mount in fs::read_dir(store::path(mount::MOUNTS_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        let lower = fs::read_to_string(mount.path().join(LOWER_FILE)).unwrap_or_default();
+        in_use.extend(lower.lines().map(|digest| digest.replace("sha256:", "")));
+    }
+    // Image blobs are named after their digest, in images/<repository>/<tag>.
+    for repository in fs::read_dir(store::path("images"))
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        for tag in fs::read_dir(repository.path())
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            for blob in fs::read_dir(tag.path()).into_iter().flatten().flatten() {
+                in_use.insert(blob.file_name().to_string_lossy().into_owned());
+            }
+            // Lazily pulled layers have no blob, only their link here.
+            in_use.extend(
+                pull::lazy_layers(&tag.path().display().to_string())
+                    .iter()
+                    .map(|digest| digest.replace("sha256:", "")),
+            );
+        }
+    }
+
+    let mut removed = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Left by an extraction that was interrupted, unless it still runs.
+        if let Some(pid) = name
+            .strip_suffix(".partial")
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, pid)| pid)
+        {
+            if !Path::new(&format!("/proc/{}", pid)).exists() {
+                fs::remove_dir_all(entry.path())?;
+            }
+            continue;
+        }
+        if !in_use.contains(&name) {
+            remove_layer(&entry.path())?;
+            removed.push(if name.starts_with(CLONE_LAYER_PREFIX) {
+                name
+            } else {
+                format!("sha256:{}", name)
+            });
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Removes an extracted or committed layer. Layers committed by btrfs are
+/// read-only subvolumes, and those committed by zfs datasets of their own.
+fn remove_layer(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    // Lazily pulled layers are links to stargz-store.
+    if path.is_symlink() {
+        fs::remove_file(path)?;
+        return Ok(());
+    }
+    if !is_mountpoint(path) {
+        fs::remove_dir_all(path)?;
+        return Ok(());
+    }
+    match run_tool("zfs", &["list", "-H", "-o", "name"], &[path]) {
+        Ok(dataset) => run_tool("zfs", &["destroy", "-r", dataset.trim()], &[])?,
+        Err(_) => run_tool("btrfs", &["subvolume", "delete"], &[path])?,
+    };
+    let _ = fs::remove_dir(path);
+    Ok(())
+}
+
+/// Makes `<dest_path>/rootfs` a copy of the rootfs of the container at
+/// `source_path`, committing it as a layer and preparing the new rootfs
+/// from that with the same snapshotter.
+pub fn clone_rootfs(source_path: &str, dest_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let source_path = Path::new(source_path);
+    let dest_path = Path::new(dest_path);
+    fs::create_dir_all(dest_path.join("rootfs"))?;
+    // Made before snapshotters, a plain directory.
+    let Some(driver) = container_snapshotter(source_path) else {
+        return copy_dir(&source_path.join("rootfs"), &dest_path.join("rootfs"));
+    };
+
+    let lower = fs::read_to_string(source_path.join(LOWER_FILE)).unwrap_or_default();
+    let layer_name = format!(
+        "{}{}",
+        CLONE_LAYER_PREFIX,
+        dest_path
+            .file_name()
+            .ok_or("The container directory has no name")?
+            .to_string_lossy()
+    );
+    let layer = layer_dir(&layer_name);
+    let layers = driver
+        .commit(source_path, &container_layers(source_path)?, &layer)
+        .map_err(|e| {
+            let _ = remove_layer(&layer);
+            format!("Failed to commit the {} rootfs: {}", driver.name(), e)
+        })?;
+
+    // Image layers keep their digest, the committed layer its name.
+    let digests: Vec<String> = layers
+        .iter()
+        .map(|dir| {
+            lower
+                .lines()
+                .find(|digest| layer_dir(digest) == *dir)
+                .unwrap_or(&layer_name)
+                .to_string()
+        })
+        .collect();
+    fs::write(dest_path.join(LOWER_FILE), digests.join("\n"))?;
+    driver
+        .prepare(dest_path, &layers)
+        .map_err(|e| format!("Failed to prepare the {} rootfs: {}", driver.name(), e))?;
+    fs::write(dest_path.join(DRIVER_FILE), driver.name())?;
+    Ok(())
+}
+
+fn layer_dir(digest: &str) -> PathBuf {
+    Path::new(&store::path(LAYERS_DIR)).join(digest.replace("sha256:", ""))
+}
+
+/// The extracted layer, extracting it from the image blob if no pull or
+/// container needed it before. Extraction happens next to it and is renamed
+/// in place, so a half extracted layer is never used.
+pub fn prepare_layer(
+    image_path: &str,
+    layer: &Layer,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = layer_dir(&layer.digest);
+    if dir.is_dir() {
+        return Ok(dir);
+    }
+    if let Some(reference) = pull::lazy_reference(image_path, &layer.digest) {
+        return link_lazy_layer(&reference, layer);
+    }
+
+    verify_blob(image_path, &layer.digest)?;
+    let partial = PathBuf::from(format!("{}.{}.partial", dir.display(), std::process::id()));
+    let _ = fs::remove_dir_all(&partial);
+    fs::create_dir_all(&partial)?;
+    if let Err(e) =
+        extract_layer(image_path, layer, &partial).and_then(|_| convert_whiteouts(&partial))
+    {
+        let _ = fs::remove_dir_all(&partial);
+        return Err(e);
+    }
+    // Another invocation may have extracted it meanwhile.
+    if fs::rename(&partial, &dir).is_err() {
+        fs::remove_dir_all(&partial)?;
+    }
+
+    Ok(dir)
+}
+
+/// Puts an eStargz layer served by stargz-store into the layer store in
+/// place of extracting it, so containers start before it is fetched.
+/// `reference` is the image the layer is fetched from, with its registry.
+pub fn link_lazy_layer(
+    reference: &str,
+    layer: &Layer,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let lazy_store = store::config()
+        .lazy_layer_store
+        .as_ref()
+        .ok_or("No lazy_layer_store is configured")?;
+    let source = lazy_store
+        .join(openssl::base64::encode_block(reference.as_bytes()))
+        .join(&layer.digest)
+        .join("diff");
+    // Looking the layer up makes stargz-store fetch its table of contents
+    // and start prefetching the files the image prioritizes.
+    if !source.is_dir() {
+        return Err(format!(
+            "stargz-store at {} cannot serve layer {} of {}",
+            lazy_store.display(),
+            layer.digest,
+            reference
+        )
+        .into());
+    }
+
+    let dir = layer_dir(&layer.digest);
+    if dir.is_dir() {
+        return Ok(dir);
+    }
+    // Left dangling when stargz-store was last unmounted.
+    let _ = fs::remove_file(&dir);
+    fs::create_dir_all(store::path(LAYERS_DIR))?;
+    match std::os::unix::fs::symlink(&source, &dir) {
+        // Another invocation may have linked it meanwhile.
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => Err(e.into()),
+        _ => Ok(dir),
+    }
+}
+
+/// Checks that the image blob named after `digest` still hashes to it,
+/// rather than being truncated, corrupted or tampered with since the pull.
+pub fn verify_blob(image_path: &str, digest: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(expected) = digest.strip_prefix("sha256:") else {
+        return Err(format!("Unsupported digest algorithm in {}", digest).into());
+    };
+    let path = format!("{}/{}", image_path, expected);
+    let mut file =
+        fs::File::open(&path).map_err(|e| format!("Failed to open blob {}: {}", digest, e))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    let actual = format!("{:x}", hasher.finalize());
+    if actual != expected {
+        return Err(format!(
+            "Blob {} is corrupted, its content hashes to sha256:{}. Pull the image again",
+            digest, actual
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Checks that what unpacks `layer` is installed, so a pull doesn't leave an
+/// image no container can be created from.
+pub fn check_decompressor(layer: &Layer) -> Result<(), Box<dyn std::error::Error>> {
+    if matches!(layer.compression()?, Compression::Zstd) && utils::find_binary("zstd").is_none() {
+        return Err(format!(
+            "Layer {} is zstd compressed, which needs zstd installed",
+            layer.digest
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Unpacks a layer blob into `dest`, keeping ownership, modes, timestamps,
+/// xattrs (file capabilities among them), links and device nodes. Nothing
+/// is written outside `dest`.
+fn extract_layer(
+    image_path: &str,
+    layer: &Layer,
+    dest: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let layer_digest = layer.digest.as_str();
+    let layer_path = format!("{}/{}", image_path, layer_digest.replace("sha256:", ""));
+    let file = fs::File::open(&layer_path)
+        .map_err(|e| format!("Failed to open layer {}: {}", layer_digest, e))?;
+
+    match layer.compression()? {
+        Compression::None => unpack_layer(BufReader::new(file), layer_digest, dest),
+        Compression::Gzip => unpack_layer(GzDecoder::new(BufReader::new(file)), layer_digest, dest),
+        // There is no zstd decoder among the dependencies, so zstd(1) does it.
+        Compression::Zstd => {
+            let mut child = Command::new("zstd")
+                .args(["-d", "-c", "-q"])
+                .stdin(file)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("Failed to run zstd for layer {}: {}", layer_digest, e))?;
+            let result = match child.stdout.take() {
+                Some(stdout) => unpack_layer(stdout, layer_digest, dest),
+                None => Err("zstd has no stdout".into()),
+            };
+            // Never leave zstd behind, however unpacking went.
+            if result.is_err() {
+                let _ = child.kill();
+            }
+            let output = child.wait_with_output()?;
+            // A failed decompression explains a failed unpack better, unless
+            // zstd only died because it was killed above.
+            let killed = result.is_err() && output.status.code().is_none();
+            if !output.status.success() && !killed {
+                return Err(format!(
+                    "Failed to decompress layer {}: {}",
+                    layer_digest,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )
+                .into());
+            }
+            result
+        }
+    }
+}
+
+fn unpack_layer(
+    reader: impl Read,
+    layer_digest: &str,
+    dest: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_ownerships(true);
+    archive.set_preserve_mtime(true);
+    archive.set_unpack_xattrs(true);
+    archive.set_overwrite(true);
+
+    let dest = fs::canonicalize(dest)?;
+    let failed = |path: &Path, e: &dyn std::fmt::Display| {
+        format!(
+            "Failed to extract {} from layer {}: {}",
+            path.display(),
+            layer_digest,
+            e
+        )
+    };
+
+    // Directories last, so their modes don't keep their content from being
+    // written and their timestamps stay as the layer has them.
+    let mut directories = Vec::new();
+    for entry in archive
+        .entries()
+        .map_err(|e| format!("Failed to read layer {}: {}", layer_digest, e))?
+    {
+        let mut entry =
+            entry.map_err(|e| format!("Failed to read layer {}: {}", layer_digest, e))?;
+        let path = entry.path()?.into_owned();
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => directories.push(entry),
+            tar::EntryType::Char | tar::EntryType::Block | tar::EntryType::Fifo => {
+                make_node(&dest, &path, entry.header()).map_err(|e| failed(&path, &e))?
+            }
+            _ => {
+                entry.unpack_in(&dest).map_err(|e| failed(&path, &e))?;
+            }
+        }
+    }
+
+    directories.sort_by(|a, b| b.path_bytes().cmp(&a.path_bytes()));
+    for mut entry in directories {
+        let path = entry.path()?.into_owned();
+        if entry.unpack_in(&dest).map_err(|e| failed(&path, &e))? {
+            let target = dest.join(&path);
+            set_mtime(&target, entry.header().mtime()?).map_err(|e| failed(&path, &e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the device node or fifo of a layer entry, which the tar crate
+/// would write out as a regular file.
+fn make_node(
+    dest: &Path,
+    path: &Path,
+    header: &tar::Header,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if path
+        .components()
+        .any(|component| matches!(component, Component::ParentDir))
+    {
+        return Err("the path leads outside the layer".into());
+    }
+    let relative: PathBuf = path
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+    let Some(parent) = relative.parent() else {
+        return Err("the path is the layer root".into());
+    };
+    fs::create_dir_all(dest.join(parent))?;
+    // A symlink in the layer could point the parent elsewhere.
+    if !fs::canonicalize(dest.join(parent))?.starts_with(dest) {
+        return Err("the path leads outside the layer".into());
+    }
+
+    let target = dest.join(&relative);
+    remove_path(&target)?;
+    let kind = match header.entry_type() {
+        tar::EntryType::Char => libc::S_IFCHR,
+        tar::EntryType::Block => libc::S_IFBLK,
+        _ => libc::S_IFIFO,
+    };
+    let mode = header.mode()? & 0o7777;
+    let device = libc::makedev(
+        header.device_major()?.unwrap_or(0),
+        header.device_minor()?.unwrap_or(0),
+    );
+    let c_target = CString::new(target.as_os_str().as_bytes())?;
+    if unsafe { libc::mknod(c_target.as_ptr(), kind | mode, device) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    if unsafe {
+        libc::lchown(
+            c_target.as_ptr(),
+            header.uid()? as libc::uid_t,
+            header.gid()? as libc::gid_t,
+        )
+    } != 0
+    {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // mknod applies the umask.
+    fs::set_permissions(&target, fs::Permissions::from_mode(mode))?;
+    set_mtime(&target, header.mtime()?)?;
+
+    Ok(())
+}
+
+fn set_mtime(path: &Path, mtime: u64) -> std::io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let time = libc::timespec {
+        tv_sec: mtime as libc::time_t,
+        tv_nsec: 0,
+    };
+    let times = [time, time];
+    if unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            times.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    } != 0
+    {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Turns the OCI whiteouts of an extracted layer into overlayfs ones: a
+/// `.wh.NAME` file into a 0/0 character device `NAME`, and a `.wh..wh..opq`
+/// file into the opaque attribute of its directory.
+fn convert_whiteouts(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+
+        if name == OPAQUE_WHITEOUT {
+            fs::remove_file(&path)?;
+            let dir = CString::new(dir.as_os_str().as_bytes())?;
+            let result = unsafe {
+                libc::lsetxattr(
+                    dir.as_ptr(),
+                    OPAQUE_XATTR.as_ptr(),
+                    b"y".as_ptr() as *const libc::c_void,
+                    1,
+                    0,
+                )
+            };
+            if result != 0 {
+                return Err(format!(
+                    "Failed to mark {} opaque: {}",
+                    dir.to_string_lossy(),
+                    std::io::Error::last_os_error()
+                )
+                .into());
+            }
+        } else if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+            fs::remove_file(&path)?;
+            let target = dir.join(hidden);
+            if let Ok(metadata) = fs::symlink_metadata(&target) {
+                if metadata.is_dir() {
+                    fs::remove_dir_all(&target)?;
+                } else {
+                    fs::remove_file(&target)?;
+                }
+            }
+            let target = CString::new(target.as_os_str().as_bytes())?;
+            if unsafe { libc::mknod(target.as_ptr(), libc::S_IFCHR, libc::makedev(0, 0)) } != 0 {
+                return Err(format!(
+                    "Failed to create whiteout for {}: {}",
+                    path.display(),
+                    std::io::Error::last_os_error()
+                )
+                .into());
+            }
+        } else if entry.file_type()?.is_dir() {
+            convert_whiteouts(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a layer file is an overlayfs whiteout, a 0/0 character device.
+fn is_whiteout(metadata: &fs::Metadata) -> bool {
+    metadata.file_type().is_char_device() && metadata.rdev() == 0
+}
+
+/// Whether the layer directory `path` hides what lower layers have in it.
+fn is_opaque(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut value = [0u8; 1];
+    let size = unsafe {
+        libc::lgetxattr(
+            path.as_ptr(),
+            OPAQUE_XATTR.as_ptr(),
+            value.as_mut_ptr() as *mut libc::c_void,
+            value.len(),
+        )
+    };
+    size == 1 && value[0] == b'y'
+}
+
+/// Deletes from `rootfs` what the layer's whiteouts and opaque directories
+/// hide, before the layer is copied over it. Only real directories of the
+/// rootfs are descended into, so a symlink in the image can't lead outside.
+fn apply_whiteouts(layer: &Path, rootfs: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if is_opaque(layer) {
+        for entry in fs::read_dir(rootfs)? {
+            remove_path(&entry?.path())?;
+        }
+    }
+    for entry in fs::read_dir(layer)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let target = rootfs.join(entry.file_name());
+        if is_whiteout(&metadata) {
+            remove_path(&target)?;
+        } else if metadata.is_dir()
+            && fs::symlink_metadata(&target).is_ok_and(|target| target.is_dir())
+        {
+            apply_whiteouts(&entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes the whiteouts and opaque attributes copied from the layer into
+/// `rootfs`, which has no use for them.
+fn remove_whiteouts(layer: &Path, rootfs: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if is_opaque(layer) {
+        if let Ok(path) = CString::new(rootfs.as_os_str().as_bytes()) {
+            unsafe { libc::lremovexattr(path.as_ptr(), OPAQUE_XATTR.as_ptr()) };
+        }
+    }
+    for entry in fs::read_dir(layer)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let target = rootfs.join(entry.file_name());
+        if is_whiteout(&metadata) {
+            remove_path(&target)?;
+        } else if metadata.is_dir()
+            && fs::symlink_metadata(&target).is_ok_and(|target| target.is_dir())
+        {
+            remove_whiteouts(&entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes a file, symlink or whole directory, never following a symlink.
+fn remove_path(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+        Ok(_) => fs::remove_file(path)?,
+        Err(_) => {}
+    }
+    Ok(())
+}
+
+/// Copies a directory's contents over another, keeping ownership, modes and
+/// links. Files are reflinked where the filesystem can (XFS, btrfs), sharing
+/// their blocks until written to. Hardlinks would share the files
+/// themselves, letting a container's writes reach the layer cache.
+fn copy_dir(from: &Path, to: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let output = Command::new("cp")
+        .args(["-a", "--reflink=auto"])
+        .arg(from.join("."))
+        .arg(to)
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to copy {}: {}",
+            from.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Whether something is mounted at `path`, which then has another device
+/// than its parent.
+fn is_mountpoint(path: &Path) -> bool {
+    match (fs::metadata(path), path.parent().map(fs::metadata)) {
+        (Ok(metadata), Some(Ok(parent))) => metadata.dev() != parent.dev(),
+        _ => false,
+    }
+}
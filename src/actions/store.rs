@@ -0,0 +1,249 @@
+use crate::actions::utils;
+use serde::Deserialize;
+use std::{
+    fs::{self, File},
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// Overrides the storage root of the config file.
+pub const ROOT_ENV: &str = "RUSTAINER_ROOT";
+/// The storage root of root, which rootless users keep in their data dir.
+const SYSTEM_ROOT: &str = "/var/lib/rustainer";
+const SYSTEM_CONFIG: &str = "/etc/rustainer/config.json";
+/// Lock files, apart from what they lock so removing that keeps them.
+const LOCKS_DIR: &str = "locks";
+/// Scratch space for work in progress, on the same filesystem as what it
+/// ends up in.
+pub const TMP_DIR: &str = "tmp";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Settings of the config file, `/etc/rustainer/config.json` for root and
+/// `$XDG_CONFIG_HOME/rustainer/config.json` for other users.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Where images, layers, containers and volumes are kept.
+    pub root: PathBuf,
+    /// The snapshotter new containers get, unless `RUSTAINER_STORAGE_DRIVER`
+    /// names another.
+    pub storage_driver: Option<String>,
+    /// How large images and their extracted layers may grow, such as `20g`.
+    /// Past it the least recently used images no container was made from
+    /// are removed.
+    pub max_image_store_size: Option<String>,
+    /// Patterns of environment variable names, such as `*PASSWORD*`, whose
+    /// values inspect, ps and events print as `*****`. Replaces the default
+    /// patterns.
+    pub mask_env: Option<Vec<String>>,
+    /// Where stargz-store is mounted, such as `/var/lib/stargz-store/store`.
+    /// eStargz layers are then pulled lazily: containers start from them
+    /// right away and file chunks are fetched as they are read.
+    pub lazy_layer_store: Option<PathBuf>,
+}
+
+/// Loads the config file and settles the storage root: `--root`, then
+/// `RUSTAINER_ROOT`, then the config file, then the default for the user.
+/// Relative roots are taken from the current directory.
+pub fn init(root_flag: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load_config()?;
+    let root = match (root_flag, std::env::var(ROOT_ENV)) {
+        (Some(root), _) => PathBuf::from(root),
+        (None, Ok(root)) if !root.is_empty() => PathBuf::from(root),
+        _ if !config.root.as_os_str().is_empty() => config.root.clone(),
+        _ => default_root()?,
+    };
+    config.root = std::path::absolute(&root)
+        .map_err(|e| format!("Invalid storage root {}: {}", root.display(), e))?;
+    if let Some(size) = &config.max_image_store_size {
+        utils::parse_size(size).map_err(|e| format!("Invalid max_image_store_size: {}", e))?;
+    }
+
+    CONFIG
+        .set(config)
+        .map_err(|_| "The storage root is already set")?;
+    Ok(())
+}
+
+pub fn config() -> &'static Config {
+    CONFIG.get().expect("the storage root is set at startup")
+}
+
+/// The `max_image_store_size` of the config file, in bytes.
+pub fn max_image_store_size() -> Option<u64> {
+    utils::parse_size(config().max_image_store_size.as_deref()?).ok()
+}
+
+pub fn root() -> &'static Path {
+    &config().root
+}
+
+/// A path in the storage root, such as `path("containers")`.
+pub fn path(relative: &str) -> String {
+    root().join(relative).display().to_string()
+}
+
+/// The directory an image is kept in.
+pub fn image_dir(repository: &str, tag: &str) -> String {
+    path(&format!("images/{}/{}", repository.replace('/', "_"), tag))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// For using something, alongside other users.
+    Shared,
+    /// For changing or removing something.
+    Exclusive,
+}
+
+/// A lock on part of the store, held until it is dropped and released by
+/// the kernel when the process dies.
+pub struct Lock {
+    _file: File,
+}
+
+/// Locks the whole store: exclusively to collect garbage, shared to add
+/// layers and images or make containers from them meanwhile.
+pub fn lock_store(mode: LockMode) -> Result<Lock, Box<dyn std::error::Error>> {
+    lock("store", mode, "the store")
+}
+
+/// Locks an image: exclusively to pull or import it, shared to make a
+/// container from it.
+pub fn lock_image(
+    repository: &str,
+    tag: &str,
+    mode: LockMode,
+) -> Result<Lock, Box<dyn std::error::Error>> {
+    lock(
+        &format!("images/{}/{}", repository.replace('/', "_"), tag),
+        mode,
+        &format!("image {}:{}", repository, tag),
+    )
+}
+
+/// Locks a container while it is created, changed or removed.
+pub fn lock_container(container_id: &str) -> Result<Lock, Box<dyn std::error::Error>> {
+    lock(
+        &format!("containers/{}", container_id),
+        LockMode::Exclusive,
+        &format!("container {}", container_id),
+    )
+}
+
+/// Locks the container name index while it is changed.
+pub fn lock_names() -> Result<Lock, Box<dyn std::error::Error>> {
+    lock("names", LockMode::Exclusive, "the container names")
+}
+
+/// Locks the bridge network's addresses while one is picked for a container,
+/// until its network namespace and metadata show it as taken.
+pub fn lock_network() -> Result<Lock, Box<dyn std::error::Error>> {
+    lock("network", LockMode::Exclusive, "the network")
+}
+
+/// Locks a container's metadata for the length of a read-modify-write,
+/// which the supervisor does while commands hold the container's lock.
+pub fn lock_metadata(container_id: &str) -> Result<Lock, Box<dyn std::error::Error>> {
+    lock(
+        &format!("metadata/{}", container_id),
+        LockMode::Exclusive,
+        &format!("the metadata of container {}", container_id),
+    )
+}
+
+/// Held by a container's health monitor while it probes, so the one started
+/// with each run of the container waits for the previous run's to finish.
+pub fn lock_health_monitor(container_id: &str) -> Result<Lock, Box<dyn std::error::Error>> {
+    lock(
+        &format!("health/{}", container_id),
+        LockMode::Exclusive,
+        &format!("the health monitor of container {}", container_id),
+    )
+}
+
+/// Removes the lock files of a removed container, once its lock is held.
+pub fn remove_container_lock(container_id: &str) {
+    let _ = fs::remove_file(path(&format!("{}/containers/{}", LOCKS_DIR, container_id)));
+    let _ = fs::remove_file(path(&format!("{}/metadata/{}", LOCKS_DIR, container_id)));
+    let _ = fs::remove_file(path(&format!("{}/health/{}", LOCKS_DIR, container_id)));
+}
+
+fn lock(name: &str, mode: LockMode, what: &str) -> Result<Lock, Box<dyn std::error::Error>> {
+    let lock_path = root().join(LOCKS_DIR).join(name);
+    if let Some(parent) = lock_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(|e| format!("Failed to open lock {}: {}", lock_path.display(), e))?;
+    let operation = match mode {
+        LockMode::Shared => libc::LOCK_SH,
+        LockMode::Exclusive => libc::LOCK_EX,
+    };
+
+    if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } != 0 {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::EWOULDBLOCK) {
+            return Err(format!("Failed to lock {}: {}", what, error).into());
+        }
+        println!("⏳ Waiting for another rustainer to release {}", what);
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+                break;
+            }
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(format!("Failed to lock {}: {}", what, error).into());
+            }
+        }
+    }
+
+    Ok(Lock { _file: file })
+}
+
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+fn default_root() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if is_root() {
+        return Ok(PathBuf::from(SYSTEM_ROOT));
+    }
+    Ok(xdg_dir("XDG_DATA_HOME", ".local/share")?.join("rustainer"))
+}
+
+fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
+    let path = if is_root() {
+        PathBuf::from(SYSTEM_CONFIG)
+    } else {
+        match xdg_dir("XDG_CONFIG_HOME", ".config") {
+            Ok(dir) => dir.join("rustainer/config.json"),
+            Err(_) => return Ok(Config::default()),
+        }
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e).into()),
+    };
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Invalid config file {}: {}", path.display(), e).into())
+}
+
+/// `$<variable>`, or `fallback` in the home directory when it is unset.
+fn xdg_dir(variable: &str, fallback: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    match std::env::var(variable) {
+        Ok(dir) if Path::new(&dir).is_absolute() => Ok(PathBuf::from(dir)),
+        _ => match std::env::var("HOME") {
+            Ok(home) if !home.is_empty() => Ok(Path::new(&home).join(fallback)),
+            _ => Err(format!("Neither {} nor HOME is set", variable).into()),
+        },
+    }
+}
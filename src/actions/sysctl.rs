@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+/// IPC namespace sysctls besides `fs.mqueue.*`.
+const IPC_SYSCTLS: [&str; 8] = [
+    "kernel.msgmax",
+    "kernel.msgmnb",
+    "kernel.msgmni",
+    "kernel.sem",
+    "kernel.shmall",
+    "kernel.shmmax",
+    "kernel.shmmni",
+    "kernel.shm_rmid_forced",
+];
+
+/// A `--sysctl` setting. Only sysctls of the container's own network and IPC
+/// namespaces are allowed, anything else would change the host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sysctl {
+    pub key: String,
+    pub value: String,
+}
+
+impl Sysctl {
+    /// Parses `KEY=VALUE`, checking the key against the namespaced sysctls.
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (key, value) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid sysctl: {}. Expected KEY=VALUE", spec))?;
+        let sysctl = Sysctl {
+            key: key.trim().to_string(),
+            value: value.trim().to_string(),
+        };
+
+        let valid_key = !sysctl.key.is_empty()
+            && sysctl
+                .key
+                .split('.')
+                .all(|part| !part.is_empty() && part != "..")
+            && !sysctl.key.contains('/');
+        if !valid_key || !(sysctl.is_network() || sysctl.is_ipc()) {
+            return Err(format!(
+                "Sysctl {} is not allowed, only net.*, fs.mqueue.* and the kernel.msg*, \
+                 kernel.sem and kernel.shm* IPC settings are namespaced",
+                sysctl.key
+            )
+            .into());
+        }
+
+        Ok(sysctl)
+    }
+
+    pub fn is_network(&self) -> bool {
+        self.key.starts_with("net.")
+    }
+
+    pub fn is_ipc(&self) -> bool {
+        self.key.starts_with("fs.mqueue.") || IPC_SYSCTLS.contains(&self.key.as_str())
+    }
+
+    /// The file under /proc/sys, which shows the namespaces of the process
+    /// reading or writing it.
+    pub fn proc_path(&self) -> String {
+        format!("/proc/sys/{}", self.key.replace('.', "/"))
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    os::fd::{AsRawFd, FromRawFd},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+/// How long output may pause before the copy stops once the container is
+/// gone, in case something else still holds the slave side open.
+const DRAIN_TIMEOUT_MS: libc::c_int = 100;
+
+/// Docker's default escape sequence for leaving a container running.
+pub const DEFAULT_DETACH_KEYS: &str = "ctrl-p,ctrl-q";
+
+/// Master side of the terminal in use, for the SIGWINCH handler.
+static MASTER_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// A pseudo-terminal for a foreground `run -t`: the container gets the slave
+/// side as its stdio while its output, and with `-i` the user's input, is
+/// copied through the master side.
+pub struct Terminal {
+    /// Held open for the resize handler, the copies use their own clones.
+    _master: File,
+    slave: Option<File>,
+    /// The user's terminal settings, while it is in raw mode.
+    saved: Option<libc::termios>,
+    output: Option<JoinHandle<()>>,
+    finished: Arc<AtomicBool>,
+}
+
+impl Terminal {
+    /// Opens the terminal and starts copying. With `interactive`, input is
+    /// passed through and a terminal on stdin goes into raw mode, leaving
+    /// keys such as Ctrl+C to the container's terminal. Typing `detach_keys`
+    /// leaves the container running and exits rustainer.
+    pub fn open(
+        container_id: &str,
+        interactive: bool,
+        detach_keys: &[u8],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let (mut master_fd, mut slave_fd) = (-1, -1);
+        let opened = unsafe {
+            libc::openpty(
+                &mut master_fd,
+                &mut slave_fd,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+            )
+        };
+        if opened != 0 {
+            return Err(format!(
+                "Failed to allocate a pseudo-TTY: {}",
+                std::io::Error::last_os_error()
+            )
+            .into());
+        }
+        let (master, slave) = unsafe {
+            libc::fcntl(master_fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            libc::fcntl(slave_fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            (File::from_raw_fd(master_fd), File::from_raw_fd(slave_fd))
+        };
+
+        MASTER_FD.store(master.as_raw_fd(), Ordering::Relaxed);
+        resize(master.as_raw_fd());
+        follow_resizes();
+
+        let saved = if interactive && unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 {
+            Some(enter_raw_mode()?)
+        } else {
+            None
+        };
+
+        if interactive {
+            let mut input = master.try_clone()?;
+            let (slave_fd, container_id) = (slave.as_raw_fd(), container_id.to_string());
+            let detach_keys = detach_keys.to_vec();
+            std::thread::spawn(move || {
+                let mut stdin = std::io::stdin().lock();
+                let mut buffer = [0u8; 1024];
+                // How much of the detach sequence was typed so far, held back
+                // until it either completes or turns out to be regular input.
+                let mut matched = 0;
+                loop {
+                    let read = match stdin.read(&mut buffer) {
+                        Ok(read) if read > 0 => read,
+                        _ => break,
+                    };
+                    let mut forward = Vec::with_capacity(read + matched);
+                    for &byte in &buffer[..read] {
+                        if detach_keys.get(matched) == Some(&byte) {
+                            matched += 1;
+                            if matched == detach_keys.len() {
+                                let _ = input.write_all(&forward);
+                                detach(input.as_raw_fd(), slave_fd, saved, &container_id);
+                            }
+                            continue;
+                        }
+                        forward.extend_from_slice(&detach_keys[..matched]);
+                        matched = 0;
+                        if detach_keys.first() == Some(&byte) {
+                            matched = 1;
+                        } else {
+                            forward.push(byte);
+                        }
+                    }
+                    if input.write_all(&forward).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let finished = Arc::new(AtomicBool::new(false));
+        let mut output = master.try_clone()?;
+        let output_finished = finished.clone();
+        let output = std::thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            let mut stdout = std::io::stdout();
+            loop {
+                let mut poll = libc::pollfd {
+                    fd: output.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                if unsafe { libc::poll(&mut poll, 1, DRAIN_TIMEOUT_MS) } == 0 {
+                    if output_finished.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    continue;
+                }
+                // Reads fail with EIO once every slave descriptor is closed.
+                match output.read(&mut buffer) {
+                    Ok(read) if read > 0 && stdout.write_all(&buffer[..read]).is_ok() => {
+                        let _ = stdout.flush();
+                    }
+                    _ => break,
+                }
+            }
+        });
+
+        Ok(Terminal {
+            _master: master,
+            slave: Some(slave),
+            saved,
+            output: Some(output),
+            finished,
+        })
+    }
+
+    /// The slave side, for one of the container's standard streams.
+    pub fn stdio(&self) -> Result<Stdio, Box<dyn std::error::Error>> {
+        let slave = self.slave.as_ref().ok_or("The terminal is closed")?;
+        Ok(slave.try_clone()?.into())
+    }
+
+    /// Once the container is gone: prints what it wrote last and gives the
+    /// user their terminal back.
+    pub fn finish(mut self) {
+        self.slave = None;
+        self.finished.store(true, Ordering::Relaxed);
+        if let Some(output) = self.output.take() {
+            let _ = output.join();
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        MASTER_FD.store(-1, Ordering::Relaxed);
+        if let Some(saved) = &self.saved {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+        }
+    }
+}
+
+/// Parses a Docker style `--detach-keys` value: comma separated keys, each a
+/// single character or `ctrl-<key>` for `a`-`z`, `@`, `[`, `\`, `]`, `^`
+/// and `_`.
+pub fn parse_detach_keys(spec: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let invalid = || format!("Invalid detach keys: {}", spec);
+    let mut keys = Vec::new();
+
+    for key in spec.split(',') {
+        let byte = match key.strip_prefix("ctrl-") {
+            Some(control) if control.len() == 1 => {
+                let byte = control.as_bytes()[0].to_ascii_uppercase();
+                if !(b'@'..=b'_').contains(&byte) {
+                    return Err(invalid().into());
+                }
+                byte - b'@'
+            }
+            _ if key.len() == 1 && key.is_ascii() => key.as_bytes()[0],
+            _ => return Err(invalid().into()),
+        };
+        keys.push(byte);
+    }
+
+    Ok(keys)
+}
+
+/// Leaves the container running after the detach keys: a forked process
+/// keeps the master side open and drains it so the container never sees a
+/// hangup, then rustainer exits without stopping anything.
+fn detach(
+    master_fd: libc::c_int,
+    slave_fd: libc::c_int,
+    saved: Option<libc::termios>,
+    container_id: &str,
+) -> ! {
+    if let Some(saved) = &saved {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+    }
+
+    match unsafe { libc::fork() } {
+        0 => unsafe {
+            libc::setsid();
+            let null = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+            for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+                libc::dup2(null, fd);
+            }
+            libc::close(null);
+            libc::close(slave_fd);
+
+            let mut buffer = [0u8; 4096];
+            loop {
+                let read = libc::read(master_fd, buffer.as_mut_ptr().cast(), buffer.len());
+                if read > 0 || (read < 0 && *libc::__errno_location() == libc::EINTR) {
+                    continue;
+                }
+                libc::_exit(0);
+            }
+        },
+        -1 => eprintln!(
+            "\r\n❌ Failed to keep the terminal open: {}",
+            std::io::Error::last_os_error()
+        ),
+        _ => println!(
+            "\r\n🔌 Detached from container {}, it keeps running",
+            super::container::short_id(container_id)
+        ),
+    }
+
+    std::process::exit(0);
+}
+
+fn enter_raw_mode() -> Result<libc::termios, Box<dyn std::error::Error>> {
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let mut raw = saved;
+    unsafe {
+        libc::cfmakeraw(&mut raw);
+        if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+
+    Ok(saved)
+}
+
+/// Gives the terminal the size of the one rustainer writes to.
+fn resize(master_fd: libc::c_int) {
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0 {
+            libc::ioctl(master_fd, libc::TIOCSWINSZ, &size);
+        }
+    }
+}
+
+fn follow_resizes() {
+    extern "C" fn on_resize(_signal: libc::c_int) {
+        let master_fd = MASTER_FD.load(Ordering::Relaxed);
+        if master_fd >= 0 {
+            resize(master_fd);
+        }
+    }
+
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_resize as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGWINCH, &action, std::ptr::null_mut());
+    }
+}
@@ -1,4 +1,9 @@
 use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// The annotation eStargz layers carry, the digest of their table of
+/// contents.
+const ESTARGZ_TOC_ANNOTATION: &str = "containerd.io/snapshot/stargz/toc.digest";
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
@@ -11,7 +16,8 @@ pub enum ManifestResponse {
 pub struct ImageManifest {
     #[serde(rename = "schemaVersion")]
     pub schema_version: i32,
-    #[serde(rename = "mediaType")]
+    // Optional in OCI manifests.
+    #[serde(rename = "mediaType", default)]
     pub media_type: String,
     pub config: Layer,
     pub layers: Vec<Layer>,
@@ -35,20 +41,142 @@ pub struct PlatformManifest {
     pub platform: Option<Platform>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Platform {
     pub architecture: String,
     pub os: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub variant: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl Platform {
+    /// The platform rustainer runs on, using OCI architecture names.
+    pub fn host() -> Self {
+        let architecture = match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            "x86" => "386",
+            "powerpc64" => "ppc64le",
+            other => other,
+        };
+
+        Platform {
+            architecture: architecture.to_string(),
+            os: "linux".to_string(),
+            variant: None,
+        }
+    }
+
+    /// The platform of Wasm images, which run on any host.
+    pub fn wasm() -> Self {
+        Platform {
+            architecture: "wasm".to_string(),
+            os: "wasi".to_string(),
+            variant: None,
+        }
+    }
+
+    pub fn is_wasm(&self) -> bool {
+        self.satisfies(&Platform::wasm())
+    }
+
+    /// Whether `self` satisfies a requested platform. A request without a
+    /// variant accepts any variant.
+    pub fn satisfies(&self, wanted: &Platform) -> bool {
+        self.os == wanted.os
+            && self.architecture == wanted.architecture
+            && (wanted.variant.is_none() || self.variant == wanted.variant)
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = value.split('/').collect();
+        if parts.len() < 2 || parts.len() > 3 || parts.iter().any(|p| p.is_empty()) {
+            return Err(format!(
+                "Invalid platform: {}. Expected os/arch[/variant]",
+                value
+            ));
+        }
+
+        Ok(Platform {
+            os: parts[0].to_string(),
+            architecture: parts[1].to_string(),
+            variant: parts.get(2).map(|v| v.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Layer {
     #[serde(rename = "mediaType")]
     pub media_type: String,
     pub size: u64,
     pub digest: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub annotations: HashMap<String, String>,
+}
+
+impl Layer {
+    /// Whether the layer is eStargz, which can be mounted before it is
+    /// fetched.
+    pub fn is_estargz(&self) -> bool {
+        self.annotations.contains_key(ESTARGZ_TOC_ANNOTATION)
+    }
+
+    /// How the layer archive is compressed, as its media type says.
+    pub fn compression(&self) -> Result<Compression, String> {
+        let media_type = self.media_type.as_str();
+        if media_type.ends_with("+zstd") || media_type.ends_with(".tar.zstd") {
+            Ok(Compression::Zstd)
+        } else if media_type.ends_with("+gzip") || media_type.ends_with(".tar.gzip") {
+            Ok(Compression::Gzip)
+        } else if media_type.ends_with(".tar") {
+            Ok(Compression::None)
+        } else {
+            Err(format!("Unsupported layer media type {}", media_type))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// The bytes an archive compressed this way starts with.
+    pub fn magic(&self) -> &'static [u8] {
+        match self {
+            Compression::None => b"",
+            Compression::Gzip => &[0x1f, 0x8b],
+            Compression::Zstd => &[0x28, 0xb5, 0x2f, 0xfd],
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "uncompressed"),
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,40 @@
+use crate::actions::{
+    cgroup::{self, ResourceLimits},
+    container,
+};
+
+/// Changes the resource limits of a running container and records them with
+/// the ones it was created with.
+pub async fn update_container(
+    reference: &str,
+    limits: &ResourceLimits,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = container::resolve_container(reference)?;
+    limits.validate()?;
+
+    if limits.is_empty() {
+        return Err("Nothing to update, give at least one limit".into());
+    }
+    if !container::is_running(&container_id) {
+        return Err(format!("Container {} is not running", container_id).into());
+    }
+    if !std::path::Path::new(&cgroup::cgroup_path(&container_id)).exists() {
+        return Err(format!("Container {} has no cgroup to update", container_id).into());
+    }
+
+    cgroup::apply(&container_id, limits)?;
+
+    if let serde_json::Value::Object(updated) = serde_json::to_value(limits)? {
+        container::update_metadata(&container_id, |metadata| {
+            for (key, value) in updated {
+                let given = !value.is_null() && value.as_array().is_none_or(|a| !a.is_empty());
+                if given {
+                    metadata["resources"][key] = value;
+                }
+            }
+        })?;
+    }
+
+    println!("✅ Updated container {}", container_id);
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use std::fs;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedUser {
+    pub uid: u32,
+    pub gid: u32,
+    pub home: Option<String>,
+    /// Supplementary groups the user is a member of in /etc/group.
+    pub groups: Vec<u32>,
+}
+
+struct PasswdEntry {
+    name: String,
+    uid: u32,
+    gid: u32,
+    home: String,
+}
+
+/// Resolves a `user[:group]` spec (names or numeric IDs) against the
+/// container's own /etc/passwd and /etc/group. An empty spec means root.
+/// Without a group in the spec, the user also gets the supplementary groups
+/// /etc/group lists them in.
+pub fn resolve_user(
+    rootfs_path: &str,
+    spec: &str,
+) -> Result<ResolvedUser, Box<dyn std::error::Error>> {
+    let (user, group) = match spec.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (spec, None),
+    };
+
+    let passwd = read_passwd(rootfs_path);
+
+    let entry = if user.is_empty() {
+        passwd.iter().find(|entry| entry.uid == 0)
+    } else if let Ok(uid) = user.parse::<u32>() {
+        // Numeric users need not exist in the image.
+        passwd.iter().find(|entry| entry.uid == uid)
+    } else {
+        Some(
+            passwd
+                .iter()
+                .find(|entry| entry.name == user)
+                .ok_or_else(|| {
+                    format!(
+                        "Unable to find user {}: no matching entries in passwd file",
+                        user
+                    )
+                })?,
+        )
+    };
+
+    let (uid, mut gid, home) = match (user.parse::<u32>(), entry) {
+        (Ok(uid), None) => (uid, 0, None),
+        (_, Some(entry)) => (entry.uid, entry.gid, Some(entry.home.clone())),
+        (Err(_), None) => (0, 0, Some("/root".to_string())),
+    };
+
+    let mut groups = Vec::new();
+    match group.filter(|g| !g.is_empty()) {
+        Some(group) => gid = resolve_group(rootfs_path, group)?,
+        None => {
+            if let Some(entry) = entry {
+                groups = member_groups(rootfs_path, &entry.name);
+            }
+        }
+    }
+
+    Ok(ResolvedUser {
+        uid,
+        gid,
+        home,
+        groups,
+    })
+}
+
+/// Maps a group name or numeric GID to a GID using the container's /etc/group.
+pub fn resolve_group(rootfs_path: &str, group: &str) -> Result<u32, Box<dyn std::error::Error>> {
+    if let Ok(gid) = group.parse::<u32>() {
+        return Ok(gid);
+    }
+
+    let content = fs::read_to_string(format!("{}/etc/group", rootfs_path)).unwrap_or_default();
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() >= 3 && fields[0] == group {
+                fields[2].parse().ok()
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| {
+            format!(
+                "Unable to find group {}: no matching entries in group file",
+                group
+            )
+            .into()
+        })
+}
+
+/// The GIDs of the groups listing `user` as a member.
+fn member_groups(rootfs_path: &str, user: &str) -> Vec<u32> {
+    let content = fs::read_to_string(format!("{}/etc/group", rootfs_path)).unwrap_or_default();
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() >= 4 && fields[3].split(',').any(|member| member == user) {
+                fields[2].parse().ok()
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn read_passwd(rootfs_path: &str) -> Vec<PasswdEntry> {
+    let content = fs::read_to_string(format!("{}/etc/passwd", rootfs_path)).unwrap_or_default();
+
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() < 6 {
+                return None;
+            }
+
+            Some(PasswdEntry {
+                name: fields[0].to_string(),
+                uid: fields[2].parse().ok()?,
+                gid: fields[3].parse().ok()?,
+                home: fields[5].to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rootfs(name: &str) -> std::path::PathBuf {
+        let rootfs =
+            std::env::temp_dir().join(format!("rustainer-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&rootfs);
+        fs::create_dir_all(rootfs.join("etc")).unwrap();
+        fs::write(
+            rootfs.join("etc/passwd"),
+            "root:x:0:0:root:/root:/bin/sh\napp:x:1000:1000::/home/app:/bin/sh\n",
+        )
+        .unwrap();
+        fs::write(
+            rootfs.join("etc/group"),
+            "root:x:0:\nwheel:x:10:root\napp:x:1000:\nvideo:x:44:root,app\nstaff:x:50:application\n",
+        )
+        .unwrap();
+        rootfs
+    }
+
+    #[test]
+    fn resolve_user_adds_member_groups() {
+        let rootfs = rootfs("user-groups");
+        let rootfs_path = rootfs.to_str().unwrap();
+
+        let root = resolve_user(rootfs_path, "").unwrap();
+        assert_eq!((root.uid, root.gid), (0, 0));
+        assert_eq!(root.groups, vec![10, 44]);
+
+        let app = resolve_user(rootfs_path, "app").unwrap();
+        assert_eq!((app.uid, app.gid), (1000, 1000));
+        assert_eq!(app.home.as_deref(), Some("/home/app"));
+        assert_eq!(app.groups, vec![44]);
+        assert_eq!(resolve_user(rootfs_path, "1000").unwrap().groups, vec![44]);
+
+        fs::remove_dir_all(&rootfs).unwrap();
+    }
+
+    #[test]
+    fn resolve_user_with_a_group_gets_only_that_group() {
+        let rootfs = rootfs("user-group-spec");
+        let rootfs_path = rootfs.to_str().unwrap();
+
+        let app = resolve_user(rootfs_path, "app:staff").unwrap();
+        assert_eq!(app.gid, 50);
+        assert!(app.groups.is_empty());
+
+        let unknown = resolve_user(rootfs_path, "4242").unwrap();
+        assert_eq!((unknown.uid, unknown.gid), (4242, 0));
+        assert!(unknown.groups.is_empty());
+        assert!(resolve_user(rootfs_path, "nobody").is_err());
+
+        fs::remove_dir_all(&rootfs).unwrap();
+    }
+}
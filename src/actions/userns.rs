@@ -0,0 +1,404 @@
+use crate::actions::container;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::CString,
+    fmt,
+    fs::{self, File},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::{fs::MetadataExt, process::CommandExt},
+    },
+    path::Path,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+const SUBUID_FILE: &str = "/etc/subuid";
+const SUBGID_FILE: &str = "/etc/subgid";
+/// IDs given to each container, enough for any user an image defines.
+const RANGE_SIZE: u32 = 65536;
+
+/// How container user IDs relate to the host's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UsernsMode {
+    /// Container IDs are host IDs.
+    #[default]
+    Host,
+    /// A private range of subordinate IDs.
+    Auto,
+    /// Like auto, but the invoking user keeps their own ID inside, so files
+    /// in bind mounts keep their owner.
+    KeepId,
+}
+
+impl FromStr for UsernsMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "" | "host" => Ok(UsernsMode::Host),
+            "auto" => Ok(UsernsMode::Auto),
+            "keep-id" => Ok(UsernsMode::KeepId),
+            _ => Err(format!(
+                "Invalid userns mode: {}. Expected host, auto or keep-id",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for UsernsMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsernsMode::Host => write!(f, "host"),
+            UsernsMode::Auto => write!(f, "auto"),
+            UsernsMode::KeepId => write!(f, "keep-id"),
+        }
+    }
+}
+
+/// One line of a uid_map or gid_map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdMapping {
+    pub container_id: u32,
+    pub host_id: u32,
+    pub size: u32,
+}
+
+/// The ID mappings of a container's user namespace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdMappings {
+    pub uids: Vec<IdMapping>,
+    pub gids: Vec<IdMapping>,
+}
+
+impl IdMappings {
+    /// Where files owned by `uid`/`gid` inside the container live on the
+    /// host, if the IDs are mapped.
+    fn host_ids(&self, uid: u32, gid: u32) -> Option<(u32, u32)> {
+        Some((host_id(&self.uids, uid)?, host_id(&self.gids, gid)?))
+    }
+}
+
+fn host_id(mappings: &[IdMapping], id: u32) -> Option<u32> {
+    mappings
+        .iter()
+        .find(|m| id >= m.container_id && id - m.container_id < m.size)
+        .map(|m| m.host_id + (id - m.container_id))
+}
+
+/// The user rustainer acts for: the one behind sudo if there is one.
+pub fn invoking_ids() -> (u32, u32) {
+    let from_env = |name: &str| std::env::var(name).ok()?.parse().ok();
+    match (from_env("SUDO_UID"), from_env("SUDO_GID")) {
+        (Some(uid), Some(gid)) => (uid, gid),
+        _ => unsafe { (libc::getuid(), libc::getgid()) },
+    }
+}
+
+/// Picks ID mappings for a new container, taking a block of subordinate IDs
+/// no other container uses. The IDs are those of the user rustainer runs as,
+/// which newuidmap checks them against.
+pub fn allocate(mode: UsernsMode) -> Result<Option<IdMappings>, Box<dyn std::error::Error>> {
+    if mode == UsernsMode::Host {
+        return Ok(None);
+    }
+
+    let owner = unsafe { libc::geteuid() };
+    let user = host_user_name(owner);
+    let in_use = mappings_in_use();
+
+    let uid_start = free_block(
+        &subordinate_ranges(SUBUID_FILE, user.as_deref(), owner),
+        in_use.iter().flat_map(|m| &m.uids),
+        SUBUID_FILE,
+    )?;
+    let gid_start = free_block(
+        &subordinate_ranges(SUBGID_FILE, user.as_deref(), owner),
+        in_use.iter().flat_map(|m| &m.gids),
+        SUBGID_FILE,
+    )?;
+
+    let mappings = match mode {
+        UsernsMode::KeepId => {
+            let (uid, gid) = invoking_ids();
+            IdMappings {
+                uids: keep_id(uid, uid_start),
+                gids: keep_id(gid, gid_start),
+            }
+        }
+        _ => IdMappings {
+            uids: vec![IdMapping {
+                container_id: 0,
+                host_id: uid_start,
+                size: RANGE_SIZE,
+            }],
+            gids: vec![IdMapping {
+                container_id: 0,
+                host_id: gid_start,
+                size: RANGE_SIZE,
+            }],
+        },
+    };
+
+    Ok(Some(mappings))
+}
+
+/// Maps `id` to itself and the rest of the container range to the block.
+fn keep_id(id: u32, start: u32) -> Vec<IdMapping> {
+    let own = IdMapping {
+        container_id: id,
+        host_id: id,
+        size: 1,
+    };
+    if id >= RANGE_SIZE {
+        return vec![
+            IdMapping {
+                container_id: 0,
+                host_id: start,
+                size: RANGE_SIZE,
+            },
+            own,
+        ];
+    }
+
+    let mut mappings = vec![own];
+    if id > 0 {
+        mappings.push(IdMapping {
+            container_id: 0,
+            host_id: start,
+            size: id,
+        });
+    }
+    if id + 1 < RANGE_SIZE {
+        mappings.push(IdMapping {
+            container_id: id + 1,
+            host_id: start + id,
+            size: RANGE_SIZE - 1 - id,
+        });
+    }
+    mappings
+}
+
+/// `(start, count)` ranges in /etc/subuid or /etc/subgid for a user, who may
+/// be listed by name or by UID.
+fn subordinate_ranges(path: &str, user: Option<&str>, uid: u32) -> Vec<(u32, u32)> {
+    let uid = uid.to_string();
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.trim().split(':').collect();
+            if fields.len() != 3 || (Some(fields[0]) != user && fields[0] != uid) {
+                return None;
+            }
+            Some((fields[1].parse().ok()?, fields[2].parse().ok()?))
+        })
+        .collect()
+}
+
+/// The lowest start of a `RANGE_SIZE` block inside `ranges` that overlaps
+/// none of the mappings in use.
+fn free_block<'a>(
+    ranges: &[(u32, u32)],
+    in_use: impl Iterator<Item = &'a IdMapping>,
+    path: &str,
+) -> Result<u32, Box<dyn std::error::Error>> {
+    if ranges.is_empty() {
+        return Err(format!("No subordinate IDs for the current user in {}", path).into());
+    }
+
+    let taken: Vec<(u64, u64)> = in_use
+        .map(|m| (m.host_id as u64, m.host_id as u64 + m.size as u64))
+        .collect();
+
+    for (start, count) in ranges {
+        let end = *start as u64 + *count as u64;
+        let mut candidate = *start as u64;
+        while candidate + RANGE_SIZE as u64 <= end {
+            let candidate_end = candidate + RANGE_SIZE as u64;
+            match taken
+                .iter()
+                .find(|(from, to)| *from < candidate_end && candidate < *to)
+            {
+                Some((_, to)) => candidate = *to,
+                None => return Ok(candidate as u32),
+            }
+        }
+    }
+
+    Err(format!("No free block of {} IDs left in {}", RANGE_SIZE, path).into())
+}
+
+fn mappings_in_use() -> Vec<IdMappings> {
+    container::list_container_ids()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|id| container::load_metadata(id))
+        .filter_map(|metadata| serde_json::from_value(metadata["process"]["userns"].clone()).ok())
+        .collect()
+}
+
+fn host_user_name(uid: u32) -> Option<String> {
+    fs::read_to_string("/etc/passwd")
+        .ok()?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && fields[2] == uid.to_string())
+        .map(|fields| fields[0].to_string())
+}
+
+/// Creates a user namespace with the given mappings and returns a handle to
+/// it, which keeps it alive for processes to join with setns.
+///
+/// The mappings must be written from outside the namespace, so a helper
+/// process creates it and is killed once they are in place.
+pub fn create_namespace(mappings: &IdMappings) -> Result<File, Box<dyn std::error::Error>> {
+    let mut helper = Command::new("sleep");
+    helper
+        .arg("infinity")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    unsafe {
+        helper.pre_exec(|| {
+            if libc::unshare(libc::CLONE_NEWUSER) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut helper = helper.spawn()?;
+
+    let result = write_id_map(helper.id(), "uid", &mappings.uids)
+        .and_then(|()| write_id_map(helper.id(), "gid", &mappings.gids))
+        .and_then(|()| Ok(File::open(format!("/proc/{}/ns/user", helper.id()))?));
+
+    let _ = helper.kill();
+    let _ = helper.wait();
+
+    result
+}
+
+/// Writes a process's uid_map or gid_map with newuidmap/newgidmap, which
+/// check the mappings against /etc/subuid and /etc/subgid. Root may write
+/// the map directly where they are not installed.
+fn write_id_map(
+    pid: u32,
+    kind: &str,
+    mappings: &[IdMapping],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tool = format!("new{}map", kind);
+    let mut args = vec![pid.to_string()];
+    for mapping in mappings {
+        args.extend([
+            mapping.container_id.to_string(),
+            mapping.host_id.to_string(),
+            mapping.size.to_string(),
+        ]);
+    }
+
+    match Command::new(&tool).args(&args).output() {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(format!(
+            "{} failed: {}",
+            tool,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into()),
+        Err(_) if unsafe { libc::geteuid() } == 0 => {
+            let map: String = mappings
+                .iter()
+                .map(|m| format!("{} {} {}\n", m.container_id, m.host_id, m.size))
+                .collect();
+            fs::write(format!("/proc/{}/{}_map", pid, kind), map)?;
+            Ok(())
+        }
+        Err(_) => Err(format!("{} is required for user namespaces, install uidmap", tool).into()),
+    }
+}
+
+/// Makes a detached mount of `path` that shows files through the user
+/// namespace's mappings, so the host's root owns them as the container's
+/// root. Needs kernel and filesystem support for idmapped mounts.
+pub fn idmapped_mount(path: &str, userns: &File) -> std::io::Result<OwnedFd> {
+    let path = CString::new(path)?;
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_open_tree,
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            libc::OPEN_TREE_CLONE | libc::OPEN_TREE_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let tree = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+    let attr = libc::mount_attr {
+        attr_set: libc::MOUNT_ATTR_IDMAP,
+        attr_clr: 0,
+        propagation: 0,
+        userns_fd: userns.as_raw_fd() as u64,
+    };
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            tree.as_raw_fd(),
+            c"".as_ptr(),
+            libc::AT_EMPTY_PATH,
+            &attr as *const libc::mount_attr,
+            std::mem::size_of::<libc::mount_attr>(),
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(tree)
+}
+
+/// Prepares a container's rootfs for its user namespace: returns whether an
+/// idmapped mount will do, otherwise chowns every file to the mapped IDs
+/// once, which costs a walk over the whole tree.
+pub fn prepare_rootfs(
+    rootfs_path: &str,
+    mappings: &IdMappings,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let supported = create_namespace(mappings)
+        .ok()
+        .is_some_and(|userns| idmapped_mount(rootfs_path, &userns).is_ok());
+    if supported {
+        return Ok(true);
+    }
+
+    println!("🔧 Idmapped mounts are not supported here, shifting rootfs ownership");
+    shift_ownership(Path::new(rootfs_path), mappings)?;
+    Ok(false)
+}
+
+/// Chowns `path`, and everything under it, from container IDs to the host
+/// IDs they map to.
+pub fn shift_ownership(
+    path: &Path,
+    mappings: &IdMappings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let metadata = fs::symlink_metadata(path)?;
+    if let Some((uid, gid)) = mappings.host_ids(metadata.uid(), metadata.gid()) {
+        std::os::unix::fs::lchown(path, Some(uid), Some(gid))?;
+        // chown clears setuid and setgid bits, put them back.
+        if !metadata.file_type().is_symlink() && metadata.mode() & 0o6000 != 0 {
+            fs::set_permissions(path, metadata.permissions())?;
+        }
+    }
+
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            shift_ownership(&entry?.path(), mappings)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::HashSet,
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Checks the name of a container, volume, secret or config, `kind` saying
+/// which for the error.
+pub fn validate_object_name(kind: &str, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+
+    if !valid {
+        return Err(format!(
+            "Invalid {} name: {}. Only [a-zA-Z0-9][a-zA-Z0-9_.-] are allowed",
+            kind, name
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Parses durations such as `90s`, `15m`, `12h` or `7d`; a bare number is
+/// taken as seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, Box<dyn std::error::Error>> {
+    let value = value.trim();
+    let split_at = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split_at);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid duration: {}", value))?;
+
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(format!("Invalid duration unit in {}. Use s, m, h or d", value).into()),
+    };
+
+    Ok(Duration::from_secs(amount * multiplier))
+}
+
+/// Parses sizes such as `512m` or `2g` (binary units, case-insensitive, an
+/// optional trailing `b`); a bare number is taken as bytes.
+pub fn parse_size(value: &str) -> Result<u64, Box<dyn std::error::Error>> {
+    let lower = value.trim().to_ascii_lowercase();
+    let split_at = lower
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(lower.len());
+    let (amount, unit) = lower.split_at(split_at);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid size: {}", value))?;
+
+    let multiplier: u64 = match unit.strip_suffix('b').unwrap_or(unit) {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        _ => return Err(format!("Invalid size unit in {}. Use b, k, m, g or t", value).into()),
+    };
+
+    amount
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Size is too large: {}", value).into())
+}
+
+/// Prints rows as left-aligned columns sized to their widest cell.
+pub fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        line.join("   ").trim_end().to_string()
+    };
+
+    println!("{}", format_row(headers.to_vec()));
+    for row in rows {
+        println!("{}", format_row(row.iter().map(String::as_str).collect()));
+    }
+}
+
+/// Shortens a value to at most `max` characters, marking the cut with `…`.
+pub fn truncate(value: &str, max: usize) -> String {
+    if value.chars().count() <= max {
+        return value.to_string();
+    }
+
+    let mut truncated: String = value.chars().take(max.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Disk usage of a directory tree in bytes, counting hardlinked files once and
+/// never following symlinks.
+pub fn dir_size(path: &Path) -> u64 {
+    let mut seen = HashSet::new();
+    dir_size_inner(path, &mut seen)
+}
+
+fn dir_size_inner(path: &Path, seen: &mut HashSet<(u64, u64)>) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+
+    if !seen.insert((metadata.dev(), metadata.ino())) {
+        return 0;
+    }
+
+    let mut size = metadata.blocks() * 512;
+    if metadata.is_dir() {
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.flatten() {
+                size += dir_size_inner(&entry.path(), seen);
+            }
+        }
+    }
+
+    size
+}
+
+/// Replaces `path` with `contents` in one step, so concurrent readers see
+/// either the old or the new file and never half of it.
+pub fn write_atomic(path: &str, contents: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let partial = format!("{}.{}.tmp", path, std::process::id());
+    fs::write(&partial, contents)?;
+    if let Err(e) = fs::rename(&partial, path) {
+        let _ = fs::remove_file(&partial);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+pub fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
+    let mut size = size as f64;
+    let mut unit_index = 0;
+
+    while size >= 1024.0 && unit_index < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit_index += 1;
+    }
+
+    format!("{:.1}{}", size, UNITS[unit_index])
+}
+
+/// Formats seconds since the epoch as an RFC 3339 UTC timestamp.
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let seconds_of_day = secs % 86400;
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    )
+}
+
+/// Looks `name` up in the directories of `PATH`.
+pub fn find_binary(name: &str) -> Option<PathBuf> {
+    std::env::var_os("PATH")
+        .iter()
+        .flat_map(std::env::split_paths)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
@@ -0,0 +1,245 @@
+use crate::actions::{
+    container, pull, rm, storage,
+    store::{self, LockMode},
+    types::{ImageManifest, Layer},
+};
+use std::{collections::HashMap, fs, path::Path};
+
+/// What `verify_store` found wrong, and how much of it was repaired.
+#[derive(Default)]
+struct Report {
+    problems: usize,
+    repaired: usize,
+}
+
+impl Report {
+    fn problem(&mut self, message: &str) {
+        println!("❌ {}", message);
+        self.problems += 1;
+    }
+
+    fn repaired(&mut self, message: &str) {
+        println!("🔧 {}", message);
+        self.repaired += 1;
+    }
+}
+
+/// Checks the store for images whose blobs are missing or do not hash to
+/// their digest, leftover files, containers that are missing what they were
+/// made of, and a name index out of step with the containers. With `repair`
+/// broken images and containers without metadata are removed, missing
+/// layers extracted again, leftovers deleted and the name index fixed.
+pub async fn verify_store(repair: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut report = Report::default();
+
+    println!("🔍 Verifying images...");
+    let layers = verify_images(repair, &mut report)?;
+
+    println!("🔍 Verifying containers...");
+    for container_id in container::list_container_ids()? {
+        verify_container(&container_id, &layers, repair, &mut report).await?;
+    }
+    for problem in container::check_name_index(repair)? {
+        report.problem(&problem);
+        if repair {
+            report.repaired("Updated the name index");
+        }
+    }
+
+    let unrepaired = report.problems - report.repaired;
+    if report.problems == 0 {
+        println!("✅ The store is consistent");
+        Ok(())
+    } else if unrepaired == 0 {
+        println!("✅ Repaired {} problem(s)", report.repaired);
+        Ok(())
+    } else if repair {
+        Err(format!("{} problem(s) could not be repaired", unrepaired).into())
+    } else {
+        Err(format!(
+            "Found {} problem(s), run with --repair to fix them",
+            report.problems
+        )
+        .into())
+    }
+}
+
+/// Verifies every image, returning where each layer of the intact ones can
+/// be extracted from.
+fn verify_images(
+    repair: bool,
+    report: &mut Report,
+) -> Result<HashMap<String, (String, Layer)>, Box<dyn std::error::Error>> {
+    let mode = if repair {
+        LockMode::Exclusive
+    } else {
+        LockMode::Shared
+    };
+    let _store_lock = store::lock_store(mode)?;
+    let mut layers = HashMap::new();
+
+    for repository in fs::read_dir(store::path("images"))
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        for tag in fs::read_dir(repository.path())
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let image_path = tag.path().display().to_string();
+            let repository = repository.file_name().to_string_lossy().replace('_', "/");
+            let tag = tag.file_name().to_string_lossy().into_owned();
+            let reference = pull::format_image_reference(&repository, &tag);
+            let _image_lock = store::lock_image(&repository, &tag, mode)?;
+
+            let manifest = match verify_image(&image_path) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    report.problem(&format!("Image {}: {}", reference, e));
+                    if repair {
+                        fs::remove_dir_all(&image_path)?;
+                        report.repaired(&format!("Removed image {}, pull it again", reference));
+                    }
+                    continue;
+                }
+            };
+
+            for leftover in leftover_files(&image_path, &manifest)? {
+                report.problem(&format!(
+                    "Image {} has a file it does not reference: {}",
+                    reference, leftover
+                ));
+                if repair {
+                    fs::remove_file(format!("{}/{}", image_path, leftover))?;
+                    report.repaired(&format!("Removed {} from image {}", leftover, reference));
+                }
+            }
+
+            for layer in manifest.layers {
+                layers.insert(layer.digest.clone(), (image_path.clone(), layer));
+            }
+        }
+    }
+    // Repositories whose images were all removed.
+    if repair {
+        for repository in fs::read_dir(store::path("images"))
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let _ = fs::remove_dir(repository.path());
+        }
+    }
+
+    Ok(layers)
+}
+
+/// Reads an image's manifest and re-hashes the blobs it references.
+fn verify_image(image_path: &str) -> Result<ImageManifest, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(format!("{}/manifest.json", image_path))
+        .map_err(|e| format!("Failed to read its manifest: {}", e))?;
+    let manifest: ImageManifest =
+        serde_json::from_str(&content).map_err(|e| format!("Invalid manifest: {}", e))?;
+
+    storage::verify_blob(image_path, &manifest.config.digest)?;
+    let lazy = pull::lazy_layers(image_path);
+    for layer in manifest.layers.iter().filter(|l| !lazy.contains(&l.digest)) {
+        storage::verify_blob(image_path, &layer.digest)?;
+    }
+    Ok(manifest)
+}
+
+/// Files in an image directory that are neither its manifest, its metadata
+/// nor a blob it references, such as what an interrupted import left.
+fn leftover_files(
+    image_path: &str,
+    manifest: &ImageManifest,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let referenced: Vec<String> = std::iter::once(&manifest.config)
+        .chain(&manifest.layers)
+        .map(|layer| layer.digest.replace("sha256:", ""))
+        .collect();
+
+    let mut leftovers = Vec::new();
+    for entry in fs::read_dir(image_path)?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name != "manifest.json"
+            && name != pull::IMAGE_METADATA_FILE
+            && !referenced.contains(&name)
+        {
+            leftovers.push(name);
+        }
+    }
+    leftovers.sort();
+    Ok(leftovers)
+}
+
+/// Checks that a container has readable metadata and what its rootfs is
+/// made of: the bundle it runs from, or its layers in the layer store.
+async fn verify_container(
+    container_id: &str,
+    layers: &HashMap<String, (String, Layer)>,
+    repair: bool,
+    report: &mut Report,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let short_id = container::short_id(container_id);
+    let lock = match container::lock(container_id) {
+        Ok(lock) => lock,
+        // Removed since it was listed.
+        Err(_) => return Ok(()),
+    };
+    let container_path = container::container_dir(container_id);
+
+    let Some(metadata) = container::load_metadata(container_id) else {
+        report.problem(&format!(
+            "Container {} has no readable metadata.json",
+            short_id
+        ));
+        if repair {
+            drop(lock);
+            rm::remove_container(container_id, true, false).await?;
+            report.repaired(&format!("Removed container {}", short_id));
+        }
+        return Ok(());
+    };
+
+    if let Some(bundle) = metadata.get("bundle").and_then(|v| v.as_str()) {
+        if !Path::new(bundle).is_dir() {
+            report.problem(&format!(
+                "Container {} runs from bundle {}, which no longer exists. Remove it with rm",
+                short_id, bundle
+            ));
+        }
+        return Ok(());
+    }
+
+    if !Path::new(&format!("{}/rootfs", container_path)).is_dir() {
+        report.problem(&format!(
+            "Container {} has no rootfs. Remove it with rm",
+            short_id
+        ));
+    }
+    for digest in storage::missing_layers(Path::new(&container_path)) {
+        report.problem(&format!(
+            "Container {} is missing layer {}",
+            short_id, digest
+        ));
+        if !repair {
+            continue;
+        }
+        match layers.get(&digest) {
+            Some((image_path, layer)) => match storage::prepare_layer(image_path, layer) {
+                Ok(_) => report.repaired(&format!("Extracted layer {} again", digest)),
+                Err(e) => println!("⚠️ Warning: Failed to extract layer {}: {}", digest, e),
+            },
+            None => println!(
+                "⚠️ Warning: No image has layer {} any more. Remove container {} with rm",
+                digest, short_id
+            ),
+        }
+    }
+
+    Ok(())
+}
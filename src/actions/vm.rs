@@ -0,0 +1,279 @@
+use std::{
+    ffi::CString,
+    fs,
+    os::{fd::AsRawFd, unix::process::CommandExt},
+    path::Path,
+    process::Command,
+};
+
+use crate::actions::{
+    cgroup::ResourceLimits,
+    container,
+    network::{NetworkMode, BRIDGE_GATEWAY},
+    run::ProcessSpec,
+    store, utils,
+};
+
+/// The `--runtime` value that boots the container in a microVM.
+pub const RUNTIME: &str = "vm";
+const FIRECRACKER: &str = "firecracker";
+/// The guest kernel every microVM boots, an uncompressed vmlinux.
+const KERNEL_PATH: &str = "vm/vmlinux";
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+const DEFAULT_VCPUS: u64 = 1;
+const DEFAULT_MEMORY_MIB: u64 = 512;
+/// Room left on the root disk beyond what the rootfs takes.
+const DISK_HEADROOM: u64 = 256 * 1024 * 1024;
+/// The tap device and bridge joining the guest to the container's veth.
+const TAP_DEVICE: &str = "tap0";
+const VM_BRIDGE: &str = "br0";
+
+/// Checks that microVMs can be booted here, and that the container's
+/// settings can be carried over into one.
+pub fn check(network: NetworkMode) -> Result<(), Box<dyn std::error::Error>> {
+    if network == NetworkMode::Host {
+        return Err("MicroVMs cannot share the host network".into());
+    }
+    match Command::new(FIRECRACKER).arg("--version").output() {
+        Ok(output) if output.status.success() => {}
+        _ => return Err(format!("MicroVMs require {} to be installed", FIRECRACKER).into()),
+    }
+    if !Path::new(&store::path(KERNEL_PATH)).exists() {
+        return Err(format!(
+            "MicroVMs require a guest kernel (vmlinux) at {}",
+            store::path(KERNEL_PATH)
+        )
+        .into());
+    }
+    for tool in ["mkfs.ext4", "ip"] {
+        if Command::new(tool).arg("-V").output().is_err() {
+            return Err(format!("MicroVMs require {} to be installed", tool).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// The command booting the container as a Firecracker microVM. The rootfs
+/// becomes the VM's root disk on first boot and the command its init; the
+/// guest has its own kernel, so namespaces, capabilities and seccomp do not
+/// apply inside it. Firecracker itself runs in the container's network
+/// namespace, where the guest is bridged to the container's interface.
+pub fn command(
+    container_id: &str,
+    rootfs_path: &str,
+    process: &ProcessSpec,
+) -> Result<Command, Box<dyn std::error::Error>> {
+    let container_dir = container::container_dir(container_id);
+    let disk = format!("{}/rootfs.ext4", container_dir);
+    if !Path::new(&disk).exists() {
+        build_disk(rootfs_path, &disk, process)?;
+    }
+
+    let metadata = container::load_metadata(container_id).unwrap_or_default();
+    let limits: ResourceLimits =
+        serde_json::from_value(metadata["resources"].clone()).unwrap_or_default();
+    let vcpus = limits
+        .cpus
+        .map_or(DEFAULT_VCPUS, |cpus| (cpus.ceil() as u64).max(1));
+    let memory_mib = limits
+        .memory
+        .map_or(DEFAULT_MEMORY_MIB, |memory| (memory / (1024 * 1024)).max(1));
+
+    let mut boot_args = vec![
+        "console=ttyS0".to_string(),
+        "reboot=k".to_string(),
+        "panic=1".to_string(),
+        "pci=off".to_string(),
+        "root=/dev/vda".to_string(),
+        if process.read_only { "ro" } else { "rw" }.to_string(),
+        format!("init={}", init_path(rootfs_path, process)?),
+    ];
+    let mut config = serde_json::json!({
+        "drives": [{
+            "drive_id": "rootfs",
+            "path_on_host": fs::canonicalize(&disk)?,
+            "is_root_device": true,
+            "is_read_only": process.read_only,
+        }],
+        "machine-config": { "vcpu_count": vcpus, "mem_size_mib": memory_mib },
+    });
+
+    if process.network == NetworkMode::Bridge {
+        connect_guest(container_id)?;
+        let ip = metadata
+            .get("ip")
+            .and_then(|v| v.as_str())
+            .ok_or("The container has no IP for its microVM")?;
+        boot_args.push(format!(
+            "ip={}::{}:255.255.0.0:{}:eth0:off",
+            ip, BRIDGE_GATEWAY, process.hostname
+        ));
+        config["network-interfaces"] =
+            serde_json::json!([{ "iface_id": "eth0", "host_dev_name": TAP_DEVICE }]);
+    }
+
+    // Unknown parameters with a value end up in init's environment, and
+    // everything after `--` in its arguments.
+    for (key, value) in &process.env {
+        boot_args.push(format!("{}={}", key, kernel_quote(value)));
+    }
+    if process.args.len() > 1 {
+        boot_args.push("--".to_string());
+        boot_args.extend(process.args[1..].iter().map(|arg| kernel_quote(arg)));
+    }
+    config["boot-source"] = serde_json::json!({
+        "kernel_image_path": store::path(KERNEL_PATH),
+        "boot_args": boot_args.join(" "),
+    });
+
+    let config_path = format!("{}/vm.json", container_dir);
+    fs::write(&config_path, serde_json::to_string_pretty(&config)?)?;
+
+    let mut cmd = Command::new(FIRECRACKER);
+    cmd.args(["--no-api", "--config-file"])
+        .arg(fs::canonicalize(&config_path)?);
+    let netns = process
+        .network
+        .has_namespace()
+        .then(|| fs::File::open(format!("/run/netns/{}", container_id)))
+        .transpose()?;
+    let cgroup_procs = process
+        .cgroup
+        .as_ref()
+        .map(|path| CString::new(format!("{}/cgroup.procs", path)))
+        .transpose()?;
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(cgroup_procs) = &cgroup_procs {
+                let fd = libc::open(cgroup_procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+                if fd < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                let written = libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+                libc::close(fd);
+                if written != 1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(netns) = &netns {
+                if libc::setns(netns.as_raw_fd(), libc::CLONE_NEWNET) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+
+    Ok(cmd)
+}
+
+/// Builds the VM's root disk from the rootfs. Files bind mounted into
+/// containers, such as /etc/hosts, are copied in; directories cannot be
+/// shared with the guest.
+fn build_disk(
+    rootfs_path: &str,
+    disk: &str,
+    process: &ProcessSpec,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for mount in &process.mounts {
+        if Path::new(&mount.source).is_file() {
+            fs::copy(&mount.source, format!("{}{}", rootfs_path, mount.target))?;
+        } else {
+            println!(
+                "⚠️ Warning: {} is not available inside the microVM",
+                mount.target
+            );
+        }
+    }
+
+    println!("🔧 Building the microVM root disk");
+    let size = utils::dir_size(Path::new(rootfs_path)) + DISK_HEADROOM;
+    let output = Command::new("mkfs.ext4")
+        .args(["-q", "-F", "-d", rootfs_path, disk])
+        .arg(format!("{}k", size / 1024))
+        .output()?;
+    if !output.status.success() {
+        let _ = fs::remove_file(disk);
+        return Err(format!(
+            "Failed to build the microVM root disk: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Bridges a tap device for the guest with the container's veth, moving the
+/// container's IP from the veth to the guest.
+fn connect_guest(container_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let ip_netns = |args: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+        let output = Command::new("ip")
+            .args(["-n", container_id])
+            .args(args)
+            .output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "Failed to set up microVM networking (ip {}): {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    };
+
+    // Already connected on an earlier start.
+    if ip_netns(&["link", "show", TAP_DEVICE]).is_ok() {
+        return Ok(());
+    }
+
+    let links = ip_netns(&["-o", "link", "show", "type", "veth"])?;
+    let veth = links
+        .lines()
+        .find_map(|line| line.split(": ").nth(1))
+        .map(|name| name.split('@').next().unwrap_or(name).to_string())
+        .ok_or("The container has no network interface to connect its microVM to")?;
+
+    ip_netns(&["tuntap", "add", "dev", TAP_DEVICE, "mode", "tap"])?;
+    ip_netns(&["link", "add", VM_BRIDGE, "type", "bridge"])?;
+    ip_netns(&["addr", "flush", "dev", &veth])?;
+    ip_netns(&["link", "set", &veth, "master", VM_BRIDGE])?;
+    ip_netns(&["link", "set", TAP_DEVICE, "master", VM_BRIDGE])?;
+    for device in [TAP_DEVICE, VM_BRIDGE] {
+        ip_netns(&["link", "set", device, "up"])?;
+    }
+
+    Ok(())
+}
+
+/// The command's path in the rootfs, which the kernel needs to run it as
+/// init, looking it up in the container's PATH like a shell would.
+fn init_path(
+    rootfs_path: &str,
+    process: &ProcessSpec,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let command = &process.args[0];
+    if command.contains('/') {
+        return Ok(command.clone());
+    }
+
+    process
+        .env
+        .get("PATH")
+        .map_or(DEFAULT_PATH, String::as_str)
+        .split(':')
+        .map(|dir| format!("{}/{}", dir.trim_end_matches('/'), command))
+        .find(|path| Path::new(&format!("{}{}", rootfs_path, path)).is_file())
+        .ok_or_else(|| format!("{} not found in the container's PATH", command).into())
+}
+
+/// Quotes a kernel command line value that has spaces.
+fn kernel_quote(value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        format!("\"{}\"", value)
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,757 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Component, Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use crate::actions::{
+    container, events,
+    run::{BindMount, TmpfsMount},
+    security::{self, ProcessLabel},
+    store, utils,
+};
+
+/// Where named volumes are kept, each in a directory of its own.
+const VOLUMES_DIR: &str = "volumes";
+/// Symlinks followed when resolving a mount target, as the kernel allows.
+const MAX_SYMLINKS: u32 = 40;
+
+/// A `-v SOURCE:CONTAINER[:OPTIONS]` mount of a host path, or with `named`
+/// of the named volume `source`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Volume {
+    pub source: String,
+    pub target: String,
+    #[serde(default)]
+    pub readonly: bool,
+    #[serde(default)]
+    pub named: bool,
+    #[serde(default)]
+    pub propagation: Option<Propagation>,
+    #[serde(default)]
+    pub relabel: Option<Relabel>,
+    /// Leaves a new named volume empty rather than filling it with what the
+    /// image has at its mount point.
+    #[serde(default)]
+    pub nocopy: bool,
+    /// Created for a `Volumes` path of the image, under a random name, and
+    /// removed with the container by `rm -v`.
+    #[serde(default)]
+    pub anonymous: bool,
+}
+
+/// Whether mounts made under a bind mount propagate between the host and the
+/// container, as `mount --make-*` sets it; `rprivate` when not given. The
+/// container's mount namespace is a slave of the host's, so shared mounts
+/// propagate across the container but not back to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Propagation {
+    Private,
+    Rprivate,
+    Shared,
+    Rshared,
+    Slave,
+    Rslave,
+}
+
+impl FromStr for Propagation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "private" => Ok(Propagation::Private),
+            "rprivate" => Ok(Propagation::Rprivate),
+            "shared" => Ok(Propagation::Shared),
+            "rshared" => Ok(Propagation::Rshared),
+            "slave" => Ok(Propagation::Slave),
+            "rslave" => Ok(Propagation::Rslave),
+            _ => Err(format!("Invalid propagation mode: {}", value)),
+        }
+    }
+}
+
+impl fmt::Display for Propagation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Propagation::Private => "private",
+            Propagation::Rprivate => "rprivate",
+            Propagation::Shared => "shared",
+            Propagation::Rshared => "rshared",
+            Propagation::Slave => "slave",
+            Propagation::Rslave => "rslave",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Propagation {
+    /// The flags `mount(2)` changes a mount's propagation with.
+    pub fn flags(&self) -> libc::c_ulong {
+        match self {
+            Propagation::Private => libc::MS_PRIVATE,
+            Propagation::Rprivate => libc::MS_PRIVATE | libc::MS_REC,
+            Propagation::Shared => libc::MS_SHARED,
+            Propagation::Rshared => libc::MS_SHARED | libc::MS_REC,
+            Propagation::Slave => libc::MS_SLAVE,
+            Propagation::Rslave => libc::MS_SLAVE | libc::MS_REC,
+        }
+    }
+}
+
+/// SELinux relabeling of a volume's content: `z` to share it with every
+/// container, `Z` to make it private to this one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Relabel {
+    Shared,
+    Private,
+}
+
+/// What is recorded about a named volume next to its data.
+#[derive(Debug, Serialize, Deserialize)]
+struct VolumeMetadata {
+    name: String,
+    created_at: u64,
+}
+
+impl Volume {
+    /// Parses `SOURCE:CONTAINER[:OPTIONS]`, the source being an absolute host
+    /// path or a volume name. Options are `ro` or `rw`, `z` or `Z`, a
+    /// propagation mode, and `nocopy` for named volumes.
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        let (source, target, options) = match parts.as_slice() {
+            [source, target] => (*source, *target, ""),
+            [source, target, options] => (*source, *target, *options),
+            _ => {
+                return Err(format!(
+                    "Invalid volume: {}. Expected SOURCE:CONTAINER[:OPTIONS]",
+                    spec
+                )
+                .into())
+            }
+        };
+
+        let named = !source.starts_with('/');
+        if named && utils::validate_object_name("volume", source).is_err() {
+            return Err(format!(
+                "Invalid volume {}: the source must be an absolute host path or a volume name, got {}",
+                spec, source
+            )
+            .into());
+        }
+        if !target.starts_with('/') || target == "/" {
+            return Err(format!(
+                "Invalid volume {}: the container path must be an absolute path other than /",
+                spec
+            )
+            .into());
+        }
+        if Path::new(target)
+            .components()
+            .any(|component| component == Component::ParentDir)
+        {
+            return Err(format!(
+                "Invalid volume {}: the container path must not contain ..",
+                spec
+            )
+            .into());
+        }
+
+        let mut volume = Volume {
+            source: source.to_string(),
+            target: target.trim_end_matches('/').to_string(),
+            readonly: false,
+            named,
+            propagation: None,
+            relabel: None,
+            nocopy: false,
+            anonymous: false,
+        };
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            let conflict = match option {
+                "ro" | "rw" => {
+                    volume.readonly = option == "ro";
+                    false
+                }
+                "z" | "Z" => volume
+                    .relabel
+                    .replace(if option == "z" {
+                        Relabel::Shared
+                    } else {
+                        Relabel::Private
+                    })
+                    .is_some(),
+                "nocopy" if named => {
+                    volume.nocopy = true;
+                    false
+                }
+                "nocopy" => {
+                    return Err(format!(
+                        "Invalid volume {}: nocopy only applies to named volumes",
+                        spec
+                    )
+                    .into())
+                }
+                _ => match option.parse::<Propagation>() {
+                    Ok(propagation) => volume.propagation.replace(propagation).is_some(),
+                    Err(_) => {
+                        return Err(format!("Invalid volume option {} in {}", option, spec).into())
+                    }
+                },
+            };
+            if conflict {
+                return Err(format!("Conflicting volume options in {}", spec).into());
+            }
+        }
+
+        Ok(volume)
+    }
+
+    /// A new anonymous volume mounted at `path`.
+    pub fn anonymous(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut volume =
+            Volume::parse(&format!("{}:{}", container::generate_container_id()?, path))?;
+        volume.anonymous = true;
+        Ok(volume)
+    }
+}
+
+/// A `--mount` option, which is a volume or a tmpfs like `-v` and `--tmpfs`
+/// make.
+#[derive(Debug, Clone)]
+pub enum MountSpec {
+    Volume(Volume),
+    Tmpfs(TmpfsMount),
+}
+
+impl MountSpec {
+    /// Parses `type=bind|volume|tmpfs,src=...,dst=...` and the options of
+    /// its type: `readonly`, `bind-propagation`, `volume-nocopy`, `tmpfs-size`
+    /// and `tmpfs-mode`. A volume without a source is anonymous.
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut mount_type = None;
+        let mut source = None;
+        let mut target = None;
+        let mut options = Vec::new();
+        let mut tmpfs_options = Vec::new();
+
+        for field in spec.split(',').filter(|field| !field.is_empty()) {
+            let (key, value) = match field.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (field, None),
+            };
+            match (key, value) {
+                ("type", Some(value)) => mount_type = Some(value),
+                ("source" | "src", Some(value)) => source = Some(value),
+                ("destination" | "dst" | "target", Some(value)) => target = Some(value),
+                ("readonly" | "ro", None | Some("true" | "1")) => options.push("ro"),
+                ("readonly" | "ro", Some("false" | "0")) => {}
+                ("bind-propagation", Some(value)) => options.push(value),
+                ("volume-nocopy", None | Some("true" | "1")) => options.push("nocopy"),
+                ("volume-nocopy", Some("false" | "0")) => {}
+                ("tmpfs-size", Some(value)) => tmpfs_options.push(format!("size={}", value)),
+                ("tmpfs-mode", Some(value)) => tmpfs_options.push(format!("mode={}", value)),
+                _ => return Err(format!("Invalid mount option {} in {}", field, spec).into()),
+            }
+        }
+
+        let target = target.ok_or_else(|| format!("Invalid mount {}: dst is required", spec))?;
+        let only = |mount_type: &str, prefix: &str, present: bool| {
+            if present {
+                Err(format!(
+                    "Invalid mount {}: {} options only apply to type={}",
+                    spec, prefix, mount_type
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        match mount_type {
+            Some("bind") => {
+                only("volume", "volume-", options.contains(&"nocopy"))?;
+                only("tmpfs", "tmpfs-", !tmpfs_options.is_empty())?;
+                let source =
+                    source.ok_or_else(|| format!("Invalid mount {}: src is required", spec))?;
+                if !source.starts_with('/') {
+                    return Err(format!(
+                        "Invalid mount {}: the source of a bind mount must be an absolute path",
+                        spec
+                    )
+                    .into());
+                }
+                Ok(MountSpec::Volume(Volume::parse(&format!(
+                    "{}:{}:{}",
+                    source,
+                    target,
+                    options.join(",")
+                ))?))
+            }
+            Some("volume") => {
+                only("tmpfs", "tmpfs-", !tmpfs_options.is_empty())?;
+                if options
+                    .iter()
+                    .any(|option| option.parse::<Propagation>().is_ok())
+                {
+                    return Err(format!(
+                        "Invalid mount {}: bind-propagation only applies to type=bind",
+                        spec
+                    )
+                    .into());
+                }
+                let volume = match source {
+                    Some(source) if source.starts_with('/') => {
+                        return Err(format!(
+                            "Invalid mount {}: the source of a volume must be a volume name",
+                            spec
+                        )
+                        .into())
+                    }
+                    Some(source) => {
+                        Volume::parse(&format!("{}:{}:{}", source, target, options.join(",")))?
+                    }
+                    None => {
+                        let mut volume = Volume::anonymous(target)?;
+                        volume.readonly = options.contains(&"ro");
+                        volume.nocopy = options.contains(&"nocopy");
+                        volume
+                    }
+                };
+                Ok(MountSpec::Volume(volume))
+            }
+            Some("tmpfs") => {
+                if source.is_some() || !options.is_empty() {
+                    return Err(format!(
+                        "Invalid mount {}: a tmpfs takes no source, and only tmpfs- options",
+                        spec
+                    )
+                    .into());
+                }
+                Ok(MountSpec::Tmpfs(TmpfsMount::parse(&format!(
+                    "{}:{}",
+                    target,
+                    tmpfs_options.join(",")
+                ))?))
+            }
+            Some(other) => Err(format!(
+                "Invalid mount type {}. Expected bind, volume or tmpfs",
+                other
+            )
+            .into()),
+            None => Err(format!("Invalid mount {}: type is required", spec).into()),
+        }
+    }
+}
+
+/// Checks that the volumes' host paths exist and that no two volumes or
+/// tmpfs mounts are mounted at the same place. Named volumes are created
+/// when mounted.
+pub fn validate(
+    volumes: &[Volume],
+    tmpfs: &[TmpfsMount],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let targets: Vec<&str> = volumes
+        .iter()
+        .map(|volume| volume.target.as_str())
+        .chain(tmpfs.iter().map(|mount| mount.target.as_str()))
+        .collect();
+    for (index, target) in targets.iter().enumerate() {
+        if targets[..index].contains(target) {
+            return Err(format!("Duplicate mount point: {}", target).into());
+        }
+    }
+
+    for volume in volumes {
+        if !volume.named {
+            fs::metadata(&volume.source)
+                .map_err(|e| format!("Invalid volume host path {}: {}", volume.source, e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the volumes' mount points in the rootfs, and named volumes that
+/// do not exist yet, returning the bind mounts to set up in the container's
+/// mount namespace. An empty named volume is first filled with what the
+/// image has at its mount point, and relabeled volumes get the container's
+/// SELinux level from `label`.
+pub fn bind_mounts(
+    rootfs_path: &str,
+    volumes: &[Volume],
+    label: Option<&ProcessLabel>,
+) -> Result<Vec<BindMount>, Box<dyn std::error::Error>> {
+    let mut mounts = Vec::new();
+    for volume in volumes {
+        let source_path = if volume.named {
+            create_volume(Some(&volume.source))?;
+            data_dir(&volume.source)
+        } else {
+            volume.source.clone()
+        };
+        let source = fs::canonicalize(&source_path)
+            .map_err(|e| format!("Invalid volume host path {}: {}", source_path, e))?;
+        let target = resolve_in_rootfs(Path::new(rootfs_path), &volume.target)?;
+        let mount_point = Path::new(rootfs_path).join(target.strip_prefix("/")?);
+        if mount_point.exists() && source.is_dir() != mount_point.is_dir() {
+            return Err(format!(
+                "Cannot mount {} on {}: one is a directory and the other is not",
+                volume.source, volume.target
+            )
+            .into());
+        }
+        if source.is_dir() {
+            if volume.named && !volume.nocopy && mount_point.is_dir() && is_empty_dir(&source) {
+                copy_contents(&mount_point, &source)?;
+            }
+            fs::create_dir_all(&mount_point)?;
+        } else {
+            if let Some(parent) = mount_point.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if !mount_point.exists() {
+                fs::write(&mount_point, "")?;
+            }
+        }
+
+        if let Some(relabel) = volume.relabel {
+            security::relabel(&source, relabel == Relabel::Private, label)?;
+        }
+
+        mounts.push(BindMount {
+            source: source.display().to_string(),
+            target: target.display().to_string(),
+            readonly: volume.readonly,
+            recursive: true,
+            propagation: volume.propagation,
+        });
+    }
+
+    Ok(mounts)
+}
+
+/// Resolves `path` in the rootfs as the container would see it, following
+/// symlinks inside the rootfs rather than on the host.
+pub(crate) fn resolve_in_rootfs(
+    rootfs: &Path,
+    path: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut resolved = PathBuf::from("/");
+    let mut pending: Vec<PathBuf> = vec![PathBuf::from(path)];
+    let mut symlinks = 0;
+
+    while let Some(remaining) = pending.pop() {
+        let mut components = remaining.components();
+        while let Some(component) = components.next() {
+            match component {
+                Component::RootDir => resolved = PathBuf::from("/"),
+                Component::CurDir | Component::Prefix(_) => {}
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::Normal(name) => {
+                    let candidate = resolved.join(name);
+                    let host_path = rootfs.join(candidate.strip_prefix("/")?);
+                    match fs::symlink_metadata(&host_path) {
+                        Ok(metadata) if metadata.file_type().is_symlink() => {
+                            symlinks += 1;
+                            if symlinks > MAX_SYMLINKS {
+                                return Err(format!("Too many symlinks in {}", path).into());
+                            }
+                            pending.push(components.as_path().to_path_buf());
+                            pending.push(fs::read_link(&host_path)?);
+                            break;
+                        }
+                        _ => resolved = candidate,
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Resolves the parent directories of a file's `path` in the rootfs, keeping
+/// its last component so a symlink there can be replaced rather than followed.
+/// Returns the path on the host.
+pub(crate) fn resolve_file_in_rootfs(
+    rootfs: &Path,
+    path: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let file = Path::new(path);
+    let (Some(parent), Some(name)) = (file.parent(), file.file_name()) else {
+        return Err(format!("Invalid file path: {}", path).into());
+    };
+    let parent = resolve_in_rootfs(rootfs, &parent.to_string_lossy())?;
+
+    Ok(rootfs.join(parent.strip_prefix("/")?).join(name))
+}
+
+fn volume_dir(name: &str) -> String {
+    format!("{}/{}", store::path(VOLUMES_DIR), name)
+}
+
+/// The directory holding a named volume's contents, which is what gets
+/// mounted.
+pub fn data_dir(name: &str) -> String {
+    format!("{}/_data", volume_dir(name))
+}
+
+fn exists(name: &str) -> bool {
+    Path::new(&format!("{}/metadata.json", volume_dir(name))).exists()
+}
+
+fn load_metadata(name: &str) -> Result<VolumeMetadata, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(format!("{}/metadata.json", volume_dir(name)))
+        .map_err(|_| format!("No such volume: {}", name))?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Creates a named volume, with a random name if none is given. Creating a
+/// volume that exists already leaves it as it is. Returns the volume's name.
+pub fn create_volume(name: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    let name = match name {
+        Some(name) => {
+            utils::validate_object_name("volume", name)?;
+            name.to_string()
+        }
+        None => container::generate_container_id()?,
+    };
+    if exists(&name) {
+        return Ok(name);
+    }
+
+    fs::create_dir_all(data_dir(&name))?;
+    let metadata = VolumeMetadata {
+        name: name.clone(),
+        created_at: container::now_secs(),
+    };
+    fs::write(
+        format!("{}/metadata.json", volume_dir(&name)),
+        serde_json::to_string_pretty(&metadata)?,
+    )?;
+    events::emit("volume", "create", &name, serde_json::json!({}));
+
+    Ok(name)
+}
+
+/// Names of every named volume.
+fn list_names() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if !Path::new(&store::path(VOLUMES_DIR)).exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut names: Vec<String> = fs::read_dir(store::path(VOLUMES_DIR))?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str().map(String::from))
+        .filter(|name| exists(name))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// IDs of the containers that mount the named volume, running or not.
+fn users(name: &str) -> Vec<String> {
+    container::containers_using(|metadata| {
+        metadata
+            .get("volumes")
+            .and_then(|volumes| serde_json::from_value::<Vec<Volume>>(volumes.clone()).ok())
+            .is_some_and(|volumes| {
+                volumes
+                    .iter()
+                    .any(|volume| volume.named && volume.source == name)
+            })
+    })
+    .unwrap_or_default()
+}
+
+pub fn list_volumes() -> Result<(), Box<dyn std::error::Error>> {
+    let rows: Vec<Vec<String>> = list_names()?
+        .into_iter()
+        .map(|name| vec!["local".to_string(), name])
+        .collect();
+
+    utils::print_table(&["DRIVER", "VOLUME NAME"], &rows);
+    Ok(())
+}
+
+/// Removes named volumes, which no container may be using anymore.
+pub fn remove_volumes(names: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    for name in names {
+        remove_volume(name)?;
+        println!("{}", name);
+    }
+
+    Ok(())
+}
+
+pub fn remove_volume(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !exists(name) {
+        return Err(format!("No such volume: {}", name).into());
+    }
+    let users = users(name);
+    if !users.is_empty() {
+        return Err(format!(
+            "Volume {} is in use by container(s) {}",
+            name,
+            users
+                .iter()
+                .map(|id| container::short_id(id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into());
+    }
+
+    fs::remove_dir_all(volume_dir(name))?;
+    events::emit("volume", "destroy", name, serde_json::json!({}));
+    Ok(())
+}
+
+/// Prints named volumes with where their data is and who uses them as JSON,
+/// in an array like `docker volume inspect`.
+pub fn inspect_volumes(names: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut documents = Vec::new();
+    for name in names {
+        let metadata = load_metadata(name)?;
+        documents.push(serde_json::json!({
+            "Name": metadata.name,
+            "Driver": "local",
+            "Mountpoint": fs::canonicalize(data_dir(name))?,
+            "CreatedAt": utils::format_timestamp(metadata.created_at),
+            "UsedBy": users(name),
+        }));
+    }
+
+    println!("{}", serde_json::to_string_pretty(&documents)?);
+    Ok(())
+}
+
+/// Creates a volume with a copy of what volume `name` holds, returning the
+/// new volume's name.
+pub fn duplicate_volume(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let copy = create_volume(None)?;
+    let source = data_dir(name);
+    if Path::new(&source).is_dir() {
+        copy_contents(Path::new(&source), Path::new(&data_dir(&copy)))?;
+    }
+    Ok(copy)
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+}
+
+/// Copies a directory's contents, ownership and modes included, into
+/// another one.
+fn copy_contents(from: &Path, to: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let output = Command::new("cp")
+        .arg("-a")
+        .arg(from.join("."))
+        .arg(to)
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to copy {} into the volume: {}",
+            from.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bind_mount() {
+        let volume = Volume::parse("/host/data:/data/").unwrap();
+        assert_eq!(volume.source, "/host/data");
+        assert_eq!(volume.target, "/data");
+        assert!(!volume.named);
+        assert!(!volume.readonly);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_specs() {
+        for spec in [
+            "/data",
+            "/a:/b:ro:extra",
+            "-bad:/data",
+            "/host:data",
+            "/host:/",
+            "/host:/data/../etc",
+        ] {
+            assert!(Volume::parse(spec).is_err(), "{} should be rejected", spec);
+        }
+    }
+    #[test]
+    fn parse_named_volume_with_options() {
+        let volume = Volume::parse("data:/data:ro,Z,rshared,nocopy").unwrap();
+        assert_eq!(volume.source, "data");
+        assert!(volume.named);
+        assert!(volume.readonly);
+        assert!(volume.nocopy);
+        assert_eq!(volume.relabel, Some(Relabel::Private));
+        assert_eq!(volume.propagation, Some(Propagation::Rshared));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_options() {
+        for spec in [
+            "/host:/data:nocopy",
+            "/host:/data:z,Z",
+            "/host:/data:shared,slave",
+            "/host:/data:bogus",
+        ] {
+            assert!(Volume::parse(spec).is_err(), "{} should be rejected", spec);
+        }
+    }
+    #[test]
+    fn parse_mount_spec() {
+        match MountSpec::parse("type=bind,src=/host,dst=/app,readonly").unwrap() {
+            MountSpec::Volume(volume) => {
+                assert_eq!(volume.source, "/host");
+                assert_eq!(volume.target, "/app");
+                assert!(volume.readonly);
+            }
+            MountSpec::Tmpfs(_) => panic!("expected a volume"),
+        }
+        match MountSpec::parse("type=tmpfs,dst=/tmp,tmpfs-size=64m").unwrap() {
+            MountSpec::Tmpfs(mount) => {
+                assert_eq!(mount.target, "/tmp");
+                assert!(mount.options.contains("size=64m"));
+            }
+            MountSpec::Volume(_) => panic!("expected a tmpfs"),
+        }
+    }
+
+    #[test]
+    fn resolve_in_rootfs_never_escapes() {
+        let rootfs =
+            std::env::temp_dir().join(format!("rustainer-test-resolve-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&rootfs);
+        fs::create_dir_all(rootfs.join("usr/lib")).unwrap();
+        std::os::unix::fs::symlink("/usr/lib", rootfs.join("lib")).unwrap();
+        std::os::unix::fs::symlink("../../../../etc", rootfs.join("usr/etc")).unwrap();
+        std::os::unix::fs::symlink("/", rootfs.join("root-link")).unwrap();
+        std::os::unix::fs::symlink("loop", rootfs.join("loop")).unwrap();
+
+        let resolve = |path| resolve_in_rootfs(&rootfs, path).unwrap();
+        assert_eq!(resolve("/../../etc/passwd"), PathBuf::from("/etc/passwd"));
+        assert_eq!(resolve("/lib/../x"), PathBuf::from("/usr/x"));
+        assert_eq!(resolve("/lib/libc.so"), PathBuf::from("/usr/lib/libc.so"));
+        assert_eq!(resolve("/usr/etc/shadow"), PathBuf::from("/etc/shadow"));
+        assert_eq!(resolve("/root-link/../../tmp"), PathBuf::from("/tmp"));
+        assert!(resolve_in_rootfs(&rootfs, "/loop/x").is_err());
+
+        fs::remove_dir_all(&rootfs).unwrap();
+    }
+}
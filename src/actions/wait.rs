@@ -0,0 +1,28 @@
+use crate::actions::container;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long whoever ran the container gets to record its exit code once its
+/// processes are gone.
+const RECORD_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Blocks until the container stops, then prints its exit code.
+pub async fn wait_container(reference: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = container::resolve_container(reference)?;
+
+    while container::is_running(&container_id) {
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    let deadline = Instant::now() + RECORD_TIMEOUT;
+    loop {
+        if let Some(exit_code) = container::exit_code(&container_id) {
+            println!("{}", exit_code);
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("No exit code was recorded for container {}", container_id).into());
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,76 @@
+use std::{path::Path, process::Command};
+
+use crate::actions::{
+    run::ProcessSpec,
+    types::{ImageManifest, Layer, Platform},
+    utils, volume,
+};
+
+/// The Wasm runtime modules are run with: the wasmtime CLI, which must be
+/// installed on the host, as there is no Wasm engine among the dependencies.
+const RUNTIME: &str = "wasmtime";
+/// The only environment the runtime gets from the host.
+const RUNTIME_PATH_ENV: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+/// Config media type of Wasm artifacts, which carry no Linux filesystem.
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.wasm.config.v0+json";
+/// Layer media types holding a bare module rather than a tar archive.
+const MODULE_LAYER_TYPES: [&str; 2] = [
+    "application/vnd.wasm.content.layer.v1+wasm",
+    "application/wasm",
+];
+/// Where a bare module layer is put in the rootfs, and run from by default.
+pub const MODULE_PATH: &str = "/module.wasm";
+
+/// Whether an image holds a Wasm workload: a Wasm artifact, or an image
+/// built for the `wasi/wasm` platform.
+pub fn is_wasm_image(manifest: &ImageManifest, platform: Option<&Platform>) -> bool {
+    manifest.config.media_type == CONFIG_MEDIA_TYPE
+        || manifest.layers.iter().any(is_module_layer)
+        || platform.is_some_and(Platform::is_wasm)
+}
+
+pub fn is_module_layer(layer: &Layer) -> bool {
+    MODULE_LAYER_TYPES.contains(&layer.media_type.as_str())
+}
+
+/// Checks that the Wasm runtime is installed.
+pub fn check_runtime() -> Result<(), Box<dyn std::error::Error>> {
+    match Command::new(RUNTIME).arg("--version").output() {
+        Ok(output) if output.status.success() => Ok(()),
+        _ => Err(format!("Running Wasm images requires {} to be installed", RUNTIME).into()),
+    }
+}
+
+/// The command running the module at `args[0]`, relative to the working
+/// directory unless absolute. The module is confined by the Wasm sandbox and
+/// only sees the rootfs, mapped as `/`, the directories mounted into it and
+/// its environment.
+pub fn command(
+    rootfs_path: &str,
+    process: &ProcessSpec,
+) -> Result<Command, Box<dyn std::error::Error>> {
+    let module = Path::new(&process.cwd).join(&process.args[0]);
+    let module = volume::resolve_in_rootfs(Path::new(rootfs_path), &module.to_string_lossy())?;
+
+    let runtime = utils::find_binary(RUNTIME)
+        .ok_or_else(|| format!("Running Wasm images requires {} to be installed", RUNTIME))?;
+    let mut cmd = Command::new(runtime);
+    // The image's environment is the module's, never the runtime's own,
+    // where variables such as LD_PRELOAD would act on the host.
+    cmd.env_clear().env("PATH", RUNTIME_PATH_ENV);
+    cmd.arg("run")
+        .arg("--dir")
+        .arg(format!("{}::/", rootfs_path));
+    for mount in &process.mounts {
+        if Path::new(&mount.source).is_dir() {
+            cmd.arg("--dir")
+                .arg(format!("{}::{}", mount.source, mount.target));
+        }
+    }
+    for (key, value) in &process.env {
+        cmd.arg("--env").arg(format!("{}={}", key, value));
+    }
+    cmd.arg(format!("{}{}", rootfs_path, module.display()))
+        .args(&process.args[1..]);
+    Ok(cmd)
+}
@@ -9,15 +9,28 @@ async fn main() {
         .version("0.1.0")
         .author("Your Name <your.email@example.com>")
         .about("A container runtime written in Rust")
+        .arg(
+            Arg::new("root")
+                .long("root")
+                .help("Storage root for images, containers and volumes [env: RUSTAINER_ROOT]")
+                .value_name("DIR"),
+        )
         .subcommand(
             Command::new("run")
                 .about("Run a container from an image")
                 .arg(
                     Arg::new("image")
-                        .help("Container image to run")
-                        .required(true)
+                        .help("Container image to run, or docker-archive:PATH[:IMAGE] or oci-archive:PATH[:NAME] to load it from an archive")
+                        .required_unless_present("bundle")
                         .index(1),
                 )
+                .arg(
+                    Arg::new("bundle")
+                        .long("bundle")
+                        .help("Run an OCI bundle directory (config.json and rootfs) instead of an image")
+                        .value_name("DIR")
+                        .conflicts_with("platform"),
+                )
                 .arg(
                     Arg::new("name")
                         .short('n')
@@ -32,6 +45,20 @@ async fn main() {
                         .help("Run container in background")
                         .action(clap::ArgAction::SetTrue),
                 )
+                .arg(
+                    Arg::new("rm")
+                        .long("rm")
+                        .help("Automatically remove the container when it exits")
+                        .conflicts_with("detach")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("restart")
+                        .long("restart")
+                        .help("Restart policy: no, on-failure[:max], always or unless-stopped")
+                        .value_name("POLICY")
+                        .default_value("no"),
+                )
                 .arg(
                     Arg::new("interactive")
                         .short('i')
@@ -46,6 +73,13 @@ async fn main() {
                         .help("Allocate a pseudo-TTY")
                         .action(clap::ArgAction::SetTrue),
                 )
+                .arg(
+                    Arg::new("detach-keys")
+                        .long("detach-keys")
+                        .help("Key sequence that detaches from a -it container (e.g., ctrl-a,a)")
+                        .value_name("KEYS")
+                        .default_value(actions::tty::DEFAULT_DETACH_KEYS),
+                )
                 .arg(
                     Arg::new("env")
                         .short('e')
@@ -54,12 +88,391 @@ async fn main() {
                         .value_name("KEY=VALUE")
                         .action(clap::ArgAction::Append),
                 )
+                .arg(
+                    Arg::new("env-file")
+                        .long("env-file")
+                        .help("Read environment variables from a file of KEY=VALUE lines")
+                        .value_name("FILE")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("mask-env")
+                        .long("mask-env")
+                        .help("Print the variable's value as ***** in inspect, ps and events")
+                        .value_name("KEY")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("workdir")
+                        .short('w')
+                        .long("workdir")
+                        .help("Working directory inside the container")
+                        .value_name("DIR"),
+                )
+                .arg(
+                    Arg::new("user")
+                        .short('u')
+                        .long("user")
+                        .help("Username or UID to run as (format: <name|uid>[:<group|gid>])")
+                        .value_name("USER"),
+                )
+                .arg(
+                    Arg::new("pull")
+                        .long("pull")
+                        .help("Pull image before running (always, missing or never)")
+                        .value_name("POLICY")
+                        .default_value("missing"),
+                )
+                .arg(
+                    Arg::new("platform")
+                        .long("platform")
+                        .help("Set platform if the image is multi-platform (e.g., linux/arm64)")
+                        .value_name("OS/ARCH[/VARIANT]"),
+                )
+                .arg(
+                    Arg::new("cidfile")
+                        .long("cidfile")
+                        .help("Write the container ID to the file")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::new("hostname")
+                        .long("hostname")
+                        .help("Container host name (defaults to the short container ID)")
+                        .value_name("NAME"),
+                )
+                .arg(
+                    Arg::new("network")
+                        .long("network")
+                        .visible_alias("net")
+                        .help("Connect the container to a network (bridge, host or none)")
+                        .value_name("MODE")
+                        .default_value("bridge"),
+                )
+                .arg(
+                    Arg::new("ip")
+                        .long("ip")
+                        .help("Static IPv4 address on the bridge network (e.g., 172.19.0.50)")
+                        .value_name("ADDRESS"),
+                )
+                .arg(
+                    Arg::new("mac-address")
+                        .long("mac-address")
+                        .help("MAC address of the container's bridge interface (e.g., 02:42:ac:13:00:32)")
+                        .value_name("ADDRESS"),
+                )
+                .arg(
+                    Arg::new("add-host")
+                        .long("add-host")
+                        .help("Add a custom host-to-IP mapping (use host-gateway for the bridge IP)")
+                        .value_name("HOST:IP")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("dns")
+                        .long("dns")
+                        .help("Set custom DNS servers")
+                        .value_name("IP")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("dns-search")
+                        .long("dns-search")
+                        .help("Set custom DNS search domains (use . to clear them)")
+                        .value_name("DOMAIN")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("dns-opt")
+                        .long("dns-opt")
+                        .visible_alias("dns-option")
+                        .help("Set DNS resolver options")
+                        .value_name("OPTION")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("memory")
+                        .short('m')
+                        .long("memory")
+                        .help("Memory limit (e.g., 512m, 2g)")
+                        .value_name("BYTES"),
+                )
+                .arg(
+                    Arg::new("memory-swap")
+                        .long("memory-swap")
+                        .help("Total memory plus swap limit, -1 for unlimited swap")
+                        .value_name("BYTES")
+                        .allow_hyphen_values(true),
+                )
+                .arg(
+                    Arg::new("memory-reservation")
+                        .long("memory-reservation")
+                        .help("Memory soft limit")
+                        .value_name("BYTES"),
+                )
+                .arg(
+                    Arg::new("cpus")
+                        .long("cpus")
+                        .help("Number of CPUs (e.g., 1.5)")
+                        .value_name("NUMBER")
+                        .value_parser(clap::value_parser!(f64)),
+                )
+                .arg(
+                    Arg::new("cpu-shares")
+                        .short('c')
+                        .long("cpu-shares")
+                        .help("CPU shares (relative weight)")
+                        .value_name("SHARES")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("cpu-period")
+                        .long("cpu-period")
+                        .help("Limit CPU CFS period in microseconds")
+                        .value_name("MICROSECONDS")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("cpu-quota")
+                        .long("cpu-quota")
+                        .help("Limit CPU CFS quota in microseconds, -1 for no limit")
+                        .value_name("MICROSECONDS")
+                        .allow_hyphen_values(true)
+                        .value_parser(clap::value_parser!(i64)),
+                )
+                .arg(
+                    Arg::new("pids-limit")
+                        .long("pids-limit")
+                        .help("Tune container pids limit (0 or -1 for unlimited, default 4096)")
+                        .value_name("NUMBER")
+                        .allow_hyphen_values(true)
+                        .value_parser(clap::value_parser!(i64)),
+                )
+                .arg(
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .help("Stop the container if it is still running after this long (e.g., 10m)")
+                        .value_name("DURATION"),
+                )
+                .arg(
+                    Arg::new("stop-signal")
+                        .long("stop-signal")
+                        .help("Signal to stop the container (default the image's or SIGTERM)")
+                        .value_name("SIGNAL"),
+                )
+                .arg(
+                    Arg::new("stop-timeout")
+                        .long("stop-timeout")
+                        .help("Seconds to wait for the container to stop before killing it")
+                        .value_name("SECONDS")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("sysctl")
+                        .long("sysctl")
+                        .help("Set a network or IPC namespace sysctl (e.g., net.core.somaxconn=4096)")
+                        .value_name("KEY=VALUE")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("oom-score-adj")
+                        .long("oom-score-adj")
+                        .help("Tune the container's OOM preferences (-1000 to 1000)")
+                        .value_name("SCORE")
+                        .allow_hyphen_values(true)
+                        .value_parser(clap::value_parser!(i32)),
+                )
+                .arg(
+                    Arg::new("oom-kill-disable")
+                        .long("oom-kill-disable")
+                        .help("Keep the OOM killer away from the container, requires --memory")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("group-add")
+                        .long("group-add")
+                        .help("Add a supplementary group, by name or GID")
+                        .value_name("GROUP")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("cgroup-parent")
+                        .long("cgroup-parent")
+                        .help("Parent cgroup for the container, relative to the cgroup root")
+                        .value_name("PATH"),
+                )
+                .arg(
+                    Arg::new("cpuset-cpus")
+                        .long("cpuset-cpus")
+                        .help("CPUs in which to allow execution (e.g., 0-3,6)")
+                        .value_name("CPUS"),
+                )
+                .arg(
+                    Arg::new("cpuset-mems")
+                        .long("cpuset-mems")
+                        .help("Memory nodes in which to allow execution (e.g., 0,1)")
+                        .value_name("MEMS"),
+                )
+                .arg(
+                    Arg::new("blkio-weight")
+                        .long("blkio-weight")
+                        .help("Block IO relative weight, between 10 and 1000")
+                        .value_name("WEIGHT")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("device-read-bps")
+                        .long("device-read-bps")
+                        .help("Limit read rate from a device (e.g., /dev/sda:10mb)")
+                        .value_name("PATH:RATE")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("device-write-bps")
+                        .long("device-write-bps")
+                        .help("Limit write rate to a device (e.g., /dev/sda:10mb)")
+                        .value_name("PATH:RATE")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("gpus")
+                        .long("gpus")
+                        .help("GPU devices to add to the container (all, <count> or device=<ids>)")
+                        .value_name("GPUS"),
+                )
+                .arg(
+                    Arg::new("privileged")
+                        .long("privileged")
+                        .help("Give extended privileges to this container")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("cap-add")
+                        .long("cap-add")
+                        .help("Add a Linux capability (or ALL)")
+                        .value_name("CAP")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("cap-drop")
+                        .long("cap-drop")
+                        .help("Drop a Linux capability (or ALL)")
+                        .value_name("CAP")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("security-opt")
+                        .long("security-opt")
+                        .help("Security options (seccomp=, apparmor=, label=)")
+                        .value_name("OPT")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("read-only")
+                        .long("read-only")
+                        .help("Mount the container's root filesystem as read only")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("init")
+                        .long("init")
+                        .help("Run an init inside the container that forwards signals and reaps processes")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("nested")
+                        .long("nested")
+                        .help("Let the container run containers itself: all capabilities, no seccomp, /dev/fuse and /dev/net/tun, and a delegated cgroup")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("share-images")
+                        .long("share-images")
+                        .help("Share the image store with a nested rustainer at /var/lib/rustainer/images")
+                        .requires("nested")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("runtime")
+                        .long("runtime")
+                        .help("OCI runtime to run the container with (e.g., runc, crun), or vm for a Firecracker microVM")
+                        .value_name("RUNTIME"),
+                )
+                .arg(
+                    Arg::new("shm-size")
+                        .long("shm-size")
+                        .help("Size of /dev/shm (e.g., 64m, 1g)")
+                        .value_name("BYTES"),
+                )
+                .arg(
+                    Arg::new("userns")
+                        .long("userns")
+                        .help("User namespace to use (host, auto or keep-id)")
+                        .value_name("MODE")
+                        .default_value("host"),
+                )
+                .arg(
+                    Arg::new("pid")
+                        .long("pid")
+                        .help("PID namespace to use (private or host)")
+                        .value_name("MODE")
+                        .default_value("private"),
+                )
+                .arg(
+                    Arg::new("ipc")
+                        .long("ipc")
+                        .help("IPC namespace to use (private or host)")
+                        .value_name("MODE")
+                        .default_value("private"),
+                )
+                .arg(
+                    Arg::new("uts")
+                        .long("uts")
+                        .help("UTS namespace to use (private or host)")
+                        .value_name("MODE")
+                        .default_value("private"),
+                )
                 .arg(
                     Arg::new("volume")
                         .short('v')
                         .long("volume")
-                        .help("Bind mount a volume")
-                        .value_name("HOST:CONTAINER")
+                        .help("Bind mount a host path or named volume (SOURCE:CONTAINER[:OPTIONS], options being ro, rw, z, Z, nocopy and a propagation mode)")
+                        .value_name("SOURCE:CONTAINER")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("mount")
+                        .long("mount")
+                        .help("Attach a mount (e.g., type=bind,src=/host,dst=/app,readonly; type=volume,src=data,dst=/data; type=tmpfs,dst=/tmp,tmpfs-size=64m)")
+                        .value_name("type=TYPE,dst=PATH[,...]")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("storage-opt")
+                        .long("storage-opt")
+                        .help("Storage driver option (size=SIZE limits what the container can write, e.g. size=10g)")
+                        .value_name("KEY=VALUE")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("tmpfs")
+                        .long("tmpfs")
+                        .help("Mount a tmpfs (e.g., /app/cache:size=256m,mode=1777)")
+                        .value_name("PATH[:OPTIONS]")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("secret")
+                        .long("secret")
+                        .help("Expose a secret at /run/secrets/NAME on a tmpfs")
+                        .value_name("NAME")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("config")
+                        .long("config")
+                        .help("Mount a config read-only (e.g., nginx.conf:/etc/nginx/nginx.conf, default target /NAME)")
+                        .value_name("NAME[:TARGET]")
                         .action(clap::ArgAction::Append),
                 )
                 .arg(
@@ -70,6 +483,44 @@ async fn main() {
                         .value_name("HOST:CONTAINER")
                         .action(clap::ArgAction::Append),
                 )
+                .arg(
+                    Arg::new("health-cmd")
+                        .long("health-cmd")
+                        .help("Command to run to check health")
+                        .value_name("COMMAND"),
+                )
+                .arg(
+                    Arg::new("health-interval")
+                        .long("health-interval")
+                        .help("Time between running the check (e.g., 30s)")
+                        .value_name("DURATION"),
+                )
+                .arg(
+                    Arg::new("health-timeout")
+                        .long("health-timeout")
+                        .help("Maximum time to allow one check to run")
+                        .value_name("DURATION"),
+                )
+                .arg(
+                    Arg::new("health-retries")
+                        .long("health-retries")
+                        .help("Consecutive failures needed to report unhealthy")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("health-start-period")
+                        .long("health-start-period")
+                        .help("Start period during which failures are not counted")
+                        .value_name("DURATION"),
+                )
+                .arg(
+                    Arg::new("no-healthcheck")
+                        .long("no-healthcheck")
+                        .help("Disable any image-specified healthcheck")
+                        .conflicts_with("health-cmd")
+                        .action(clap::ArgAction::SetTrue),
+                )
                 .arg(
                     Arg::new("command")
                         .help("Command to run in the container")
@@ -85,10 +536,132 @@ async fn main() {
                         .help("Image to pull (e.g., nginx:latest)")
                         .required(true)
                         .index(1),
+                )
+                .arg(
+                    Arg::new("platform")
+                        .long("platform")
+                        .help("Pull the image for this platform instead of the host's")
+                        .value_name("OS/ARCH[/VARIANT]"),
+                ),
+        )
+        .subcommand(
+            Command::new("images")
+                .about("List locally stored images")
+                .arg(
+                    Arg::new("digests")
+                        .long("digests")
+                        .help("Show digests")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("rmi")
+                .about("Remove one or more images")
+                .arg(
+                    Arg::new("image")
+                        .help("Images to remove")
+                        .required(true)
+                        .index(1)
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .help("Remove images stopped containers were made from or that are mounted")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("image")
+                .about("Manage images")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("mount")
+                        .about("Mount an image read-only at a host path, or list mounted images")
+                        .arg(Arg::new("image").help("Image to mount").index(1))
+                        .arg(
+                            Arg::new("target")
+                                .help("Directory to mount the image at")
+                                .requires("image")
+                                .index(2),
+                        ),
+                )
+                .subcommand(
+                    Command::new("export-disk")
+                        .about("Flatten an image into an ext4 or squashfs filesystem image")
+                        .arg(
+                            Arg::new("image")
+                                .help("Image to export")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .help("Filesystem to make (ext4 or squashfs)")
+                                .value_name("FORMAT")
+                                .default_value("ext4"),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .short('o')
+                                .long("output")
+                                .help("File to write the filesystem image to")
+                                .value_name("FILE")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("size")
+                                .long("size")
+                                .help("Size of an ext4 image (e.g., 2g), large enough for the files by default")
+                                .value_name("SIZE"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("unmount")
+                        .visible_alias("umount")
+                        .about("Unmount an image mounted with image mount")
+                        .arg(
+                            Arg::new("target")
+                                .help("Directory the image is mounted at")
+                                .required(true)
+                                .index(1),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("ps")
+                .about("List containers")
+                .arg(
+                    Arg::new("all")
+                        .short('a')
+                        .long("all")
+                        .help("Show all containers (default shows just running)")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("filter")
+                        .short('f')
+                        .long("filter")
+                        .help("Filter output (status=created|running|exited, ancestor=IMAGE, name=PATTERN)")
+                        .value_name("KEY=VALUE")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("size")
+                        .short('s')
+                        .long("size")
+                        .help("Display the disk usage of each container's writable layer")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("Output format: table, json or a template like '{{.ID}} {{.Ports}}'")
+                        .value_name("FORMAT"),
                 ),
         )
-        .subcommand(Command::new("images").about("List locally stored images"))
-        .subcommand(Command::new("ps").about("List containers"))
         .subcommand(
             Command::new("rm")
                 .about("Remove one or more containers")
@@ -104,114 +677,1111 @@ async fn main() {
                         .long("force")
                         .help("Force the removal of a running container")
                         .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("volumes")
+                        .short('v')
+                        .long("volumes")
+                        .help("Remove the container's anonymous volumes")
+                        .action(clap::ArgAction::SetTrue),
                 ),
         )
-        .get_matches();
-
-    match matches.subcommand() {
-        Some(("run", sub_matches)) => {
-            if let Err(e) = handle_run_command(sub_matches).await {
-                eprintln!("Error: {}", e);
-                process::exit(1);
-            }
-        }
-        Some(("pull", sub_matches)) => {
-            if let Err(e) = handle_pull_command(sub_matches).await {
-                eprintln!("Error: {}", e);
-                process::exit(1);
-            }
-        }
-        Some(("images", _)) => {
-            if let Err(e) = handle_images_command().await {
-                eprintln!("Error: {}", e);
-                process::exit(1);
-            }
-        }
-        Some(("ps", _)) => {
-            if let Err(e) = handle_ps_command().await {
-                eprintln!("Error: {}", e);
-                process::exit(1);
-            }
-        }
-        Some(("rm", sub_matches)) => {
-            if let Err(e) = handle_rm_command(sub_matches).await {
-                eprintln!("Error: {}", e);
-                process::exit(1);
-            }
-        }
-        _ => {
-            eprintln!(
-                "No subcommand provided. Use 'rustainer pull <image>', 'rustainer run <image>', 'rustainer images', 'rustainer ps', or 'rustainer rm <container>'."
-            );
-            process::exit(1);
-        }
-    }
-}
-
-async fn handle_run_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let image = matches.get_one::<String>("image").unwrap().clone();
-    let name = matches.get_one::<String>("name").cloned();
-    let detach = matches.get_flag("detach");
-    let interactive = matches.get_flag("interactive");
-    let tty = matches.get_flag("tty");
-
-    let env_vars = matches
-        .get_many::<String>("env")
-        .unwrap_or_default()
-        .cloned()
-        .collect();
-
-    let volumes = matches
-        .get_many::<String>("volume")
+        .subcommand(
+            Command::new("inspect")
+                .about("Show a container's configuration and state as JSON")
+                .arg(
+                    Arg::new("container")
+                        .help("Container ID or name to inspect")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            Command::new("stop")
+                .about("Stop a running container with its stop signal, killing it after a timeout")
+                .arg(
+                    Arg::new("container")
+                        .help("Container ID or name to stop")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("time")
+                        .short('t')
+                        .long("time")
+                        .help("Seconds to wait before killing the container")
+                        .value_name("SECONDS")
+                        .value_parser(clap::value_parser!(u64)),
+                ),
+        )
+        .subcommand(
+            Command::new("checkpoint")
+                .about("Save the state of a running container's processes with CRIU")
+                .arg(
+                    Arg::new("container")
+                        .help("Container ID or name to checkpoint")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("leave-running")
+                        .long("leave-running")
+                        .help("Keep the container running after the checkpoint")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("restore")
+                .about("Start a container again from its last checkpoint")
+                .arg(
+                    Arg::new("container")
+                        .help("Container ID or name to restore")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Move a running container to another host over SSH")
+                .arg(
+                    Arg::new("container")
+                        .help("Container ID or name to migrate")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("destination")
+                        .help("Host to migrate to (e.g., user@host)")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::new("remote-dir")
+                        .long("remote-dir")
+                        .help("Storage root of rustainer on the destination")
+                        .value_name("DIR")
+                        .default_value("/var/lib/rustainer"),
+                ),
+        )
+        .subcommand(
+            Command::new("update")
+                .about("Update the CPU pinning of a running container")
+                .arg(
+                    Arg::new("container")
+                        .help("Container ID or name to update")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("cpuset-cpus")
+                        .long("cpuset-cpus")
+                        .help("CPUs in which to allow execution (e.g., 0-3,6)")
+                        .value_name("CPUS"),
+                )
+                .arg(
+                    Arg::new("cpuset-mems")
+                        .long("cpuset-mems")
+                        .help("Memory nodes in which to allow execution (e.g., 0,1)")
+                        .value_name("MEMS"),
+                ),
+        )
+        .subcommand(
+            Command::new("wait")
+                .about("Block until a container stops, then print its exit code")
+                .arg(
+                    Arg::new("container")
+                        .help("Container ID or name to wait for")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export a container's filesystem as a tar archive")
+                .arg(
+                    Arg::new("container")
+                        .help("Container ID or name to export")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("Write to a file instead of STDOUT")
+                        .value_name("FILE"),
+                ),
+        )
+        .subcommand(
+            Command::new("clone")
+                .about("Copy a container's filesystem and configuration into a new, stopped container")
+                .arg(
+                    Arg::new("container")
+                        .help("Container ID or name to clone")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("name")
+                        .short('n')
+                        .long("name")
+                        .help("Assign a name to the clone")
+                        .value_name("NAME"),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Create an image from a filesystem tarball")
+                .arg(
+                    Arg::new("file")
+                        .help("Tarball to import, or '-' to read from STDIN")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("image")
+                        .help("Name for the new image (e.g., myimage:latest)")
+                        .required(true)
+                        .index(2),
+                ),
+        )
+        .subcommand(
+            Command::new("container")
+                .about("Manage containers")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("prune")
+                        .about("Remove all stopped containers and the image layers left unused")
+                        .arg(
+                            Arg::new("filter")
+                                .long("filter")
+                                .help("Only prune containers created before a duration ago")
+                                .value_name("until=DURATION"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("store")
+                .about("Manage the storage root")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("verify")
+                        .about("Check images, layers and containers for missing or corrupted content")
+                        .arg(
+                            Arg::new("repair")
+                                .long("repair")
+                                .help("Remove broken images, extract missing layers again and fix the name index")
+                                .action(clap::ArgAction::SetTrue),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("volume")
+                .about("Manage named volumes")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("Create a volume")
+                        .arg(
+                            Arg::new("name")
+                                .help("Volume name (default is a random ID)")
+                                .index(1),
+                        ),
+                )
+                .subcommand(Command::new("ls").about("List volumes"))
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove one or more volumes not used by any container")
+                        .arg(
+                            Arg::new("volume")
+                                .help("Volume names")
+                                .required(true)
+                                .index(1)
+                                .action(clap::ArgAction::Append),
+                        ),
+                )
+                .subcommand(
+                    Command::new("inspect")
+                        .about("Show volumes as JSON")
+                        .arg(
+                            Arg::new("volume")
+                                .help("Volume names")
+                                .required(true)
+                                .index(1)
+                                .action(clap::ArgAction::Append),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("secret")
+                .about("Manage secrets")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("Store a secret, encrypted")
+                        .arg(
+                            Arg::new("name")
+                                .help("Secret name")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::new("file")
+                                .help("File to read the secret from, or '-' to read from STDIN")
+                                .required(true)
+                                .index(2),
+                        ),
+                )
+                .subcommand(Command::new("ls").about("List secrets"))
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove one or more secrets not used by any container")
+                        .arg(
+                            Arg::new("secret")
+                                .help("Secret names")
+                                .required(true)
+                                .index(1)
+                                .action(clap::ArgAction::Append),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Manage configs")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("Store a config")
+                        .arg(
+                            Arg::new("name")
+                                .help("Config name")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::new("file")
+                                .help("File to read the config from, or '-' to read from STDIN")
+                                .required(true)
+                                .index(2),
+                        ),
+                )
+                .subcommand(Command::new("ls").about("List configs"))
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove one or more configs not used by any container")
+                        .arg(
+                            Arg::new("config")
+                                .help("Config names")
+                                .required(true)
+                                .index(1)
+                                .action(clap::ArgAction::Append),
+                        ),
+                )
+                .subcommand(
+                    Command::new("inspect")
+                        .about("Show configs and their contents as JSON")
+                        .arg(
+                            Arg::new("config")
+                                .help("Config names")
+                                .required(true)
+                                .index(1)
+                                .action(clap::ArgAction::Append),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Display a live stream of container resource usage")
+                .arg(
+                    Arg::new("container")
+                        .help("Containers to show (default shows all running)")
+                        .index(1)
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("no-stream")
+                        .long("no-stream")
+                        .help("Print a single snapshot instead of refreshing")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("events")
+                .about("Stream container, image and network lifecycle events")
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .help("Also show events from this long ago (e.g., 10m, 2h)")
+                        .value_name("DURATION"),
+                )
+                .arg(
+                    Arg::new("until")
+                        .long("until")
+                        .help("Stop after events up to this long ago instead of following")
+                        .value_name("DURATION"),
+                )
+                .arg(
+                    Arg::new("filter")
+                        .short('f')
+                        .long("filter")
+                        .help("Filter events (type=container|image|network|volume, event=ACTION, container=ID)")
+                        .value_name("KEY=VALUE")
+                        .action(clap::ArgAction::Append),
+                ),
+        )
+        .subcommand(
+            Command::new("healthcheck")
+                .about("Run healthcheck probes for a container (internal)")
+                .hide(true)
+                .arg(Arg::new("container").required(true).index(1)),
+        )
+        .subcommand(
+            Command::new("supervise")
+                .about("Supervise a detached container (internal)")
+                .hide(true)
+                .arg(Arg::new("container").required(true).index(1)),
+        )
+        .get_matches();
+
+    if let Err(e) = actions::store::init(matches.get_one::<String>("root").map(String::as_str)) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+
+    match matches.subcommand() {
+        Some(("run", sub_matches)) => match handle_run_command(sub_matches).await {
+            Ok(exit_code) => process::exit(exit_code),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        },
+        Some(("pull", sub_matches)) => {
+            if let Err(e) = handle_pull_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("images", sub_matches)) => {
+            if let Err(e) = handle_images_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("rmi", sub_matches)) => {
+            if let Err(e) = handle_rmi_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("image", sub_matches)) => {
+            if let Err(e) = handle_image_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("ps", sub_matches)) => {
+            if let Err(e) = handle_ps_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("rm", sub_matches)) => {
+            if let Err(e) = handle_rm_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("inspect", sub_matches)) => {
+            if let Err(e) = handle_inspect_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("stop", sub_matches)) => {
+            if let Err(e) = handle_stop_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("checkpoint", sub_matches)) => {
+            if let Err(e) = handle_checkpoint_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("restore", sub_matches)) => {
+            if let Err(e) = handle_restore_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("migrate", sub_matches)) => {
+            if let Err(e) = handle_migrate_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("update", sub_matches)) => {
+            if let Err(e) = handle_update_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("wait", sub_matches)) => {
+            if let Err(e) = handle_wait_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("export", sub_matches)) => {
+            if let Err(e) = handle_export_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("clone", sub_matches)) => {
+            if let Err(e) = handle_clone_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("import", sub_matches)) => {
+            if let Err(e) = handle_import_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("container", sub_matches)) => {
+            if let Err(e) = handle_container_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("store", sub_matches)) => {
+            if let Err(e) = handle_store_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("volume", sub_matches)) => {
+            if let Err(e) = handle_volume_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("secret", sub_matches)) => {
+            if let Err(e) = handle_secret_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("config", sub_matches)) => {
+            if let Err(e) = handle_config_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("stats", sub_matches)) => {
+            if let Err(e) = handle_stats_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("events", sub_matches)) => {
+            if let Err(e) = handle_events_command(sub_matches).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("healthcheck", sub_matches)) => {
+            let container_id = sub_matches.get_one::<String>("container").unwrap();
+            if let Err(e) = actions::health::monitor(container_id).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("supervise", sub_matches)) => {
+            let container_id = sub_matches.get_one::<String>("container").unwrap();
+            if let Err(e) = actions::restart::supervise(container_id).await {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!(
+                "No subcommand provided. Use 'rustainer --help' to list the available commands."
+            );
+            process::exit(1);
+        }
+    }
+}
+
+async fn handle_run_command(matches: &ArgMatches) -> Result<i32, Box<dyn std::error::Error>> {
+    let bundle = matches.get_one::<String>("bundle").cloned();
+    let name = matches.get_one::<String>("name").cloned();
+    let workdir = matches.get_one::<String>("workdir").cloned();
+    let user = matches.get_one::<String>("user").cloned();
+    let hostname = matches.get_one::<String>("hostname").cloned();
+    let ip = matches.get_one::<String>("ip").cloned();
+    let mac_address = matches.get_one::<String>("mac-address").cloned();
+    let cidfile = matches.get_one::<String>("cidfile").cloned();
+    let platform = matches.get_one::<String>("platform").cloned();
+    let gpus = matches.get_one::<String>("gpus").cloned();
+    let privileged = matches.get_flag("privileged");
+    let detach = matches.get_flag("detach");
+    let auto_remove = matches.get_flag("rm");
+    let restart_policy = matches
+        .get_one::<String>("restart")
+        .unwrap()
+        .parse::<actions::restart::RestartPolicy>()?;
+    let network = matches
+        .get_one::<String>("network")
+        .unwrap()
+        .parse::<actions::network::NetworkMode>()?;
+    let pull_policy = matches
+        .get_one::<String>("pull")
+        .unwrap()
+        .parse::<actions::pull::PullPolicy>()?;
+    let size = |arg: &str| -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        matches
+            .get_one::<String>(arg)
+            .map(|value| actions::utils::parse_size(value))
+            .transpose()
+    };
+    let memory_swap = match matches.get_one::<String>("memory-swap").map(String::as_str) {
+        Some("-1") => Some(-1),
+        Some(value) => Some(actions::utils::parse_size(value)? as i64),
+        None => None,
+    };
+    let device_rates = |arg: &str| {
+        matches
+            .get_many::<String>(arg)
+            .unwrap_or_default()
+            .map(|spec| actions::cgroup::DeviceRate::parse(spec))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let resources = actions::cgroup::ResourceLimits {
+        memory: size("memory")?,
+        memory_swap,
+        memory_reservation: size("memory-reservation")?,
+        cpus: matches.get_one::<f64>("cpus").copied(),
+        cpu_shares: matches.get_one::<u64>("cpu-shares").copied(),
+        cpu_quota: matches.get_one::<i64>("cpu-quota").copied(),
+        cpu_period: matches.get_one::<u64>("cpu-period").copied(),
+        pids_limit: matches.get_one::<i64>("pids-limit").copied(),
+        blkio_weight: matches.get_one::<u64>("blkio-weight").copied(),
+        device_read_bps: device_rates("device-read-bps")?,
+        device_write_bps: device_rates("device-write-bps")?,
+        cpuset_cpus: matches.get_one::<String>("cpuset-cpus").cloned(),
+        cpuset_mems: matches.get_one::<String>("cpuset-mems").cloned(),
+    };
+    let interactive = matches.get_flag("interactive");
+    let tty = matches.get_flag("tty");
+    let detach_keys =
+        actions::tty::parse_detach_keys(matches.get_one::<String>("detach-keys").unwrap())?;
+
+    let env_vars = matches
+        .get_many::<String>("env")
         .unwrap_or_default()
         .cloned()
         .collect();
 
+    let env_files = matches
+        .get_many::<String>("env-file")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let mask_env = matches
+        .get_many::<String>("mask-env")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+
+    let extra_hosts = matches
+        .get_many::<String>("add-host")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+
+    let list = |arg: &str| -> Vec<String> {
+        matches
+            .get_many::<String>(arg)
+            .unwrap_or_default()
+            .cloned()
+            .collect()
+    };
+    let dns = actions::dns::DnsConfig {
+        nameservers: list("dns"),
+        search: list("dns-search"),
+        options: list("dns-opt"),
+    };
+    let cap_add = list("cap-add");
+    let cap_drop = list("cap-drop");
+    let security_opts = list("security-opt");
+    let read_only = matches.get_flag("read-only");
+    let userns = matches
+        .get_one::<String>("userns")
+        .unwrap()
+        .parse::<actions::userns::UsernsMode>()?;
+    let namespace = |arg: &str| {
+        matches
+            .get_one::<String>(arg)
+            .unwrap()
+            .parse::<actions::namespaces::NamespaceMode>()
+    };
+    let pid_mode = namespace("pid")?;
+    let ipc_mode = namespace("ipc")?;
+    let uts_mode = namespace("uts")?;
+    let shm_size = size("shm-size")?;
+    let init = matches.get_flag("init");
+    let runtime = matches.get_one::<String>("runtime").cloned();
+    let nested = matches.get_flag("nested");
+    let share_images = matches.get_flag("share-images");
+    let oom_score_adj = matches.get_one::<i32>("oom-score-adj").copied();
+    let oom_kill_disable = matches.get_flag("oom-kill-disable");
+    let stop_signal = matches.get_one::<String>("stop-signal").cloned();
+    let stop_timeout = matches.get_one::<u64>("stop-timeout").copied();
+    let cgroup_parent = matches.get_one::<String>("cgroup-parent").cloned();
+    let group_add = list("group-add");
+    let storage_opts = matches
+        .get_many::<String>("storage-opt")
+        .unwrap_or_default()
+        .map(|spec| actions::storage::StorageOpt::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    let sysctls = matches
+        .get_many::<String>("sysctl")
+        .unwrap_or_default()
+        .map(|spec| actions::sysctl::Sysctl::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let volumes = matches
+        .get_many::<String>("volume")
+        .unwrap_or_default()
+        .map(|spec| actions::volume::Volume::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    let tmpfs = matches
+        .get_many::<String>("tmpfs")
+        .unwrap_or_default()
+        .map(|spec| actions::run::TmpfsMount::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mounts = matches
+        .get_many::<String>("mount")
+        .unwrap_or_default()
+        .map(|spec| actions::volume::MountSpec::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let secrets = matches
+        .get_many::<String>("secret")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+
+    let configs = matches
+        .get_many::<String>("config")
+        .unwrap_or_default()
+        .map(|spec| actions::config::ConfigMount::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+
     let ports = matches
         .get_many::<String>("port")
         .unwrap_or_default()
         .cloned()
         .collect();
 
-    let command = matches
+    let mut command: Option<Vec<String>> = matches
         .get_many::<String>("command")
         .map(|vals| vals.cloned().collect());
+    // A bundle takes the image's place, so every positional is the command.
+    let image = match &bundle {
+        Some(bundle) => {
+            if let Some(first) = matches.get_one::<String>("image") {
+                command
+                    .get_or_insert_with(Vec::new)
+                    .insert(0, first.clone());
+            }
+            bundle.clone()
+        }
+        None => matches.get_one::<String>("image").unwrap().clone(),
+    };
+
+    let duration = |arg: &str| -> Result<Option<std::time::Duration>, Box<dyn std::error::Error>> {
+        matches
+            .get_one::<String>(arg)
+            .map(|value| actions::utils::parse_duration(value))
+            .transpose()
+    };
+    let timeout = duration("timeout")?;
+    let health = actions::health::HealthOverrides {
+        cmd: matches.get_one::<String>("health-cmd").cloned(),
+        interval: duration("health-interval")?,
+        timeout: duration("health-timeout")?,
+        retries: matches.get_one::<u32>("health-retries").copied(),
+        start_period: duration("health-start-period")?,
+        disabled: matches.get_flag("no-healthcheck"),
+    };
 
     let config = actions::run::RunConfig {
         image,
         name,
         detach,
+        auto_remove,
+        restart_policy,
         interactive,
         tty,
         env_vars,
+        env_files,
+        mask_env,
         volumes,
+        tmpfs,
+        mounts,
+        secrets,
+        configs,
         ports,
         command,
+        workdir,
+        user,
+        hostname,
+        extra_hosts,
+        dns,
+        network,
+        ip,
+        mac_address,
+        cidfile,
+        pull_policy,
+        platform,
+        resources,
+        gpus,
+        privileged,
+        cap_add,
+        cap_drop,
+        security_opts,
+        read_only,
+        userns,
+        pid_mode,
+        ipc_mode,
+        uts_mode,
+        shm_size,
+        oom_score_adj,
+        oom_kill_disable,
+        stop_signal,
+        stop_timeout,
+        sysctls,
+        storage_opts,
+        cgroup_parent,
+        group_add,
+        timeout,
+        init,
+        runtime,
+        bundle,
+        nested,
+        share_images,
+        detach_keys,
+        health,
     };
 
-    actions::run::run_container(config).await?;
-    Ok(())
+    actions::run::run_container(config).await
 }
 
 async fn handle_pull_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
     let image = matches.get_one::<String>("image").unwrap();
-    actions::pull::pull_image(image).await?;
+    let platform = matches.get_one::<String>("platform");
+    actions::pull::pull_image(image, platform.map(|p| p.as_str())).await?;
     Ok(())
 }
 
-async fn handle_images_command() -> Result<(), Box<dyn std::error::Error>> {
-    actions::images::list_images().await?;
+async fn handle_images_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let show_digests = matches.get_flag("digests");
+    actions::images::list_images(show_digests).await?;
     Ok(())
 }
 
-async fn handle_ps_command() -> Result<(), Box<dyn std::error::Error>> {
-    actions::ls::list_containers().await?;
+async fn handle_rmi_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let images: Vec<String> = matches
+        .get_many::<String>("image")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    actions::images::remove_images(&images, matches.get_flag("force"))?;
+    Ok(())
+}
+
+async fn handle_image_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    match matches.subcommand() {
+        Some(("mount", sub_matches)) => {
+            let image = sub_matches.get_one::<String>("image");
+            let target = sub_matches.get_one::<String>("target");
+            match (image, target) {
+                (Some(image), Some(target)) => actions::mount::mount_image(image, target)?,
+                (None, _) => actions::mount::list_image_mounts()?,
+                (Some(_), None) => return Err("image mount needs a directory to mount at".into()),
+            }
+        }
+        Some(("export-disk", sub_matches)) => {
+            let image = sub_matches.get_one::<String>("image").unwrap();
+            let format = sub_matches
+                .get_one::<String>("format")
+                .unwrap()
+                .parse::<actions::disk::DiskFormat>()?;
+            let output = sub_matches.get_one::<String>("output").unwrap();
+            let size = sub_matches
+                .get_one::<String>("size")
+                .map(|size| actions::utils::parse_size(size))
+                .transpose()?;
+            actions::disk::export_disk(image, format, output, size)?;
+        }
+        Some(("unmount", sub_matches)) => {
+            let target = sub_matches.get_one::<String>("target").unwrap();
+            actions::mount::unmount_image(target)?;
+        }
+        _ => unreachable!("subcommand_required guarantees an image subcommand"),
+    }
+
+    Ok(())
+}
+
+async fn handle_ps_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let all = matches.get_flag("all");
+    let filters: Vec<String> = matches
+        .get_many::<String>("filter")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let filter = actions::ls::ContainerFilter::parse(&filters)?;
+    let format =
+        actions::ls::OutputFormat::parse(matches.get_one::<String>("format").map(String::as_str))?;
+
+    let with_size = matches.get_flag("size");
+
+    actions::ls::list_containers(all, &filter, &format, with_size).await?;
     Ok(())
 }
 
 async fn handle_rm_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
     let container_id = matches.get_one::<String>("container").unwrap();
 
-    actions::rm::remove_container(container_id).await?;
+    let force = matches.get_flag("force");
+    let remove_volumes = matches.get_flag("volumes");
+
+    actions::rm::remove_container(container_id, force, remove_volumes).await?;
+    Ok(())
+}
+
+async fn handle_inspect_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = matches.get_one::<String>("container").unwrap();
+
+    actions::inspect::inspect_container(container_id).await?;
+    Ok(())
+}
+
+async fn handle_stop_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = matches.get_one::<String>("container").unwrap();
+    let timeout = matches.get_one::<u64>("time").copied();
+
+    actions::stop::stop_container(container_id, timeout).await?;
+    Ok(())
+}
+
+async fn handle_checkpoint_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = matches.get_one::<String>("container").unwrap();
+    let leave_running = matches.get_flag("leave-running");
+    actions::checkpoint::checkpoint_container(container_id, leave_running).await
+}
+
+async fn handle_restore_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = matches.get_one::<String>("container").unwrap();
+    actions::checkpoint::restore_container(container_id).await
+}
+
+async fn handle_migrate_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = matches.get_one::<String>("container").unwrap();
+    let destination = matches.get_one::<String>("destination").unwrap();
+    let remote_dir = matches.get_one::<String>("remote-dir").unwrap();
+    actions::migrate::migrate_container(container_id, destination, remote_dir).await
+}
+
+async fn handle_update_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = matches.get_one::<String>("container").unwrap();
+    let limits = actions::cgroup::ResourceLimits {
+        cpuset_cpus: matches.get_one::<String>("cpuset-cpus").cloned(),
+        cpuset_mems: matches.get_one::<String>("cpuset-mems").cloned(),
+        ..Default::default()
+    };
+
+    actions::update::update_container(container_id, &limits).await?;
+    Ok(())
+}
+
+async fn handle_wait_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = matches.get_one::<String>("container").unwrap();
+
+    actions::wait::wait_container(container_id).await?;
+    Ok(())
+}
+
+async fn handle_export_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = matches.get_one::<String>("container").unwrap();
+    let output = matches.get_one::<String>("output");
+
+    actions::export::export_container(container_id, output.map(String::as_str)).await?;
+    Ok(())
+}
+
+async fn handle_clone_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let container_id = matches.get_one::<String>("container").unwrap();
+    let name = matches.get_one::<String>("name");
+
+    actions::clone::clone_container(container_id, name.map(String::as_str)).await?;
+    Ok(())
+}
+
+async fn handle_import_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let file = matches.get_one::<String>("file").unwrap();
+    let image = matches.get_one::<String>("image").unwrap();
+
+    actions::import::import_image(file, image).await?;
+    Ok(())
+}
+
+async fn handle_container_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    match matches.subcommand() {
+        Some(("prune", sub_matches)) => {
+            let until = match sub_matches.get_one::<String>("filter") {
+                Some(filter) => {
+                    let duration = filter.strip_prefix("until=").ok_or_else(|| {
+                        format!("Unsupported filter: {}. Expected until=<duration>", filter)
+                    })?;
+                    Some(actions::utils::parse_duration(duration)?)
+                }
+                None => None,
+            };
+
+            actions::prune::prune_containers(until).await?;
+        }
+        _ => unreachable!("subcommand_required guarantees a container subcommand"),
+    }
+
+    Ok(())
+}
+
+async fn handle_store_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    match matches.subcommand() {
+        Some(("verify", sub_matches)) => {
+            actions::verify::verify_store(sub_matches.get_flag("repair")).await?
+        }
+        _ => unreachable!("subcommand_required guarantees a store subcommand"),
+    }
+
+    Ok(())
+}
+
+async fn handle_volume_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let volumes = |sub_matches: &ArgMatches| -> Vec<String> {
+        sub_matches
+            .get_many::<String>("volume")
+            .unwrap_or_default()
+            .cloned()
+            .collect()
+    };
+
+    match matches.subcommand() {
+        Some(("create", sub_matches)) => {
+            let name = sub_matches.get_one::<String>("name");
+            println!(
+                "{}",
+                actions::volume::create_volume(name.map(String::as_str))?
+            );
+        }
+        Some(("ls", _)) => actions::volume::list_volumes()?,
+        Some(("rm", sub_matches)) => actions::volume::remove_volumes(&volumes(sub_matches))?,
+        Some(("inspect", sub_matches)) => actions::volume::inspect_volumes(&volumes(sub_matches))?,
+        _ => unreachable!("subcommand_required guarantees a volume subcommand"),
+    }
+
+    Ok(())
+}
+
+async fn handle_secret_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    match matches.subcommand() {
+        Some(("create", sub_matches)) => {
+            let name = sub_matches.get_one::<String>("name").unwrap();
+            let file = sub_matches.get_one::<String>("file").unwrap();
+            actions::secret::create_secret(name, file)?;
+            println!("{}", name);
+        }
+        Some(("ls", _)) => actions::secret::list_secrets()?,
+        Some(("rm", sub_matches)) => {
+            let names: Vec<String> = sub_matches
+                .get_many::<String>("secret")
+                .unwrap_or_default()
+                .cloned()
+                .collect();
+            actions::secret::remove_secrets(&names)?;
+        }
+        _ => unreachable!("subcommand_required guarantees a secret subcommand"),
+    }
+
+    Ok(())
+}
+
+async fn handle_config_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let configs = |sub_matches: &ArgMatches| -> Vec<String> {
+        sub_matches
+            .get_many::<String>("config")
+            .unwrap_or_default()
+            .cloned()
+            .collect()
+    };
+
+    match matches.subcommand() {
+        Some(("create", sub_matches)) => {
+            let name = sub_matches.get_one::<String>("name").unwrap();
+            let file = sub_matches.get_one::<String>("file").unwrap();
+            actions::config::create_config(name, file)?;
+            println!("{}", name);
+        }
+        Some(("ls", _)) => actions::config::list_configs()?,
+        Some(("rm", sub_matches)) => actions::config::remove_configs(&configs(sub_matches))?,
+        Some(("inspect", sub_matches)) => actions::config::inspect_configs(&configs(sub_matches))?,
+        _ => unreachable!("subcommand_required guarantees a config subcommand"),
+    }
+
+    Ok(())
+}
+
+async fn handle_stats_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let containers: Vec<String> = matches
+        .get_many::<String>("container")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let no_stream = matches.get_flag("no-stream");
+
+    actions::stats::show_stats(&containers, no_stream).await?;
+    Ok(())
+}
+
+async fn handle_events_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let now = actions::container::now_secs();
+    let ago = |arg: &str| -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        match matches.get_one::<String>(arg) {
+            Some(value) => {
+                let duration = actions::utils::parse_duration(value)?;
+                Ok(Some(now.saturating_sub(duration.as_secs())))
+            }
+            None => Ok(None),
+        }
+    };
+    let since = ago("since")?;
+    let until = ago("until")?;
+
+    let filters: Vec<String> = matches
+        .get_many::<String>("filter")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let filter = actions::events::EventFilter::parse(&filters)?;
+
+    actions::events::stream_events(since, until, &filter).await?;
     Ok(())
 }